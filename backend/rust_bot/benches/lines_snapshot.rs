@@ -0,0 +1,45 @@
+//! Стоимость `AddLines` в зависимости от количества ключей в кеше линий.
+//! Крейт - только бинарник, поэтому здесь тот же шаблон, что у `LinesStore`:
+//! `im::HashMap` ключей с `Arc` историями, копия снимка и замена изменённых историй.
+//!
+//! `cargo bench --bench lines_snapshot`
+use std::{collections::VecDeque, hint::black_box, sync::Arc, time::{Duration, Instant}};
+
+/// Ключей в одном `AddLines`, как у `db_writer` за минуту
+const BATCH: usize = 100;
+const ROUNDS: i64 = 200;
+/// Линий в истории ключа, как `MAX_LINES` у `CacheAggregator`
+const MAX_LINES: usize = 100;
+
+type Snapshot = im::HashMap<String, Arc<VecDeque<(i64, f64)>>>;
+
+/// Время `ROUNDS` пакетов по `BATCH` ключам в снимке из `count` ключей
+fn add_lines_time(
+    count: usize
+) -> Duration {
+    let keys: Vec<String> = (0..count).map(|i| format!("bybit:gate.io:coin{i}usdt")).collect();
+    let mut snapshot: Snapshot = keys.iter().map(|key| (key.clone(), Arc::new(VecDeque::from([(0, 0.5)])))).collect();
+    let touched: Vec<&String> = keys.iter().step_by(count / BATCH).take(BATCH).collect();
+
+    let started = Instant::now();
+    for round in 1..=ROUNDS {
+        let mut next = snapshot.clone();
+        for key in touched.iter() {
+            let mut lines = next.get(*key).map(|lines| lines.as_ref().clone()).unwrap_or_default();
+            lines.push_back((round * 60, 0.5));
+            while lines.len() > MAX_LINES {
+                lines.pop_front();
+            }
+            next.insert((*key).clone(), Arc::new(lines));
+        }
+        snapshot = black_box(next);
+    }
+    started.elapsed()
+}
+
+fn main() {
+    for count in [1_000, 10_000, 50_000] {
+        let best = (0..5).map(|_| add_lines_time(count)).min().unwrap();
+        println!("AddLines по {BATCH} ключам при {count} ключей: {:?} на пакет", best / ROUNDS as u32);
+    }
+}
@@ -0,0 +1,3 @@
+-- Новое значение enum нельзя использовать в той же транзакции, где оно добавлено,
+-- поэтому оно вынесено в отдельную миграцию
+ALTER TYPE timeframe ADD VALUE IF NOT EXISTS '1h';
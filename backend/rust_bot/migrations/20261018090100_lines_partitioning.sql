@@ -0,0 +1,71 @@
+-- storage.lines разбивается по timeframe (LIST), а каждый timeframe по времени (RANGE).
+-- Старые партиции удаляются фоновой задачей LinesMaintenance целиком, без DELETE по таблице.
+
+ALTER TABLE storage.lines RENAME TO lines_legacy;
+ALTER TABLE storage.lines_legacy RENAME CONSTRAINT lines_pkey TO lines_legacy_pkey;
+
+CREATE TABLE storage.lines (
+    timestamp BIGINT NOT NULL,
+    long_exchange exchange_type NOT NULL,
+    short_exchange exchange_type NOT NULL,
+    symbol VARCHAR(255) NOT NULL,
+    timeframe timeframe NOT NULL,
+    value FLOAT NOT NULL,
+    PRIMARY KEY (timeframe, timestamp, long_exchange, short_exchange, symbol)
+) PARTITION BY LIST (timeframe);
+
+CREATE TABLE storage.lines_1m PARTITION OF storage.lines
+    FOR VALUES IN ('1m') PARTITION BY RANGE (timestamp);
+
+CREATE TABLE storage.lines_1h PARTITION OF storage.lines
+    FOR VALUES IN ('1h') PARTITION BY RANGE (timestamp);
+
+-- Сюда попадают строки, для которых ещё не создана партиция
+CREATE TABLE storage.lines_1m_default PARTITION OF storage.lines_1m DEFAULT;
+CREATE TABLE storage.lines_1h_default PARTITION OF storage.lines_1h DEFAULT;
+
+-- Создаёт партицию storage.lines_<tf>_p<YYYYMMDD> на диапазон [range_from, range_to)
+CREATE OR REPLACE FUNCTION storage.ensure_lines_partition(
+    tf TEXT,
+    range_from BIGINT,
+    range_to BIGINT
+) RETURNS TEXT AS $$
+DECLARE
+    partition_name TEXT := format(
+        'lines_%s_p%s',
+        tf,
+        to_char(to_timestamp(range_from) AT TIME ZONE 'UTC', 'YYYYMMDD')
+    );
+BEGIN
+    EXECUTE format(
+        'CREATE TABLE IF NOT EXISTS storage.%I PARTITION OF storage.%I FOR VALUES FROM (%s) TO (%s)',
+        partition_name,
+        'lines_' || tf,
+        range_from,
+        range_to
+    );
+    RETURN partition_name;
+END;
+$$ LANGUAGE plpgsql;
+
+-- Партиции под уже накопленные данные (по суткам)
+DO $$
+DECLARE
+    day_start BIGINT;
+    last_day BIGINT;
+BEGIN
+    SELECT min(timestamp) - min(timestamp) % 86400, max(timestamp)
+    INTO day_start, last_day
+    FROM storage.lines_legacy;
+
+    WHILE day_start IS NOT NULL AND day_start <= last_day LOOP
+        PERFORM storage.ensure_lines_partition('1m', day_start, day_start + 86400);
+        day_start := day_start + 86400;
+    END LOOP;
+END$$;
+
+INSERT INTO storage.lines (timestamp, long_exchange, short_exchange, symbol, timeframe, value)
+SELECT timestamp, long_exchange, short_exchange, symbol, timeframe, value
+FROM storage.lines_legacy;
+
+DROP TABLE storage.lines_legacy;
@@ -0,0 +1,44 @@
+-- Бумажная торговля: позиции пользователей и их исполнения.
+-- Время в мс Unix, суммы в котируемой валюте (USDT)
+
+CREATE TABLE IF NOT EXISTS storage.paper_positions (
+    id BIGINT PRIMARY KEY,
+    user_id VARCHAR(255) NOT NULL,
+    symbol VARCHAR(255) NOT NULL,
+    long_exchange exchange_type NOT NULL,
+    short_exchange exchange_type NOT NULL,
+    status VARCHAR(16) NOT NULL,
+    notional FLOAT NOT NULL,
+    qty FLOAT NOT NULL,
+    long_entry FLOAT NOT NULL,
+    short_entry FLOAT NOT NULL,
+    entry_spread_pct FLOAT NOT NULL,
+    take_profit_pct FLOAT,
+    stop_loss_pct FLOAT,
+    fees FLOAT NOT NULL,
+    opened_at BIGINT NOT NULL,
+    closed_at BIGINT,
+    long_exit FLOAT,
+    short_exit FLOAT,
+    exit_spread_pct FLOAT,
+    realized_pnl FLOAT,
+    close_reason VARCHAR(32)
+);
+
+CREATE INDEX IF NOT EXISTS paper_positions_user_idx ON storage.paper_positions (user_id, opened_at DESC);
+CREATE INDEX IF NOT EXISTS paper_positions_open_idx ON storage.paper_positions (status) WHERE status = 'open';
+
+CREATE TABLE IF NOT EXISTS storage.paper_fills (
+    id BIGSERIAL PRIMARY KEY,
+    position_id BIGINT NOT NULL REFERENCES storage.paper_positions (id),
+    exchange exchange_type NOT NULL,
+    side VARCHAR(4) NOT NULL,
+    qty FLOAT NOT NULL,
+    price FLOAT NOT NULL,
+    -- Лучшая цена стакана в момент исполнения, разница с price - проскальзывание
+    best_price FLOAT NOT NULL,
+    fee FLOAT NOT NULL,
+    timestamp BIGINT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS paper_fills_position_idx ON storage.paper_fills (position_id);
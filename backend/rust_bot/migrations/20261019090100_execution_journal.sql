@@ -0,0 +1,38 @@
+-- Автоматическое исполнение арбитража: итоги исполнений и журнал решений по ним.
+-- Время в мс Unix, суммы в котируемой валюте (USDT)
+
+CREATE TABLE IF NOT EXISTS storage.executions (
+    id BIGINT PRIMARY KEY,
+    symbol VARCHAR(255) NOT NULL,
+    long_exchange exchange_type NOT NULL,
+    short_exchange exchange_type NOT NULL,
+    status VARCHAR(16) NOT NULL,
+    threshold_pct FLOAT NOT NULL,
+    spread_pct FLOAT NOT NULL,
+    qty FLOAT NOT NULL,
+    buy_price FLOAT NOT NULL,
+    sell_price FLOAT NOT NULL,
+    long_qty FLOAT NOT NULL,
+    short_qty FLOAT NOT NULL,
+    cash_flow FLOAT NOT NULL,
+    fees FLOAT NOT NULL,
+    started_at BIGINT NOT NULL,
+    finished_at BIGINT
+);
+
+CREATE INDEX IF NOT EXISTS executions_started_idx ON storage.executions (started_at DESC);
+
+CREATE TABLE IF NOT EXISTS storage.execution_journal (
+    id BIGSERIAL PRIMARY KEY,
+    execution_id BIGINT NOT NULL REFERENCES storage.executions (id),
+    decision VARCHAR(16) NOT NULL,
+    exchange exchange_type,
+    side VARCHAR(4),
+    client_order_id VARCHAR(64),
+    qty FLOAT,
+    price FLOAT,
+    note TEXT NOT NULL,
+    timestamp BIGINT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS execution_journal_execution_idx ON storage.execution_journal (execution_id);
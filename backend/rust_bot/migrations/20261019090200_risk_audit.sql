@@ -0,0 +1,26 @@
+-- Предторговые риск-проверки: нарушения лимитов и включённые kill switch.
+-- Время в мс Unix, суммы в котируемой валюте (USDT)
+
+CREATE TABLE IF NOT EXISTS storage.risk_breaches (
+    id BIGSERIAL PRIMARY KEY,
+    check_name VARCHAR(32) NOT NULL,
+    exchange exchange_type,
+    symbol VARCHAR(255),
+    client_order_id VARCHAR(64),
+    value FLOAT NOT NULL,
+    limit_value FLOAT NOT NULL,
+    message TEXT NOT NULL,
+    -- Kill switch, включённый нарушением: `global` или биржа
+    killed VARCHAR(32),
+    timestamp BIGINT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS risk_breaches_timestamp_idx ON storage.risk_breaches (timestamp DESC);
+
+-- Только включённые switch: выключение удаляет строку
+CREATE TABLE IF NOT EXISTS storage.risk_kill_switches (
+    scope VARCHAR(32) PRIMARY KEY,
+    reason TEXT NOT NULL,
+    source VARCHAR(16) NOT NULL,
+    activated_at BIGINT NOT NULL
+);
@@ -1,26 +1,32 @@
-use std::{collections::BTreeMap, sync::Arc};
+use std::{collections::BTreeMap, sync::Arc, time::Duration};
 
 use rust_decimal::Decimal;
-use tokio::sync::{Mutex, mpsc, watch};
+use tokio::sync::Mutex;
 use tokio_tungstenite::tungstenite::Message;
 
-use crate::{models::{exchange::{PriceCache, TickerInfo}, orderbook::OrderBookEventData, websocket::Symbol}, services::exchange::{exchange_adapter::ExchangeAdapter, exchange_aggregator::ExchangeStoreCMD}};
+use crate::{config::ExchangeConfig, models::{exchange::{PriceCache, TickerInfo}, orderbook::{EventTime, OrderBookEventData}, websocket::Symbol}, services::exchange::{exchange_adapter::{Endpoints, ExchangeAdapter, ExchangeLimits, Heartbeat}, exchange_aggregator::BookEventSender}};
 
 #[allow(unused)]
 pub struct BinanceAdapter {
-    price_cache: Arc<Mutex<PriceCache>>
+    price_cache: Arc<Mutex<PriceCache>>,
+    endpoints: Endpoints
 }
 
 impl BinanceAdapter {
-    pub fn new() -> Arc<Self> {
-        Arc::new(Self { price_cache: Arc::new(Mutex::new(PriceCache::new())) })
+    pub fn new(
+        config: &ExchangeConfig
+    ) -> Arc<Self> {
+        Arc::new(Self {
+            price_cache: Arc::new(Mutex::new(PriceCache::new())),
+            endpoints: Endpoints::new("wss://stream.binance.com:443/ws", "https://api.binance.com", config)
+        })
     }
 }
 
 #[async_trait::async_trait]
 impl ExchangeAdapter for BinanceAdapter {
-    fn ws_url(self: Arc<Self>) -> &'static str {
-        "wss://stream.binance.com:443/ws"
+    fn ws_url(self: Arc<Self>) -> String {
+        self.endpoints.ws_url.clone()
     }
 
     fn requires_auth(
@@ -51,14 +57,71 @@ impl ExchangeAdapter for BinanceAdapter {
         self: Arc<Self>,
         tickers: &Vec<TickerInfo>,
         client: &reqwest::Client,
-        sender_data: watch::Sender<ExchangeStoreCMD>
+        sender_data: BookEventSender
     ) {
         
     }
 
-    fn create_subscribe_messages(
+    fn limits(
+        self: Arc<Self>
+    ) -> ExchangeLimits {
+        ExchangeLimits {
+            topics_per_connection: 1024,
+            topics_per_symbol: 2,
+            args_per_message: 200,
+            messages_per_second: 5,
+            max_connections: 300,
+        }
+    }
+
+    /// Binance отвечает на ping фреймы WebSocket
+    fn heartbeat(
+        self: Arc<Self>
+    ) -> Heartbeat {
+        Heartbeat {
+            interval: Duration::from_secs(30),
+            max_missed: 2,
+        }
+    }
+
+    fn create_ping_message(
+        self: Arc<Self>,
+        _id: u64
+    ) -> Message {
+        Message::Ping(Vec::new())
+    }
+
+    fn is_pong(
+        self: Arc<Self>,
+        _msg: &str
+    ) -> bool {
+        false
+    }
+
+    fn create_ticker_subscribe_messages(
+        self: Arc<Self>,
+        _symbols: &[Arc<Symbol>]
+    ) -> Vec<Message> {
+        todo!()
+    }
+
+    fn create_ticker_unsubscribe_messages(
+        self: Arc<Self>,
+        _symbols: &[Arc<Symbol>]
+    ) -> Vec<Message> {
+        todo!()
+    }
+
+    fn create_depth_subscribe_messages(
+        self: Arc<Self>,
+        _symbols: &[Arc<Symbol>]
+    ) -> Vec<Message> {
+        todo!()
+    }
+
+    fn create_depth_unsubscribe_messages(
         self: Arc<Self>,
-        _symbol: Arc<Symbol>
+        _symbols: &[Arc<Symbol>]
     ) -> Vec<Message> {
         todo!()
     }
@@ -72,8 +135,7 @@ impl ExchangeAdapter for BinanceAdapter {
     async fn parse_message(
         self: Arc<Self>,
         _msg: String,
-        _snapshot_channel: mpsc::Sender<ExchangeStoreCMD>,
-        _data_aggregator_tx: watch::Sender<ExchangeStoreCMD>,
+        _data_aggregator_tx: BookEventSender,
     ) {
         
     }
@@ -81,7 +143,7 @@ impl ExchangeAdapter for BinanceAdapter {
     async fn parse_tickers(
         self: Arc<Self>,
         msg: Arc<String>,
-        sender_data: watch::Sender<ExchangeStoreCMD>
+        sender_data: BookEventSender
     ) {
 
     }
@@ -97,8 +159,7 @@ impl ExchangeAdapter for BinanceAdapter {
     async fn parse_orderbook(
         self: Arc<Self>,
         _msg: Arc<String>,
-        _snapshot_channel: mpsc::Sender<ExchangeStoreCMD>,
-        _sender_data: watch::Sender<ExchangeStoreCMD>
+        _sender_data: BookEventSender
     ) {
         
     }
@@ -106,8 +167,8 @@ impl ExchangeAdapter for BinanceAdapter {
     async fn handle_snapshot<'a>(
         self: Arc<Self>,
         _data: Option<OrderBookEventData<'a>>,
-        _snapshot_channel: mpsc::Sender<ExchangeStoreCMD>,
-        _sender_data: watch::Sender<ExchangeStoreCMD>
+        _time: EventTime,
+        _sender_data: BookEventSender
     ) {
 
     }
@@ -115,7 +176,8 @@ impl ExchangeAdapter for BinanceAdapter {
     async fn handle_delta<'a>(
         self: Arc<Self>,
         _data: Option<OrderBookEventData<'a>>,
-        _sender_data: watch::Sender<ExchangeStoreCMD>
+        _time: EventTime,
+        _sender_data: BookEventSender
     ) {
 
     }
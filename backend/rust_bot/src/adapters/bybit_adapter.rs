@@ -1,23 +1,47 @@
-use std::{sync::Arc};
-use tokio::sync::{Mutex, mpsc, watch};
+use std::{sync::Arc, time::Duration};
+use tokio::sync::Mutex;
 use tokio_tungstenite::tungstenite::Message;
 
-use crate::{models::{exchange::{PriceCache, TickerEvent, TickerInfo, TickerResponse}, orderbook::{BookEvent, Delta, OrderBookEvent, OrderBookEventData, Snapshot}, websocket::Symbol}, services::exchange::{exchange_adapter::ExchangeAdapter, exchange_aggregator::{ExchangeStoreCMD, parse_levels__}}};
+use crate::{config::ExchangeConfig, models::{exchange::{PriceCache, TickerEvent, TickerInfo, TickerResponse}, orderbook::{BookEvent, Delta, EventTime, OrderBookEvent, OrderBookEventData, Snapshot}, websocket::Symbol}, services::exchange::{exchange_adapter::{Endpoints, ExchangeAdapter, ExchangeLimits, Heartbeat}, exchange_aggregator::{BookEventSender, parse_levels__}}};
 
 pub struct BybitAdapter {
-    price_cache: Arc<Mutex<PriceCache>>
+    price_cache: Arc<Mutex<PriceCache>>,
+    endpoints: Endpoints
 }
 
 impl BybitAdapter {
-    pub fn new() -> Arc<Self> {
-        Arc::new(Self { price_cache: Arc::new(Mutex::new(PriceCache::new())) })
+    pub fn new(
+        config: &ExchangeConfig
+    ) -> Arc<Self> {
+        Arc::new(Self {
+            price_cache: Arc::new(Mutex::new(PriceCache::new())),
+            endpoints: Endpoints::new("wss://stream.bybit.com/v5/public/spot", "https://api.bybit.com", config)
+        })
+    }
+
+    /// Упаковывает топики в сообщения по `args_per_message`
+    fn topic_messages(
+        self: Arc<Self>,
+        op: &str,
+        topics: Vec<String>
+    ) -> Vec<Message> {
+        topics
+            .chunks(self.limits().args_per_message)
+            .map(|args| Message::Text(
+                serde_json::json!({
+                    "op": op,
+                    "channel_type": "spot",
+                    "args": args
+                }).to_string().into()
+            ))
+            .collect()
     }
 }
 
 #[async_trait::async_trait]
 impl ExchangeAdapter for BybitAdapter {
-    fn ws_url(self: Arc<Self>) -> &'static str {
-        "wss://stream.bybit.com/v5/public/spot"
+    fn ws_url(self: Arc<Self>) -> String {
+        self.endpoints.ws_url.clone()
     }
 
     fn requires_auth(
@@ -41,7 +65,7 @@ impl ExchangeAdapter for BybitAdapter {
     }
 
     async fn get_tickers(self: Arc<Self>, client: &reqwest::Client) -> Option<Vec<TickerInfo>> {
-        let url = format!("https://api.bybit.com/v5/market/tickers?category=spot");
+        let url = format!("{}/v5/market/tickers?category=spot", self.endpoints.rest_url);
         let response = client.get(url).send().await;
 
         let Ok(response) = response else { return None };
@@ -59,30 +83,81 @@ impl ExchangeAdapter for BybitAdapter {
         self: Arc<Self>,
         _tickers: &Vec<TickerInfo>,
         _client: &reqwest::Client,
-        _sender_data: watch::Sender<ExchangeStoreCMD>
+        _sender_data: BookEventSender
     ) {
         
     }
 
-    fn create_subscribe_messages(
+    /// Spot: не больше 10 топиков в одном `subscribe`
+    fn limits(
+        self: Arc<Self>
+    ) -> ExchangeLimits {
+        ExchangeLimits {
+            topics_per_connection: 100,
+            topics_per_symbol: 2,
+            args_per_message: 10,
+            messages_per_second: 10,
+            max_connections: 20,
+        }
+    }
+
+    fn heartbeat(
+        self: Arc<Self>
+    ) -> Heartbeat {
+        Heartbeat {
+            interval: Duration::from_secs(20),
+            max_missed: 2,
+        }
+    }
+
+    fn create_ping_message(
+        self: Arc<Self>,
+        id: u64
+    ) -> Message {
+        Message::Text(serde_json::json!({
+            "op": "ping",
+            "req_id": id.to_string()
+        }).to_string())
+    }
+
+    /// `{"success":true,"ret_msg":"pong","op":"ping",...}`
+    fn is_pong(
+        self: Arc<Self>,
+        msg: &str
+    ) -> bool {
+        msg.contains("\"ret_msg\":\"pong\"")
+    }
+
+    fn create_ticker_subscribe_messages(
         self: Arc<Self>,
-        symbol: Arc<Symbol>
+        symbols: &[Arc<Symbol>]
     ) -> Vec<Message> {
-        let orderbook_str = format!("orderbook.50.{}", symbol);
-        let price_str = format!("tickers.{}", symbol);
-
-        let message = Message::Text(
-            serde_json::json!({
-                "op": "subscribe",
-                "channel_type": "spot",
-                "args": [
-                    orderbook_str,
-                    price_str
-                ]
-            }).to_string().into()
-        );
-        
-        vec![message]
+        let topics = symbols.iter().map(|symbol| format!("tickers.{}", symbol)).collect();
+        self.topic_messages("subscribe", topics)
+    }
+
+    fn create_ticker_unsubscribe_messages(
+        self: Arc<Self>,
+        symbols: &[Arc<Symbol>]
+    ) -> Vec<Message> {
+        let topics = symbols.iter().map(|symbol| format!("tickers.{}", symbol)).collect();
+        self.topic_messages("unsubscribe", topics)
+    }
+
+    fn create_depth_subscribe_messages(
+        self: Arc<Self>,
+        symbols: &[Arc<Symbol>]
+    ) -> Vec<Message> {
+        let topics = symbols.iter().map(|symbol| format!("orderbook.50.{}", symbol)).collect();
+        self.topic_messages("subscribe", topics)
+    }
+
+    fn create_depth_unsubscribe_messages(
+        self: Arc<Self>,
+        symbols: &[Arc<Symbol>]
+    ) -> Vec<Message> {
+        let topics = symbols.iter().map(|symbol| format!("orderbook.50.{}", symbol)).collect();
+        self.topic_messages("unsubscribe", topics)
     }
 
     fn cache(
@@ -94,13 +169,12 @@ impl ExchangeAdapter for BybitAdapter {
     async fn parse_message(
         self: Arc<Self>,
         msg: String,
-        snapshot_channel: mpsc::Sender<ExchangeStoreCMD>,
-        sender_data: watch::Sender<ExchangeStoreCMD>,
+        sender_data: BookEventSender,
     ) {
         let msg_arc = Arc::new(msg);
 
         if msg_arc.contains("orderbook") {
-            self.clone().parse_orderbook(msg_arc.clone(), snapshot_channel, sender_data.clone()).await;
+            self.clone().parse_orderbook(msg_arc.clone(), sender_data.clone()).await;
         }
 
         if msg_arc.contains("tickers") {
@@ -111,9 +185,16 @@ impl ExchangeAdapter for BybitAdapter {
     async fn parse_tickers(
         self: Arc<Self>,
         msg: Arc<String>,
-        sender_data: watch::Sender<ExchangeStoreCMD>
+        sender_data: BookEventSender
     ) {
-        let json: TickerEvent<'_> = serde_json::from_str(&msg).unwrap();
+        let json: TickerEvent<'_> = match serde_json::from_str(&msg) {
+            Ok(json) => json,
+            Err(e) => {
+                sender_data.parse_error(e);
+                return;
+            }
+        };
+        let time = EventTime::received(json.timestamp);
         let result = json.result;
         
         if let Some(data) = result {
@@ -135,15 +216,12 @@ impl ExchangeAdapter for BybitAdapter {
                     if symbol == "btcusdt" {
                         tracing::info!("BybitAdapter -> {}", last_price)
                     }
-                    let _ = sender_data.send(
-                        ExchangeStoreCMD::Event(
-                            BookEvent::TickerUpdate { 
-                                symbol, 
-                                last_price, 
-                                volume 
-                            }
-                        )
-                    );
+                    sender_data.send(BookEvent::TickerUpdate { 
+                        symbol, 
+                        last_price, 
+                        volume,
+                        time
+                    }).await;
                 }
             }
         }
@@ -152,19 +230,25 @@ impl ExchangeAdapter for BybitAdapter {
     async fn parse_orderbook(
         self: Arc<Self>,
         msg: Arc<String>,
-        snapshot_channel: mpsc::Sender<ExchangeStoreCMD>,
-        sender_data: watch::Sender<ExchangeStoreCMD>
+        sender_data: BookEventSender
     ) {
         
-        let json: OrderBookEvent<'_> = serde_json::from_str(&msg).unwrap();
+        let json: OrderBookEvent<'_> = match serde_json::from_str(&msg) {
+            Ok(json) => json,
+            Err(e) => {
+                sender_data.parse_error(e);
+                return;
+            }
+        };
+        let time = EventTime::received(json.timestamp);
         let data = json.data;
 
         match json.order_type.as_deref() {
             Some("snapshot") => {
-                self.handle_snapshot(data, snapshot_channel, sender_data.clone()).await;
+                self.handle_snapshot(data, time, sender_data.clone()).await;
             },
             Some("delta") => {
-                self.handle_delta(data, sender_data).await;
+                self.handle_delta(data, time, sender_data).await;
             },
             _ => {}
         }
@@ -173,8 +257,8 @@ impl ExchangeAdapter for BybitAdapter {
     async fn handle_snapshot<'a>(
         self: Arc<Self>,
         data: Option<OrderBookEventData<'a>>,
-        snapshot_channel: mpsc::Sender<ExchangeStoreCMD>,
-        _sender_data: watch::Sender<ExchangeStoreCMD>
+        time: EventTime,
+        sender_data: BookEventSender
     ) {
         if let Some(data) = data {
             let ticker = data.symbol;
@@ -190,18 +274,15 @@ impl ExchangeAdapter for BybitAdapter {
                 let asks = parse_levels__(asks);
                 let bids = parse_levels__(bids);
 
-                let _ = snapshot_channel.send(
-                    ExchangeStoreCMD::Event(
-                        BookEvent::Snapshot { 
-                            symbol: symbol, 
-                            snapshot: Snapshot {
-                                a: asks,
-                                b: bids,
-                                last_update_id: None,
-                            }
-                        }
-                    )
-                ).await;
+                sender_data.send(BookEvent::Snapshot { 
+                    symbol: symbol, 
+                    snapshot: Snapshot {
+                        a: asks,
+                        b: bids,
+                        last_update_id: None,
+                    },
+                    time
+                }).await;
             }
         }
     }
@@ -209,7 +290,8 @@ impl ExchangeAdapter for BybitAdapter {
     async fn handle_delta<'a>(
         self: Arc<Self>,
         data: Option<OrderBookEventData<'a>>,
-        sender_data: watch::Sender<ExchangeStoreCMD>
+        time: EventTime,
+        sender_data: BookEventSender
     ) {
         if let Some(data) = data {
             let ticker = data.symbol;
@@ -226,19 +308,16 @@ impl ExchangeAdapter for BybitAdapter {
                 let bids = parse_levels__(bids);
                 let is_valid = self.is_valid_book(&asks, &bids);
                 if is_valid {
-                    let _ = sender_data.send(
-                        ExchangeStoreCMD::Event(
-                            BookEvent::Delta { 
-                                symbol: symbol, 
-                                delta: Delta {
-                                    a: asks,
-                                    b: bids,
-                                    from_version: None,
-                                    to_version: None
-                                }
-                            }
-                        )
-                    );
+                    sender_data.send(BookEvent::Delta { 
+                        symbol: symbol, 
+                        delta: Delta {
+                            a: asks,
+                            b: bids,
+                            from_version: None,
+                            to_version: None
+                        },
+                        time
+                    }).await;
                 }
             }
         }
@@ -0,0 +1,560 @@
+use std::{sync::Arc, time::Duration};
+use serde::{Deserialize, de::DeserializeOwned};
+use tokio_tungstenite::tungstenite::Message;
+
+use crate::{config::ExchangeConfig, models::{exchange::ExchangeType, inventory::Balance, order::{ClientOrderId, FillReport, OrderRequest, OrderSide, OrderStatus, OrderType, OrderUpdate, TimeInForce}, websocket::Symbol}, services::exchange::{exchange_adapter::Heartbeat, trading_adapter::{Credentials, PrivateEvent, TradingAdapter, TradingEndpoints, TradingError, format_decimal, hmac_sha256_hex, parse_decimal}}};
+
+/// Ответ REST v5: `{"retCode":0,"retMsg":"OK","result":{...}}`
+#[derive(Debug, Deserialize)]
+#[serde(rename_all="camelCase")]
+struct BybitResponse {
+    ret_code: i64,
+    ret_msg: String,
+    #[serde(default)]
+    result: serde_json::Value,
+}
+
+#[derive(Debug, Deserialize)]
+struct BybitList<T> {
+    list: Vec<T>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all="camelCase")]
+struct BybitCreated {
+    order_id: String,
+}
+
+/// Ордер в `/v5/order/realtime` и топике `order`
+#[derive(Debug, Deserialize)]
+#[serde(rename_all="camelCase")]
+struct BybitOrder {
+    order_id: String,
+    order_link_id: String,
+    order_status: String,
+    #[serde(default)]
+    cum_exec_qty: String,
+    #[serde(default)]
+    avg_price: String,
+    #[serde(default)]
+    updated_time: String,
+}
+
+/// Исполнение в топике `execution`
+#[derive(Debug, Deserialize)]
+#[serde(rename_all="camelCase")]
+struct BybitExecution {
+    symbol: String,
+    side: String,
+    order_id: String,
+    order_link_id: String,
+    exec_id: String,
+    exec_price: String,
+    exec_qty: String,
+    #[serde(default)]
+    exec_fee: String,
+    #[serde(default)]
+    fee_currency: Option<String>,
+    #[serde(default)]
+    exec_type: Option<String>,
+    exec_time: String,
+}
+
+/// Счёт в `/v5/account/wallet-balance` и топике `wallet`
+#[derive(Debug, Deserialize)]
+struct BybitWallet {
+    #[serde(default)]
+    coin: Vec<BybitCoin>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all="camelCase")]
+struct BybitCoin {
+    coin: String,
+    #[serde(default)]
+    wallet_balance: String,
+    #[serde(default)]
+    locked: String,
+}
+
+/// Сообщение приватного потока: ответ на `auth`/`subscribe` или данные топика
+#[derive(Debug, Deserialize)]
+struct BybitPrivateMessage {
+    op: Option<String>,
+    success: Option<bool>,
+    #[serde(default)]
+    ret_msg: String,
+    topic: Option<String>,
+    #[serde(rename="creationTime")]
+    creation_time: Option<i64>,
+    #[serde(default)]
+    data: serde_json::Value,
+}
+
+/// <b>BybitTrading</b> приватный API Bybit v5 для спота.
+///
+/// <br>• REST подписывается HMAC-SHA256 от `timestamp + api_key + recv_window + тело или query`
+/// <br>• WebSocket `/v5/private`: `auth` с подписью `GET/realtime<expires>`, топики `order.spot`, `execution.spot` и `wallet`
+/// <br>• Остатки берутся с единого счёта (`UNIFIED`)
+/// <br>• client order id передаётся как `orderLinkId`, повтор биржа отклоняет
+pub struct BybitTrading {
+    endpoints: TradingEndpoints,
+    credentials: Credentials,
+    recv_window_ms: u64,
+}
+
+impl BybitTrading {
+    /// `None` - нет ключа API
+    pub fn new(
+        config: &ExchangeConfig
+    ) -> Option<Arc<Self>> {
+        let (api_key, api_secret) = config.trading.credentials(ExchangeType::Bybit)?;
+
+        Some(Arc::new(Self {
+            endpoints: TradingEndpoints::new(
+                ("https://api.bybit.com", "wss://stream.bybit.com/v5/private"),
+                ("https://api-testnet.bybit.com", "wss://stream-testnet.bybit.com/v5/private"),
+                &config.trading
+            ),
+            credentials: Credentials { api_key, api_secret },
+            recv_window_ms: config.trading.recv_window_ms,
+        }))
+    }
+
+    /// Подпись запроса, её же проверяет `MockExchange`
+    pub fn sign(
+        credentials: &Credentials,
+        timestamp: i64,
+        recv_window_ms: u64,
+        payload: &str
+    ) -> String {
+        hmac_sha256_hex(&credentials.api_secret, &format!("{timestamp}{}{recv_window_ms}{payload}", credentials.api_key))
+    }
+
+    /// Подпись `auth` приватного WebSocket
+    pub fn sign_ws(
+        credentials: &Credentials,
+        expires: i64
+    ) -> String {
+        hmac_sha256_hex(&credentials.api_secret, &format!("GET/realtime{expires}"))
+    }
+
+    /// `query` для GET, тело JSON для POST
+    async fn request<T: DeserializeOwned>(
+        &self,
+        client: &reqwest::Client,
+        method: reqwest::Method,
+        path: &str,
+        query: &str,
+        body: Option<serde_json::Value>
+    ) -> Result<T, TradingError> {
+        let body = body.map(|body| body.to_string());
+        let timestamp = chrono::Utc::now().timestamp_millis();
+        let payload = body.as_deref().unwrap_or(query);
+        let signature = Self::sign(&self.credentials, timestamp, self.recv_window_ms, payload);
+
+        let mut url = format!("{}{path}", self.endpoints.rest_url);
+        if !query.is_empty() {
+            url = format!("{url}?{query}");
+        }
+
+        let mut request = client
+            .request(method, url)
+            .header("X-BAPI-API-KEY", &self.credentials.api_key)
+            .header("X-BAPI-TIMESTAMP", timestamp.to_string())
+            .header("X-BAPI-RECV-WINDOW", self.recv_window_ms.to_string())
+            .header("X-BAPI-SIGN", signature);
+        if let Some(body) = body {
+            request = request.header("Content-Type", "application/json").body(body);
+        }
+
+        let response = request.send().await?;
+        let status = response.status();
+        let text = response.text().await?;
+
+        let parsed = match serde_json::from_str::<BybitResponse>(&text) {
+            Ok(parsed) => parsed,
+            // Лимит по IP и отказ авторизации приходят без JSON
+            Err(_) if status.as_u16() == 403 => return Err(TradingError::RateLimited(text)),
+            Err(_) if status.as_u16() == 401 => return Err(TradingError::Auth(text)),
+            Err(e) => return Err(TradingError::Decode(format!("HTTP {status}: {e}"))),
+        };
+
+        if parsed.ret_code != 0 {
+            return Err(Self::error(parsed.ret_code, parsed.ret_msg));
+        }
+
+        serde_json::from_value(parsed.result).map_err(|e| TradingError::Decode(e.to_string()))
+    }
+
+    /// Коды ошибок v5, остальные - `Exchange`
+    fn error(
+        code: i64,
+        message: String
+    ) -> TradingError {
+        match code {
+            10002 | 10003 | 10004 | 10005 | 10007 => TradingError::Auth(message),
+            10006 | 10018 => TradingError::RateLimited(message),
+            10001 | 170130 | 170136 | 170137 | 170140 => TradingError::InvalidOrder(message),
+            110004 | 110007 | 170131 => TradingError::InsufficientBalance(message),
+            110072 | 170141 => TradingError::DuplicateOrder(message),
+            110001 | 170213 => TradingError::OrderNotFound(message),
+            _ => TradingError::Exchange { code: code.to_string(), message },
+        }
+    }
+
+    fn status(
+        status: &str
+    ) -> Result<OrderStatus, TradingError> {
+        match status {
+            "Created" | "New" | "Untriggered" | "Triggered" => Ok(OrderStatus::New),
+            "PartiallyFilled" => Ok(OrderStatus::PartiallyFilled),
+            "Filled" => Ok(OrderStatus::Filled),
+            "Cancelled" | "PartiallyFilledCanceled" | "Deactivated" => Ok(OrderStatus::Cancelled),
+            "Rejected" => Ok(OrderStatus::Rejected),
+            _ => Err(TradingError::Decode(format!("неизвестный статус {status}"))),
+        }
+    }
+
+    fn order_update(
+        order: BybitOrder
+    ) -> Result<OrderUpdate, TradingError> {
+        let avg_price = parse_decimal(&order.avg_price)?;
+
+        Ok(OrderUpdate {
+            exchange_id: ExchangeType::Bybit,
+            client_order_id: order.order_link_id,
+            order_id: Some(order.order_id),
+            status: Self::status(&order.order_status)?,
+            filled_qty: parse_decimal(&order.cum_exec_qty)?,
+            avg_price: (avg_price > 0.0).then_some(avg_price),
+            time: order.updated_time.parse().unwrap_or_else(|_| chrono::Utc::now().timestamp_millis()),
+        })
+    }
+
+    fn fill_report(
+        execution: BybitExecution
+    ) -> Result<FillReport, TradingError> {
+        Ok(FillReport {
+            exchange_id: ExchangeType::Bybit,
+            client_order_id: execution.order_link_id,
+            order_id: execution.order_id,
+            trade_id: execution.exec_id,
+            symbol: execution.symbol.to_ascii_lowercase(),
+            side: if execution.side == "Buy" { OrderSide::Buy } else { OrderSide::Sell },
+            qty: parse_decimal(&execution.exec_qty)?,
+            price: parse_decimal(&execution.exec_price)?,
+            fee: parse_decimal(&execution.exec_fee)?,
+            fee_currency: execution.fee_currency.filter(|currency| !currency.is_empty()),
+            time: execution.exec_time.parse().unwrap_or_else(|_| chrono::Utc::now().timestamp_millis()),
+        })
+    }
+
+    /// Свободный остаток - весь остаток за вычетом занятого спотовыми ордерами
+    fn balances(
+        wallets: Vec<BybitWallet>,
+        time: i64
+    ) -> Result<Vec<Balance>, TradingError> {
+        wallets
+            .into_iter()
+            .flat_map(|wallet| wallet.coin)
+            .map(|coin| {
+                let total = parse_decimal(&coin.wallet_balance)?;
+                let locked = parse_decimal(&coin.locked)?;
+                Ok(Balance {
+                    exchange_id: ExchangeType::Bybit,
+                    asset: coin.coin.to_ascii_uppercase(),
+                    free: (total - locked).max(0.0),
+                    locked,
+                    time,
+                })
+            })
+            .collect()
+    }
+
+    async fn find_order(
+        &self,
+        client: &reqwest::Client,
+        path: &str,
+        symbol: &Symbol,
+        client_order_id: &ClientOrderId
+    ) -> Result<Option<OrderUpdate>, TradingError> {
+        let query = format!("category=spot&symbol={}&orderLinkId={client_order_id}", symbol.to_ascii_uppercase());
+        let orders: BybitList<BybitOrder> = self.request(client, reqwest::Method::GET, path, &query, None).await?;
+
+        orders.list.into_iter().next().map(Self::order_update).transpose()
+    }
+}
+
+#[async_trait::async_trait]
+impl TradingAdapter for BybitTrading {
+    fn exchange_id(&self) -> ExchangeType {
+        ExchangeType::Bybit
+    }
+
+    async fn place_order(
+        self: Arc<Self>,
+        client: &reqwest::Client,
+        request: &OrderRequest
+    ) -> Result<OrderUpdate, TradingError> {
+        let mut body = serde_json::json!({
+            "category": "spot",
+            "symbol": request.symbol.to_ascii_uppercase(),
+            "side": match request.side { OrderSide::Buy => "Buy", OrderSide::Sell => "Sell" },
+            "qty": format_decimal(request.qty),
+            "orderLinkId": request.client_order_id,
+        });
+
+        match request.order_type {
+            OrderType::Limit => {
+                let price = request.price.ok_or_else(|| TradingError::InvalidOrder("лимитный ордер без цены".into()))?;
+                body["orderType"] = "Limit".into();
+                body["price"] = format_decimal(price).into();
+                body["timeInForce"] = match request.time_in_force {
+                    TimeInForce::Gtc => "GTC",
+                    TimeInForce::Ioc => "IOC",
+                    TimeInForce::PostOnly => "PostOnly",
+                }.into();
+            },
+            OrderType::Market => {
+                // Без `marketUnit` рыночная покупка считается в котируемой валюте
+                body["orderType"] = "Market".into();
+                body["marketUnit"] = "baseCoin".into();
+            },
+        }
+
+        let created: BybitCreated = self.request(client, reqwest::Method::POST, "/v5/order/create", "", Some(body)).await?;
+
+        Ok(OrderUpdate {
+            exchange_id: ExchangeType::Bybit,
+            client_order_id: request.client_order_id.clone(),
+            order_id: Some(created.order_id),
+            status: OrderStatus::New,
+            filled_qty: 0.0,
+            avg_price: None,
+            time: chrono::Utc::now().timestamp_millis(),
+        })
+    }
+
+    async fn cancel_order(
+        self: Arc<Self>,
+        client: &reqwest::Client,
+        symbol: &Symbol,
+        client_order_id: &ClientOrderId
+    ) -> Result<(), TradingError> {
+        let body = serde_json::json!({
+            "category": "spot",
+            "symbol": symbol.to_ascii_uppercase(),
+            "orderLinkId": client_order_id,
+        });
+
+        let _: serde_json::Value = self.request(client, reqwest::Method::POST, "/v5/order/cancel", "", Some(body)).await?;
+        Ok(())
+    }
+
+    /// Открытые ордера в `realtime`, закрытые - в `history`
+    async fn get_order(
+        self: Arc<Self>,
+        client: &reqwest::Client,
+        symbol: &Symbol,
+        client_order_id: &ClientOrderId
+    ) -> Result<Option<OrderUpdate>, TradingError> {
+        if let Some(order) = self.find_order(client, "/v5/order/realtime", symbol, client_order_id).await? {
+            return Ok(Some(order));
+        }
+
+        self.find_order(client, "/v5/order/history", symbol, client_order_id).await
+    }
+
+    async fn get_balances(
+        self: Arc<Self>,
+        client: &reqwest::Client
+    ) -> Result<Vec<Balance>, TradingError> {
+        let wallets: BybitList<BybitWallet> = self.request(client, reqwest::Method::GET, "/v5/account/wallet-balance", "accountType=UNIFIED", None).await?;
+        Self::balances(wallets.list, chrono::Utc::now().timestamp_millis())
+    }
+
+    fn private_ws_url(self: Arc<Self>) -> String {
+        self.endpoints.ws_url.clone()
+    }
+
+    fn create_private_subscribe_messages(
+        self: Arc<Self>
+    ) -> Vec<Message> {
+        let expires = chrono::Utc::now().timestamp_millis() + 10_000;
+        let signature = Self::sign_ws(&self.credentials, expires);
+
+        vec![
+            Message::Text(serde_json::json!({
+                "req_id": "auth",
+                "op": "auth",
+                "args": [self.credentials.api_key, expires, signature]
+            }).to_string()),
+            Message::Text(serde_json::json!({
+                "req_id": "subscribe",
+                "op": "subscribe",
+                "args": ["order.spot", "execution.spot", "wallet"]
+            }).to_string()),
+        ]
+    }
+
+    fn parse_private_message(
+        self: Arc<Self>,
+        msg: &str
+    ) -> Result<Vec<PrivateEvent>, TradingError> {
+        let message: BybitPrivateMessage = serde_json::from_str(msg).map_err(|e| TradingError::Decode(e.to_string()))?;
+
+        match (message.op.as_deref(), message.success) {
+            (Some("auth"), Some(false)) => return Err(TradingError::Auth(message.ret_msg)),
+            (Some("subscribe"), Some(false)) => return Err(TradingError::Exchange { code: "subscribe".into(), message: message.ret_msg }),
+            _ => {}
+        }
+
+        let Some(topic) = message.topic else { return Ok(Vec::new()) };
+        let decode = |e: serde_json::Error| TradingError::Decode(e.to_string());
+
+        if topic.starts_with("order") {
+            let orders: Vec<BybitOrder> = serde_json::from_value(message.data).map_err(decode)?;
+            return orders.into_iter().map(|order| Self::order_update(order).map(PrivateEvent::Order)).collect();
+        }
+
+        if topic.starts_with("execution") {
+            let executions: Vec<BybitExecution> = serde_json::from_value(message.data).map_err(decode)?;
+            return executions
+                .into_iter()
+                .filter(|execution| execution.exec_type.as_deref().is_none_or(|exec_type| exec_type == "Trade"))
+                .map(|execution| Self::fill_report(execution).map(PrivateEvent::Fill))
+                .collect();
+        }
+
+        if topic == "wallet" {
+            let wallets: Vec<BybitWallet> = serde_json::from_value(message.data).map_err(decode)?;
+            return Ok(Self::balances(wallets, message.creation_time.unwrap_or_else(|| chrono::Utc::now().timestamp_millis()))?
+                .into_iter()
+                .map(PrivateEvent::Balance)
+                .collect());
+        }
+
+        Ok(Vec::new())
+    }
+
+    fn heartbeat(
+        self: Arc<Self>
+    ) -> Heartbeat {
+        Heartbeat {
+            interval: Duration::from_secs(20),
+            max_missed: 2,
+        }
+    }
+
+    fn create_ping_message(
+        self: Arc<Self>,
+        id: u64
+    ) -> Message {
+        Message::Text(serde_json::json!({
+            "op": "ping",
+            "req_id": id.to_string()
+        }).to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::BybitTrading;
+    use crate::{models::{exchange::ExchangeType, order::{OrderRequest, OrderSide, OrderStatus, OrderType, TimeInForce, new_client_order_id}}, services::exchange::trading_adapter::{Credentials, TradingAdapter, TradingError}, tests::{MOCK_API_KEY, MOCK_API_SECRET, mock_trading, mock_trading_options}};
+
+    fn limit_buy(
+        qty: f64,
+        price: f64,
+        time_in_force: TimeInForce
+    ) -> OrderRequest {
+        OrderRequest {
+            exchange_id: ExchangeType::Bybit,
+            client_order_id: new_client_order_id(),
+            symbol: "btcusdt".into(),
+            side: OrderSide::Buy,
+            order_type: OrderType::Limit,
+            qty,
+            price: Some(price),
+            time_in_force,
+            reduce_only: false,
+        }
+    }
+
+    /// Эталон посчитан отдельно от этого кода, через `hmac` из Python
+    #[test]
+    fn sign_matches_reference() {
+        let credentials = Credentials { api_key: MOCK_API_KEY.into(), api_secret: MOCK_API_SECRET.into() };
+        let signature = BybitTrading::sign(&credentials, 1_700_000_000_000, 5000, r#"{"category":"spot","symbol":"BTCUSDT"}"#);
+        assert_eq!(signature, "868f480146ca9f2565a028497580cd3645e1cbc04b4f14225b95f7d912688073");
+    }
+
+    /// IOC исполняется на `fill_ratio`, остаток отменяется: статус `Cancelled` с исполненным количеством
+    #[tokio::test]
+    async fn ioc_partial_fill_is_cancelled_with_filled_qty() {
+        let (mock, config) = mock_trading(ExchangeType::Bybit, &["BTCUSDT"], Vec::new(), mock_trading_options(0.4, None)).await;
+        let trading = BybitTrading::new(&config).unwrap();
+        let client = reqwest::Client::new();
+
+        let request = limit_buy(1.0, 100.0, TimeInForce::Ioc);
+        let created = trading.clone().place_order(&client, &request).await.unwrap();
+        assert!(created.order_id.is_some());
+
+        let order = trading.get_order(&client, &request.symbol, &request.client_order_id).await.unwrap().unwrap();
+        assert_eq!(order.status, OrderStatus::Cancelled);
+        assert_eq!(order.order_id, created.order_id);
+        assert!((order.filled_qty - 0.4).abs() < 1e-9);
+        assert_eq!(order.avg_price, Some(100.0));
+        assert_eq!(mock.trading().unwrap().rejected_signatures(), 0);
+    }
+
+    /// Повтор с тем же client order id не создаёт второй ордер, а первый находится по id
+    #[tokio::test]
+    async fn replay_with_same_client_order_id_is_duplicate() {
+        let (mock, config) = mock_trading(ExchangeType::Bybit, &["BTCUSDT"], Vec::new(), mock_trading_options(0.0, None)).await;
+        let trading = BybitTrading::new(&config).unwrap();
+        let client = reqwest::Client::new();
+
+        let request = limit_buy(1.0, 99.0, TimeInForce::Gtc);
+        let created = trading.clone().place_order(&client, &request).await.unwrap();
+
+        let replay = trading.clone().place_order(&client, &request).await;
+        assert!(matches!(replay, Err(TradingError::DuplicateOrder(_))), "{replay:?}");
+        assert_eq!(mock.trading().unwrap().orders(), 1);
+
+        let order = trading.get_order(&client, &request.symbol, &request.client_order_id).await.unwrap().unwrap();
+        assert_eq!(order.order_id, created.order_id);
+        assert_eq!(order.status, OrderStatus::New);
+    }
+
+    #[tokio::test]
+    async fn exchange_errors_map_to_trading_error() {
+        let balances = BTreeMap::from([("USDT".to_string(), 10.0)]);
+        let (mock, config) = mock_trading(ExchangeType::Bybit, &["BTCUSDT"], Vec::new(), mock_trading_options(1.0, Some(balances))).await;
+        let trading = BybitTrading::new(&config).unwrap();
+        let client = reqwest::Client::new();
+
+        let result = trading.clone().place_order(&client, &limit_buy(1.0, 100.0, TimeInForce::Gtc)).await;
+        assert!(matches!(result, Err(TradingError::InsufficientBalance(_))), "{result:?}");
+
+        let result = trading.clone().place_order(&client, &limit_buy(0.01, 0.0, TimeInForce::Gtc)).await;
+        assert!(matches!(result, Err(TradingError::InvalidOrder(_))), "{result:?}");
+
+        let result = trading.clone().cancel_order(&client, &"btcusdt".into(), &new_client_order_id()).await;
+        assert!(matches!(result, Err(TradingError::OrderNotFound(_))), "{result:?}");
+        assert_eq!(mock.trading().unwrap().rejected_signatures(), 0);
+
+        let mut wrong_secret = config.clone();
+        wrong_secret.trading.api_secret = Some("other-secret".into());
+        let result = BybitTrading::new(&wrong_secret).unwrap().get_balances(&client).await;
+        assert!(matches!(result, Err(TradingError::Auth(_))), "{result:?}");
+
+        let mut wrong_key = config;
+        wrong_key.trading.api_key = Some("other-key".into());
+        let result = BybitTrading::new(&wrong_key).unwrap().get_balances(&client).await;
+        assert!(matches!(result, Err(TradingError::Auth(_))), "{result:?}");
+        assert_eq!(mock.trading().unwrap().rejected_signatures(), 2);
+    }
+}
@@ -1,23 +1,59 @@
 use std::{sync::Arc, time::Duration};
-use tokio::sync::{Mutex, Semaphore, mpsc, watch};
+use tokio::sync::{Mutex, Semaphore};
 use tokio_tungstenite::tungstenite::Message;
 
-use crate::{models::{exchange::{PriceCache, TickerEvent, TickerInfo}, orderbook::{BookEvent, OrderBookEvent, OrderBookEventData, Snapshot}, websocket::Symbol}, services::exchange::{exchange_adapter::ExchangeAdapter, exchange_aggregator::{ExchangeStoreCMD, parse_levels__}}};
+use crate::{config::ExchangeConfig, models::{exchange::{PriceCache, TickerEvent, TickerInfo}, orderbook::{BookEvent, EventTime, OrderBookEvent, OrderBookEventData, Snapshot}, websocket::Symbol}, services::exchange::{exchange_adapter::{Endpoints, ExchangeAdapter, ExchangeLimits, Heartbeat}, exchange_aggregator::{BookEventSender, parse_levels__}}};
 
 pub struct GateAdapter {
-    price_cache: Arc<Mutex<PriceCache>>
+    price_cache: Arc<Mutex<PriceCache>>,
+    endpoints: Endpoints
 }
 
 impl GateAdapter {
-    pub fn new() -> Arc<Self> {
-        Arc::new(Self { price_cache: Arc::new(Mutex::new(PriceCache::new())) })
+    pub fn new(
+        config: &ExchangeConfig
+    ) -> Arc<Self> {
+        Arc::new(Self {
+            price_cache: Arc::new(Mutex::new(PriceCache::new())),
+            endpoints: Endpoints::new("wss://api.gateio.ws/ws/v4/", "https://api.gateio.ws/api/v4", config)
+        })
+    }
+
+    fn tickers_messages(
+        self: Arc<Self>,
+        event: &str,
+        symbols: &[Arc<Symbol>]
+    ) -> Vec<Message> {
+        symbols
+            .chunks(self.limits().args_per_message)
+            .map(|payload| Message::Text(
+                serde_json::json!({
+                    "channel": "spot.tickers",
+                    "event": event,
+                    "payload": payload
+                }).to_string().into()
+            ))
+            .collect()
+    }
+
+    fn order_book_message(
+        event: &str,
+        symbol: &Symbol
+    ) -> Message {
+        Message::Text(
+            serde_json::json!({
+                "channel": "spot.order_book",
+                "event": event,
+                "payload": [symbol, "50", "100ms"]
+            }).to_string().into()
+        )
     }
 }
 
 #[async_trait::async_trait]
 impl ExchangeAdapter for GateAdapter {
-    fn ws_url(self: Arc<Self>) -> &'static str {
-        "wss://api.gateio.ws/ws/v4/"
+    fn ws_url(self: Arc<Self>) -> String {
+        self.endpoints.ws_url.clone()
     }
 
     fn requires_auth(
@@ -41,7 +77,7 @@ impl ExchangeAdapter for GateAdapter {
     }
 
     async fn get_tickers(self: Arc<Self>, client: &reqwest::Client) -> Option<Vec<TickerInfo>> {
-        let url = "https://api.gateio.ws/api/v4/spot/currency_pairs?limit=10";
+        let url = format!("{}/spot/currency_pairs?limit=10", self.endpoints.rest_url);
         let response = client
             .get(url)
             .send()
@@ -62,7 +98,7 @@ impl ExchangeAdapter for GateAdapter {
         self: Arc<Self>,
         tickers: &Vec<TickerInfo>,
         client: &reqwest::Client,
-        sender_data: watch::Sender<ExchangeStoreCMD>,
+        sender_data: BookEventSender,
     ) {
         // Загружать лениво, только когда юзер просит(один раз)
         // Избегают повторную загрузку во время двух запросов одновременно для даного тикера
@@ -73,7 +109,7 @@ impl ExchangeAdapter for GateAdapter {
         for info in tickers {
             let symbol = info.symbol.clone();
             if let Some(symbol) = symbol {
-                let url = format!("https://api.gateio.ws/api/v4/spot/order_book?currency_pair={symbol}&limit=1000");
+                let url = format!("{}/spot/order_book?currency_pair={symbol}&limit=1000", self.endpoints.rest_url);
                 urls.push((url, symbol));
             }
         }
@@ -126,27 +162,78 @@ impl ExchangeAdapter for GateAdapter {
         }
     }
 
-    fn create_subscribe_messages(
+    /// `spot.tickers` принимает список пар, а `spot.order_book` - одну пару на сообщение
+    fn limits(
+        self: Arc<Self>
+    ) -> ExchangeLimits {
+        ExchangeLimits {
+            topics_per_connection: 100,
+            topics_per_symbol: 2,
+            args_per_message: 50,
+            messages_per_second: 10,
+            max_connections: 20,
+        }
+    }
+
+    fn heartbeat(
+        self: Arc<Self>
+    ) -> Heartbeat {
+        Heartbeat {
+            interval: Duration::from_secs(15),
+            max_missed: 2,
+        }
+    }
+
+    fn create_ping_message(
         self: Arc<Self>,
-        symbol: Arc<Symbol>
+        id: u64
+    ) -> Message {
+        Message::Text(serde_json::json!({
+            "id": id,
+            "time": chrono::Utc::now().timestamp(),
+            "channel": "spot.ping"
+        }).to_string())
+    }
+
+    fn is_pong(
+        self: Arc<Self>,
+        msg: &str
+    ) -> bool {
+        msg.contains("\"spot.pong\"")
+    }
+
+    fn create_ticker_subscribe_messages(
+        self: Arc<Self>,
+        symbols: &[Arc<Symbol>]
     ) -> Vec<Message> {
-        let message1 = Message::Text(
-            serde_json::json!({
-                "channel": "spot.order_book",
-                "event": "subscribe",
-                "payload": [symbol, "50", "100ms"]
-            }).to_string().into()
-        );
+        self.tickers_messages("subscribe", symbols)
+    }
 
-        let message2 = Message::Text(
-            serde_json::json!({
-                "channel": "spot.tickers",
-                "event": "subscribe",
-                "payload": [symbol]
-            }).to_string().into()
-        );
+    fn create_ticker_unsubscribe_messages(
+        self: Arc<Self>,
+        symbols: &[Arc<Symbol>]
+    ) -> Vec<Message> {
+        self.tickers_messages("unsubscribe", symbols)
+    }
+
+    fn create_depth_subscribe_messages(
+        self: Arc<Self>,
+        symbols: &[Arc<Symbol>]
+    ) -> Vec<Message> {
+        symbols
+            .iter()
+            .map(|symbol| Self::order_book_message("subscribe", symbol))
+            .collect()
+    }
 
-        vec![message1, message2]
+    fn create_depth_unsubscribe_messages(
+        self: Arc<Self>,
+        symbols: &[Arc<Symbol>]
+    ) -> Vec<Message> {
+        symbols
+            .iter()
+            .map(|symbol| Self::order_book_message("unsubscribe", symbol))
+            .collect()
     }
 
     fn cache(
@@ -158,8 +245,7 @@ impl ExchangeAdapter for GateAdapter {
     async fn parse_message(
         self: Arc<Self>,
         msg: String,
-        snapshot_channel: mpsc::Sender<ExchangeStoreCMD>,
-        sender_data: watch::Sender<ExchangeStoreCMD>
+        sender_data: BookEventSender
     ) {
         let msg_arc = Arc::new(msg);
         if !msg_arc.contains("update") {
@@ -167,7 +253,7 @@ impl ExchangeAdapter for GateAdapter {
         }
         
         if msg_arc.contains("spot.order_book") {
-            self.clone().parse_orderbook(msg_arc.clone(), snapshot_channel, sender_data.clone()).await;
+            self.clone().parse_orderbook(msg_arc.clone(), sender_data.clone()).await;
         }
 
         if msg_arc.contains("spot.tickers") {
@@ -178,9 +264,16 @@ impl ExchangeAdapter for GateAdapter {
     async fn parse_tickers(
         self: Arc<Self>,
         msg: Arc<String>,
-        sender_data: watch::Sender<ExchangeStoreCMD>
+        sender_data: BookEventSender
     ) {
-        let json: TickerEvent<'_> = serde_json::from_str(&msg).unwrap();
+        let json: TickerEvent<'_> = match serde_json::from_str(&msg) {
+            Ok(json) => json,
+            Err(e) => {
+                sender_data.parse_error(e);
+                return;
+            }
+        };
+        let time = EventTime::received(json.timestamp);
         if let Some(result) = json.result {
             let ticker = result.symbol;
             let last_price = result.last_price;
@@ -201,13 +294,12 @@ impl ExchangeAdapter for GateAdapter {
                 
                 let is_valid_price = self.is_valid_price(last_price, &symbol).await;
                 if is_valid_price {
-                    let _ = sender_data.send(ExchangeStoreCMD::Event(
-                        BookEvent::TickerUpdate { 
-                            symbol, 
-                            last_price: last_price, 
-                            volume: volume
-                        }
-                    ));
+                    sender_data.send(BookEvent::TickerUpdate { 
+                        symbol, 
+                        last_price: last_price, 
+                        volume: volume,
+                        time
+                    }).await;
                 }
             }
         }
@@ -216,19 +308,25 @@ impl ExchangeAdapter for GateAdapter {
     async fn parse_orderbook(
         self: Arc<Self>,
         msg: Arc<String>,
-        snapshot_channel: mpsc::Sender<ExchangeStoreCMD>,
-        sender_data: watch::Sender<ExchangeStoreCMD>
+        sender_data: BookEventSender
     ) {
-        let json: OrderBookEvent<'_> = serde_json::from_str(&msg).unwrap();
+        let json: OrderBookEvent<'_> = match serde_json::from_str(&msg) {
+            Ok(json) => json,
+            Err(e) => {
+                sender_data.parse_error(e);
+                return;
+            }
+        };
+        let time = EventTime::received(json.timestamp);
         let data = json.data;
-        self.handle_snapshot(data, snapshot_channel, sender_data.clone()).await;
+        self.handle_snapshot(data, time, sender_data.clone()).await;
     }
 
     async fn handle_snapshot<'a>(
         self: Arc<Self>,
         data: Option<OrderBookEventData<'a>>,
-        _snapshot_channel: mpsc::Sender<ExchangeStoreCMD>,
-        sender_data: watch::Sender<ExchangeStoreCMD>
+        time: EventTime,
+        sender_data: BookEventSender
     ) {
         if let Some(data) = data {
             let ticker = data.symbol;
@@ -247,16 +345,15 @@ impl ExchangeAdapter for GateAdapter {
                     
                     let is_valid_book = self.is_valid_book(&asks, &bids);
                     if is_valid_book {
-                        let _ = sender_data.send(ExchangeStoreCMD::Event(
-                            BookEvent::Snapshot { 
-                                symbol,
-                                snapshot: Snapshot { 
-                                    a: asks, 
-                                    b: bids, 
-                                    last_update_id: None,
-                                }
+                        sender_data.send(BookEvent::Snapshot { 
+                            symbol,
+                            snapshot: Snapshot { 
+                                a: asks, 
+                                b: bids, 
+                                last_update_id: None,
                             },
-                        ));
+                            time
+                        },).await;
                     }
                 }
             }
@@ -266,7 +363,8 @@ impl ExchangeAdapter for GateAdapter {
     async fn handle_delta<'a>(
         self: Arc<Self>,
         _data: Option<OrderBookEventData<'a>>,
-        _sender_data: watch::Sender<ExchangeStoreCMD>
+        _time: EventTime,
+        _sender_data: BookEventSender
     ) {
 
     }
@@ -0,0 +1,572 @@
+use std::{sync::Arc, time::Duration};
+use serde::{Deserialize, de::DeserializeOwned};
+use tokio_tungstenite::tungstenite::Message;
+
+use crate::{config::ExchangeConfig, models::{exchange::ExchangeType, inventory::Balance, order::{ClientOrderId, FillReport, OrderRequest, OrderSide, OrderStatus, OrderType, OrderUpdate, TimeInForce}, websocket::Symbol}, services::exchange::{exchange_adapter::Heartbeat, trading_adapter::{Credentials, PrivateEvent, TradingAdapter, TradingEndpoints, TradingError, format_decimal, hmac_sha512_hex, parse_decimal, sha512_hex, split_symbol}}};
+
+/// Ошибка REST v4: `{"label":"INVALID_SIGNATURE","message":"..."}`
+#[derive(Debug, Deserialize)]
+struct GateError {
+    label: String,
+    #[serde(default)]
+    message: String,
+}
+
+/// Ордер в ответе REST и канале `spot.orders`. В канале нет `status`, вместо него `event`
+#[derive(Debug, Deserialize)]
+struct GateOrder {
+    id: String,
+    #[serde(default)]
+    text: String,
+    #[serde(default)]
+    status: Option<String>,
+    #[serde(default)]
+    event: Option<String>,
+    #[serde(default)]
+    finish_as: Option<String>,
+    #[serde(rename="type", default)]
+    order_type: String,
+    side: String,
+    amount: String,
+    #[serde(default)]
+    left: String,
+    #[serde(default)]
+    filled_amount: Option<String>,
+    #[serde(default)]
+    filled_total: String,
+    #[serde(default)]
+    avg_deal_price: String,
+    #[serde(default)]
+    update_time_ms: serde_json::Value,
+}
+
+/// Исполнение в канале `spot.usertrades`
+#[derive(Debug, Deserialize)]
+struct GateTrade {
+    id: serde_json::Value,
+    order_id: String,
+    #[serde(default)]
+    text: String,
+    currency_pair: String,
+    side: String,
+    amount: String,
+    price: String,
+    #[serde(default)]
+    fee: String,
+    #[serde(default)]
+    fee_currency: Option<String>,
+    #[serde(default)]
+    create_time_ms: serde_json::Value,
+}
+
+/// Остаток в `/spot/accounts` и канале `spot.balances`. В канале занятая часть - `freeze`
+#[derive(Debug, Deserialize)]
+struct GateAccount {
+    currency: String,
+    available: String,
+    #[serde(default, alias="freeze")]
+    locked: String,
+    #[serde(default)]
+    timestamp_ms: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Deserialize)]
+struct GatePrivateMessage {
+    channel: String,
+    event: String,
+    #[serde(default)]
+    error: Option<GateWsError>,
+    #[serde(default)]
+    result: serde_json::Value,
+}
+
+#[derive(Debug, Deserialize)]
+struct GateWsError {
+    code: i64,
+    message: String,
+}
+
+/// <b>GateTrading</b> приватный API Gate v4 для спота.
+///
+/// <br>• REST подписывается HMAC-SHA512 от `метод\nпуть\nquery\nsha512(тело)\ntimestamp`
+/// <br>• WebSocket: каждая подписка на `spot.orders`, `spot.usertrades` и `spot.balances` несёт свою подпись
+/// <br>• client order id передаётся как `text` с префиксом `t-`. Gate не проверяет его уникальность,
+/// поэтому повтор после потерянного ответа решает `OrderGateway` через `get_order`
+pub struct GateTrading {
+    endpoints: TradingEndpoints,
+    credentials: Credentials,
+}
+
+impl GateTrading {
+    /// `None` - нет ключа API
+    pub fn new(
+        config: &ExchangeConfig
+    ) -> Option<Arc<Self>> {
+        let (api_key, api_secret) = config.trading.credentials(ExchangeType::Gate)?;
+
+        Some(Arc::new(Self {
+            endpoints: TradingEndpoints::new(
+                ("https://api.gateio.ws/api/v4", "wss://api.gateio.ws/ws/v4/"),
+                ("https://api-testnet.gateapi.io/api/v4", "wss://ws-testnet.gate.com/v4/ws/spot"),
+                &config.trading
+            ),
+            credentials: Credentials { api_key, api_secret },
+        }))
+    }
+
+    /// Подпись запроса, её же проверяет `MockExchange`. `path` - полный путь URL, включая `/api/v4`
+    pub fn sign(
+        credentials: &Credentials,
+        method: &str,
+        path: &str,
+        query: &str,
+        body: &[u8],
+        timestamp: i64
+    ) -> String {
+        hmac_sha512_hex(&credentials.api_secret, &format!("{method}\n{path}\n{query}\n{}\n{timestamp}", sha512_hex(body)))
+    }
+
+    /// Подпись подписки на приватный канал
+    pub fn sign_ws(
+        credentials: &Credentials,
+        channel: &str,
+        event: &str,
+        time: i64
+    ) -> String {
+        hmac_sha512_hex(&credentials.api_secret, &format!("channel={channel}&event={event}&time={time}"))
+    }
+
+    fn currency_pair(
+        symbol: &Symbol
+    ) -> Result<String, TradingError> {
+        let (base, quote) = split_symbol(symbol).ok_or_else(|| TradingError::InvalidOrder(format!("неизвестная котируемая валюта в {symbol}")))?;
+        Ok(format!("{base}_{quote}"))
+    }
+
+    fn text(
+        client_order_id: &ClientOrderId
+    ) -> String {
+        format!("t-{client_order_id}")
+    }
+
+    async fn request<T: DeserializeOwned>(
+        &self,
+        client: &reqwest::Client,
+        method: reqwest::Method,
+        path: &str,
+        query: &str,
+        body: Option<serde_json::Value>
+    ) -> Result<T, TradingError> {
+        let body = body.map(|body| body.to_string()).unwrap_or_default();
+        let mut url = format!("{}{path}", self.endpoints.rest_url);
+        if !query.is_empty() {
+            url = format!("{url}?{query}");
+        }
+
+        let full_path = url::Url::parse(&url)
+            .map_err(|e| TradingError::InvalidOrder(format!("{url}: {e}")))?
+            .path()
+            .to_string();
+        let timestamp = chrono::Utc::now().timestamp();
+        let signature = Self::sign(&self.credentials, method.as_str(), &full_path, query, body.as_bytes(), timestamp);
+
+        let mut request = client
+            .request(method, url)
+            .header("KEY", &self.credentials.api_key)
+            .header("Timestamp", timestamp.to_string())
+            .header("SIGN", signature)
+            .header("Accept", "application/json");
+        if !body.is_empty() {
+            request = request.header("Content-Type", "application/json").body(body);
+        }
+
+        let response = request.send().await?;
+        let status = response.status();
+        let text = response.text().await?;
+
+        if !status.is_success() {
+            return Err(match serde_json::from_str::<GateError>(&text) {
+                Ok(error) => Self::error(error),
+                Err(_) if status.as_u16() == 429 => TradingError::RateLimited(text),
+                Err(_) => TradingError::Exchange { code: status.as_u16().to_string(), message: text },
+            });
+        }
+
+        serde_json::from_str(&text).map_err(|e| TradingError::Decode(e.to_string()))
+    }
+
+    /// Метки ошибок v4, остальные - `Exchange`
+    fn error(
+        error: GateError
+    ) -> TradingError {
+        match error.label.as_str() {
+            "INVALID_KEY" | "INVALID_SIGNATURE" | "MISSING_REQUIRED_HEADER" | "REQUEST_EXPIRED" | "FORBIDDEN" | "READ_ONLY" => TradingError::Auth(error.message),
+            "TOO_MANY_REQUESTS" => TradingError::RateLimited(error.message),
+            "BALANCE_NOT_ENOUGH" => TradingError::InsufficientBalance(error.message),
+            "ORDER_NOT_FOUND" => TradingError::OrderNotFound(error.message),
+            "INVALID_PARAM_VALUE" | "INVALID_ARGUMENT" | "INVALID_PRECISION" | "INVALID_CURRENCY_PAIR" | "INVALID_CURRENCY" => TradingError::InvalidOrder(error.message),
+            _ => TradingError::Exchange { code: error.label, message: error.message },
+        }
+    }
+
+    fn order_update(
+        order: GateOrder
+    ) -> Result<OrderUpdate, TradingError> {
+        let amount = parse_decimal(&order.amount)?;
+        let avg_price = parse_decimal(&order.avg_deal_price)?;
+        let filled_total = parse_decimal(&order.filled_total)?;
+
+        // Рыночная покупка задана в котируемой валюте, исполненное количество считается по сумме сделок
+        let filled_qty = match &order.filled_amount {
+            Some(filled_amount) => parse_decimal(filled_amount)?,
+            None if order.order_type == "market" && order.side == "buy" => if avg_price > 0.0 { filled_total / avg_price } else { 0.0 },
+            None => (amount - parse_decimal(&order.left)?).max(0.0),
+        };
+
+        let finished = order.status.as_deref().is_some_and(|status| status != "open") || order.event.as_deref() == Some("finish");
+        let status = match (finished, order.finish_as.as_deref(), order.status.as_deref()) {
+            (false, ..) if filled_qty > 0.0 => OrderStatus::PartiallyFilled,
+            (false, ..) => OrderStatus::New,
+            (true, Some("filled"), _) | (true, None, Some("closed")) => OrderStatus::Filled,
+            (true, ..) => OrderStatus::Cancelled,
+        };
+
+        Ok(OrderUpdate {
+            exchange_id: ExchangeType::Gate,
+            client_order_id: order.text.strip_prefix("t-").unwrap_or(&order.text).to_string(),
+            order_id: Some(order.id),
+            status,
+            filled_qty,
+            avg_price: (avg_price > 0.0).then_some(avg_price),
+            time: time_ms(&order.update_time_ms),
+        })
+    }
+
+    fn fill_report(
+        trade: GateTrade
+    ) -> Result<FillReport, TradingError> {
+        Ok(FillReport {
+            exchange_id: ExchangeType::Gate,
+            client_order_id: trade.text.strip_prefix("t-").unwrap_or(&trade.text).to_string(),
+            order_id: trade.order_id,
+            trade_id: match trade.id {
+                serde_json::Value::String(id) => id,
+                id => id.to_string(),
+            },
+            symbol: trade.currency_pair.replace('_', "").to_ascii_lowercase(),
+            side: if trade.side == "buy" { OrderSide::Buy } else { OrderSide::Sell },
+            qty: parse_decimal(&trade.amount)?,
+            price: parse_decimal(&trade.price)?,
+            fee: parse_decimal(&trade.fee)?,
+            fee_currency: trade.fee_currency.filter(|currency| !currency.is_empty()),
+            time: time_ms(&trade.create_time_ms),
+        })
+    }
+
+    fn balance(
+        account: GateAccount
+    ) -> Result<Balance, TradingError> {
+        Ok(Balance {
+            exchange_id: ExchangeType::Gate,
+            asset: account.currency.to_ascii_uppercase(),
+            free: parse_decimal(&account.available)?,
+            locked: parse_decimal(&account.locked)?,
+            time: account.timestamp_ms.as_ref().map(time_ms).unwrap_or_else(|| chrono::Utc::now().timestamp_millis()),
+        })
+    }
+
+    /// `spot.balances` подписывается без `payload`
+    fn subscribe_message(
+        &self,
+        channel: &str,
+        payload: &[&str]
+    ) -> Message {
+        let time = chrono::Utc::now().timestamp();
+
+        Message::Text(serde_json::json!({
+            "time": time,
+            "channel": channel,
+            "event": "subscribe",
+            "payload": payload,
+            "auth": {
+                "method": "api_key",
+                "KEY": self.credentials.api_key,
+                "SIGN": Self::sign_ws(&self.credentials, channel, "subscribe", time)
+            }
+        }).to_string())
+    }
+}
+
+/// Время Gate приходит числом мс или строкой секунд с дробной частью (`"1622638707.554000"`)
+fn time_ms(
+    value: &serde_json::Value
+) -> i64 {
+    let time = match value {
+        serde_json::Value::Number(number) => number.as_f64(),
+        serde_json::Value::String(text) => text.parse::<f64>().ok(),
+        _ => None,
+    };
+
+    match time {
+        Some(time) if time < 1e11 => (time * 1000.0) as i64,
+        Some(time) => time as i64,
+        None => chrono::Utc::now().timestamp_millis(),
+    }
+}
+
+#[async_trait::async_trait]
+impl TradingAdapter for GateTrading {
+    fn exchange_id(&self) -> ExchangeType {
+        ExchangeType::Gate
+    }
+
+    async fn place_order(
+        self: Arc<Self>,
+        client: &reqwest::Client,
+        request: &OrderRequest
+    ) -> Result<OrderUpdate, TradingError> {
+        let mut body = serde_json::json!({
+            "text": Self::text(&request.client_order_id),
+            "currency_pair": Self::currency_pair(&request.symbol)?,
+            "side": match request.side { OrderSide::Buy => "buy", OrderSide::Sell => "sell" },
+            "account": "spot",
+        });
+
+        match request.order_type {
+            OrderType::Limit => {
+                let price = request.price.ok_or_else(|| TradingError::InvalidOrder("лимитный ордер без цены".into()))?;
+                body["type"] = "limit".into();
+                body["amount"] = format_decimal(request.qty).into();
+                body["price"] = format_decimal(price).into();
+                body["time_in_force"] = match request.time_in_force {
+                    TimeInForce::Gtc => "gtc",
+                    TimeInForce::Ioc => "ioc",
+                    TimeInForce::PostOnly => "poc",
+                }.into();
+            },
+            OrderType::Market => {
+                let amount = match request.side {
+                    OrderSide::Sell => request.qty,
+                    OrderSide::Buy => {
+                        let price = request.price.ok_or_else(|| TradingError::InvalidOrder("рыночной покупке на Gate нужна оценка цены".into()))?;
+                        request.qty * price
+                    },
+                };
+                body["type"] = "market".into();
+                body["amount"] = format_decimal(amount).into();
+                body["time_in_force"] = "ioc".into();
+            },
+        }
+
+        let order: GateOrder = self.request(client, reqwest::Method::POST, "/spot/orders", "", Some(body)).await?;
+        Self::order_update(order)
+    }
+
+    /// Отмена по `text` работает только первые 30 минут после создания ордера
+    async fn cancel_order(
+        self: Arc<Self>,
+        client: &reqwest::Client,
+        symbol: &Symbol,
+        client_order_id: &ClientOrderId
+    ) -> Result<(), TradingError> {
+        let path = format!("/spot/orders/{}", Self::text(client_order_id));
+        let query = format!("currency_pair={}", Self::currency_pair(symbol)?);
+
+        let _: GateOrder = self.request(client, reqwest::Method::DELETE, &path, &query, None).await?;
+        Ok(())
+    }
+
+    async fn get_order(
+        self: Arc<Self>,
+        client: &reqwest::Client,
+        symbol: &Symbol,
+        client_order_id: &ClientOrderId
+    ) -> Result<Option<OrderUpdate>, TradingError> {
+        let path = format!("/spot/orders/{}", Self::text(client_order_id));
+        let query = format!("currency_pair={}", Self::currency_pair(symbol)?);
+
+        match self.request::<GateOrder>(client, reqwest::Method::GET, &path, &query, None).await {
+            Ok(order) => Self::order_update(order).map(Some),
+            Err(TradingError::OrderNotFound(_)) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    async fn get_balances(
+        self: Arc<Self>,
+        client: &reqwest::Client
+    ) -> Result<Vec<Balance>, TradingError> {
+        let accounts: Vec<GateAccount> = self.request(client, reqwest::Method::GET, "/spot/accounts", "", None).await?;
+        accounts.into_iter().map(Self::balance).collect()
+    }
+
+    fn private_ws_url(self: Arc<Self>) -> String {
+        self.endpoints.ws_url.clone()
+    }
+
+    fn create_private_subscribe_messages(
+        self: Arc<Self>
+    ) -> Vec<Message> {
+        vec![
+            self.subscribe_message("spot.orders", &["!all"]),
+            self.subscribe_message("spot.usertrades", &["!all"]),
+            self.subscribe_message("spot.balances", &[]),
+        ]
+    }
+
+    /// Ошибка подписки означает отказ в авторизации: остальные параметры подписки постоянные
+    fn parse_private_message(
+        self: Arc<Self>,
+        msg: &str
+    ) -> Result<Vec<PrivateEvent>, TradingError> {
+        let message: GatePrivateMessage = serde_json::from_str(msg).map_err(|e| TradingError::Decode(e.to_string()))?;
+
+        if let Some(error) = message.error {
+            return Err(TradingError::Auth(format!("{} {}: {}", message.channel, error.code, error.message)));
+        }
+
+        if message.event != "update" {
+            return Ok(Vec::new());
+        }
+
+        let decode = |e: serde_json::Error| TradingError::Decode(e.to_string());
+        match message.channel.as_str() {
+            "spot.orders" => {
+                let orders: Vec<GateOrder> = serde_json::from_value(message.result).map_err(decode)?;
+                orders.into_iter().map(|order| Self::order_update(order).map(PrivateEvent::Order)).collect()
+            },
+            "spot.usertrades" => {
+                let trades: Vec<GateTrade> = serde_json::from_value(message.result).map_err(decode)?;
+                trades.into_iter().map(|trade| Self::fill_report(trade).map(PrivateEvent::Fill)).collect()
+            },
+            "spot.balances" => {
+                let accounts: Vec<GateAccount> = serde_json::from_value(message.result).map_err(decode)?;
+                accounts.into_iter().map(|account| Self::balance(account).map(PrivateEvent::Balance)).collect()
+            },
+            _ => Ok(Vec::new())
+        }
+    }
+
+    fn heartbeat(
+        self: Arc<Self>
+    ) -> Heartbeat {
+        Heartbeat {
+            interval: Duration::from_secs(10),
+            max_missed: 2,
+        }
+    }
+
+    fn create_ping_message(
+        self: Arc<Self>,
+        id: u64
+    ) -> Message {
+        Message::Text(serde_json::json!({
+            "time": chrono::Utc::now().timestamp(),
+            "id": id,
+            "channel": "spot.ping"
+        }).to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::GateTrading;
+    use crate::{models::{exchange::ExchangeType, order::{OrderRequest, OrderSide, OrderStatus, OrderType, TimeInForce, new_client_order_id}}, services::exchange::trading_adapter::{Credentials, TradingAdapter, TradingError}, tests::{MOCK_API_KEY, MOCK_API_SECRET, mock_trading, mock_trading_options}};
+
+    fn limit_buy(
+        qty: f64,
+        price: f64,
+        time_in_force: TimeInForce
+    ) -> OrderRequest {
+        OrderRequest {
+            exchange_id: ExchangeType::Gate,
+            client_order_id: new_client_order_id(),
+            symbol: "btcusdt".into(),
+            side: OrderSide::Buy,
+            order_type: OrderType::Limit,
+            qty,
+            price: Some(price),
+            time_in_force,
+            reduce_only: false,
+        }
+    }
+
+    /// Эталон посчитан отдельно от этого кода, через `hmac` из Python
+    #[test]
+    fn sign_matches_reference() {
+        let credentials = Credentials { api_key: MOCK_API_KEY.into(), api_secret: MOCK_API_SECRET.into() };
+        let signature = GateTrading::sign(&credentials, "POST", "/api/v4/spot/orders", "", br#"{"currency_pair":"BTC_USDT"}"#, 1_700_000_000);
+        assert_eq!(signature, "a6c0be96901f6c9e11f60d202930a4e07c551b75da7987de285d11aab9efc39226ecf52b3f29fb118a07f7b4169ecbc58fc20adf4ce8a47a499c847630f6a8d9");
+    }
+
+    /// Gate возвращает ордер сразу после создания: IOC уже отменён с исполненной частью
+    #[tokio::test]
+    async fn ioc_partial_fill_is_cancelled_with_filled_qty() {
+        let (mock, config) = mock_trading(ExchangeType::Gate, &["BTC_USDT"], Vec::new(), mock_trading_options(0.4, None)).await;
+        let trading = GateTrading::new(&config).unwrap();
+        let client = reqwest::Client::new();
+
+        let request = limit_buy(1.0, 100.0, TimeInForce::Ioc);
+        let created = trading.clone().place_order(&client, &request).await.unwrap();
+        assert_eq!(created.client_order_id, request.client_order_id);
+        assert_eq!(created.status, OrderStatus::Cancelled);
+        assert!((created.filled_qty - 0.4).abs() < 1e-9);
+        assert_eq!(created.avg_price, Some(100.0));
+
+        let order = trading.get_order(&client, &request.symbol, &request.client_order_id).await.unwrap().unwrap();
+        assert_eq!(order.status, OrderStatus::Cancelled);
+        assert!((order.filled_qty - 0.4).abs() < 1e-9);
+        assert_eq!(mock.trading().unwrap().rejected_signatures(), 0);
+    }
+
+    /// Gate не проверяет повтор `text`, поэтому перед повтором ордер ищется по client order id
+    #[tokio::test]
+    async fn replay_finds_order_by_client_order_id() {
+        let (mock, config) = mock_trading(ExchangeType::Gate, &["BTC_USDT"], Vec::new(), mock_trading_options(0.0, None)).await;
+        let trading = GateTrading::new(&config).unwrap();
+        let client = reqwest::Client::new();
+
+        let request = limit_buy(1.0, 99.0, TimeInForce::Gtc);
+        let created = trading.clone().place_order(&client, &request).await.unwrap();
+        assert_eq!(created.status, OrderStatus::New);
+
+        let order = trading.clone().get_order(&client, &request.symbol, &request.client_order_id).await.unwrap().unwrap();
+        assert_eq!(order.order_id, created.order_id);
+        assert_eq!(order.client_order_id, request.client_order_id);
+        assert_eq!(mock.trading().unwrap().orders(), 1);
+
+        let unknown = trading.get_order(&client, &request.symbol, &new_client_order_id()).await.unwrap();
+        assert!(unknown.is_none());
+    }
+
+    #[tokio::test]
+    async fn exchange_errors_map_to_trading_error() {
+        let balances = BTreeMap::from([("USDT".to_string(), 10.0)]);
+        let (mock, config) = mock_trading(ExchangeType::Gate, &["BTC_USDT"], Vec::new(), mock_trading_options(1.0, Some(balances))).await;
+        let trading = GateTrading::new(&config).unwrap();
+        let client = reqwest::Client::new();
+
+        let result = trading.clone().place_order(&client, &limit_buy(1.0, 100.0, TimeInForce::Gtc)).await;
+        assert!(matches!(result, Err(TradingError::InsufficientBalance(_))), "{result:?}");
+
+        let result = trading.clone().place_order(&client, &limit_buy(0.01, 0.0, TimeInForce::Gtc)).await;
+        assert!(matches!(result, Err(TradingError::InvalidOrder(_))), "{result:?}");
+
+        let result = trading.clone().cancel_order(&client, &"btcusdt".into(), &new_client_order_id()).await;
+        assert!(matches!(result, Err(TradingError::OrderNotFound(_))), "{result:?}");
+        assert_eq!(mock.trading().unwrap().rejected_signatures(), 0);
+
+        let mut wrong_secret = config.clone();
+        wrong_secret.trading.api_secret = Some("other-secret".into());
+        let result = GateTrading::new(&wrong_secret).unwrap().get_balances(&client).await;
+        assert!(matches!(result, Err(TradingError::Auth(_))), "{result:?}");
+
+        let mut wrong_key = config;
+        wrong_key.trading.api_key = Some("other-key".into());
+        let result = GateTrading::new(&wrong_key).unwrap().get_balances(&client).await;
+        assert!(matches!(result, Err(TradingError::Auth(_))), "{result:?}");
+        assert_eq!(mock.trading().unwrap().rejected_signatures(), 2);
+    }
+}
@@ -1,17 +1,44 @@
-use std::{collections::BTreeMap, sync::Arc};
+use std::{collections::BTreeMap, sync::Arc, time::Duration};
+use itertools::Itertools;
 use rust_decimal::Decimal;
-use tokio::sync::{Mutex, mpsc, watch};
+use tokio::sync::Mutex;
 use tokio_tungstenite::tungstenite::Message;
 
-use crate::{models::{exchange::{PriceCache, TickerInfo, TickerResponse}, exchange_key::ApiKeyResponse, orderbook::OrderBookEventData, websocket::Symbol}, services::exchange::{exchange_adapter::ExchangeAdapter, exchange_aggregator::ExchangeStoreCMD}};
+use crate::{config::ExchangeConfig, models::{exchange::{PriceCache, TickerInfo, TickerResponse}, exchange_key::ApiKeyResponse, orderbook::{EventTime, OrderBookEventData}, websocket::Symbol}, services::exchange::{exchange_adapter::{Endpoints, ExchangeAdapter, ExchangeLimits, Heartbeat}, exchange_aggregator::BookEventSender}};
 
 pub struct KuCoinAdapter {
-    price_cache: Arc<Mutex<PriceCache>>
+    price_cache: Arc<Mutex<PriceCache>>,
+    endpoints: Endpoints
 }
 
 impl KuCoinAdapter {
-    pub fn new() -> Arc<Self> {
-        Arc::new(Self { price_cache: Arc::new(Mutex::new(PriceCache::new())) })
+    pub fn new(
+        config: &ExchangeConfig
+    ) -> Arc<Self> {
+        Arc::new(Self {
+            price_cache: Arc::new(Mutex::new(PriceCache::new())),
+            endpoints: Endpoints::new("wss://ws-api-spot.kucoin.com", "https://api.kucoin.com", config)
+        })
+    }
+
+    /// Топик KuCoin принимает список символов через запятую (`/market/ticker:BTC-USDT,ETH-USDT`)
+    fn topic_messages(
+        self: Arc<Self>,
+        message_type: &str,
+        prefix: &str,
+        symbols: &[Arc<Symbol>]
+    ) -> Vec<Message> {
+        symbols
+            .chunks(self.limits().args_per_message)
+            .map(|chunk| Message::Text(
+                serde_json::json!({
+                    "type": message_type,
+                    "topic": format!("{}:{}", prefix, chunk.iter().join(",")),
+                    "response": true,
+                    "privateChannel": false,
+                }).to_string().into()
+            ))
+            .collect()
     }
 }
 
@@ -19,8 +46,8 @@ impl KuCoinAdapter {
 impl ExchangeAdapter for KuCoinAdapter {
     fn ws_url(
         self: Arc<Self>
-    ) -> &'static str {
-        "wss://ws-api-spot.kucoin.com"
+    ) -> String {
+        self.endpoints.ws_url.clone()
     }
 
     fn requires_auth(
@@ -47,7 +74,7 @@ impl ExchangeAdapter for KuCoinAdapter {
         self: Arc<Self>,
         client: &reqwest::Client
     ) -> Result<String, reqwest::Error> {
-        let url = "https://api.kucoin.com/api/v1/bullet-public";
+        let url = format!("{}/api/v1/bullet-public", self.endpoints.rest_url);
         
         let response = client.post(url)  
             .send()
@@ -65,7 +92,7 @@ impl ExchangeAdapter for KuCoinAdapter {
         self: Arc<Self>, 
         client: &reqwest::Client
     ) -> Option<Vec<TickerInfo>> {
-        let url = "https://api.kucoin.com/api/v1/market/allTickers";
+        let url = format!("{}/api/v1/market/allTickers", self.endpoints.rest_url);
         let response = client.get(url).send().await;
         
         let Ok(response) = response else { return None };
@@ -83,43 +110,81 @@ impl ExchangeAdapter for KuCoinAdapter {
         self: Arc<Self>,
         tickers: &Vec<TickerInfo>,
         client: &reqwest::Client,
-        sender_data: watch::Sender<ExchangeStoreCMD>
+        sender_data: BookEventSender
     ) {
         
     }
 
-    fn create_subscribe_messages(
+    /// 100 топиков на соединение и 100 сообщений за 10 секунд
+    fn limits(
+        self: Arc<Self>
+    ) -> ExchangeLimits {
+        ExchangeLimits {
+            topics_per_connection: 100,
+            topics_per_symbol: 3,
+            args_per_message: 100,
+            messages_per_second: 10,
+            max_connections: 50,
+        }
+    }
+
+    /// Сервер ждёт ping каждые `pingInterval` (18 секунд) и закрывает соединение через `pingTimeout`
+    fn heartbeat(
+        self: Arc<Self>
+    ) -> Heartbeat {
+        Heartbeat {
+            interval: Duration::from_secs(18),
+            max_missed: 2,
+        }
+    }
+
+    fn create_ping_message(
+        self: Arc<Self>,
+        id: u64
+    ) -> Message {
+        Message::Text(serde_json::json!({
+            "id": id.to_string(),
+            "type": "ping"
+        }).to_string())
+    }
+
+    fn is_pong(
+        self: Arc<Self>,
+        msg: &str
+    ) -> bool {
+        msg.contains("\"type\":\"pong\"")
+    }
+
+    fn create_ticker_subscribe_messages(
+        self: Arc<Self>,
+        symbols: &[Arc<Symbol>]
+    ) -> Vec<Message> {
+        let mut messages = self.clone().topic_messages("subscribe", "/market/ticker", symbols);
+        messages.extend(self.topic_messages("subscribe", "/market/snapshot", symbols));
+        messages
+    }
+
+    fn create_ticker_unsubscribe_messages(
+        self: Arc<Self>,
+        symbols: &[Arc<Symbol>]
+    ) -> Vec<Message> {
+        let mut messages = self.clone().topic_messages("unsubscribe", "/market/ticker", symbols);
+        messages.extend(self.topic_messages("unsubscribe", "/market/snapshot", symbols));
+        messages
+    }
+
+    fn create_depth_subscribe_messages(
+        self: Arc<Self>,
+        symbols: &[Arc<Symbol>]
+    ) -> Vec<Message> {
+        self.topic_messages("subscribe", "/spotMarket/level2Depth50", symbols)
+    }
+
+    fn create_depth_unsubscribe_messages(
         self: Arc<Self>,
-        symbol: Arc<Symbol>
+        symbols: &[Arc<Symbol>]
     ) -> Vec<Message> {
-        let message1 = Message::Text(
-            serde_json::json!({
-                "type": "subscribe",
-                "topic": format!("/spotMarket/level2Depth50:{}", symbol),
-                "response": true,
-                "privateChannel": false,
-            }).to_string().into()
-        );
-
-        let message2 = Message::Text(
-            serde_json::json!({
-                "type": "subscribe",
-                "topic": format!("/market/ticker:{}", symbol),
-                "response": true,
-                "privateChannel": false,
-            }).to_string().into()
-        );
-
-        let message3 = Message::Text(
-            serde_json::json!({
-                "type": "subscribe",
-                "topic": format!("/market/snapshot:{}", symbol),
-                "response": true,
-                "privateChannel": false,
-            }).to_string().into()
-        );
-
-        vec![message1, message2, message3]
+        self.topic_messages("unsubscribe", "/spotMarket/level2Depth50", symbols)
     }
 
     fn cache(
@@ -131,8 +196,7 @@ impl ExchangeAdapter for KuCoinAdapter {
     async fn parse_message(
         self: Arc<Self>,
         msg: String,
-        _snapshot_channel: mpsc::Sender<ExchangeStoreCMD>,
-        _data_aggregator_tx: watch::Sender<ExchangeStoreCMD>
+        _data_aggregator_tx: BookEventSender
     ) {
         // Парсим orderbook
         if msg.contains("level2Depth50") {
@@ -148,7 +212,7 @@ impl ExchangeAdapter for KuCoinAdapter {
     async fn parse_tickers(
         self: Arc<Self>,
         msg: Arc<String>,
-        sender_data: watch::Sender<ExchangeStoreCMD>
+        sender_data: BookEventSender
     ) {
 
     }
@@ -164,8 +228,7 @@ impl ExchangeAdapter for KuCoinAdapter {
     async fn parse_orderbook(
         self: Arc<Self>,
         _msg: Arc<String>,
-        _snapshot_channel: mpsc::Sender<ExchangeStoreCMD>,
-        _sender_data: watch::Sender<ExchangeStoreCMD>
+        _sender_data: BookEventSender
     ) {
         
     }
@@ -173,8 +236,8 @@ impl ExchangeAdapter for KuCoinAdapter {
     async fn handle_snapshot<'a>(
         self: Arc<Self>,
         _data: Option<OrderBookEventData<'a>>,
-        _snapshot_channel: mpsc::Sender<ExchangeStoreCMD>,
-        _sender_data: watch::Sender<ExchangeStoreCMD>
+        _time: EventTime,
+        _sender_data: BookEventSender
     ) {
 
     }
@@ -182,7 +245,8 @@ impl ExchangeAdapter for KuCoinAdapter {
     async fn handle_delta<'a>(
         self: Arc<Self>,
         _data: Option<OrderBookEventData<'a>>,
-        _sender_data: watch::Sender<ExchangeStoreCMD>
+        _time: EventTime,
+        _sender_data: BookEventSender
     ) {
 
     }
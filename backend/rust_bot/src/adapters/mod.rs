@@ -1,4 +1,6 @@
 pub mod bybit_adapter;
 pub mod gate_adapter;
 pub mod binance_adapter;
-pub mod kucoin_adapter;
\ No newline at end of file
+pub mod kucoin_adapter;
+pub mod bybit_trading;
+pub mod gate_trading;
@@ -0,0 +1,794 @@
+use std::{collections::{HashMap, HashSet}, net::SocketAddr, path::{Path, PathBuf}, sync::{Arc, LazyLock}, time::Duration};
+use anyhow::{Context, bail};
+use arc_swap::ArcSwap;
+use serde::Deserialize;
+use crate::models::{aggregator::KeyMarketType, exchange::ExchangeType, websocket::Symbol};
+
+static CONFIG: LazyLock<ArcSwap<Config>> = LazyLock::new(|| ArcSwap::from_pointee(Config::default()));
+
+/// Текущая конфигурация. До `set` возвращает значения по умолчанию.
+/// Пороги читаются при каждом использовании, поэтому применяются без перезапуска
+pub fn get() -> Arc<Config> {
+    CONFIG.load_full()
+}
+
+pub fn set(
+    config: Config
+) {
+    CONFIG.store(Arc::new(config));
+}
+
+/// <b>Config</b> настройки из TOML файла (`CONFIG_PATH`, по умолчанию `config.toml`).
+/// Все секции необязательные, неизвестные поля - ошибка
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub server: ServerConfig,
+    pub admin: AdminConfig,
+    pub metrics: MetricsConfig,
+    pub recorder: RecorderConfig,
+    pub database: DatabaseConfig,
+    pub pipeline: PipelineConfig,
+    pub lines: LinesConfig,
+    pub paper: PaperConfig,
+    pub execution: ExecutionConfig,
+    pub risk: RiskConfig,
+    /// Фильтр символов для всех бирж
+    pub symbols: SymbolFilter,
+    pub exchanges: HashMap<ExchangeType, ExchangeConfig>,
+}
+
+impl Default for Config {
+    /// Без файла включены Bybit и Gate
+    fn default() -> Self {
+        let enabled = ExchangeConfig { enabled: true, ..Default::default() };
+
+        Self {
+            server: ServerConfig::default(),
+            admin: AdminConfig::default(),
+            metrics: MetricsConfig::default(),
+            recorder: RecorderConfig::default(),
+            database: DatabaseConfig::default(),
+            pipeline: PipelineConfig::default(),
+            lines: LinesConfig::default(),
+            paper: PaperConfig::default(),
+            execution: ExecutionConfig::default(),
+            risk: RiskConfig::default(),
+            symbols: SymbolFilter::default(),
+            exchanges: HashMap::from([
+                (ExchangeType::Bybit, enabled.clone()),
+                (ExchangeType::Gate, enabled),
+            ]),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    /// Адрес WebSocket сервера клиентов
+    pub bind: String,
+    /// Очередь сообщений одного клиента
+    pub client_queue: usize,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            bind: "127.0.0.1:9000".into(),
+            client_queue: 100,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AdminConfig {
+    /// Адрес WebSocket API администратора
+    pub bind: String,
+    /// Bearer токен. Если не задан, берётся `ADMIN_TOKEN`, без токена API выключен
+    pub token: Option<String>,
+}
+
+impl Default for AdminConfig {
+    fn default() -> Self {
+        Self {
+            bind: "127.0.0.1:9001".into(),
+            token: None,
+        }
+    }
+}
+
+impl AdminConfig {
+    pub fn token(&self) -> Option<String> {
+        self.token
+            .clone()
+            .or_else(|| std::env::var("ADMIN_TOKEN").ok())
+            .filter(|token| !token.is_empty())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MetricsConfig {
+    pub enabled: bool,
+    /// Адрес HTTP endpoint `/metrics` в формате Prometheus
+    pub bind: String,
+}
+
+impl Default for MetricsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            bind: "127.0.0.1:9002".into(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RecorderConfig {
+    /// Каталог записей, у каждой биржи свой подкаталог
+    pub dir: PathBuf,
+    /// Размер несжатых данных, после которого начинается новый файл
+    pub max_file_mb: u64,
+}
+
+impl Default for RecorderConfig {
+    fn default() -> Self {
+        Self {
+            dir: "recordings".into(),
+            max_file_mb: 256,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DatabaseConfig {
+    /// Если не задан, берётся `DATABASE_URL`
+    pub url: Option<String>,
+    pub max_connections: u32,
+}
+
+impl Default for DatabaseConfig {
+    fn default() -> Self {
+        Self {
+            url: None,
+            max_connections: 10,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PipelineConfig {
+    /// Стаканов в LRU кеше одной биржи
+    pub orderbook_cache_capacity: usize,
+    /// Очередь событий от адаптера к `ExchangeStore`
+    pub exchange_store_queue: usize,
+    pub data_mapping_queue: usize,
+    pub manager_transmitter_queue: usize,
+    /// Уровней стакана в каждую сторону для клиентов
+    pub ui_depth: usize,
+    /// Объём сделки в USDT, на который считается исполнимый спред
+    pub spread_notional: f64,
+    /// Через сколько секунд без обновлений стакан считается устаревшим
+    pub book_stale_secs: u64,
+    /// Максимальная разница времени ног спреда в мс
+    pub spread_max_skew_ms: i64,
+    /// Сколько подписка на стакан без потребителей держится перед отпиской
+    pub subscription_idle_grace_secs: u64,
+    /// Размер линий в кеше `CacheAggregator`, сверх него вытесняются самые холодные пары
+    pub lines_cache_memory_bytes: usize,
+    /// Самых активных за сутки пар, история которых загружается при старте. 0 - без прогрева
+    pub lines_cache_warm_up_pairs: usize,
+}
+
+impl Default for PipelineConfig {
+    fn default() -> Self {
+        Self {
+            orderbook_cache_capacity: 1000,
+            exchange_store_queue: 4096,
+            data_mapping_queue: 1024,
+            manager_transmitter_queue: 1024,
+            ui_depth: 6,
+            spread_notional: 100.0,
+            book_stale_secs: 30,
+            spread_max_skew_ms: 500,
+            subscription_idle_grace_secs: 60,
+            lines_cache_memory_bytes: 64 * 1024 * 1024,
+            lines_cache_warm_up_pairs: 50,
+        }
+    }
+}
+
+/// Обслуживание `storage.lines` актором `LinesMaintenance`
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LinesConfig {
+    /// Как часто создаются партиции, сжимаются и удаляются линии. Применяется при старте
+    pub maintenance_interval_secs: u64,
+    /// Сколько дней хранятся `1m` линии, 0 - всегда
+    pub retention_1m_days: u32,
+    /// Сколько дней хранятся `1h` линии, 0 - всегда
+    pub retention_1h_days: u32,
+}
+
+impl Default for LinesConfig {
+    fn default() -> Self {
+        Self {
+            maintenance_interval_secs: 3600,
+            retention_1m_days: 14,
+            retention_1h_days: 0,
+        }
+    }
+}
+
+impl LinesConfig {
+    pub fn maintenance_interval(&self) -> Duration {
+        Duration::from_secs(self.maintenance_interval_secs)
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PaperConfig {
+    /// Комиссия taker за сделку в базисных пунктах
+    pub fee_bps: f64,
+    /// Как часто переоцениваются открытые позиции
+    pub mark_interval_ms: u64,
+    /// Сколько ждать стаканы ног при открытии
+    pub open_timeout_secs: u64,
+    /// Открытых позиций одного пользователя
+    pub max_open_per_user: usize,
+    /// Наибольший объём ноги в котируемой валюте
+    pub max_notional: f64,
+}
+
+impl Default for PaperConfig {
+    fn default() -> Self {
+        Self {
+            fee_bps: 10.0,
+            mark_interval_ms: 1000,
+            open_timeout_secs: 10,
+            max_open_per_user: 10,
+            max_notional: 10_000.0,
+        }
+    }
+}
+
+impl PaperConfig {
+    pub fn fee_rate(&self) -> f64 {
+        self.fee_bps / 10_000.0
+    }
+
+    pub fn mark_interval(&self) -> Duration {
+        Duration::from_millis(self.mark_interval_ms)
+    }
+
+    pub fn open_timeout(&self) -> Duration {
+        Duration::from_secs(self.open_timeout_secs)
+    }
+}
+
+/// Автоматическое исполнение арбитража через `OrderGateway`
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ExecutionConfig {
+    /// Без него спреды только журналируются как пропущенные
+    pub enabled: bool,
+    /// Допустимое ухудшение цены догоняющей ноги от цены плана, в базисных пунктах
+    pub slippage_bps: f64,
+    /// Сколько ждать итог IOC ноги, после этого она отменяется
+    pub leg_timeout_ms: u64,
+    /// Попыток догнать недостающую ногу, после них исполненная нога откатывается
+    pub chase_attempts: usize,
+    /// Пауза пары после исполнения
+    pub cooldown_ms: u64,
+    /// Разница ног меньше этой суммы в котируемой валюте не догоняется и не откатывается
+    pub min_notional: f64,
+    pub pairs: Vec<ExecutionPair>,
+}
+
+impl Default for ExecutionConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            slippage_bps: 10.0,
+            leg_timeout_ms: 3000,
+            chase_attempts: 2,
+            cooldown_ms: 10_000,
+            min_notional: 5.0,
+            pairs: Vec::new(),
+        }
+    }
+}
+
+impl ExecutionConfig {
+    pub fn slippage_rate(&self) -> f64 {
+        self.slippage_bps / 10_000.0
+    }
+
+    pub fn leg_timeout(&self) -> Duration {
+        Duration::from_millis(self.leg_timeout_ms)
+    }
+
+    pub fn cooldown(&self) -> Duration {
+        Duration::from_millis(self.cooldown_ms)
+    }
+
+    /// Пара исполнения для направления `key`
+    pub fn pair(
+        &self,
+        key: &KeyMarketType
+    ) -> Option<&ExecutionPair> {
+        self.pairs.iter().find(|pair| {
+            pair.long_exchange == key.long_exchange && pair.short_exchange == key.short_exchange && pair.symbol == *key.symbol
+        })
+    }
+}
+
+/// Направление арбитража: покупка на `long_exchange`, продажа на `short_exchange`.
+/// Объём сделки - `pipeline.spread_notional`
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ExecutionPair {
+    /// Символ в общем формате (`btcusdt`)
+    pub symbol: Symbol,
+    pub long_exchange: ExchangeType,
+    pub short_exchange: ExchangeType,
+    /// Исполнимый спред в %, от которого отправляются ноги
+    pub threshold_pct: f64,
+}
+
+/// Проверки каждого ордера перед отправкой на биржу. Суммы в котируемой валюте
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RiskConfig {
+    /// Наибольшая сумма одного ордера
+    pub max_order_notional: f64,
+    /// Лимиты по символам (`btcusdt`): сумма ордера вместе с неисполненной суммой
+    /// незавершённых ордеров символа на всех биржах
+    pub symbol_max_notional: HashMap<Symbol, f64>,
+    /// Лимиты по биржам: сумма ордера вместе с неисполненной суммой незавершённых ордеров биржи
+    pub exchange_max_notional: HashMap<ExchangeType, f64>,
+    /// Незавершённых ордеров на всех биржах
+    pub max_open_legs: usize,
+    /// Убыток исполнений за сутки UTC, после которого включается общий kill switch
+    pub max_daily_loss: f64,
+    /// Стакан биржи ордера без обновлений дольше этого считается устаревшим
+    pub max_book_age_ms: u64,
+    /// Допустимое отклонение цены ордера от средней середины стаканов бирж, в %
+    pub price_band_pct: f64,
+    /// Нарушение `max_order_notional` или ценового коридора выключает торговлю на бирже ордера
+    pub kill_on_breach: bool,
+}
+
+impl Default for RiskConfig {
+    fn default() -> Self {
+        Self {
+            max_order_notional: 1000.0,
+            symbol_max_notional: HashMap::new(),
+            exchange_max_notional: HashMap::new(),
+            max_open_legs: 10,
+            max_daily_loss: 100.0,
+            max_book_age_ms: 5000,
+            price_band_pct: 3.0,
+            kill_on_breach: true,
+        }
+    }
+}
+
+impl PipelineConfig {
+    pub fn book_stale_after(&self) -> Duration {
+        Duration::from_secs(self.book_stale_secs)
+    }
+
+    pub fn subscription_idle_grace(&self) -> Duration {
+        Duration::from_secs(self.subscription_idle_grace_secs)
+    }
+}
+
+/// Символы в общем формате (`btcusdt`). Пустой `allow` - разрешены все
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SymbolFilter {
+    pub allow: HashSet<Symbol>,
+    pub deny: HashSet<Symbol>,
+}
+
+impl SymbolFilter {
+    pub fn allows(
+        &self,
+        symbol: &Symbol
+    ) -> bool {
+        (self.allow.is_empty() || self.allow.contains(symbol)) && !self.deny.contains(symbol)
+    }
+
+    fn validate(
+        &self,
+        section: &str
+    ) -> anyhow::Result<()> {
+        for symbol in self.allow.iter().chain(self.deny.iter()) {
+            if symbol.is_empty() || !symbol.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit()) {
+                bail!("{section}: символ `{symbol}` должен быть в общем формате, например `btcusdt`");
+            }
+        }
+
+        if let Some(symbol) = self.allow.intersection(&self.deny).next() {
+            bail!("{section}: символ `{symbol}` одновременно в allow и deny");
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ExchangeConfig {
+    pub enabled: bool,
+    /// Переопределение WebSocket адреса, например для локального mock сервера
+    pub ws_url: Option<String>,
+    /// Переопределение базового REST адреса (`https://api.bybit.com`)
+    pub rest_url: Option<String>,
+    /// Фильтр символов биржи, применяется вместе с общим
+    pub symbols: SymbolFilter,
+    /// Запись входящих кадров биржи в `recorder.dir`, включается без переподключения
+    pub record: bool,
+    /// Приватный API для ордеров, применяется только при старте
+    pub trading: TradingConfig,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TradingConfig {
+    pub enabled: bool,
+    /// Адреса testnet биржи вместо боевых
+    pub testnet: bool,
+    /// Если не заданы, берутся `<BYBIT|GATE>_API_KEY` и `<BYBIT|GATE>_API_SECRET`
+    pub api_key: Option<String>,
+    pub api_secret: Option<String>,
+    /// Переопределение REST адреса приватного API, например для mock сервера
+    pub rest_url: Option<String>,
+    /// Переопределение адреса приватного WebSocket
+    pub ws_url: Option<String>,
+    /// Сколько биржа считает подписанный запрос действительным
+    pub recv_window_ms: u64,
+    pub request_timeout_ms: u64,
+}
+
+impl Default for TradingConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            testnet: false,
+            api_key: None,
+            api_secret: None,
+            rest_url: None,
+            ws_url: None,
+            recv_window_ms: 5000,
+            request_timeout_ms: 5000,
+        }
+    }
+}
+
+impl TradingConfig {
+    /// Ключ и секрет из конфига или окружения. `None` - торговля на бирже невозможна
+    pub fn credentials(
+        &self,
+        exchange_id: ExchangeType
+    ) -> Option<(String, String)> {
+        let prefix = match exchange_id {
+            ExchangeType::Bybit => "BYBIT",
+            ExchangeType::Gate => "GATE",
+            _ => return None
+        };
+        let value = |value: &Option<String>, name: &str| value
+            .clone()
+            .or_else(|| std::env::var(format!("{prefix}_{name}")).ok())
+            .filter(|value| !value.is_empty());
+
+        Some((value(&self.api_key, "API_KEY")?, value(&self.api_secret, "API_SECRET")?))
+    }
+
+    pub fn request_timeout(&self) -> Duration {
+        Duration::from_millis(self.request_timeout_ms)
+    }
+}
+
+impl ExchangeConfig {
+    /// Адреса изменились - сессию биржи нужно пересоздать
+    pub fn endpoints_changed(
+        &self,
+        other: &Self
+    ) -> bool {
+        self.ws_url != other.ws_url || self.rest_url != other.rest_url
+    }
+}
+
+/// Биржи, для которых есть адаптер
+pub const SUPPORTED_EXCHANGES: [ExchangeType; 3] = [ExchangeType::Bybit, ExchangeType::Gate, ExchangeType::KuCoin];
+
+/// Биржи с приватным торговым API
+pub const TRADING_EXCHANGES: [ExchangeType; 2] = [ExchangeType::Bybit, ExchangeType::Gate];
+
+impl Config {
+    /// Читает и проверяет файл. Если файла нет, используются значения по умолчанию
+    pub fn load(
+        path: impl AsRef<Path>
+    ) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        if !path.exists() {
+            tracing::warn!("Config -> {} не найден, используются значения по умолчанию", path.display());
+            return Ok(Self::default());
+        }
+
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("не удалось прочитать {}", path.display()))?;
+
+        let config: Self = toml::from_str(&content)
+            .with_context(|| format!("ошибка в {}", path.display()))?;
+
+        config.validate()
+            .with_context(|| format!("ошибка в {}", path.display()))?;
+
+        Ok(config)
+    }
+
+    pub fn validate(
+        &self
+    ) -> anyhow::Result<()> {
+        self.server.bind
+            .parse::<SocketAddr>()
+            .with_context(|| format!("server.bind: `{}` не является адресом host:port", self.server.bind))?;
+
+        self.admin.bind
+            .parse::<SocketAddr>()
+            .with_context(|| format!("admin.bind: `{}` не является адресом host:port", self.admin.bind))?;
+
+        self.metrics.bind
+            .parse::<SocketAddr>()
+            .with_context(|| format!("metrics.bind: `{}` не является адресом host:port", self.metrics.bind))?;
+
+        if self.recorder.max_file_mb == 0 {
+            bail!("recorder.max_file_mb должен быть больше 0");
+        }
+
+        if self.database.max_connections == 0 {
+            bail!("database.max_connections должен быть больше 0");
+        }
+
+        let pipeline = &self.pipeline;
+        for (name, value) in [
+            ("server.client_queue", self.server.client_queue),
+            ("pipeline.orderbook_cache_capacity", pipeline.orderbook_cache_capacity),
+            ("pipeline.exchange_store_queue", pipeline.exchange_store_queue),
+            ("pipeline.data_mapping_queue", pipeline.data_mapping_queue),
+            ("pipeline.manager_transmitter_queue", pipeline.manager_transmitter_queue),
+            ("pipeline.lines_cache_memory_bytes", pipeline.lines_cache_memory_bytes),
+        ] {
+            if value == 0 {
+                bail!("{name} должен быть больше 0");
+            }
+        }
+
+        if !(1..=50).contains(&pipeline.ui_depth) {
+            bail!("pipeline.ui_depth должен быть от 1 до 50, сейчас {}", pipeline.ui_depth);
+        }
+
+        if !(pipeline.spread_notional.is_finite() && pipeline.spread_notional > 0.0) {
+            bail!("pipeline.spread_notional должен быть положительным числом");
+        }
+
+        if pipeline.spread_max_skew_ms < 0 {
+            bail!("pipeline.spread_max_skew_ms не может быть отрицательным");
+        }
+
+        if self.lines.maintenance_interval_secs == 0 {
+            bail!("lines.maintenance_interval_secs должен быть больше 0");
+        }
+
+        let paper = &self.paper;
+        if !(paper.fee_bps.is_finite() && paper.fee_bps >= 0.0) {
+            bail!("paper.fee_bps не может быть отрицательным");
+        }
+
+        if paper.mark_interval_ms == 0 || paper.max_open_per_user == 0 {
+            bail!("paper.mark_interval_ms и paper.max_open_per_user должны быть больше 0");
+        }
+
+        if !(paper.max_notional.is_finite() && paper.max_notional > 0.0) {
+            bail!("paper.max_notional должен быть положительным числом");
+        }
+
+        let execution = &self.execution;
+        if !(execution.slippage_bps.is_finite() && execution.slippage_bps >= 0.0) {
+            bail!("execution.slippage_bps не может быть отрицательным");
+        }
+
+        if execution.leg_timeout_ms == 0 {
+            bail!("execution.leg_timeout_ms должен быть больше 0");
+        }
+
+        if !(execution.min_notional.is_finite() && execution.min_notional >= 0.0) {
+            bail!("execution.min_notional не может быть отрицательным");
+        }
+
+        for pair in execution.pairs.iter() {
+            let section = format!("execution.pairs {} {}/{}", pair.symbol, pair.long_exchange, pair.short_exchange);
+
+            if pair.long_exchange == pair.short_exchange {
+                bail!("{section}: биржи ног должны различаться");
+            }
+
+            if !(pair.threshold_pct.is_finite() && pair.threshold_pct > 0.0) {
+                bail!("{section}: threshold_pct должен быть положительным");
+            }
+
+            if pair.symbol.is_empty() || !pair.symbol.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit()) {
+                bail!("{section}: символ должен быть в общем формате, например `btcusdt`");
+            }
+
+            if execution.pairs.iter().filter(|other| other.symbol == pair.symbol && other.long_exchange == pair.long_exchange && other.short_exchange == pair.short_exchange).count() > 1 {
+                bail!("{section}: направление указано дважды");
+            }
+        }
+
+        let risk = &self.risk;
+        let limits = [("max_order_notional", risk.max_order_notional), ("max_daily_loss", risk.max_daily_loss), ("price_band_pct", risk.price_band_pct)]
+            .into_iter()
+            .map(|(name, value)| (name.to_string(), value))
+            .chain(risk.symbol_max_notional.iter().map(|(symbol, value)| (format!("symbol_max_notional.{symbol}"), *value)))
+            .chain(risk.exchange_max_notional.iter().map(|(exchange_id, value)| (format!("exchange_max_notional.{exchange_id}"), *value)));
+        for (name, value) in limits {
+            if !(value.is_finite() && value > 0.0) {
+                bail!("risk.{name} должен быть положительным числом");
+            }
+        }
+
+        if risk.max_open_legs == 0 || risk.max_book_age_ms == 0 {
+            bail!("risk.max_open_legs и risk.max_book_age_ms должны быть больше 0");
+        }
+
+        self.symbols.validate("symbols")?;
+
+        for (exchange_id, exchange) in self.exchanges.iter() {
+            let section = format!("exchanges.{}", serde_json::to_string(exchange_id)?.trim_matches('"'));
+
+            if exchange.enabled && !SUPPORTED_EXCHANGES.contains(exchange_id) {
+                bail!("{section}: для биржи {exchange_id} нет адаптера");
+            }
+
+            for (name, url, schemes) in [
+                ("ws_url", &exchange.ws_url, ["ws", "wss"]),
+                ("rest_url", &exchange.rest_url, ["http", "https"]),
+                ("trading.ws_url", &exchange.trading.ws_url, ["ws", "wss"]),
+                ("trading.rest_url", &exchange.trading.rest_url, ["http", "https"]),
+            ] {
+                let Some(url) = url else { continue };
+                let parsed = url::Url::parse(url)
+                    .with_context(|| format!("{section}.{name}: `{url}` не является URL"))?;
+
+                if !schemes.contains(&parsed.scheme()) {
+                    bail!("{section}.{name}: ожидается схема {}, получено `{}`", schemes.join(" или "), parsed.scheme());
+                }
+            }
+
+            exchange.symbols.validate(&format!("{section}.symbols"))?;
+
+            let trading = &exchange.trading;
+            if trading.enabled {
+                if !TRADING_EXCHANGES.contains(exchange_id) {
+                    bail!("{section}.trading: для биржи {exchange_id} нет торгового адаптера");
+                }
+
+                if trading.credentials(*exchange_id).is_none() {
+                    bail!("{section}.trading: не заданы api_key и api_secret");
+                }
+
+                if trading.recv_window_ms == 0 || trading.request_timeout_ms == 0 {
+                    bail!("{section}.trading: recv_window_ms и request_timeout_ms должны быть больше 0");
+                }
+            }
+        }
+
+        if !self.exchanges.values().any(|exchange| exchange.enabled) {
+            tracing::warn!("Config -> нет включённых бирж");
+        }
+
+        Ok(())
+    }
+
+    /// Возвращает настройки, которые применяются только при старте процесса, к значениям `running`.
+    /// Очередь и кеш `ExchangeStore` берутся при запуске сессии биржи, поэтому не входят сюда.
+    /// Результат - изменённые секции, для них нужен перезапуск
+    pub fn keep_startup_settings(
+        &mut self,
+        running: &Self
+    ) -> Vec<&'static str> {
+        let mut changed = Vec::new();
+
+        if self.server != running.server {
+            self.server = running.server.clone();
+            changed.push("server");
+        }
+
+        if self.admin.bind != running.admin.bind {
+            self.admin.bind = running.admin.bind.clone();
+            changed.push("admin.bind");
+        }
+
+        if self.metrics != running.metrics {
+            self.metrics = running.metrics.clone();
+            changed.push("metrics");
+        }
+
+        if self.database != running.database {
+            self.database = running.database.clone();
+            changed.push("database");
+        }
+
+        if self.pipeline.data_mapping_queue != running.pipeline.data_mapping_queue {
+            self.pipeline.data_mapping_queue = running.pipeline.data_mapping_queue;
+            changed.push("pipeline.data_mapping_queue");
+        }
+
+        if self.pipeline.manager_transmitter_queue != running.pipeline.manager_transmitter_queue {
+            self.pipeline.manager_transmitter_queue = running.pipeline.manager_transmitter_queue;
+            changed.push("pipeline.manager_transmitter_queue");
+        }
+
+        for (exchange_id, exchange) in self.exchanges.iter_mut() {
+            let running = running.exchange(*exchange_id).trading;
+            if exchange.trading != running {
+                exchange.trading = running;
+                changed.push("exchanges.*.trading");
+            }
+        }
+
+        changed
+    }
+
+    /// Настройки биржи. Биржа без секции выключена
+    pub fn exchange(
+        &self,
+        exchange_id: ExchangeType
+    ) -> ExchangeConfig {
+        self.exchanges
+            .get(&exchange_id)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// Символ разрешён общим фильтром и фильтром биржи
+    pub fn allows_symbol(
+        &self,
+        exchange_id: ExchangeType,
+        symbol: &Symbol
+    ) -> bool {
+        self.symbols.allows(symbol)
+            && self.exchanges.get(&exchange_id).is_none_or(|exchange| exchange.symbols.allows(symbol))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Пример конфига проходит `deny_unknown_fields` и `validate`, а его значения совпадают со значениями по умолчанию
+    #[test]
+    fn example_config_matches_defaults() {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("config.example.toml");
+        assert!(path.exists());
+
+        let example = Config::load(&path).unwrap();
+        let defaults = Config::default();
+
+        assert_eq!(format!("{:?}", example.pipeline), format!("{:?}", defaults.pipeline));
+        assert_eq!(format!("{:?}", example.lines), format!("{:?}", defaults.lines));
+        assert_eq!(format!("{:?}", example.risk), format!("{:?}", defaults.risk));
+        assert_eq!(format!("{:?}", example.execution), format!("{:?}", defaults.execution));
+        assert_eq!(format!("{:?}", example.paper), format!("{:?}", defaults.paper));
+    }
+}
@@ -1,15 +1,22 @@
-use std::{sync::Arc, time::Duration};
-use tokio::sync::{mpsc, watch};
-use tracing_subscriber::{EnvFilter, layer::SubscriberExt, util::SubscriberInitExt};
+use std::{path::PathBuf, sync::Arc, time::Duration};
+use tokio::{signal, sync::mpsc};
+use tracing::info;
+use tracing_subscriber::{EnvFilter, layer::SubscriberExt, reload, util::SubscriberInitExt};
 
-use crate::{services::{cache_aggregator::{CacheAggregator, CacheAggregatorCmd}, data_access_layer::DataAccessLayer, data_aggregator::{DataAggregator, DataAggregatorCmd}, data_mapping::{DataMapping}, exchange::exchange_channel_store::ExchangeChannelStore, manager_transmitter::{ManagerTransmitter}}, transport::client_aggregator::{ClientAggregator, ClientAggregatorCmd}};
+use crate::{services::{backtest::{self, BacktestArgs}, cache_aggregator::{CacheAggregator, CacheAggregatorCmd}, clock, data_access_layer::{DataAccessLayer, DataAccessLayerCmd}, data_aggregator::DataAggregator, data_mapping::{DataMapping}, exchange::{exchange_channel_store::ExchangeChannelStore, exchange_control::{self, ExchangeControl}, exchange_health::HealthMonitor, frame_replay::{RecordedFrames, Replay, ReplayArgs}, mock_exchange::{self, MockExchangeArgs}}, latency, lines_maintenance::LinesMaintenance, metrics, manager_transmitter::{ManagerTransmitter}, arbitrage_executor::ArbitrageExecutor, inventory_tracker::InventoryTracker, order_gateway::OrderGateway, paper_trading::PaperTrading, risk_manager::RiskManager, queue, supervisor::Supervisor}, transport::{admin::{AdminApi, TradingHandles}, client_aggregator::{ClientAggregator, ClientAggregatorCmd}}};
 
+mod config;
 mod exchanges;
 mod transport;
 mod services;
 mod storage;
 mod models;
 mod adapters;
+#[cfg(test)]
+mod tests;
+
+/// Сколько ждать акторы после сигнала остановки
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(10);
 
 mod mexc_orderbook {
     include!(concat!(env!("OUT_DIR"), "/_.rs"));
@@ -21,20 +28,82 @@ async fn main() {
         .pretty()
         .with_target(false);
 
-    let filter = EnvFilter::new("info,sqlx::query=off");
+    // Уровень логов меняется без перезапуска через API администратора
+    let (filter, log_filter) = reload::Layer::new(EnvFilter::new("info,sqlx::query=off"));
 
     tracing_subscriber::registry()
         .with(filter)
         .with(fmt_layer)
         .init();
 
-    let storage_pool = storage::pool::create_pool().await.ok();
+    let config_path = std::env::var("CONFIG_PATH").unwrap_or_else(|_| "config.toml".into());
+    match config::Config::load(&config_path) {
+        Ok(config) => config::set(config),
+        Err(e) => {
+            tracing::error!("Config -> {e:#}");
+            std::process::exit(1);
+        }
+    }
+
+    // `rust_bot backtest ...` считает сделки стратегии и завершается, конвейер не запускается
+    match BacktestArgs::parse(std::env::args().skip(1)) {
+        Ok(None) => {},
+        Ok(Some(args)) => {
+            if let Err(e) = backtest::run(args).await {
+                tracing::error!("Backtest -> {e:#}");
+                std::process::exit(1);
+            }
+            return;
+        },
+        Err(e) => {
+            tracing::error!("Backtest -> {e:#}");
+            std::process::exit(1);
+        }
+    }
+
+    // `rust_bot mock-exchange ...` поднимает локальную биржу для прогонов без сети и работает до Ctrl+C
+    match MockExchangeArgs::parse(std::env::args().skip(1)) {
+        Ok(None) => {},
+        Ok(Some(args)) => {
+            if let Err(e) = mock_exchange::run(args).await {
+                tracing::error!("MockExchange -> {e:#}");
+                std::process::exit(1);
+            }
+            return;
+        },
+        Err(e) => {
+            tracing::error!("MockExchange -> {e:#}");
+            std::process::exit(1);
+        }
+    }
+
+    // `rust_bot replay <файлы>` подаёт записанные кадры бирж вместо WebSocket.
+    // Часы переключаются на время записи до запуска акторов
+    let replay = match ReplayArgs::parse(std::env::args().skip(1)).and_then(|args| {
+        args.map(|args| RecordedFrames::open(&args.paths).map(|frames| (args, frames))).transpose()
+    }) {
+        Ok(replay) => replay,
+        Err(e) => {
+            tracing::error!("Replay -> {e:#}");
+            std::process::exit(1);
+        }
+    };
+    if let Some((_, frames)) = &replay {
+        clock::start_replay(frames.start_us());
+    }
+
+    // Все акторы конвейера работают под supervisor: упавший актор перезапускается,
+    // по SIGINT/SIGTERM все останавливаются и доделывают незаписанное
+    let supervisor = Supervisor::new();
+
+    let storage_pool = storage::pool::create_pool(&config::get().database).await.ok();
         
-    let (manager_transmitter_tx, manager_transmitter_rx) = mpsc::channel(1024);
+    let (manager_transmitter_tx, manager_transmitter_rx) = mpsc::channel(config::get().pipeline.manager_transmitter_queue);
         
     let data_mapping = DataMapping::new(manager_transmitter_tx.clone());
     let data_mapping_tx = data_mapping.data_mapping_tx.clone();
-    data_mapping.run();
+    let books_queue = data_mapping.books_queue.clone();
+    supervisor.spawn_actor("DataMapping", data_mapping);
 
     // Запускаем агррегаторы
     let (cache_aggregator_tx, cache_aggregator_rx) = mpsc::channel::<Arc<CacheAggregatorCmd>>(64);
@@ -43,7 +112,10 @@ async fn main() {
         data_mapping_tx.clone(),
         storage_pool.clone()
     );
-    tokio::spawn(cache_aggregator.run());
+    supervisor.spawn_actor("CacheAggregator", cache_aggregator);
+
+    // Партиции, downsampling и retention для storage.lines
+    supervisor.spawn_actor("LinesMaintenance", LinesMaintenance::new(storage_pool.clone()));
 
     // Каналы для получения данных с data aggregator
     let (client_aggregator_chart_tx, client_aggregator_chart_rx) = mpsc::channel::<Arc<ClientAggregatorCmd>>(64);
@@ -51,66 +123,187 @@ async fn main() {
     // Канал для приёма команд от пользователя
     let (client_aggregator_tx, client_aggregator_rx) = mpsc::channel::<ClientAggregatorCmd>(64);
 
+    // Канал спроса клиентов на стаканы бирж
+    let (data_access_layer_tx, data_access_layer_rx) = mpsc::channel::<DataAccessLayerCmd>(64);
+
     let client_aggregator = ClientAggregator::new(
         client_aggregator_rx,
         client_aggregator_chart_rx,
         cache_aggregator_tx.clone(),
+        data_access_layer_tx.clone(),
     );
-    tokio::spawn(client_aggregator.run());
+    supervisor.spawn_actor("ClientAggregator", client_aggregator);
     
-    let (data_aggregator_tx, data_aggregator_rx) = watch::channel::<DataAggregatorCmd>(DataAggregatorCmd::Default);
+    let exchange_channel_store = ExchangeChannelStore::new();
+    let exchange_channel_store_tx = exchange_channel_store.sender_channel.clone();
+    supervisor.spawn_actor("ExchangeChannelStore", exchange_channel_store);
+
+    // Риск и ордера останавливаются после остальных: исполнитель откатывает через них начатые исполнения
+    let trading = Supervisor::new();
+
+    // Лимиты и kill switch, их проходит каждый ордер
+    let (risk_manager, risk) = RiskManager::new(
+        exchange_channel_store_tx.clone(),
+        storage_pool.clone()
+    );
+    trading.spawn_actor("RiskManager", risk_manager);
+
+    // Ордера на биржах с включённой торговлей
+    let (order_gateway, orders) = OrderGateway::new(risk.clone());
+    trading.spawn_actor("OrderGateway", order_gateway);
+
+    // Остатки на биржах, по ним отмечаются исполнимые спреды
+    let (inventory_tracker, inventory) = InventoryTracker::new(orders.clone());
+    supervisor.spawn_actor("InventoryTracker", inventory_tracker);
+
     let data_aggregator = DataAggregator::new(
-        data_aggregator_rx, 
-        data_mapping_tx.clone(),
+        books_queue,
         cache_aggregator_tx.clone(),
+        inventory.clone(),
         storage_pool.clone(),
     );
     let register_symbol_tx = data_aggregator.register_symbol_tx.clone();
+    let book_updates = data_aggregator.book_updates.clone();
+    let executable_spreads = data_aggregator.executable_spreads.clone();
 
     let manager_transmitter = ManagerTransmitter::new(
+        manager_transmitter_rx,
         client_aggregator_chart_tx.clone(),
         cache_aggregator_tx.clone(),
     );
-    tokio::spawn(async move {
-        manager_transmitter.run(manager_transmitter_rx).await;
-    });
-
-    let exchange_channel_store = ExchangeChannelStore::new();
-    let exchange_channel_store_tx = exchange_channel_store.sender_channel.clone();
-    tokio::spawn(exchange_channel_store.run());
+    supervisor.spawn_actor("ManagerTransmitter", manager_transmitter);
 
     let data_access_layer = DataAccessLayer::new(
+        data_access_layer_rx,
         cache_aggregator_tx.clone(),
         data_mapping_tx.clone(),
         exchange_channel_store_tx.clone(),
-        data_aggregator_tx.clone()
+        book_updates.clone()
+    );
+    supervisor.spawn_actor("DataAccessLayer", data_access_layer);
+
+    // Бумажные позиции клиентов по живым стаканам
+    let (paper_trading, paper) = PaperTrading::new(
+        data_access_layer_tx.clone(),
+        exchange_channel_store_tx.clone(),
+        storage_pool.clone()
     );
-    tokio::spawn(data_access_layer.run());
+    supervisor.spawn_actor("PaperTrading", paper_trading);
 
-    tokio::spawn(
-        data_aggregator.run()
+    // Исполнение спредов выше порогов `execution.pairs`
+    let (arbitrage_executor, executions) = ArbitrageExecutor::new(
+        executable_spreads.clone(),
+        orders.clone(),
+        risk.clone(),
+        data_access_layer_tx.clone(),
+        storage_pool.clone()
     );
+    supervisor.spawn_actor("ArbitrageExecutor", arbitrage_executor);
+
+    supervisor.spawn_actor("DataAggregator", data_aggregator);
+
+    // Статусы соединений и свежесть стаканов бирж
+    let (health_monitor, health) = HealthMonitor::new();
+    supervisor.spawn_actor("HealthMonitor", health_monitor);
+
+    // Счётчики очередей рыночных данных
+    supervisor.spawn_task("QueueStats", || queue::report_stats(Duration::from_secs(60)));
 
-    // Запуск биржевых вебсокетов
-    tokio::spawn({
-        async move {
-            services::exchange::exchanges_run::run_ws_exchanges(
-                register_symbol_tx,
-                exchange_channel_store_tx
-            ).await;
+    // Задержки бирж и конвейера рыночных данных
+    supervisor.spawn_task("Latency", || latency::report_latency(Duration::from_secs(60)));
+
+    // Метрики конвейера для Prometheus
+    supervisor.spawn_task("Metrics", metrics::serve);
+
+    // Запуск биржевых вебсокетов и их перезапуск при изменении конфига
+    let (exchange_control, exchange_control_handle) = ExchangeControl::new(
+        &config_path,
+        register_symbol_tx.clone(),
+        exchange_channel_store_tx.clone(),
+        health.clone(),
+        supervisor.clone()
+    );
+    match replay {
+        // При воспроизведении биржи берутся из записи, перезагрузка конфига из API недоступна
+        Some((args, frames)) => {
+            match Replay::new(frames, args.speed, register_symbol_tx, book_updates, health.clone(), supervisor.clone()) {
+                Ok(replay) => supervisor.spawn_actor("Replay", replay),
+                Err(e) => {
+                    tracing::error!("Replay -> {e:#}");
+                    std::process::exit(1);
+                }
+            }
+        },
+        None => {
+            supervisor.spawn_actor("ExchangeControl", exchange_control);
+            supervisor.spawn_task("ConfigWatcher", {
+                let config_path = PathBuf::from(config_path);
+                let exchange_control_handle = exchange_control_handle.clone();
+                move || exchange_control::watch_config(config_path.clone(), exchange_control_handle.clone())
+            });
         }
-    });
+    }
+
+    // API администратора
+    let admin = AdminApi::new(
+        client_aggregator_tx.clone(),
+        exchange_channel_store_tx,
+        exchange_control_handle,
+        health.clone(),
+        TradingHandles {
+            orders: orders.clone(),
+            executions,
+            inventory,
+            risk
+        },
+        log_filter
+    );
+    supervisor.spawn_task("AdminApi", move || admin.clone().run());
     
-    tokio::spawn({
-        let client_aggregator_tx = client_aggregator_tx.clone();
-        async move {
-            transport::ws::connect_async(
-                client_aggregator_tx,
-            ).await;
-        }
+    supervisor.spawn_task("ArbitrationWebsocket", {
+        let supervisor = supervisor.clone();
+        move || transport::ws::connect_async(
+            client_aggregator_tx.clone(),
+            health.clone(),
+            paper.clone(),
+            executable_spreads.clone(),
+            supervisor.clone(),
+        )
     });
 
-    loop {
-        tokio::time::sleep(Duration::from_millis(100)).await
+    // Воспроизведение останавливает приложение само после последнего кадра
+    tokio::select! {
+        _ = shutdown_signal() => {},
+        _ = supervisor.token().cancelled_owned() => {}
+    }
+    info!("Остановка...");
+
+    supervisor.shutdown();
+    let stopped = supervisor.wait(SHUTDOWN_TIMEOUT).await;
+    trading.shutdown();
+    if !(stopped && trading.wait(SHUTDOWN_TIMEOUT).await) {
+        tracing::warn!("Не все задачи завершились за {:?}", SHUTDOWN_TIMEOUT);
+    }
+
+    info!("Остановлено");
+}
+
+/// Ctrl+C или SIGTERM от оркестратора
+async fn shutdown_signal() {
+    let terminate = async {
+        match signal::unix::signal(signal::unix::SignalKind::terminate()) {
+            Ok(mut sigterm) => {
+                sigterm.recv().await;
+            },
+            Err(e) => {
+                tracing::error!("SIGTERM -> {e}");
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+
+    tokio::select! {
+        _ = signal::ctrl_c() => {},
+        _ = terminate => {}
     }
 }
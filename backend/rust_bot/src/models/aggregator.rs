@@ -2,7 +2,7 @@ use std::{sync::Arc};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 
-use crate::models::{data_mapping::{DataJson, SnapshotJson}, exchange::ExchangeType, websocket::{ChannelSubscription, ClientId, Symbol, WsClientMessage}};
+use crate::models::{data_mapping::{DataJson, SnapshotJson}, exchange::ExchangeType, inventory::InventoryFit, orderbook::EventTime, websocket::{ChannelSubscription, ClientId, Symbol, WsClientMessage}};
 
 pub enum ClientAggregatorUse {
     #[allow(unused)]
@@ -54,7 +54,7 @@ impl KeyPair {
     }
 }
 
-#[derive(Deserialize, Clone, Serialize, Debug, Hash, PartialEq, Eq)]
+#[derive(Deserialize, Clone, Serialize, Debug, Hash, PartialEq, Eq, PartialOrd, Ord)]
 pub enum JsonPairUniqueId {
     OrderBook,
     LinesHistory,
@@ -69,6 +69,8 @@ pub enum JsonPairData {
     OrderBook {
         long: Arc<SnapshotJson>,
         short: Arc<SnapshotJson>,
+        /// Время ног расходится больше `SPREAD_MAX_SKEW_MS`: спред может быть артефактом отстающей биржи
+        skewed: bool,
     },
     LinesHistory {
         long: Vec<Value>,
@@ -100,27 +102,43 @@ pub struct SpreadPair {
     pub long_spread: f64,
     pub short_exchange: ExchangeType,
     pub short_spread: f64,
-    pub timestamp: i64
+    pub timestamp: i64,
+    #[allow(unused)]
+    pub long_time: Option<EventTime>,
+    #[allow(unused)]
+    pub short_time: Option<EventTime>,
+    /// Время ног расходится больше `SPREAD_MAX_SKEW_MS`
+    pub skewed: bool,
 }
 
-impl SpreadPair {
-    pub fn new(
-        symbol: Arc<Symbol>,
-        long_exchange: ExchangeType,
-        long_spread: f64,
-        short_exchange: ExchangeType,
-        short_spread: f64,
-        timestamp: i64,
-    ) -> Self {
-        Self { 
-            symbol,
-            long_exchange,
-            long_spread, 
-            short_exchange,
-            short_spread, 
-            timestamp 
-        }
-    }
+/// <b>ExecutableSpread</b> спред, который можно взять сейчас на `pipeline.spread_notional`:
+/// покупка на long бирже проходом по ask и продажа на short бирже проходом по bid
+#[derive(Debug, Clone, Serialize)]
+pub struct ExecutableSpread {
+    pub key: KeyMarketType,
+    /// Количество базовой валюты в каждой ноге
+    pub qty: f64,
+    /// Средние цены исполнения ног
+    pub buy_price: f64,
+    pub sell_price: f64,
+    /// Последние задетые уровни: лимитные цены, по которым ноги исполнятся целиком
+    pub buy_limit: f64,
+    pub sell_limit: f64,
+    pub spread_pct: f64,
+    /// Время ног расходится больше `SPREAD_MAX_SKEW_MS`
+    pub skewed: bool,
+    /// Хватает ли остатков на биржах, `None` - остатки бирж неизвестны
+    pub inventory: Option<InventoryFit>,
+    /// Время расчёта в мс Unix
+    pub time: i64,
+}
+
+/// Исполнимые спреды символа после обновления стакана. Пустой список - спредов больше нет
+#[derive(Debug, Clone, Serialize)]
+pub struct ExecutableSpreads {
+    pub symbol: Arc<Symbol>,
+    pub spreads: Vec<Arc<ExecutableSpread>>,
+    pub time: i64,
 }
 
 #[derive(Clone, Debug)]
@@ -129,15 +147,18 @@ pub struct Quote {
     pub symbol: Option<Arc<Symbol>>,
     pub bid: Option<f64>,
     pub ask: Option<f64>,
+    pub time: Option<EventTime>,
 }
 
 impl Quote {
+    #[allow(unused)]
     pub fn new() -> Self {
         Self { 
             exchange_id: None, 
             symbol: None, 
             bid: None, 
-            ask: None 
+            ask: None,
+            time: None
         }
     }
 }
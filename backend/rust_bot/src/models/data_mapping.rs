@@ -4,11 +4,14 @@ use ordered_float::OrderedFloat;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 
+use crate::models::orderbook::EventTime;
+
 #[derive(Debug, Deserialize, Clone, Serialize, Hash, PartialEq, Eq)]
 pub struct SnapshotJson {
     pub asks: Vec<Value>,
     pub bids: Vec<Value>,
     pub last_price: OrderedFloat<f64>,
+    pub time: Option<EventTime>,
 }
 
 #[derive(Deserialize, Serialize, Debug,  Hash, PartialEq, Eq)]
@@ -37,6 +37,8 @@ pub struct TickerEventData<'a> {
 #[derive(Debug, Deserialize, Serialize, Clone)]
 #[serde(bound(deserialize = "'de: 'a"))]
 pub struct TickerEvent<'a> {
+    #[serde(rename="ts", alias="time_ms")]
+    pub timestamp: Option<i64>,
     #[serde(rename="result", alias="data")]
     pub result: Option<TickerEventData<'a>>
 }
@@ -1,6 +1,6 @@
-use std::sync::Arc;
+use std::{sync::Arc, time::Instant};
 
-use crate::models::{orderbook::Snapshot, websocket::Symbol};
+use crate::models::{orderbook::{EventTime, Snapshot}, websocket::Symbol};
 
 #[derive(Clone, Debug)]
 /// <b>BookData</b> хранит данные `биржи`, получаемые с Websocket
@@ -8,7 +8,11 @@ pub struct BookData {
     pub snapshot: Option<Snapshot>,
     pub last_price: Option<f64>,
     pub volume24h: Option<f64>,
-    pub symbol: Arc<Symbol>
+    pub symbol: Arc<Symbol>,
+    /// Время последнего изменения, по нему отсекаются устаревшие стаканы
+    pub updated_at: Option<Instant>,
+    /// Время последнего применённого события биржи
+    pub time: Option<EventTime>
 }
 
 impl BookData {
@@ -17,7 +21,9 @@ impl BookData {
             snapshot: None, 
             last_price: None, 
             volume24h: None,
-            symbol: Arc::new(String::new())
+            symbol: Arc::new(String::new()),
+            updated_at: None,
+            time: None
         }
     }
 }
@@ -26,5 +32,7 @@ impl BookData {
 pub struct BookDataWithArc {
     pub snapshot: Option<Arc<Snapshot>>,
     pub last_price: Option<f64>,
-    pub volume24h: Option<f64>
+    pub volume24h: Option<f64>,
+    pub updated_at: Option<Instant>,
+    pub time: Option<EventTime>
 }
\ No newline at end of file
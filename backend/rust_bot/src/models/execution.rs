@@ -0,0 +1,150 @@
+use serde::Serialize;
+
+use crate::{models::{exchange::ExchangeType, order::{ClientOrderId, OrderRequest, OrderSide}, websocket::Symbol}, services::clock};
+
+pub type ExecutionId = i64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all="snake_case")]
+pub enum ExecutionStatus {
+    Running,
+    /// Ноги исполнены на одинаковый объём, возможно после догона
+    Hedged,
+    /// Лишний объём одной ноги закрыт откатом
+    Unwound,
+    /// Ни одна нога не исполнилась
+    Missed,
+    /// Разница ног осталась, нужна ручная проверка
+    Unbalanced,
+    /// Процесс остановился посреди исполнения
+    Interrupted,
+}
+
+impl ExecutionStatus {
+    /// Значение, под которым статус хранится в Postgres
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Running => "running",
+            Self::Hedged => "hedged",
+            Self::Unwound => "unwound",
+            Self::Missed => "missed",
+            Self::Unbalanced => "unbalanced",
+            Self::Interrupted => "interrupted",
+        }
+    }
+}
+
+/// Решение исполнителя, каждое пишется в журнал
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all="snake_case")]
+pub enum Decision {
+    /// Спред выше порога, ноги отправляются
+    Enter,
+    /// Ордер отправлен в `OrderGateway`
+    Submit,
+    /// Итог ордера: исполненный объём или отказ
+    Result,
+    /// Недостающая нога отправляется заново в пределах `slippage_bps`
+    Chase,
+    /// Лишний объём исполненной ноги закрывается рыночным ордером
+    Unwind,
+    /// Разница ног оставлена
+    Abandon,
+    Finish,
+}
+
+impl Decision {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Enter => "enter",
+            Self::Submit => "submit",
+            Self::Result => "result",
+            Self::Chase => "chase",
+            Self::Unwind => "unwind",
+            Self::Abandon => "abandon",
+            Self::Finish => "finish",
+        }
+    }
+}
+
+/// Запись журнала исполнения. Поля ордера есть у решений по конкретному ордеру
+#[derive(Debug, Clone, Serialize)]
+pub struct JournalEntry {
+    pub decision: Decision,
+    pub exchange_id: Option<ExchangeType>,
+    pub side: Option<OrderSide>,
+    pub client_order_id: Option<ClientOrderId>,
+    pub qty: Option<f64>,
+    pub price: Option<f64>,
+    pub note: String,
+    pub time: i64,
+}
+
+impl JournalEntry {
+    pub fn note(
+        decision: Decision,
+        note: String
+    ) -> Self {
+        Self {
+            decision,
+            exchange_id: None,
+            side: None,
+            client_order_id: None,
+            qty: None,
+            price: None,
+            note,
+            time: clock::now_ms(),
+        }
+    }
+
+    /// Решение по ордеру, количество и цена из запроса
+    pub fn order(
+        decision: Decision,
+        request: &OrderRequest,
+        note: String
+    ) -> Self {
+        Self {
+            exchange_id: Some(request.exchange_id),
+            side: Some(request.side),
+            client_order_id: Some(request.client_order_id.clone()),
+            qty: Some(request.qty),
+            price: request.price,
+            ..Self::note(decision, note)
+        }
+    }
+}
+
+/// <b>Execution</b> одно исполнение арбитража: покупка на `long_exchange` и продажа на `short_exchange`.
+/// Суммы в котируемой валюте, время в мс Unix
+#[derive(Debug, Clone, Serialize)]
+pub struct Execution {
+    pub id: ExecutionId,
+    pub symbol: Symbol,
+    pub long_exchange: ExchangeType,
+    pub short_exchange: ExchangeType,
+    pub status: ExecutionStatus,
+    pub threshold_pct: f64,
+    /// План по стаканам в момент входа
+    pub spread_pct: f64,
+    pub qty: f64,
+    pub buy_price: f64,
+    pub sell_price: f64,
+    /// Куплено на long бирже за вычетом отката
+    pub long_qty: f64,
+    /// Продано на short бирже за вычетом отката
+    pub short_qty: f64,
+    /// Продажи минус покупки и комиссии. При равных ногах - результат исполнения
+    pub cash_flow: f64,
+    /// Комиссии бирж из приватных потоков, пересчитанные в котируемую валюту
+    pub fees: f64,
+    pub started_at: i64,
+    pub finished_at: Option<i64>,
+    pub journal: Vec<JournalEntry>,
+}
+
+impl Execution {
+    /// Лишний объём long ноги, отрицательный - short ноги
+    pub fn imbalance(&self) -> f64 {
+        self.long_qty - self.short_qty
+    }
+}
@@ -0,0 +1,155 @@
+use std::collections::{BTreeMap, HashMap};
+use serde::Serialize;
+
+use crate::{models::{aggregator::ExecutableSpread, exchange::ExchangeType}, services::exchange::trading_adapter::split_symbol};
+
+/// Остаток валюты на бирже. Валюта в верхнем регистре, как её отдаёт `split_symbol`
+#[derive(Debug, Clone, Serialize)]
+pub struct Balance {
+    pub exchange_id: ExchangeType,
+    pub asset: String,
+    /// Доступно для новых ордеров
+    pub free: f64,
+    /// Занято открытыми ордерами
+    pub locked: f64,
+    /// Время биржи или опроса в мс Unix
+    pub time: i64,
+}
+
+/// Остатки одной биржи. Известны только после полного опроса REST
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ExchangeBalances {
+    pub balances: HashMap<String, Balance>,
+    /// Последний опрос или обновление из приватного потока
+    pub updated_at: i64,
+}
+
+/// Валюта по всем биржам
+#[derive(Debug, Clone, Serialize)]
+pub struct AssetInventory {
+    pub asset: String,
+    pub free: f64,
+    pub locked: f64,
+    pub exchanges: Vec<Balance>,
+}
+
+/// Хватает ли средств на спред: котируемой валюты на long бирже и базовой на short бирже
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct InventoryFit {
+    /// Обе ноги объёма спреда покрыты остатками
+    pub executable: bool,
+    /// Наибольший объём ног в базовой валюте, комиссии не учитываются
+    pub max_qty: f64,
+}
+
+/// <b>Inventory</b> остатки на биржах с включённой торговлей.
+///
+/// <br>• Опрос REST заменяет остатки биржи целиком, приватный поток обновляет отдельные валюты
+/// <br>• Биржа без опроса или с остатками старше `stale_after_ms` считается неизвестной
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Inventory {
+    pub exchanges: HashMap<ExchangeType, ExchangeBalances>,
+    #[serde(skip)]
+    pub stale_after_ms: i64,
+}
+
+impl Inventory {
+    pub fn new(
+        stale_after_ms: i64
+    ) -> Self {
+        Self {
+            exchanges: HashMap::new(),
+            stale_after_ms,
+        }
+    }
+
+    pub fn replace(
+        &mut self,
+        exchange_id: ExchangeType,
+        balances: Vec<Balance>,
+        now: i64
+    ) {
+        self.exchanges.insert(exchange_id, ExchangeBalances {
+            balances: balances.into_iter().map(|balance| (balance.asset.clone(), balance)).collect(),
+            updated_at: now,
+        });
+    }
+
+    /// Обновление из потока до первого опроса пропускается: без него остальные валюты неизвестны
+    pub fn update(
+        &mut self,
+        balance: Balance,
+        now: i64
+    ) -> bool {
+        let Some(exchange) = self.exchanges.get_mut(&balance.exchange_id) else { return false };
+        exchange.balances.insert(balance.asset.clone(), balance);
+        exchange.updated_at = now;
+        true
+    }
+
+    pub fn remove(
+        &mut self,
+        exchange_id: ExchangeType
+    ) {
+        self.exchanges.remove(&exchange_id);
+    }
+
+    /// Свободный остаток валюты, `None` - остатки биржи неизвестны или устарели
+    pub fn free(
+        &self,
+        exchange_id: ExchangeType,
+        asset: &str,
+        now: i64
+    ) -> Option<f64> {
+        let exchange = self.exchanges.get(&exchange_id)?;
+        if now - exchange.updated_at > self.stale_after_ms {
+            return None;
+        }
+
+        Some(exchange.balances.get(asset).map(|balance| balance.free).unwrap_or_default())
+    }
+
+    /// Покупка на long бирже оценивается по `buy_limit`: по этой цене уходит лимитная нога
+    pub fn fit(
+        &self,
+        spread: &ExecutableSpread,
+        now: i64
+    ) -> Option<InventoryFit> {
+        let (base, quote) = split_symbol(&spread.key.symbol)?;
+        let quote_free = self.free(spread.key.long_exchange, &quote, now)?;
+        let base_free = self.free(spread.key.short_exchange, &base, now)?;
+
+        let max_qty = (quote_free / spread.buy_limit).min(base_free).max(0.0);
+        Some(InventoryFit {
+            executable: max_qty >= spread.qty,
+            max_qty,
+        })
+    }
+
+    /// Сводка по валютам, биржи внутри валюты по имени
+    pub fn assets(&self) -> Vec<AssetInventory> {
+        let mut assets: BTreeMap<&str, AssetInventory> = BTreeMap::new();
+
+        for exchange in self.exchanges.values() {
+            for balance in exchange.balances.values() {
+                let asset = assets.entry(&balance.asset).or_insert_with(|| AssetInventory {
+                    asset: balance.asset.clone(),
+                    free: 0.0,
+                    locked: 0.0,
+                    exchanges: Vec::new(),
+                });
+                asset.free += balance.free;
+                asset.locked += balance.locked;
+                asset.exchanges.push(balance.clone());
+            }
+        }
+
+        assets
+            .into_values()
+            .map(|mut asset| {
+                asset.exchanges.sort_by_key(|balance| balance.exchange_id.to_string());
+                asset
+            })
+            .collect()
+    }
+}
@@ -1,3 +1,5 @@
+use std::collections::VecDeque;
+
 use get_size::GetSize;
 use serde::{Deserialize, Serialize};
 use sqlx::{Type, prelude::FromRow};
@@ -34,10 +36,78 @@ impl Line {
     }
 }
 
-#[derive(Debug, Clone, Deserialize, Serialize, Type, GetSize)]
+/// <b>LineHistory</b> неизменяемый кольцевой буфер линий одного ключа, отсортированный по `timestamp`.
+/// Добавление линии создаёт новый буфер, поэтому опубликованная история никогда не меняется
+#[derive(Debug, Clone, Default, GetSize)]
+pub struct LineHistory {
+    lines: VecDeque<Line>,
+}
+
+impl LineHistory {
+    pub fn from_lines(
+        lines: impl IntoIterator<Item = Line>,
+        capacity: usize,
+    ) -> Self {
+        let mut lines = lines.into_iter().collect::<Vec<Line>>();
+        lines.sort_by_key(|line| line.timestamp);
+        lines.dedup_by_key(|line| line.timestamp);
+
+        let skip = lines.len().saturating_sub(capacity);
+        Self {
+            lines: lines.into_iter().skip(skip).collect()
+        }
+    }
+
+    /// Возвращает новый буфер с `line`. Линия с тем же `timestamp` заменяется
+    pub fn with_line(
+        &self,
+        line: Line,
+        capacity: usize,
+    ) -> Self {
+        let mut lines = self.lines.clone();
+
+        match lines.binary_search_by_key(&line.timestamp, |l| l.timestamp) {
+            Ok(pos) => lines[pos] = line,
+            Err(pos) => lines.insert(pos, line),
+        }
+
+        while lines.len() > capacity {
+            lines.pop_front();
+        }
+
+        Self { lines }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Line> {
+        self.lines.iter()
+    }
+}
+
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, Type, GetSize, PartialEq, Eq, Hash)]
 #[sqlx(type_name="timeframe", rename_all="lowercase")]
 pub enum TimeFrame {
     #[serde(rename="1m")]
     #[sqlx(rename="1m")]
-    One
+    One,
+    #[serde(rename="1h")]
+    #[sqlx(rename="1h")]
+    OneHour
+}
+
+impl TimeFrame {
+    /// Значение, под которым timeframe хранится в Postgres
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::One => "1m",
+            Self::OneHour => "1h",
+        }
+    }
+
+    /// Длина одной свечи в секундах
+    pub fn seconds(&self) -> i64 {
+        match self {
+            Self::One => 60,
+            Self::OneHour => 3600,
+        }
+    }
 }
\ No newline at end of file
@@ -5,4 +5,10 @@ pub mod websocket;
 pub mod aggregator;
 pub mod exchange_key;
 pub mod exchange_aggregator;
-pub mod data_mapping;
\ No newline at end of file
+pub mod data_mapping;
+pub mod paper;
+pub mod order;
+pub mod execution;
+pub mod inventory;
+pub mod risk;
+
@@ -0,0 +1,252 @@
+use std::{collections::BTreeMap, sync::{LazyLock, atomic::{AtomicU64, Ordering}}};
+use serde::{Deserialize, Serialize};
+
+use crate::{models::{exchange::ExchangeType, websocket::Symbol}, services::{clock, exchange::trading_adapter::split_symbol}};
+
+/// Идентификатор ордера, который задаём мы. Если ответ на создание потерян,
+/// ордер находится на бирже по этому id, поэтому запрос можно безопасно повторить
+pub type ClientOrderId = String;
+
+static CLIENT_ORDER_SEQ: AtomicU64 = AtomicU64::new(0);
+static CLIENT_ORDER_EPOCH: LazyLock<u64> = LazyLock::new(|| clock::now_ms().max(0) as u64);
+
+/// Новый id вида `rb<время старта><номер>`: уникален между перезапусками и не длиннее
+/// 28 символов, которые Gate оставляет после обязательного префикса `t-`
+pub fn new_client_order_id() -> ClientOrderId {
+    let seq = CLIENT_ORDER_SEQ.fetch_add(1, Ordering::Relaxed);
+    format!("rb{}{}", to_base36(*CLIENT_ORDER_EPOCH), to_base36(seq))
+}
+
+fn to_base36(
+    mut value: u64
+) -> String {
+    const DIGITS: &[u8] = b"0123456789abcdefghijklmnopqrstuvwxyz";
+    let mut result = Vec::new();
+    loop {
+        result.push(DIGITS[(value % 36) as usize]);
+        value /= 36;
+        if value == 0 {
+            break;
+        }
+    }
+    result.reverse();
+    String::from_utf8(result).unwrap_or_default()
+}
+
+/// id, который можно передать бирже: латиница, цифры, `-` и `_`
+pub fn is_valid_client_order_id(
+    id: &str
+) -> bool {
+    !id.is_empty() && id.len() <= 28 && id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all="snake_case")]
+pub enum OrderSide {
+    Buy,
+    Sell,
+}
+
+impl OrderSide {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Buy => "buy",
+            Self::Sell => "sell",
+        }
+    }
+
+    #[allow(unused)]
+    pub fn opposite(&self) -> Self {
+        match self {
+            Self::Buy => Self::Sell,
+            Self::Sell => Self::Buy,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all="snake_case")]
+pub enum OrderType {
+    Limit,
+    /// Gate принимает рыночную покупку в котируемой валюте, поэтому для неё нужна `price` как оценка
+    Market,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all="snake_case")]
+pub enum TimeInForce {
+    #[default]
+    Gtc,
+    /// Неисполненный остаток отменяется сразу
+    Ioc,
+    PostOnly,
+}
+
+/// <b>OrderRequest</b> ордер на одной бирже. Символ в общем формате (`btcusdt`),
+/// адаптер переводит его в формат биржи
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OrderRequest {
+    pub exchange_id: ExchangeType,
+    pub client_order_id: ClientOrderId,
+    pub symbol: Symbol,
+    pub side: OrderSide,
+    pub order_type: OrderType,
+    /// Количество базовой валюты
+    pub qty: f64,
+    pub price: Option<f64>,
+    #[serde(default)]
+    pub time_in_force: TimeInForce,
+    /// Только сокращает открытый объём, как откат лишней ноги исполнения. Kill switch,
+    /// суточный убыток и лимит незавершённых ордеров его не останавливают, на биржу флаг не уходит
+    #[serde(default)]
+    pub reduce_only: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all="snake_case")]
+pub enum OrderStatus {
+    /// Отправлен, биржа ещё не ответила
+    Pending,
+    New,
+    PartiallyFilled,
+    Filled,
+    /// Отменён, возможно после частичного исполнения
+    Cancelled,
+    Rejected,
+}
+
+impl OrderStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Pending => "pending",
+            Self::New => "new",
+            Self::PartiallyFilled => "partially_filled",
+            Self::Filled => "filled",
+            Self::Cancelled => "cancelled",
+            Self::Rejected => "rejected",
+        }
+    }
+
+    /// Ордер больше не изменится
+    pub fn is_final(&self) -> bool {
+        matches!(self, Self::Filled | Self::Cancelled | Self::Rejected)
+    }
+}
+
+/// Состояние ордера по ответу REST или сообщению приватного потока
+#[derive(Debug, Clone, Serialize)]
+pub struct OrderUpdate {
+    pub exchange_id: ExchangeType,
+    pub client_order_id: ClientOrderId,
+    pub order_id: Option<String>,
+    pub status: OrderStatus,
+    pub filled_qty: f64,
+    /// Средняя цена исполненной части
+    pub avg_price: Option<f64>,
+    /// Время биржи в мс Unix
+    pub time: i64,
+}
+
+/// Исполнение части ордера из приватного потока
+#[derive(Debug, Clone, Serialize)]
+pub struct FillReport {
+    pub exchange_id: ExchangeType,
+    pub client_order_id: ClientOrderId,
+    pub order_id: String,
+    /// id сделки на бирже, повтор одного исполнения после переподключения отбрасывается по нему
+    pub trade_id: String,
+    pub symbol: Symbol,
+    pub side: OrderSide,
+    pub qty: f64,
+    pub price: f64,
+    pub fee: f64,
+    pub fee_currency: Option<String>,
+    pub time: i64,
+}
+
+/// <b>OrderState</b> ордер, как его видит `OrderGateway`: запрос и последнее известное состояние
+#[derive(Debug, Clone, Serialize)]
+pub struct OrderState {
+    pub request: OrderRequest,
+    pub order_id: Option<String>,
+    pub status: OrderStatus,
+    pub filled_qty: f64,
+    pub avg_price: Option<f64>,
+    /// Комиссии исполнений по валютам
+    pub fees: BTreeMap<String, f64>,
+    /// Причина отказа биржи
+    pub error: Option<String>,
+    pub created_at: i64,
+    pub updated_at: i64,
+}
+
+impl OrderState {
+    pub fn new(
+        request: OrderRequest
+    ) -> Self {
+        let now = clock::now_ms();
+        Self {
+            request,
+            order_id: None,
+            status: OrderStatus::Pending,
+            filled_qty: 0.0,
+            avg_price: None,
+            fees: BTreeMap::new(),
+            error: None,
+            created_at: now,
+            updated_at: now,
+        }
+    }
+
+    /// Обновления REST и приватного потока приходят в любом порядке,
+    /// поэтому финальный статус и исполненный объём не откатываются назад
+    pub fn apply(
+        &mut self,
+        update: &OrderUpdate
+    ) -> bool {
+        if self.status.is_final() && (!update.status.is_final() || update.filled_qty <= self.filled_qty) {
+            return false;
+        }
+
+        if update.filled_qty < self.filled_qty || (update.filled_qty == self.filled_qty && update.status == self.status) {
+            return false;
+        }
+
+        if update.order_id.is_some() {
+            self.order_id = update.order_id.clone();
+        }
+        self.status = update.status;
+        self.filled_qty = update.filled_qty;
+        self.avg_price = update.avg_price.or(self.avg_price);
+        self.updated_at = clock::now_ms();
+        true
+    }
+
+    /// Комиссии в котируемой валюте символа, базовая пересчитывается по `price`.
+    /// Сторонние валюты (`GT`, `BNB`) пересчитать не по чему, они возвращаются отдельно
+    pub fn fee_in_quote(
+        &self,
+        price: f64
+    ) -> (f64, Vec<(String, f64)>) {
+        let (base, quote) = split_symbol(&self.request.symbol).unzip();
+        let mut total = 0.0;
+        let mut other = Vec::new();
+
+        for (currency, fee) in &self.fees {
+            if quote.as_ref() == Some(currency) {
+                total += fee;
+            } else if base.as_ref() == Some(currency) {
+                total += fee * price;
+            } else {
+                other.push((currency.clone(), *fee));
+            }
+        }
+
+        (total, other)
+    }
+
+    #[allow(unused)]
+    pub fn remaining_qty(&self) -> f64 {
+        (self.request.qty - self.filled_qty).max(0.0)
+    }
+}
@@ -1,7 +1,7 @@
 use std::{collections::{BTreeMap}};
 use rust_decimal::Decimal;
 use serde::{Deserialize, Serialize};
-use crate::models::websocket::Symbol;
+use crate::{models::websocket::Symbol, services::clock};
 
 #[derive(Deserialize, Debug, Serialize)]
 pub struct OrderBookEvent<'a> {
@@ -30,6 +30,72 @@ pub struct Snapshot {
     pub last_update_id: Option<u64>,
 }
 
+/// Исполнение заявки по уровням стакана
+pub struct Fill {
+    /// Средняя цена исполнения
+    pub price: f64,
+    pub best: f64,
+    /// Последний задетый уровень: лимитная цена, по которой заявка исполнится целиком
+    pub worst: f64,
+    /// Объёма стакана хватило
+    pub complete: bool,
+}
+
+impl Snapshot {
+    /// Покупка `qty` по ask от лучшей цены
+    pub fn buy(
+        &self,
+        qty: f64
+    ) -> Option<Fill> {
+        Self::walk(self.a.iter(), qty)
+    }
+
+    /// Продажа `qty` по bid от лучшей цены
+    pub fn sell(
+        &self,
+        qty: f64
+    ) -> Option<Fill> {
+        Self::walk(self.b.iter().rev(), qty)
+    }
+
+    /// Проход по уровням от лучшей цены. Нехватка объёма исполняется по худшему уровню
+    fn walk<'a>(
+        levels: impl Iterator<Item = (&'a Decimal, &'a f64)>,
+        qty: f64
+    ) -> Option<Fill> {
+        let mut best = None;
+        let mut worst = 0.0;
+        let mut left = qty;
+        let mut cost = 0.0;
+
+        for (price, volume) in levels {
+            let price = price.as_f64();
+            best.get_or_insert(price);
+            worst = price;
+
+            let take = left.min(*volume);
+            cost += take * price;
+            left -= take;
+            if left <= 0.0 {
+                break;
+            }
+        }
+
+        let best = best?;
+        let complete = left <= 0.0;
+        if !complete {
+            cost += left * worst;
+        }
+
+        Some(Fill {
+            price: cost / qty,
+            best,
+            worst,
+            complete,
+        })
+    }
+}
+
 #[derive(Debug, Clone, Serialize)]
 pub struct Delta {
     pub a: BTreeMap<Decimal, f64>,
@@ -45,20 +111,60 @@ pub struct SnapshotUi {
     pub last_price: f64,
 }
 
+/// Время события в unix мс: биржевое время и локальное время получения
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq, Eq, Hash)]
+pub struct EventTime {
+    pub exchange_ts: Option<i64>,
+    pub received_at: i64,
+}
+
+impl EventTime {
+    /// Событие, полученное сейчас. При воспроизведении - время получения из записи
+    pub fn received(
+        exchange_ts: Option<i64>
+    ) -> Self {
+        Self {
+            exchange_ts,
+            received_at: clock::now_ms(),
+        }
+    }
+
+    /// Задержка от биржи до получения
+    pub fn feed_latency(&self) -> Option<i64> {
+        self.exchange_ts.map(|exchange_ts| self.received_at - exchange_ts)
+    }
+
+    /// Время для сравнения с другой биржей: биржевое, если биржа его прислала
+    pub fn event_ts(&self) -> i64 {
+        self.exchange_ts.unwrap_or(self.received_at)
+    }
+
+    /// Разница во времени двух ног спреда в мс
+    pub fn skew(
+        &self,
+        other: &EventTime
+    ) -> i64 {
+        (self.event_ts() - other.event_ts()).abs()
+    }
+}
+
 #[derive(Debug, Clone)]
 pub enum BookEvent {
     Snapshot { 
         symbol: Symbol,
         snapshot: Snapshot,
+        time: EventTime,
     },
     Delta { 
         symbol: Symbol, 
-        delta: Delta 
+        delta: Delta,
+        time: EventTime,
     },
     TickerUpdate {
         symbol: Symbol,
         last_price: f64,
         volume: f64,
+        time: EventTime,
     }
 }
 
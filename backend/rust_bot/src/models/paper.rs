@@ -0,0 +1,220 @@
+use serde::{Deserialize, Serialize};
+use sqlx::{Row, postgres::PgRow, prelude::FromRow};
+
+use crate::models::{exchange::ExchangeType, websocket::{ChannelType, Symbol}};
+
+pub type PositionId = i64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all="snake_case")]
+pub enum PaperStatus {
+    Open,
+    Closed,
+}
+
+impl PaperStatus {
+    /// Значение, под которым статус хранится в Postgres
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Open => "open",
+            Self::Closed => "closed",
+        }
+    }
+
+    pub fn parse(
+        value: &str
+    ) -> Option<Self> {
+        match value {
+            "open" => Some(Self::Open),
+            "closed" => Some(Self::Closed),
+            _ => None
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all="snake_case")]
+pub enum CloseReason {
+    Manual,
+    TakeProfit,
+    StopLoss,
+}
+
+impl CloseReason {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Manual => "manual",
+            Self::TakeProfit => "take_profit",
+            Self::StopLoss => "stop_loss",
+        }
+    }
+
+    pub fn parse(
+        value: &str
+    ) -> Option<Self> {
+        match value {
+            "manual" => Some(Self::Manual),
+            "take_profit" => Some(Self::TakeProfit),
+            "stop_loss" => Some(Self::StopLoss),
+            _ => None
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all="snake_case")]
+pub enum FillSide {
+    Buy,
+    Sell,
+}
+
+impl FillSide {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Buy => "buy",
+            Self::Sell => "sell",
+        }
+    }
+}
+
+/// Оценка открытой позиции по текущим стаканам, в Postgres не хранится
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct PaperMark {
+    /// Спред закрытия в %, по ценам, по которым позиция закрылась бы сейчас
+    pub exit_spread_pct: f64,
+    /// PnL закрытия сейчас с комиссиями входа и выхода
+    pub unrealized_pnl: f64,
+    pub updated_at: i64,
+}
+
+/// <b>PaperPosition</b> бумажная арбитражная позиция: покупка `qty` на `long_exchange` и продажа на `short_exchange`.
+/// Суммы в котируемой валюте, время в мс Unix
+#[derive(Debug, Clone, Serialize)]
+pub struct PaperPosition {
+    pub id: PositionId,
+    pub user_id: String,
+    pub symbol: Symbol,
+    pub long_exchange: ExchangeType,
+    pub short_exchange: ExchangeType,
+    pub status: PaperStatus,
+    /// Объём каждой ноги по лучшей цене long биржи при открытии
+    pub notional: f64,
+    pub qty: f64,
+    /// Средние цены исполнения ног
+    pub long_entry: f64,
+    pub short_entry: f64,
+    pub entry_spread_pct: f64,
+    /// Правила закрытия в % от `notional` по PnL
+    pub take_profit_pct: Option<f64>,
+    pub stop_loss_pct: Option<f64>,
+    /// Комиссии входа, после закрытия - входа и выхода
+    pub fees: f64,
+    pub opened_at: i64,
+    pub closed_at: Option<i64>,
+    pub long_exit: Option<f64>,
+    pub short_exit: Option<f64>,
+    pub exit_spread_pct: Option<f64>,
+    pub realized_pnl: Option<f64>,
+    pub close_reason: Option<CloseReason>,
+
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub mark: Option<PaperMark>,
+}
+
+impl<'r> FromRow<'r, PgRow> for PaperPosition {
+    fn from_row(
+        row: &'r PgRow
+    ) -> Result<Self, sqlx::Error> {
+        let status: String = row.try_get("status")?;
+        let close_reason: Option<String> = row.try_get("close_reason")?;
+
+        Ok(Self {
+            id: row.try_get("id")?,
+            user_id: row.try_get("user_id")?,
+            symbol: row.try_get("symbol")?,
+            long_exchange: row.try_get("long_exchange")?,
+            short_exchange: row.try_get("short_exchange")?,
+            status: PaperStatus::parse(&status).ok_or_else(|| sqlx::Error::Decode(format!("неизвестный статус {status}").into()))?,
+            notional: row.try_get("notional")?,
+            qty: row.try_get("qty")?,
+            long_entry: row.try_get("long_entry")?,
+            short_entry: row.try_get("short_entry")?,
+            entry_spread_pct: row.try_get("entry_spread_pct")?,
+            take_profit_pct: row.try_get("take_profit_pct")?,
+            stop_loss_pct: row.try_get("stop_loss_pct")?,
+            fees: row.try_get("fees")?,
+            opened_at: row.try_get("opened_at")?,
+            closed_at: row.try_get("closed_at")?,
+            long_exit: row.try_get("long_exit")?,
+            short_exit: row.try_get("short_exit")?,
+            exit_spread_pct: row.try_get("exit_spread_pct")?,
+            realized_pnl: row.try_get("realized_pnl")?,
+            close_reason: close_reason.as_deref().and_then(CloseReason::parse),
+
+            mark: None,
+        })
+    }
+}
+
+/// Исполнение одной ноги позиции
+#[derive(Debug, Clone, Serialize)]
+pub struct PaperFill {
+    pub position_id: PositionId,
+    pub exchange: ExchangeType,
+    pub side: FillSide,
+    pub qty: f64,
+    /// Средняя цена по уровням стакана
+    pub price: f64,
+    pub best_price: f64,
+    pub fee: f64,
+    pub timestamp: i64,
+}
+
+/// Запрос клиента в канале `paper`.
+/// `{"channel":"paper","action":"open","userId":"...","longExchange":"bybit","shortExchange":"gate.io","ticker":"btc","notional":100}`
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all="camelCase")]
+pub struct PaperMessage {
+    pub channel: ChannelType,
+    pub user_id: String,
+    #[serde(flatten)]
+    pub request: PaperRequest,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag="action", rename_all="snake_case", rename_all_fields="camelCase")]
+pub enum PaperRequest {
+    /// Позиции пользователя и их обновления
+    Subscribe,
+    Open {
+        long_exchange: ExchangeType,
+        short_exchange: ExchangeType,
+        ticker: Symbol,
+        notional: f64,
+        take_profit_pct: Option<f64>,
+        stop_loss_pct: Option<f64>,
+    },
+    Close {
+        position_id: PositionId,
+    },
+}
+
+/// Сообщение клиенту в канале `paper`
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag="event", rename_all="snake_case")]
+pub enum PaperEvent {
+    /// Ответ на `subscribe`: открытые и последние закрытые позиции
+    Positions {
+        positions: Vec<PaperPosition>
+    },
+    /// Позиция открыта, переоценена или закрыта
+    Position {
+        position: Box<PaperPosition>
+    },
+    Fill {
+        fill: PaperFill
+    },
+    Error {
+        message: String
+    },
+}
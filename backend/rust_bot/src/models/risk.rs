@@ -0,0 +1,195 @@
+use std::fmt;
+use serde::{Deserialize, Serialize};
+
+use crate::models::{exchange::ExchangeType, order::ClientOrderId, websocket::Symbol};
+
+/// Предторговая проверка, по которой отклонён ордер
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all="snake_case")]
+pub enum RiskCheck {
+    /// Торговля выключена kill switch
+    KillSwitch,
+    /// Убыток исполнений за сутки UTC достиг `risk.max_daily_loss`
+    DailyLoss,
+    /// Незавершённых ордеров уже `risk.max_open_legs`
+    OpenLegs,
+    /// Стакан биржи ордера старше `risk.max_book_age_ms` или его нет
+    StaleBook,
+    /// Сумма ордера больше `risk.max_order_notional`
+    OrderNotional,
+    /// Сумма ордера вместе с незавершёнными ордерами символа больше лимита символа
+    SymbolNotional,
+    /// Сумма ордера вместе с незавершёнными ордерами биржи больше лимита биржи
+    ExchangeNotional,
+    /// Цена дальше `risk.price_band_pct` от средней середины стаканов бирж
+    PriceBand,
+}
+
+impl RiskCheck {
+    /// Значение, под которым проверка хранится в Postgres
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::KillSwitch => "kill_switch",
+            Self::DailyLoss => "daily_loss",
+            Self::OpenLegs => "open_legs",
+            Self::StaleBook => "stale_book",
+            Self::OrderNotional => "order_notional",
+            Self::SymbolNotional => "symbol_notional",
+            Self::ExchangeNotional => "exchange_notional",
+            Self::PriceBand => "price_band",
+        }
+    }
+
+    /// Сумма или цена вне лимита говорят об ошибке в источнике ордеров, а не о состоянии рынка.
+    /// Лимиты символа и биржи упираются в уже открытые ордера, поэтому к ним не относятся
+    pub fn is_fat_finger(&self) -> bool {
+        matches!(self, Self::OrderNotional | Self::PriceBand)
+    }
+}
+
+/// Незавершённые ордера `OrderGateway` на момент проверки, без проверяемого
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Exposure {
+    pub open_legs: usize,
+    /// Неисполненная сумма ордеров на символ проверяемого по всем биржам
+    pub symbol_notional: f64,
+    /// Неисполненная сумма ордеров на бирже проверяемого по всем символам
+    pub exchange_notional: f64,
+}
+
+/// Что выключает kill switch: всю торговлю или одну биржу
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all="snake_case")]
+pub enum KillScope {
+    Global,
+    Exchange(ExchangeType),
+}
+
+impl KillScope {
+    /// `global` или имя биржи из конфига (`bybit`, `gate.io`)
+    pub fn key(&self) -> String {
+        match self {
+            Self::Global => "global".into(),
+            Self::Exchange(exchange_id) => serde_json::to_value(exchange_id)
+                .ok()
+                .and_then(|value| value.as_str().map(String::from))
+                .unwrap_or_else(|| exchange_id.to_string()),
+        }
+    }
+
+    pub fn parse(
+        value: &str
+    ) -> Option<Self> {
+        if value == "global" {
+            return Some(Self::Global);
+        }
+        serde_json::from_value(serde_json::Value::String(value.into())).ok().map(Self::Exchange)
+    }
+
+    pub fn covers(
+        &self,
+        exchange_id: ExchangeType
+    ) -> bool {
+        match self {
+            Self::Global => true,
+            Self::Exchange(scope) => *scope == exchange_id,
+        }
+    }
+}
+
+impl fmt::Display for KillScope {
+    fn fmt(
+        &self,
+        f: &mut fmt::Formatter<'_>
+    ) -> fmt::Result {
+        f.write_str(&self.key())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all="snake_case")]
+pub enum KillSource {
+    Admin,
+    /// Включён нарушением лимита
+    Breach,
+}
+
+impl KillSource {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Admin => "admin",
+            Self::Breach => "breach",
+        }
+    }
+
+    pub fn parse(
+        value: &str
+    ) -> Option<Self> {
+        match value {
+            "admin" => Some(Self::Admin),
+            "breach" => Some(Self::Breach),
+            _ => None
+        }
+    }
+}
+
+/// Включённый kill switch. Выключается только из админки
+#[derive(Debug, Clone, Serialize)]
+pub struct KillSwitch {
+    pub scope: KillScope,
+    pub reason: String,
+    pub source: KillSource,
+    pub activated_at: i64,
+}
+
+/// Включённые kill switch, публикуются `RiskManager`
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct KillSwitches {
+    pub active: Vec<KillSwitch>,
+}
+
+impl KillSwitches {
+    /// Switch, который запрещает ордера на бирже
+    pub fn blocking(
+        &self,
+        exchange_id: ExchangeType
+    ) -> Option<&KillSwitch> {
+        self.active.iter().find(|switch| switch.scope.covers(exchange_id))
+    }
+}
+
+/// <b>RiskBreach</b> нарушение лимита. Поля ордера пусты у нарушений вне ордера, например по убытку
+/// после исполнения. `value` и `limit` в единицах проверки: суммы, мс, % или количество ордеров
+#[derive(Debug, Clone, Serialize)]
+pub struct RiskBreach {
+    pub check: RiskCheck,
+    pub exchange_id: Option<ExchangeType>,
+    pub symbol: Option<Symbol>,
+    pub client_order_id: Option<ClientOrderId>,
+    pub value: f64,
+    pub limit: f64,
+    pub message: String,
+    /// Kill switch, включённый этим нарушением
+    pub killed: Option<KillScope>,
+    pub time: i64,
+}
+
+impl fmt::Display for RiskBreach {
+    fn fmt(
+        &self,
+        f: &mut fmt::Formatter<'_>
+    ) -> fmt::Result {
+        write!(f, "{}: {}", self.check.as_str(), self.message)
+    }
+}
+
+/// Состояние риск-проверок для админки
+#[derive(Debug, Clone, Serialize)]
+pub struct RiskStatus {
+    pub kill_switches: Vec<KillSwitch>,
+    /// Результат исполнений за сутки UTC
+    pub daily_pnl: f64,
+    pub max_daily_loss: f64,
+    /// Последние нарушения, новые первыми
+    pub breaches: Vec<RiskBreach>,
+}
@@ -9,8 +9,34 @@ use crate::models::{aggregator::{JsonPairData, JsonPairUniqueId, KeyMarketType},
 pub type ClientId = Uuid;
 pub type Symbol = String;
 
+/// Символ в общем формате `btcusdt`: без разделителей `_`, `-`, `/` и в нижнем регистре
+pub fn normalize_symbol(
+    symbol: &str
+) -> Symbol {
+    symbol
+        .chars()
+        .filter(|c| *c != '_' && *c != '-' && *c != '/')
+        .map(|c| c.to_ascii_lowercase())
+        .collect()
+}
+
+/// Команда соединению биржи. Подписки по `key` и тикеры восстанавливаются после переподключения
 pub enum WsCmd {
-    Subscribe(Vec<Message>)
+    Subscribe {
+        key: String,
+        messages: Vec<Message>
+    },
+    Unsubscribe {
+        key: String,
+        messages: Vec<Message>
+    },
+    /// Символы биржи, сообщения строит соединение через `ExchangeAdapter`
+    SubscribeTickers {
+        symbols: Vec<Arc<Symbol>>
+    },
+    UnsubscribeTickers {
+        symbols: Vec<Arc<Symbol>>
+    }
 }
 
 #[derive(Deserialize, Debug, Clone)]
@@ -35,6 +61,12 @@ pub enum ChartEvent {
 pub enum ChannelType {
     OrderBook,
     Chart,
+    /// Статусы бирж и символа, см. `HealthReport`
+    Health,
+    /// Бумажная торговля, см. `PaperMessage`
+    Paper,
+    /// Исполнимые спреды символа с остатками бирж, см. `ExecutableSpreads`
+    Opportunities,
     Unknown
 }
 
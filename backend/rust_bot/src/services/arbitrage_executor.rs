@@ -0,0 +1,670 @@
+use std::{collections::{HashMap, HashSet, VecDeque}, sync::Arc, time::Duration};
+use async_trait::async_trait;
+use tokio::{sync::{broadcast, mpsc, oneshot}, task::JoinSet, time::Instant};
+use tokio_util::sync::CancellationToken;
+use tracing::info;
+
+use crate::{config::{self, ExecutionConfig}, models::{aggregator::{ExecutableSpread, ExecutableSpreads, KeyMarketType}, exchange::ExchangeType, execution::{Decision, Execution, ExecutionId, ExecutionStatus, JournalEntry}, order::{OrderRequest, OrderSide, OrderState, OrderType, TimeInForce, new_client_order_id}}, services::{clock, data_access_layer::DataAccessLayerCmd, exchange::trading_adapter::TradingError, metrics::Metric, order_gateway::{OrderEvent, OrderGatewayHandle}, risk_manager::RiskHandle, supervisor::Actor}, storage::execution_storage};
+
+const EXECUTOR_NAME: &str = "ArbitrageExecutor";
+
+/// Сколько последних исполнений отдаёт `executions`
+const RECENT_LIMIT: usize = 100;
+/// Как часто подписки на стаканы сверяются с `execution.pairs`
+const PAIRS_INTERVAL: Duration = Duration::from_secs(1);
+/// Сколько при остановке ждать, пока идущие исполнения откатят разницу ног
+const SHUTDOWN_GRACE: Duration = Duration::from_secs(5);
+
+pub enum ExecutorCmd {
+    Executions {
+        reply: oneshot::Sender<Vec<Execution>>
+    },
+}
+
+#[derive(Clone)]
+pub struct ExecutorHandle {
+    tx: mpsc::Sender<ExecutorCmd>,
+}
+
+impl ExecutorHandle {
+    /// Идущие и последние завершённые исполнения с журналами, новые первыми
+    pub async fn executions(&self) -> Vec<Execution> {
+        let (reply, rx) = oneshot::channel();
+        self.tx.send(ExecutorCmd::Executions { reply }).await.ok();
+        rx.await.unwrap_or_default()
+    }
+}
+
+/// <b>ArbitrageExecutor</b> исполняет исполнимые спреды `DataAggregator` выше порога пары из `execution.pairs`.
+///
+/// <br>• Обе ноги отправляются одновременно IOC ордерами по последним задетым уровням стаканов
+/// <br>• Если одна нога исполнилась больше другой, недостающая догоняется IOC ордерами с ценой
+/// не хуже плана на `slippage_bps`, после `chase_attempts` лишний объём откатывается рыночным ордером
+/// `reduce_only`, который kill switch и лимит незавершённых ордеров не держат
+/// <br>• Каждое решение пишется в `storage.execution_journal`, итог - в `storage.executions`
+///
+/// Спреды, на которые по `InventoryTracker` не хватает остатков или биржа ноги выключена kill switch,
+/// пропускаются. Итог завершённых исполнений идёт в суточный убыток `RiskManager`.
+/// По паре идёт не больше одного исполнения, после него пара ждёт `cooldown_ms`.
+/// При остановке новые исполнения не начинаются, а идущие отменяют ноги, не догоняют и откатывают
+/// разницу ног. Не успевшие за `SHUTDOWN_GRACE` обрываются и помечаются `interrupted`.
+/// `OrderGateway` и `RiskManager` поэтому останавливаются после исполнителя
+pub struct ArbitrageExecutor {
+    rx: mpsc::Receiver<ExecutorCmd>,
+    spreads: broadcast::Sender<Arc<ExecutableSpreads>>,
+    orders: OrderGatewayHandle,
+    risk: RiskHandle,
+    data_access_layer_tx: mpsc::Sender<DataAccessLayerCmd>,
+    pool: Option<sqlx::PgPool>,
+
+    updates_tx: mpsc::Sender<Execution>,
+    updates_rx: mpsc::Receiver<Execution>,
+    running: JoinSet<Execution>,
+    /// Пара каждого идущего исполнения
+    busy: HashMap<tokio::task::Id, KeyMarketType>,
+    cooldown: HashMap<KeyMarketType, Instant>,
+    recent: VecDeque<Execution>,
+    /// Пары, стаканы которых запрошены у `DataAccessLayer`
+    books: HashSet<KeyMarketType>,
+    next_id: ExecutionId,
+    loaded: bool,
+
+    started: Arc<Metric>,
+}
+
+impl ArbitrageExecutor {
+    pub fn new(
+        spreads: broadcast::Sender<Arc<ExecutableSpreads>>,
+        orders: OrderGatewayHandle,
+        risk: RiskHandle,
+        data_access_layer_tx: mpsc::Sender<DataAccessLayerCmd>,
+        pool: Option<sqlx::PgPool>
+    ) -> (Self, ExecutorHandle) {
+        let (tx, rx) = mpsc::channel(64);
+        let (updates_tx, updates_rx) = mpsc::channel(256);
+
+        let this = Self {
+            rx,
+            spreads,
+            orders,
+            risk,
+            data_access_layer_tx,
+            pool,
+
+            updates_tx,
+            updates_rx,
+            running: JoinSet::new(),
+            busy: HashMap::new(),
+            cooldown: HashMap::new(),
+            recent: VecDeque::new(),
+            books: HashSet::new(),
+            next_id: 0,
+            loaded: false,
+
+            started: Metric::counter("executions_started_total", "Начатые исполнения арбитража", &[]),
+        };
+
+        (this, ExecutorHandle { tx })
+    }
+}
+
+#[async_trait]
+impl Actor for ArbitrageExecutor {
+    async fn run(
+        &mut self,
+        shutdown: CancellationToken
+    ) {
+        if !self.loaded {
+            self.load().await;
+        }
+
+        let mut spreads = self.spreads.subscribe();
+        let mut pairs = tokio::time::interval(PAIRS_INTERVAL);
+
+        info!("{} -> is running", EXECUTOR_NAME);
+
+        loop {
+            tokio::select! {
+                _ = shutdown.cancelled() => break,
+                Some(cmd) = self.rx.recv() => self.handle_cmd(cmd),
+                spread = spreads.recv() => match spread {
+                    Ok(batch) => {
+                        for spread in &batch.spreads {
+                            self.on_spread(spread.clone(), &shutdown);
+                        }
+                    },
+                    // Пропущенные спреды уже устарели
+                    Err(broadcast::error::RecvError::Lagged(skipped)) => tracing::debug!("{} -> пропущено спредов: {skipped}", EXECUTOR_NAME),
+                    Err(broadcast::error::RecvError::Closed) => break,
+                },
+                Some(execution) = self.updates_rx.recv() => self.remember(execution),
+                Some(result) = self.running.join_next_with_id() => self.on_finished(result).await,
+                _ = pairs.tick() => self.sync_books().await,
+            }
+        }
+
+        // Идущие исполнения видят ту же отмену и доводят откат до конца
+        if !self.running.is_empty() {
+            info!("{} -> остановка, ждём исполнений: {}", EXECUTOR_NAME, self.running.len());
+            let deadline = Instant::now() + SHUTDOWN_GRACE;
+            while !self.running.is_empty() {
+                tokio::select! {
+                    Some(result) = self.running.join_next_with_id() => self.on_finished(result).await,
+                    _ = tokio::time::sleep_until(deadline) => break,
+                }
+            }
+        }
+
+        // Ноги оборванных исполнений могли остаться неравными, это видно по статусу
+        if !self.running.is_empty() {
+            tracing::warn!("{} -> прервано исполнений: {}", EXECUTOR_NAME, self.running.len());
+            self.running.shutdown().await;
+            self.busy.clear();
+            if let Err(e) = execution_storage::interrupt_running(&self.pool).await {
+                tracing::error!("{} -> прерванные исполнения: {e}", EXECUTOR_NAME);
+            }
+        }
+    }
+}
+
+impl ArbitrageExecutor {
+    async fn load(
+        &mut self
+    ) {
+        match execution_storage::get_max_execution_id(&self.pool).await {
+            Ok(id) => self.next_id = id,
+            Err(e) => tracing::error!("{} -> id исполнений: {e}", EXECUTOR_NAME),
+        }
+
+        match execution_storage::interrupt_running(&self.pool).await {
+            Ok(0) => {},
+            Ok(count) => tracing::warn!("{} -> исполнений, оборванных прошлым запуском: {count}", EXECUTOR_NAME),
+            Err(e) => tracing::error!("{} -> прерванные исполнения: {e}", EXECUTOR_NAME),
+        }
+
+        self.loaded = true;
+    }
+
+    fn handle_cmd(
+        &mut self,
+        cmd: ExecutorCmd
+    ) {
+        match cmd {
+            ExecutorCmd::Executions {
+                reply
+            } => {
+                reply.send(self.recent.iter().cloned().collect()).ok();
+            },
+        }
+    }
+
+    fn on_spread(
+        &mut self,
+        spread: Arc<ExecutableSpread>,
+        shutdown: &CancellationToken
+    ) {
+        let config = config::get();
+        let settings = &config.execution;
+        // Без известных остатков решает биржа: ордер без средств она отклонит
+        if !settings.enabled || spread.skewed || spread.inventory.is_some_and(|fit| !fit.executable) {
+            return;
+        }
+
+        let Some(pair) = settings.pair(&spread.key) else { return };
+        if spread.spread_pct < pair.threshold_pct || self.busy.values().any(|key| *key == spread.key) {
+            return;
+        }
+
+        if self.cooldown.get(&spread.key).is_some_and(|until| Instant::now() < *until) {
+            return;
+        }
+
+        if self.risk.is_blocked(spread.key.long_exchange) || self.risk.is_blocked(spread.key.short_exchange) {
+            return;
+        }
+
+        self.next_id += 1;
+        let execution = Execution {
+            id: self.next_id,
+            symbol: spread.key.symbol.to_string(),
+            long_exchange: spread.key.long_exchange,
+            short_exchange: spread.key.short_exchange,
+            status: ExecutionStatus::Running,
+            threshold_pct: pair.threshold_pct,
+            spread_pct: spread.spread_pct,
+            qty: spread.qty,
+            buy_price: spread.buy_price,
+            sell_price: spread.sell_price,
+            long_qty: 0.0,
+            short_qty: 0.0,
+            cash_flow: 0.0,
+            fees: 0.0,
+            started_at: clock::now_ms(),
+            finished_at: None,
+            journal: Vec::new(),
+        };
+
+        let run = ExecutionRun {
+            execution,
+            plan: spread.clone(),
+            settings: settings.clone(),
+            orders: self.orders.clone(),
+            pool: self.pool.clone(),
+            updates: self.updates_tx.clone(),
+            stop: shutdown.clone(),
+            rejected: HashSet::new(),
+            risk_rejected: HashSet::new(),
+            filled: 0.0,
+            unwound: false,
+        };
+
+        let task = self.running.spawn(run.run());
+        self.busy.insert(task.id(), spread.key.clone());
+        self.started.inc();
+    }
+
+    async fn on_finished(
+        &mut self,
+        result: Result<(tokio::task::Id, Execution), tokio::task::JoinError>
+    ) {
+        let (id, execution) = match result {
+            Ok((id, execution)) => (id, Some(execution)),
+            Err(e) => {
+                tracing::error!("{} -> исполнение упало: {e}", EXECUTOR_NAME);
+                (e.id(), None)
+            }
+        };
+
+        if let Some(key) = self.busy.remove(&id) {
+            self.cooldown.insert(key, Instant::now() + config::get().execution.cooldown());
+        }
+
+        if let Some(execution) = execution {
+            Metric::counter("executions_finished_total", "Завершённые исполнения арбитража по итогу", &[("status", execution.status.as_str())]).inc();
+            if matches!(execution.status, ExecutionStatus::Hedged | ExecutionStatus::Unwound) {
+                self.risk.realized(execution.cash_flow).await;
+            }
+            self.remember(execution);
+        }
+    }
+
+    /// Последнее состояние исполнения для `executions`
+    fn remember(
+        &mut self,
+        execution: Execution
+    ) {
+        match self.recent.iter_mut().find(|recent| recent.id == execution.id) {
+            Some(recent) => *recent = execution,
+            None => {
+                self.recent.push_front(execution);
+                self.recent.truncate(RECENT_LIMIT);
+            },
+        }
+    }
+
+    /// Стаканы пар из конфига нужны `DataAggregator` для исполнимых спредов, пока исполнение включено
+    async fn sync_books(
+        &mut self
+    ) {
+        let config = config::get();
+        let wanted: HashSet<KeyMarketType> = if config.execution.enabled {
+            config.execution.pairs
+                .iter()
+                .map(|pair| KeyMarketType::new(pair.long_exchange, pair.short_exchange, Arc::new(pair.symbol.clone())))
+                .collect()
+        } else {
+            HashSet::new()
+        };
+
+        for key in wanted.difference(&self.books) {
+            for exchange_id in [key.long_exchange, key.short_exchange] {
+                self.data_access_layer_tx.send(DataAccessLayerCmd::AcquireBook { exchange_id, symbol: key.symbol.clone() }).await.ok();
+            }
+        }
+
+        for key in self.books.difference(&wanted) {
+            for exchange_id in [key.long_exchange, key.short_exchange] {
+                self.data_access_layer_tx.send(DataAccessLayerCmd::ReleaseBook { exchange_id, symbol: key.symbol.clone() }).await.ok();
+            }
+        }
+
+        self.books = wanted;
+
+        let now = Instant::now();
+        self.cooldown.retain(|_, until| *until > now);
+    }
+}
+
+/// Одно исполнение от входа до итога, работает отдельной задачей
+struct ExecutionRun {
+    execution: Execution,
+    plan: Arc<ExecutableSpread>,
+    settings: ExecutionConfig,
+    orders: OrderGatewayHandle,
+    pool: Option<sqlx::PgPool>,
+    updates: mpsc::Sender<Execution>,
+    /// Остановка исполнителя: ноги не ждут таймаута, догона нет, остаётся откат
+    stop: CancellationToken,
+    /// Биржи, отклонившие ордер не из-за сети: повтор на них бесполезен
+    rejected: HashSet<ExchangeType>,
+    /// Биржи, ордер на которые не пропустил `RiskManager`. Откат `reduce_only` проверки остановки не держат
+    risk_rejected: HashSet<ExchangeType>,
+    /// Исполнено по всем ордерам исполнения
+    filled: f64,
+    unwound: bool,
+}
+
+impl ExecutionRun {
+    async fn run(
+        mut self
+    ) -> Execution {
+        if let Err(e) = execution_storage::add_execution(&self.pool, &self.execution).await {
+            tracing::error!("{} -> исполнение #{}: {e}", EXECUTOR_NAME, self.execution.id);
+        }
+
+        let plan = self.plan.clone();
+        self.record(JournalEntry::note(Decision::Enter, format!(
+            "{} {}/{}: спред {:.4}% не ниже порога {}%, {} по {} / {}",
+            plan.key.symbol,
+            plan.key.long_exchange,
+            plan.key.short_exchange,
+            plan.spread_pct,
+            self.execution.threshold_pct,
+            plan.qty,
+            plan.buy_price,
+            plan.sell_price
+        ))).await;
+
+        // Остановка пришла раньше ног: ничего не отправлено
+        if self.stop.is_cancelled() {
+            return self.finish().await;
+        }
+
+        let buy = self.request(plan.key.long_exchange, OrderSide::Buy, OrderType::Limit, plan.qty, plan.buy_limit);
+        let sell = self.request(plan.key.short_exchange, OrderSide::Sell, OrderType::Limit, plan.qty, plan.sell_limit);
+        self.record(JournalEntry::order(Decision::Submit, &buy, "нога long".into())).await;
+        self.record(JournalEntry::order(Decision::Submit, &sell, "нога short".into())).await;
+
+        let timeout = self.settings.leg_timeout();
+        let (buy_result, sell_result) = tokio::join!(
+            execute_leg(&self.orders, buy.clone(), timeout, &self.stop),
+            execute_leg(&self.orders, sell.clone(), timeout, &self.stop)
+        );
+        self.settle(&buy, buy_result).await;
+        self.settle(&sell, sell_result).await;
+
+        self.rebalance().await;
+        self.finish().await
+    }
+
+    /// Догон недостающей ноги, затем откат лишнего объёма
+    async fn rebalance(
+        &mut self
+    ) {
+        let plan = self.plan.clone();
+        let slippage = self.settings.slippage_rate();
+        let mut attempt = 0;
+
+        while self.unbalanced() && attempt < self.settings.chase_attempts {
+            if self.stop.is_cancelled() {
+                self.record(JournalEntry::note(Decision::Chase, "остановка, догон пропущен".into())).await;
+                break;
+            }
+
+            attempt += 1;
+            let imbalance = self.execution.imbalance();
+            let (exchange_id, side, price) = if imbalance > 0.0 {
+                (plan.key.short_exchange, OrderSide::Sell, plan.sell_limit * (1.0 - slippage))
+            } else {
+                (plan.key.long_exchange, OrderSide::Buy, plan.buy_limit * (1.0 + slippage))
+            };
+
+            if self.rejected.contains(&exchange_id) || self.risk_rejected.contains(&exchange_id) {
+                self.record(JournalEntry::note(Decision::Chase, format!("{exchange_id} отклонила ордер, догон пропущен"))).await;
+                break;
+            }
+
+            let request = self.request(exchange_id, side, OrderType::Limit, imbalance.abs(), price);
+            self.record(JournalEntry::order(Decision::Chase, &request, format!(
+                "попытка {attempt} из {}: разница ног {:.8}, цена хуже плана не больше {} bps",
+                self.settings.chase_attempts,
+                imbalance,
+                self.settings.slippage_bps
+            ))).await;
+
+            let result = execute_leg(&self.orders, request.clone(), self.settings.leg_timeout(), &self.stop).await;
+            self.settle(&request, result).await;
+        }
+
+        if !self.unbalanced() {
+            return;
+        }
+
+        // Лишний объём закрывается на той же бирже, где исполнился
+        let imbalance = self.execution.imbalance();
+        let (exchange_id, side, estimate) = if imbalance > 0.0 {
+            (plan.key.long_exchange, OrderSide::Sell, plan.buy_price)
+        } else {
+            (plan.key.short_exchange, OrderSide::Buy, plan.sell_price)
+        };
+
+        if self.rejected.contains(&exchange_id) {
+            self.record(JournalEntry::note(Decision::Unwind, format!("{exchange_id} отклонила ордер, откат пропущен"))).await;
+            return;
+        }
+
+        let mut request = self.request(exchange_id, side, OrderType::Market, imbalance.abs(), estimate);
+        request.reduce_only = true;
+        self.record(JournalEntry::order(Decision::Unwind, &request, format!("догон не закрыл разницу ног {imbalance:.8}"))).await;
+
+        // Откат ждёт итога и при остановке
+        let result = execute_leg(&self.orders, request.clone(), self.settings.leg_timeout(), &CancellationToken::new()).await;
+        self.settle(&request, result).await;
+        self.unwound = true;
+    }
+
+    async fn finish(
+        mut self
+    ) -> Execution {
+        let status = if self.filled <= 0.0 {
+            ExecutionStatus::Missed
+        } else if self.unbalanced() {
+            ExecutionStatus::Unbalanced
+        } else if self.unwound {
+            ExecutionStatus::Unwound
+        } else {
+            ExecutionStatus::Hedged
+        };
+
+        if status == ExecutionStatus::Unbalanced {
+            self.record(JournalEntry::note(Decision::Abandon, format!(
+                "разница ног {:.8} остаётся, нужна ручная проверка",
+                self.execution.imbalance()
+            ))).await;
+        }
+
+        self.execution.status = status;
+        self.execution.finished_at = Some(clock::now_ms());
+        self.record(JournalEntry::note(Decision::Finish, format!(
+            "{}: long {}, short {}, денежный поток {:.6}, комиссии {:.6}",
+            status.as_str(),
+            self.execution.long_qty,
+            self.execution.short_qty,
+            self.execution.cash_flow,
+            self.execution.fees
+        ))).await;
+
+        if let Err(e) = execution_storage::finish_execution(&self.pool, &self.execution).await {
+            tracing::error!("{} -> исполнение #{}: {e}", EXECUTOR_NAME, self.execution.id);
+        }
+
+        self.execution
+    }
+
+    /// Итог ордера двигает объёмы ног и денежный поток
+    async fn settle(
+        &mut self,
+        request: &OrderRequest,
+        result: Result<OrderState, TradingError>
+    ) {
+        let state = match result {
+            Ok(state) => state,
+            Err(e) => {
+                if matches!(e, TradingError::Risk(_)) {
+                    self.risk_rejected.insert(request.exchange_id);
+                } else if !e.is_unknown_outcome() && !matches!(e, TradingError::RateLimited(_)) {
+                    self.rejected.insert(request.exchange_id);
+                }
+                self.record(JournalEntry::order(Decision::Result, request, format!("отказ: {e}"))).await;
+                return;
+            }
+        };
+
+        let price = state.avg_price.or(request.price).unwrap_or_default();
+        // Bybit берёт комиссию покупки в базовой валюте, в денежный поток она идёт по цене исполнения
+        let (fee, other_fees) = state.fee_in_quote(price);
+        let delta = match request.side {
+            OrderSide::Buy => state.filled_qty,
+            OrderSide::Sell => -state.filled_qty,
+        };
+
+        if request.exchange_id == self.plan.key.long_exchange {
+            self.execution.long_qty += delta;
+        } else {
+            self.execution.short_qty -= delta;
+        }
+        self.execution.cash_flow -= delta * price + fee;
+        self.execution.fees += fee;
+        self.filled += state.filled_qty;
+
+        let mut note = match (state.status.is_final(), &state.error) {
+            (true, _) => format!("{}: исполнено {} из {}", state.status.as_str(), state.filled_qty, request.qty),
+            (false, Some(error)) => format!("итог неизвестен ({error}), исполнено {} из {}", state.filled_qty, request.qty),
+            (false, None) => format!("не завершился за {:?}, исполнено {} из {}", self.settings.leg_timeout(), state.filled_qty, request.qty),
+        };
+
+        if !other_fees.is_empty() {
+            let fees: Vec<String> = other_fees.iter().map(|(currency, fee)| format!("{fee} {currency}")).collect();
+            note.push_str(&format!(", комиссия не пересчитана в котируемую валюту: {}", fees.join(", ")));
+        }
+
+        let mut entry = JournalEntry::order(Decision::Result, request, note);
+        entry.qty = Some(state.filled_qty);
+        entry.price = state.avg_price;
+        self.record(entry).await;
+    }
+
+    /// Разница ног больше `min_notional`
+    fn unbalanced(&self) -> bool {
+        let imbalance = self.execution.imbalance().abs();
+        imbalance > self.plan.qty * 1e-9 && imbalance * self.plan.buy_price >= self.settings.min_notional
+    }
+
+    fn request(
+        &self,
+        exchange_id: ExchangeType,
+        side: OrderSide,
+        order_type: OrderType,
+        qty: f64,
+        price: f64
+    ) -> OrderRequest {
+        OrderRequest {
+            exchange_id,
+            client_order_id: new_client_order_id(),
+            symbol: self.plan.key.symbol.to_string(),
+            side,
+            order_type,
+            qty,
+            // У рыночного ордера - оценка для покупки на Gate
+            price: Some(price),
+            time_in_force: TimeInForce::Ioc,
+            reduce_only: false,
+        }
+    }
+
+    async fn record(
+        &mut self,
+        entry: JournalEntry
+    ) {
+        match (&entry.exchange_id, &entry.side) {
+            (Some(exchange_id), Some(side)) => info!(
+                "{} -> #{} {} {} {} {} по {}: {}",
+                EXECUTOR_NAME,
+                self.execution.id,
+                entry.decision.as_str(),
+                exchange_id,
+                side.as_str(),
+                entry.qty.unwrap_or_default(),
+                entry.price.unwrap_or_default(),
+                entry.note
+            ),
+            _ => info!("{} -> #{} {}: {}", EXECUTOR_NAME, self.execution.id, entry.decision.as_str(), entry.note),
+        }
+
+        if let Err(e) = execution_storage::add_journal_entry(&self.pool, self.execution.id, &entry).await {
+            tracing::error!("{} -> журнал #{}: {e}", EXECUTOR_NAME, self.execution.id);
+        }
+
+        self.execution.journal.push(entry);
+        self.updates.try_send(self.execution.clone()).ok();
+    }
+}
+
+/// Ордер до завершения. IOC, который не завершился за `timeout` или до `stop`, отменяется, итог - состояние
+/// после отмены или, если отмена не прошла, после сверки с биржей
+async fn execute_leg(
+    orders: &OrderGatewayHandle,
+    request: OrderRequest,
+    timeout: Duration,
+    stop: &CancellationToken
+) -> Result<OrderState, TradingError> {
+    // Подписка до отправки, иначе быстрое исполнение можно пропустить
+    let mut events = orders.subscribe();
+    let client_order_id = request.client_order_id.clone();
+
+    let mut state = match orders.place(request).await {
+        Ok(state) => state,
+        // Ордер мог дойти до биржи, `OrderGateway` сверит его сам
+        Err(e) if e.is_unknown_outcome() => match orders.order(client_order_id.clone()).await {
+            Some(state) => state,
+            None => return Err(e),
+        },
+        Err(e) => return Err(e),
+    };
+
+    let expired = async {
+        tokio::select! {
+            _ = tokio::time::sleep(timeout) => {},
+            _ = stop.cancelled() => {},
+        }
+    };
+    tokio::pin!(expired);
+
+    while !state.status.is_final() {
+        tokio::select! {
+            event = events.recv() => match event {
+                Ok(OrderEvent::Order(update)) if update.request.client_order_id == client_order_id => state = update,
+                Ok(_) => {},
+                Err(broadcast::error::RecvError::Lagged(_)) => {
+                    if let Some(update) = orders.order(client_order_id.clone()).await {
+                        state = update;
+                    }
+                },
+                Err(broadcast::error::RecvError::Closed) => break,
+            },
+            _ = &mut expired => {
+                // Без отмены исполнения могли прийти после последнего события, итог берётся у биржи
+                let update = match orders.cancel(client_order_id.clone()).await {
+                    Ok(update) => Some(update),
+                    Err(_) => orders.sync(client_order_id.clone()).await,
+                };
+                if let Some(update) = update {
+                    state = update;
+                }
+                break;
+            }
+        }
+    }
+
+    Ok(state)
+}
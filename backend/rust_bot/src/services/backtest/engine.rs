@@ -0,0 +1,276 @@
+use std::{collections::HashMap, sync::Arc};
+use serde::Serialize;
+
+use crate::{models::{aggregator::KeyMarketType, exchange::ExchangeType, orderbook::Snapshot, websocket::Symbol}, services::backtest::strategy::{ExitReason, Strategy}};
+
+/// Спред пары бирж в момент времени
+#[derive(Debug, Clone)]
+pub struct SpreadSample {
+    pub key: KeyMarketType,
+    pub timestamp_ms: i64,
+    /// Спред открытия в %: покупка на long бирже по ask, продажа на short бирже по bid
+    pub open_pct: f64,
+    /// Спред закрытия в %: продажа на long бирже по bid, покупка на short бирже по ask.
+    /// Позиция зарабатывает `open_pct - close_pct`
+    pub close_pct: f64,
+    /// Стаканы long и short биржи. Без них сделка считается по спреду, без проскальзывания
+    pub books: Option<(Arc<Snapshot>, Arc<Snapshot>)>,
+}
+
+impl SpreadSample {
+    /// `None` - у одного из стаканов нет цен с нужной стороны
+    pub fn from_books(
+        key: KeyMarketType,
+        timestamp_ms: i64,
+        long: Arc<Snapshot>,
+        short: Arc<Snapshot>
+    ) -> Option<Self> {
+        let long_ask = long.a.keys().next()?.as_f64();
+        let long_bid = long.b.keys().next_back()?.as_f64();
+        let short_ask = short.a.keys().next()?.as_f64();
+        let short_bid = short.b.keys().next_back()?.as_f64();
+
+        let open_mid = (long_ask + short_bid) / 2.0;
+        let close_mid = (long_bid + short_ask) / 2.0;
+
+        Some(Self {
+            key,
+            timestamp_ms,
+            open_pct: (short_bid - long_ask) / open_mid * 100.0,
+            close_pct: (short_ask - long_bid) / close_mid * 100.0,
+            books: Some((long, short)),
+        })
+    }
+}
+
+/// Комиссии, объём и лимиты бэктеста
+#[derive(Debug, Clone, Serialize)]
+pub struct BacktestSettings {
+    /// Комиссия taker за одну сделку, доля от объёма
+    pub fee_rate: f64,
+    /// Объём каждой ноги в котируемой валюте
+    pub notional: f64,
+    /// Открытых позиций одновременно по всем парам. По одной паре позиция всегда одна
+    pub max_positions: usize,
+}
+
+/// Цены исполнения ног позиции, есть только при сделке по стаканам
+#[derive(Debug, Clone, Copy)]
+pub struct LegPrices {
+    pub qty: f64,
+    pub long: f64,
+    pub short: f64,
+}
+
+#[derive(Debug, Clone)]
+pub struct Position {
+    pub key: KeyMarketType,
+    pub opened_at_ms: i64,
+    pub open_pct: f64,
+    pub prices: Option<LegPrices>,
+    pub fees: f64,
+    pub slippage: f64,
+}
+
+/// Закрытая позиция. Суммы в котируемой валюте
+#[derive(Debug, Clone, Serialize)]
+pub struct Trade {
+    pub symbol: Arc<Symbol>,
+    pub long_exchange: ExchangeType,
+    pub short_exchange: ExchangeType,
+    pub opened_at_ms: i64,
+    pub closed_at_ms: i64,
+    pub open_pct: f64,
+    pub close_pct: f64,
+    /// Количество базовой валюты в каждой ноге, только при сделке по стаканам
+    pub qty: Option<f64>,
+    pub gross_pnl: f64,
+    pub fees: f64,
+    /// Потери от исполнения глубже лучшей цены, уже учтены в `gross_pnl`
+    pub slippage: f64,
+    pub net_pnl: f64,
+    pub exit_reason: ExitReason,
+}
+
+/// Входы, которые стратегия хотела, но бэктест не открыл
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Rejected {
+    /// Достигнут `max_positions`
+    pub position_limit: u64,
+    /// В стакане не хватило объёма на `notional`
+    pub depth: u64,
+}
+
+/// <b>Backtester</b> прогоняет сэмплы спредов через `Strategy` по порядку времени.
+///
+/// <br>• Вход: обе ноги на `notional`, по стаканам - проходом по уровням с долей комиссии за каждую ногу
+/// <br>• Выход: так же в обратную сторону. Если объёма стакана не хватает, остаток исполняется по худшему уровню
+/// <br>• Без стаканов (история `storage.lines`) результат считается как `(open_pct - close_pct)` от `notional`
+pub struct Backtester {
+    strategy: Box<dyn Strategy>,
+    pub settings: BacktestSettings,
+    positions: HashMap<KeyMarketType, Position>,
+    /// Последний сэмпл пары, по нему закрываются позиции в конце данных
+    last_samples: HashMap<KeyMarketType, SpreadSample>,
+
+    pub trades: Vec<Trade>,
+    pub rejected: Rejected,
+    pub samples: u64,
+}
+
+impl Backtester {
+    pub fn new(
+        strategy: Box<dyn Strategy>,
+        settings: BacktestSettings
+    ) -> Self {
+        Self {
+            strategy,
+            settings,
+            positions: HashMap::new(),
+            last_samples: HashMap::new(),
+
+            trades: Vec::new(),
+            rejected: Rejected::default(),
+            samples: 0,
+        }
+    }
+
+    pub fn strategy_name(&self) -> String {
+        self.strategy.name()
+    }
+
+    pub fn on_sample(
+        &mut self,
+        sample: SpreadSample
+    ) {
+        self.samples += 1;
+
+        match self.positions.get(&sample.key) {
+            Some(position) => {
+                if let Some(reason) = self.strategy.should_exit(position, &sample) {
+                    self.close(&sample, reason);
+                }
+            },
+            None => {
+                if self.strategy.should_enter(&sample) {
+                    self.open(&sample);
+                }
+            }
+        }
+
+        self.last_samples.insert(sample.key.clone(), sample);
+    }
+
+    /// Закрывает оставшиеся позиции по последним сэмплам пар
+    pub fn finish(
+        &mut self
+    ) {
+        let keys: Vec<KeyMarketType> = self.positions.keys().cloned().collect();
+        for key in keys {
+            if let Some(sample) = self.last_samples.get(&key).cloned() {
+                self.close(&sample, ExitReason::EndOfData);
+            }
+        }
+
+        self.trades.sort_by_key(|trade| trade.closed_at_ms);
+    }
+
+    fn open(
+        &mut self,
+        sample: &SpreadSample
+    ) {
+        if self.positions.len() >= self.settings.max_positions {
+            self.rejected.position_limit += 1;
+            return;
+        }
+
+        let notional = self.settings.notional;
+        let fee_rate = self.settings.fee_rate;
+
+        let position = match &sample.books {
+            Some((long, short)) => {
+                let Some(best_ask) = long.a.keys().next().map(|price| price.as_f64()) else { return };
+                let qty = notional / best_ask;
+
+                let (Some(long_fill), Some(short_fill)) = (
+                    long.buy(qty),
+                    short.sell(qty)
+                ) else { return };
+
+                if !long_fill.complete || !short_fill.complete {
+                    self.rejected.depth += 1;
+                    return;
+                }
+
+                Position {
+                    key: sample.key.clone(),
+                    opened_at_ms: sample.timestamp_ms,
+                    open_pct: sample.open_pct,
+                    prices: Some(LegPrices { qty, long: long_fill.price, short: short_fill.price }),
+                    fees: fee_rate * qty * (long_fill.price + short_fill.price),
+                    slippage: qty * (long_fill.price - long_fill.best) + qty * (short_fill.best - short_fill.price),
+                }
+            },
+            None => Position {
+                key: sample.key.clone(),
+                opened_at_ms: sample.timestamp_ms,
+                open_pct: sample.open_pct,
+                prices: None,
+                fees: fee_rate * notional * 2.0,
+                slippage: 0.0,
+            }
+        };
+
+        self.positions.insert(sample.key.clone(), position);
+    }
+
+    fn close(
+        &mut self,
+        sample: &SpreadSample,
+        exit_reason: ExitReason
+    ) {
+        let Some(position) = self.positions.remove(&sample.key) else { return };
+        let fee_rate = self.settings.fee_rate;
+
+        let exit = match (position.prices, &sample.books) {
+            (Some(prices), Some((long, short))) => {
+                match (
+                    long.sell(prices.qty),
+                    short.buy(prices.qty)
+                ) {
+                    (Some(long_fill), Some(short_fill)) => Some((
+                        prices.qty * (long_fill.price - prices.long) + prices.qty * (prices.short - short_fill.price),
+                        fee_rate * prices.qty * (long_fill.price + short_fill.price),
+                        prices.qty * (long_fill.best - long_fill.price) + prices.qty * (short_fill.price - short_fill.best),
+                    )),
+                    _ => None
+                }
+            },
+            _ => None
+        };
+
+        // Без стаканов закрытия - по спреду
+        let (gross_pnl, close_fees, close_slippage) = exit.unwrap_or_else(|| (
+            (position.open_pct - sample.close_pct) / 100.0 * self.settings.notional,
+            fee_rate * self.settings.notional * 2.0,
+            0.0
+        ));
+
+        let fees = position.fees + close_fees;
+        self.trades.push(Trade {
+            symbol: position.key.symbol.clone(),
+            long_exchange: position.key.long_exchange,
+            short_exchange: position.key.short_exchange,
+            opened_at_ms: position.opened_at_ms,
+            closed_at_ms: sample.timestamp_ms,
+            open_pct: position.open_pct,
+            close_pct: sample.close_pct,
+            qty: position.prices.map(|prices| prices.qty),
+            gross_pnl,
+            fees,
+            slippage: position.slippage + close_slippage,
+            net_pnl: gross_pnl - fees,
+            exit_reason,
+        });
+    }
+}
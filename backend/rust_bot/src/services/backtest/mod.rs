@@ -0,0 +1,170 @@
+pub mod engine;
+pub mod report;
+pub mod source;
+pub mod strategy;
+
+use std::{collections::HashMap, path::PathBuf, time::Duration};
+use anyhow::{Context, bail};
+use tracing::info;
+
+use crate::{config, models::exchange::ExchangeType, services::{backtest::{engine::{BacktestSettings, Backtester}, report::Report, source::PairFilter, strategy::ThresholdStrategy}, exchange::frame_replay::RecordedFrames}, storage};
+
+const USAGE: &str = "usage: rust_bot backtest lines --symbol <symbol> [--from <unix s>] [--to <unix s>] [options]
+       rust_bot backtest replay <файл .rbfr.gz или каталог>... [--symbol <symbol>] [options]
+
+options:
+    --long <exchange> --short <exchange>   только пары с этими биржами
+    --entry <pct>          вход, когда спред открытия не меньше (по умолчанию 0.5)
+    --exit <pct>           выход, когда спред закрытия не больше (по умолчанию 0.1)
+    --stop <pct>           выход, когда спред закрытия не меньше
+    --max-hold-mins <n>    выход через n минут
+    --fee-bps <bps>        комиссия taker за сделку (по умолчанию 10)
+    --notional <quote>     объём ноги (по умолчанию 1000)
+    --max-positions <n>    открытых позиций одновременно (по умолчанию 5)
+    --out <dir>            каталог для report.json и trades.csv (по умолчанию backtest)";
+
+/// Откуда берутся спреды
+#[derive(Debug, Clone)]
+pub enum BacktestSource {
+    /// `1m` история `storage.lines`
+    Lines {
+        from: i64,
+        to: i64,
+    },
+    /// Записи кадров `FrameRecorder`
+    Replay {
+        paths: Vec<PathBuf>,
+    },
+}
+
+/// Аргументы `rust_bot backtest`
+#[derive(Debug, Clone)]
+pub struct BacktestArgs {
+    pub source: BacktestSource,
+    pub filter: PairFilter,
+    pub strategy: ThresholdStrategy,
+    pub settings: BacktestSettings,
+    pub out: PathBuf,
+}
+
+impl BacktestArgs {
+    /// `None` - приложение запущено без подкоманды `backtest`
+    pub fn parse(
+        mut args: impl Iterator<Item = String>
+    ) -> anyhow::Result<Option<Self>> {
+        if args.next().as_deref() != Some("backtest") {
+            return Ok(None);
+        }
+
+        let kind = args.next().context(USAGE)?;
+
+        let mut flags = HashMap::new();
+        let mut paths = Vec::new();
+        while let Some(arg) = args.next() {
+            match arg.strip_prefix("--") {
+                Some(flag) => {
+                    let value = args.next().with_context(|| format!("--{flag} без значения\n{USAGE}"))?;
+                    flags.insert(flag.to_string(), value);
+                },
+                None => paths.push(PathBuf::from(arg)),
+            }
+        }
+
+        let mut take = |flag: &str| flags.remove(flag);
+        let number = |flag: &str, value: Option<String>| -> anyhow::Result<Option<f64>> {
+            value.map(|value| value.parse::<f64>().with_context(|| format!("--{flag}: не число {value}"))).transpose()
+        };
+        let exchange = |value: Option<String>| -> anyhow::Result<Option<ExchangeType>> {
+            value.map(|value| {
+                serde_json::from_value::<ExchangeType>(serde_json::Value::String(value.clone()))
+                    .with_context(|| format!("неизвестная биржа {value}"))
+            }).transpose()
+        };
+
+        let source = match kind.as_str() {
+            "lines" => BacktestSource::Lines {
+                from: number("from", take("from"))?.unwrap_or(0.0) as i64,
+                to: number("to", take("to"))?.unwrap_or(i64::MAX as f64) as i64,
+            },
+            "replay" if !paths.is_empty() => BacktestSource::Replay { paths: std::mem::take(&mut paths) },
+            _ => bail!(USAGE),
+        };
+        if !paths.is_empty() {
+            bail!("лишние аргументы {:?}\n{USAGE}", paths);
+        }
+
+        let filter = PairFilter {
+            symbol: take("symbol").map(|symbol| symbol.to_lowercase()),
+            long_exchange: exchange(take("long"))?,
+            short_exchange: exchange(take("short"))?,
+        };
+
+        if matches!(source, BacktestSource::Lines { .. }) && filter.symbol.is_none() {
+            bail!("для истории storage.lines нужен --symbol\n{USAGE}");
+        }
+
+        let strategy = ThresholdStrategy {
+            entry_pct: number("entry", take("entry"))?.unwrap_or(0.5),
+            exit_pct: number("exit", take("exit"))?.unwrap_or(0.1),
+            stop_pct: number("stop", take("stop"))?,
+            max_hold: number("max-hold-mins", take("max-hold-mins"))?.map(|mins| Duration::from_secs_f64(mins * 60.0)),
+        };
+
+        let settings = BacktestSettings {
+            fee_rate: number("fee-bps", take("fee-bps"))?.unwrap_or(10.0) / 10_000.0,
+            notional: number("notional", take("notional"))?.unwrap_or(1000.0),
+            max_positions: number("max-positions", take("max-positions"))?.unwrap_or(5.0) as usize,
+        };
+        if settings.notional <= 0.0 || settings.max_positions == 0 {
+            bail!("--notional и --max-positions должны быть больше 0");
+        }
+
+        let out = PathBuf::from(take("out").unwrap_or_else(|| "backtest".into()));
+
+        if let Some(flag) = flags.keys().next() {
+            bail!("неизвестный флаг --{flag}\n{USAGE}");
+        }
+
+        Ok(Some(Self { source, filter, strategy, settings, out }))
+    }
+}
+
+/// Прогоняет стратегию по истории или записи и пишет отчёт. Конвейер рыночных данных не запускается
+pub async fn run(
+    args: BacktestArgs
+) -> anyhow::Result<()> {
+    let mut backtester = Backtester::new(Box::new(args.strategy), args.settings);
+
+    let source = match args.source {
+        BacktestSource::Lines { from, to } => {
+            let pool = storage::pool::create_pool(&config::get().database).await.ok();
+            if pool.is_none() {
+                bail!("нет подключения к базе");
+            }
+
+            source::run_lines(&pool, &args.filter, from, to, &mut backtester).await?;
+            format!("storage.lines [{from}, {to}]")
+        },
+        BacktestSource::Replay { paths } => {
+            let frames = RecordedFrames::open(&paths)?;
+            source::run_replay(frames, &args.filter, &mut backtester).await?;
+            format!("replay {:?}", paths)
+        }
+    };
+    backtester.finish();
+
+    let report = Report::new(&backtester, source);
+    report.write(&args.out)?;
+
+    info!(
+        "Backtest -> {}: сэмплов {}, сделок {}, net PnL {:.2}, max drawdown {:.2}, отчёт в {}",
+        report.strategy,
+        report.samples,
+        report.statistics.trades,
+        report.statistics.net_pnl,
+        report.statistics.max_drawdown,
+        args.out.display()
+    );
+
+    Ok(())
+}
@@ -0,0 +1,134 @@
+use std::{fmt::Write as _, fs, path::Path};
+use serde::Serialize;
+
+use crate::services::backtest::engine::{BacktestSettings, Backtester, Rejected, Trade};
+
+/// Итоги бэктеста. Суммы в котируемой валюте
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Statistics {
+    pub trades: usize,
+    pub wins: usize,
+    pub losses: usize,
+    pub win_rate: f64,
+    pub gross_pnl: f64,
+    pub fees: f64,
+    pub slippage: f64,
+    pub net_pnl: f64,
+    pub avg_net_pnl: f64,
+    /// Наибольшее падение накопленного `net_pnl` от предыдущего максимума
+    pub max_drawdown: f64,
+    pub avg_hold_secs: f64,
+}
+
+impl Statistics {
+    /// `trades` отсортированы по времени закрытия
+    pub fn from_trades(
+        trades: &[Trade]
+    ) -> Self {
+        let mut stats = Self {
+            trades: trades.len(),
+            ..Default::default()
+        };
+        if trades.is_empty() {
+            return stats;
+        }
+
+        let mut equity = 0.0;
+        let mut peak = 0.0;
+        let mut hold_ms = 0;
+
+        for trade in trades {
+            if trade.net_pnl > 0.0 {
+                stats.wins += 1;
+            } else {
+                stats.losses += 1;
+            }
+
+            stats.gross_pnl += trade.gross_pnl;
+            stats.fees += trade.fees;
+            stats.slippage += trade.slippage;
+            hold_ms += trade.closed_at_ms - trade.opened_at_ms;
+
+            equity += trade.net_pnl;
+            peak = f64::max(peak, equity);
+            stats.max_drawdown = stats.max_drawdown.max(peak - equity);
+        }
+
+        stats.net_pnl = equity;
+        stats.win_rate = stats.wins as f64 / stats.trades as f64;
+        stats.avg_net_pnl = stats.net_pnl / stats.trades as f64;
+        stats.avg_hold_secs = hold_ms as f64 / stats.trades as f64 / 1000.0;
+
+        stats
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Report {
+    pub strategy: String,
+    pub source: String,
+    pub settings: BacktestSettings,
+    pub samples: u64,
+    pub statistics: Statistics,
+    pub rejected: Rejected,
+    pub trades: Vec<Trade>,
+}
+
+impl Report {
+    pub fn new(
+        backtester: &Backtester,
+        source: String
+    ) -> Self {
+        Self {
+            strategy: backtester.strategy_name(),
+            source,
+            settings: backtester.settings.clone(),
+            samples: backtester.samples,
+            statistics: Statistics::from_trades(&backtester.trades),
+            rejected: backtester.rejected.clone(),
+            trades: backtester.trades.clone(),
+        }
+    }
+
+    /// `report.json` - отчёт целиком, `trades.csv` - список сделок
+    pub fn write(
+        &self,
+        dir: &Path
+    ) -> anyhow::Result<()> {
+        fs::create_dir_all(dir)?;
+        fs::write(dir.join("report.json"), serde_json::to_string_pretty(self)?)?;
+        fs::write(dir.join("trades.csv"), self.trades_csv())?;
+
+        Ok(())
+    }
+
+    fn trades_csv(
+        &self
+    ) -> String {
+        let mut out = String::from(
+            "symbol,long_exchange,short_exchange,opened_at_ms,closed_at_ms,open_pct,close_pct,qty,gross_pnl,fees,slippage,net_pnl,exit_reason\n"
+        );
+
+        for trade in self.trades.iter() {
+            let _ = writeln!(
+                out,
+                "{},{},{},{},{},{},{},{},{},{},{},{},{}",
+                trade.symbol,
+                trade.long_exchange,
+                trade.short_exchange,
+                trade.opened_at_ms,
+                trade.closed_at_ms,
+                trade.open_pct,
+                trade.close_pct,
+                trade.qty.map(|qty| qty.to_string()).unwrap_or_default(),
+                trade.gross_pnl,
+                trade.fees,
+                trade.slippage,
+                trade.net_pnl,
+                trade.exit_reason.as_str(),
+            );
+        }
+
+        out
+    }
+}
@@ -0,0 +1,140 @@
+use std::{collections::HashMap, sync::Arc};
+
+use crate::{models::{aggregator::KeyMarketType, exchange::ExchangeType, orderbook::{BookEvent, Snapshot}, websocket::Symbol}, services::{backtest::engine::{Backtester, SpreadSample}, exchange::{exchange_aggregator::{BookEventSender, ExchangeStore, ExchangeStoreCMD}, frame_recorder::FrameKind, frame_replay::{RecordedFrames, replay_adapter}}}, storage::line_storage::get_lines_range};
+
+/// Какие пары участвуют в бэктесте. Пустое поле - любое значение
+#[derive(Debug, Clone, Default)]
+pub struct PairFilter {
+    pub symbol: Option<Symbol>,
+    pub long_exchange: Option<ExchangeType>,
+    pub short_exchange: Option<ExchangeType>,
+}
+
+impl PairFilter {
+    pub fn allows(
+        &self,
+        key: &KeyMarketType
+    ) -> bool {
+        self.symbol.as_ref().is_none_or(|symbol| *symbol == *key.symbol)
+            && self.long_exchange.is_none_or(|exchange| exchange == key.long_exchange)
+            && self.short_exchange.is_none_or(|exchange| exchange == key.short_exchange)
+    }
+}
+
+/// Сэмплы из `1m` истории `storage.lines`.
+/// Спред закрытия пары - линия обратного направления с обратным знаком, стаканов нет
+pub async fn run_lines(
+    pool: &Option<sqlx::PgPool>,
+    filter: &PairFilter,
+    from: i64,
+    to: i64,
+    backtester: &mut Backtester
+) -> anyhow::Result<()> {
+    let Some(symbol) = &filter.symbol else {
+        anyhow::bail!("для истории storage.lines нужен --symbol");
+    };
+
+    let lines = get_lines_range(pool, symbol, from, to).await?;
+    let values: HashMap<(i64, ExchangeType, ExchangeType), f64> = lines
+        .iter()
+        .map(|line| ((line.timestamp, line.long_exchange, line.short_exchange), line.value))
+        .collect();
+
+    let symbol = Arc::new(symbol.clone());
+    for line in lines.iter() {
+        let key = KeyMarketType::new(line.long_exchange, line.short_exchange, symbol.clone());
+        if !filter.allows(&key) {
+            continue;
+        }
+
+        let close_pct = values
+            .get(&(line.timestamp, line.short_exchange, line.long_exchange))
+            .map(|value| -value)
+            .unwrap_or(line.value);
+
+        backtester.on_sample(SpreadSample {
+            key,
+            timestamp_ms: line.timestamp * 1000,
+            open_pct: line.value,
+            close_pct,
+            books: None,
+        });
+    }
+
+    Ok(())
+}
+
+/// Сэмплы из записи кадров. Кадры разбираются настоящими адаптерами, стаканы собираются
+/// как в `ExchangeStore`, а после каждого кадра считаются спреды символа со всеми биржами, где есть его стакан
+pub async fn run_replay(
+    mut frames: RecordedFrames,
+    filter: &PairFilter,
+    backtester: &mut Backtester
+) -> anyhow::Result<()> {
+    let mut exchanges = HashMap::new();
+    for exchange_id in frames.exchanges() {
+        let (sender_data, events_rx) = BookEventSender::channel(exchange_id);
+        exchanges.insert(exchange_id, (replay_adapter(exchange_id)?, sender_data, events_rx));
+    }
+
+    let mut books: HashMap<Symbol, HashMap<ExchangeType, Arc<Snapshot>>> = HashMap::new();
+
+    while let Some((exchange_id, frame)) = frames.next() {
+        if frame.kind != FrameKind::Text {
+            continue;
+        }
+        let Ok(text) = String::from_utf8(frame.payload) else { continue };
+        let Some((adapter, sender_data, events_rx)) = exchanges.get_mut(&exchange_id) else { continue };
+        if adapter.clone().is_pong(&text) {
+            continue;
+        }
+        adapter.clone().parse_message(text, sender_data.clone()).await;
+
+        let mut changed = Vec::new();
+        while let Ok(cmd) = events_rx.try_recv() {
+            let ExchangeStoreCMD::Event(event) = cmd else { continue };
+            match event {
+                BookEvent::Snapshot { symbol, snapshot, .. } => {
+                    books.entry(symbol.clone()).or_default().insert(exchange_id, Arc::new(snapshot));
+                    changed.push(symbol);
+                },
+                BookEvent::Delta { symbol, delta, .. } => {
+                    // Дельта до первого снапшота не применяется, как в `ExchangeStore`
+                    let Some(book) = books.get_mut(&symbol).and_then(|books| books.get_mut(&exchange_id)) else { continue };
+                    ExchangeStore::handle_delta_data(delta, Arc::make_mut(book));
+                    changed.push(symbol);
+                },
+                BookEvent::TickerUpdate { .. } => {}
+            }
+        }
+        changed.dedup();
+
+        let timestamp_ms = frame.wall_us / 1000;
+        for symbol in changed {
+            let Some(symbol_books) = books.get(&symbol) else { continue };
+            let Some(book) = symbol_books.get(&exchange_id) else { continue };
+            let symbol = Arc::new(symbol.clone());
+
+            for (other_id, other) in symbol_books.iter() {
+                if *other_id == exchange_id {
+                    continue;
+                }
+
+                for (key, long, short) in [
+                    (KeyMarketType::new(exchange_id, *other_id, symbol.clone()), book, other),
+                    (KeyMarketType::new(*other_id, exchange_id, symbol.clone()), other, book),
+                ] {
+                    if !filter.allows(&key) {
+                        continue;
+                    }
+
+                    if let Some(sample) = SpreadSample::from_books(key, timestamp_ms, long.clone(), short.clone()) {
+                        backtester.on_sample(sample);
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,88 @@
+use std::time::Duration;
+use serde::Serialize;
+
+use crate::services::backtest::engine::{Position, SpreadSample};
+
+/// Почему позиция закрыта
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all="snake_case")]
+pub enum ExitReason {
+    /// Спред закрытия дошёл до цели
+    Target,
+    StopLoss,
+    MaxHold,
+    /// Данные закончились, позиция закрыта по последним ценам
+    EndOfData,
+}
+
+impl ExitReason {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ExitReason::Target => "target",
+            ExitReason::StopLoss => "stop_loss",
+            ExitReason::MaxHold => "max_hold",
+            ExitReason::EndOfData => "end_of_data",
+        }
+    }
+}
+
+/// <b>Strategy</b> правила входа и выхода бэктеста.
+/// Вызывается для каждого сэмпла пары: `should_enter` - пока по паре нет позиции, `should_exit` - пока она открыта.
+/// Лимиты позиций, комиссии и проскальзывание считает `Backtester`, а не стратегия
+pub trait Strategy: Send {
+    fn name(&self) -> String;
+    fn should_enter(
+        &mut self,
+        sample: &SpreadSample
+    ) -> bool;
+    fn should_exit(
+        &mut self,
+        position: &Position,
+        sample: &SpreadSample
+    ) -> Option<ExitReason>;
+}
+
+/// <b>ThresholdStrategy</b> вход, когда спред открытия не меньше `entry_pct`,
+/// выход, когда спред закрытия опустился до `exit_pct`
+#[derive(Debug, Clone)]
+pub struct ThresholdStrategy {
+    pub entry_pct: f64,
+    pub exit_pct: f64,
+    /// Выход, если спред закрытия вырос до этого значения
+    pub stop_pct: Option<f64>,
+    pub max_hold: Option<Duration>,
+}
+
+impl Strategy for ThresholdStrategy {
+    fn name(&self) -> String {
+        format!("threshold(entry={}%, exit={}%)", self.entry_pct, self.exit_pct)
+    }
+
+    fn should_enter(
+        &mut self,
+        sample: &SpreadSample
+    ) -> bool {
+        sample.open_pct >= self.entry_pct
+    }
+
+    fn should_exit(
+        &mut self,
+        position: &Position,
+        sample: &SpreadSample
+    ) -> Option<ExitReason> {
+        if sample.close_pct <= self.exit_pct {
+            return Some(ExitReason::Target);
+        }
+
+        if self.stop_pct.is_some_and(|stop_pct| sample.close_pct >= stop_pct) {
+            return Some(ExitReason::StopLoss);
+        }
+
+        let held_ms = sample.timestamp_ms - position.opened_at_ms;
+        if self.max_hold.is_some_and(|max_hold| held_ms >= max_hold.as_millis() as i64) {
+            return Some(ExitReason::MaxHold);
+        }
+
+        None
+    }
+}
@@ -1,16 +1,25 @@
-use std::{collections::{HashMap, HashSet, VecDeque}, sync::Arc};
-use tokio::sync::{RwLock, mpsc, watch};
-use crate::{models::{aggregator::KeyMarketType, exchange::ExchangeType, line::Line, websocket::Symbol}, services::data_mapping::DataMappingCmd, storage::line_storage::{get_spread_history}};
+use std::{collections::{HashMap, HashSet, VecDeque}, sync::Arc, time::{Duration, Instant}};
+use async_trait::async_trait;
+use chrono::Utc;
+use get_size::GetSize;
+use lru::LruCache;
+use tokio::sync::{mpsc, watch};
+use tokio_util::sync::CancellationToken;
+use crate::{config, models::{aggregator::KeyMarketType, line::{Line, LineHistory}}, services::{data_mapping::DataMappingCmd, lines_store::{LinesSnapshot, LinesStore}, metrics::Metric, supervisor::Actor}, storage::line_storage::{get_active_pairs, get_spread_history}};
 
 const MAX_LINES: usize = 100;
+/// Сколько времени ключ без истории в базе не запрашивается повторно
+const EMPTY_KEY_TTL: Duration = Duration::from_secs(60);
+/// За какой период считается активность пар при прогреве
+const WARM_UP_WINDOW: i64 = 24 * 60 * 60;
 
 #[derive(Debug, Clone)]
 pub enum CacheAggregatorCmd {
     AddLines {
-        lines: Vec<(Line, (ExchangeType, ExchangeType, Arc<std::string::String>))>
+        lines: Vec<(Line, KeyMarketType)>
     },
     Subscribe {
-        reply: mpsc::Sender<watch::Receiver<Arc<RwLock<Arc<HashMap<(ExchangeType, ExchangeType), HashMap<Arc<Symbol>, Arc<RwLock<VecDeque<Line>>>>>>>>>>
+        reply: mpsc::Sender<watch::Receiver<Arc<LinesSnapshot>>>
     },
     InitAllLines {
         key: KeyMarketType,
@@ -18,13 +27,23 @@ pub enum CacheAggregatorCmd {
 }
 
 pub struct CacheAggregator {
-    cache_lines: Arc<RwLock<Arc<HashMap<(ExchangeType, ExchangeType), HashMap<Arc<Symbol>, Arc<RwLock<VecDeque<Line>>>>>>>>,
+    store: LinesStore,
     initialization_keys: HashSet<KeyMarketType>,
+    /// Ключи, для которых база вернула пустую историю (negative cache)
+    empty_keys: HashMap<KeyMarketType, Instant>,
+
+    /// Размер пар в байтах, порядок - от самых холодных к недавно запрошенным.
+    /// Ключ пары не зависит от направления, см. `pair_key`
+    pair_sizes: LruCache<KeyMarketType, usize>,
+    /// Сумма `pair_sizes`, чтобы вытеснение не обходило все пары на каждом `AddLines`
+    cache_size: usize,
+    /// Прогрев выполняется один раз, а не после каждого перезапуска
+    warmed_up: bool,
+    cache_bytes: Arc<Metric>,
+    cache_pairs: Arc<Metric>,
 
     cache_aggregator_rx: mpsc::Receiver<Arc<CacheAggregatorCmd>>,
-    data_mapping_tx: watch::Sender<DataMappingCmd>,
-    watch_tx: watch::Sender<Arc<RwLock<Arc<HashMap<(ExchangeType, ExchangeType), HashMap<Arc<Symbol>, Arc<RwLock<VecDeque<Line>>>>>>>>>,
-    watch_rx: watch::Receiver<Arc<RwLock<Arc<HashMap<(ExchangeType, ExchangeType), HashMap<Arc<Symbol>, Arc<RwLock<VecDeque<Line>>>>>>>>>,
+    data_mapping_tx: mpsc::Sender<DataMappingCmd>,
 
     pool: Option<sqlx::PgPool>,
 }
@@ -32,150 +51,341 @@ pub struct CacheAggregator {
 impl CacheAggregator {
     pub fn new(
         cache_aggregator_rx: mpsc::Receiver<Arc<CacheAggregatorCmd>>,
-        data_mapping_tx: watch::Sender<DataMappingCmd>,
+        data_mapping_tx: mpsc::Sender<DataMappingCmd>,
 
         pool: Option<sqlx::PgPool>,
     ) -> Self {
-        let (watch_tx, watch_rx) = watch::channel(Arc::new(RwLock::new(Arc::new(HashMap::new()))));
-        
-        Self { 
-            cache_lines: Arc::new(RwLock::new(Arc::new(HashMap::new()))),
+        Self {
+            store: LinesStore::new(),
             initialization_keys: HashSet::new(),
-            
+            empty_keys: HashMap::new(),
+
+            pair_sizes: LruCache::unbounded(),
+            cache_size: 0,
+            warmed_up: false,
+            cache_bytes: Metric::gauge("lines_cache_bytes", "Размер линий в кеше по get_size", &[]),
+            cache_pairs: Metric::gauge("lines_cache_pairs", "Пары бирж в кеше линий", &[]),
+
             cache_aggregator_rx,
             data_mapping_tx,
 
-            watch_tx, 
-            watch_rx,
-
             pool,
         }
     }
+}
 
-    pub async fn run(
-        mut self,
-    ) {        
-        while let Some(cmd) = self.cache_aggregator_rx.recv().await {
+#[async_trait]
+impl Actor for CacheAggregator {
+    async fn run(
+        &mut self,
+        shutdown: CancellationToken
+    ) {
+        if !self.warmed_up {
+            self.warmed_up = true;
+            self.warm_up().await;
+        }
+
+        while let Some(cmd) = tokio::select! {
+            cmd = self.cache_aggregator_rx.recv() => cmd,
+            _ = shutdown.cancelled() => None
+        } {
             match cmd.as_ref() {
-                CacheAggregatorCmd::AddLines { 
+                CacheAggregatorCmd::AddLines {
                     lines
                 } => {
-                    let mut lock = self.cache_lines.write().await;
-                    let mut new_map = (*lock).as_ref().clone();
-
-                    for (line, (long_exchange, short_exchange, symbol)) in lines.into_iter() {                        
-                        let map = new_map
-                            .entry((*long_exchange, *short_exchange))
-                            .or_insert_with(HashMap::new);
-                        
-                        let deque = map
-                            .entry(symbol.clone())
-                            .or_insert_with(|| Arc::new(RwLock::new(VecDeque::new())));
-
-                        let mut dq = deque.write().await;
-
-                        dq.retain(|el| el.timestamp != line.timestamp);
-                        
-                        let pos = dq.iter().position(|l| l.timestamp >= line.timestamp)
-                            .unwrap_or(dq.len());
-                        dq.insert(pos, line.clone());
-
-                        if dq.len() > MAX_LINES {
-                            dq.pop_front();
-                        }
-                    }
-
-                    *lock = Arc::new(new_map);
-                    if let Some(err) = self.watch_tx.send(self.cache_lines.clone()).err() {
-                        tracing::error!("CacheAggregator(CacheAggregatorCmd::AddLines) -> {err}")
-                    }
+                    self.add_lines(lines);
                 },
                 CacheAggregatorCmd::Subscribe {
                     reply
                 } => {
-                   let _ = reply.send(self.watch_rx.clone()).await;
+                   let _ = reply.send(self.store.subscribe()).await;
                 },
-                CacheAggregatorCmd::InitAllLines { 
+                CacheAggregatorCmd::InitAllLines {
                     key,
                 } => {
-                    if !self.initialization_keys.contains(&key) {
-                        let result = get_spread_history(&self.pool, &key.symbol, key.long_exchange, key.short_exchange).await;
-                        if let Ok(lines) = result {
-                            if !lines.is_empty() {
-                                let _ = self.data_mapping_tx.send(DataMappingCmd::LinesFromDbToJsonPair(lines.clone()));
-                                self.initialization_keys.insert(key.clone());
-                                
-                                let mut lock = self.cache_lines.write().await;
-                                let mut new_map = (*lock).as_ref().clone();
-                                
-                                if let (Some(lines), Some(short_lines)) = (lines.get(&(key.long_exchange, key.short_exchange, key.symbol.clone())), lines.get(&(key.short_exchange, key.long_exchange, key.symbol.clone()))) {
-                                    for line in lines {
-                                        let map = new_map
-                                            .entry((key.long_exchange, key.short_exchange))
-                                            .or_insert_with(HashMap::new);
-
-                                        let deque = map
-                                            .entry(key.symbol.clone())
-                                            .or_insert_with(|| Arc::new(RwLock::new(VecDeque::new())));
-
-                                        let mut dq = deque.write().await;
-                                    
-                                        dq.push_back(line.clone());
-
-                                        if dq.len() > MAX_LINES {
-                                            dq.pop_front();
-                                        }
-                                    }
-
-                                    for line in short_lines {
-                                        let map = new_map
-                                            .entry((key.short_exchange, key.long_exchange))
-                                            .or_insert_with(HashMap::new);
-
-                                        let deque = map
-                                            .entry(key.symbol.clone())
-                                            .or_insert_with(|| Arc::new(RwLock::new(VecDeque::new())));
-
-                                        let mut dq = deque.write().await;
-                                    
-                                        dq.push_back(line.clone());
-
-                                        if dq.len() > MAX_LINES {
-                                            dq.pop_front();
-                                        }
-                                    }
-                                    *lock = Arc::new(new_map);
-                                }
-                            }
-                        }
-                    } else {
-                        let cache_lines = self.cache_lines.write().await.clone();
-                        if let (
-                            Some(long_map), 
-                            Some(short_map), 
-                        ) = (
-                            cache_lines.get(&(key.long_exchange, key.short_exchange)), 
-                            cache_lines.get(&(key.short_exchange, key.long_exchange)), 
-                        ) {
-                            if let (
-                                Some(long_data), 
-                                Some(short_data)
-                            ) = (
-                                short_map.get(&key.symbol), 
-                                long_map.get(&key.symbol)
-                            ) {
-                                let _ = self.data_mapping_tx.send(DataMappingCmd::LinesToJsonPair(
-                                    long_data.clone(), 
-                                    short_data.clone(),
-                                    key.symbol.clone(),
-                                    key.long_exchange,
-                                    key.short_exchange
-                                ));
-                            }
-                        }
-                    }
+                    self.init_all_lines(key).await;
                 }
             }
         }
     }
-}
\ No newline at end of file
+}
+
+impl CacheAggregator {
+    /// Загружает историю самых активных пар до первого подписчика
+    async fn warm_up(
+        &mut self,
+    ) {
+        let warm_up_pairs = config::get().pipeline.lines_cache_warm_up_pairs;
+        if self.pool.is_none() || warm_up_pairs == 0 {
+            return;
+        }
+
+        let since = Utc::now().timestamp() - WARM_UP_WINDOW;
+        let pairs = match get_active_pairs(&self.pool, since, warm_up_pairs as i64 * 2).await {
+            Ok(pairs) => pairs,
+            Err(e) => {
+                tracing::error!("CacheAggregator(WarmUp) -> {e}");
+                return;
+            }
+        };
+
+        let mut loaded = HashSet::new();
+        for (long_exchange, short_exchange, symbol) in pairs {
+            let key = Self::pair_key(&KeyMarketType::new(long_exchange, short_exchange, Arc::new(symbol)));
+            if loaded.len() >= warm_up_pairs || !loaded.insert(key.clone()) {
+                continue;
+            }
+
+            match get_spread_history(&self.pool, &key.symbol, key.long_exchange, key.short_exchange).await {
+                Ok(lines) => {
+                    self.store_history(&key, &lines);
+                },
+                Err(e) => {
+                    tracing::error!("CacheAggregator(WarmUp) -> {e}");
+                }
+            }
+        }
+
+        self.evict_cold_pairs(None);
+        self.store.publish();
+
+        tracing::info!("CacheAggregator -> прогрето пар: {}", self.pair_sizes.len());
+    }
+
+    /// Стоимость зависит только от количества новых линий: каждый ключ получает новый буфер,
+    /// а снимок обновляется за O(log n)
+    fn add_lines(
+        &mut self,
+        lines: &[(Line, KeyMarketType)]
+    ) {
+        let mut touched = HashSet::new();
+
+        self.store.update(|snapshot| {
+            for (line, key) in lines.iter() {
+                let history = snapshot
+                    .get(key)
+                    .map(|history| history.with_line(line.clone(), MAX_LINES))
+                    .unwrap_or_else(|| LineHistory::from_lines([line.clone()], MAX_LINES));
+
+                snapshot.insert(key.clone(), Arc::new(history));
+                touched.insert(Self::pair_key(key));
+            }
+        });
+
+        for pair_key in touched {
+            // В базе нет ничего, кроме только что записанных линий, поэтому кеш полный
+            let reversed = Self::reversed(&pair_key);
+            let was_empty = self.empty_keys.remove(&pair_key).is_some();
+            let was_reversed_empty = self.empty_keys.remove(&reversed).is_some();
+            if was_empty || was_reversed_empty {
+                self.initialization_keys.insert(pair_key.clone());
+                self.initialization_keys.insert(reversed);
+            }
+
+            let size = self.pair_size(&pair_key);
+            if let Some(old_size) = self.pair_sizes.peek_mut(&pair_key) {
+                self.cache_size = self.cache_size - *old_size + size;
+                *old_size = size;
+            } else {
+                // Новые пары никто не запрашивал, поэтому они самые холодные
+                self.pair_sizes.put(pair_key.clone(), size);
+                self.pair_sizes.demote(&pair_key);
+                self.cache_size += size;
+            }
+        }
+
+        self.evict_cold_pairs(None);
+        self.store.publish();
+    }
+
+    async fn init_all_lines(
+        &mut self,
+        key: &KeyMarketType,
+    ) {
+        let pair_key = Self::pair_key(key);
+        self.pair_sizes.promote(&pair_key);
+
+        if !self.initialization_keys.contains(&key) {
+            if let Some(checked_at) = self.empty_keys.get(key) {
+                if checked_at.elapsed() < EMPTY_KEY_TTL {
+                    return;
+                }
+                self.empty_keys.remove(key);
+            }
+
+            let result = get_spread_history(&self.pool, &key.symbol, key.long_exchange, key.short_exchange).await;
+            if let Ok(lines) = result {
+                if lines.is_empty() {
+                    self.empty_keys.insert(key.clone(), Instant::now());
+                    return;
+                }
+
+                // Снимок здесь не публикуется: новых линий нет
+                let _ = self.data_mapping_tx.send(DataMappingCmd::LinesFromDbToJsonPair(lines.clone())).await;
+                self.store_history(key, &lines);
+                self.evict_cold_pairs(Some(&pair_key));
+            }
+        } else {
+            if let (
+                Some(long_data),
+                Some(short_data)
+            ) = (
+                self.store.get(key),
+                self.store.get(&Self::reversed(key)),
+            ) {
+                let _ = self.data_mapping_tx.send(DataMappingCmd::LinesToJsonPair(
+                    long_data,
+                    short_data,
+                    key.symbol.clone(),
+                    key.long_exchange,
+                    key.short_exchange
+                )).await;
+            }
+        }
+    }
+
+    /// Записывает историю из базы в кеш. В базе все линии из кеша уже есть, поэтому история заменяется целиком
+    fn store_history(
+        &mut self,
+        key: &KeyMarketType,
+        lines: &HashMap<KeyMarketType, VecDeque<Line>>
+    ) {
+        let reversed = Self::reversed(key);
+        let (
+            Some(long_lines),
+            Some(short_lines)
+        ) = (
+            lines.get(key),
+            lines.get(&reversed)
+        ) else {
+            return;
+        };
+
+        self.store.update(|snapshot| {
+            snapshot.insert(key.clone(), Arc::new(LineHistory::from_lines(long_lines.iter().cloned(), MAX_LINES)));
+            snapshot.insert(reversed.clone(), Arc::new(LineHistory::from_lines(short_lines.iter().cloned(), MAX_LINES)));
+        });
+
+        self.initialization_keys.insert(key.clone());
+        self.initialization_keys.insert(reversed);
+
+        let pair_key = Self::pair_key(key);
+        let size = self.pair_size(&pair_key);
+        if let Some(old_size) = self.pair_sizes.put(pair_key, size) {
+            self.cache_size -= old_size;
+        }
+        self.cache_size += size;
+    }
+
+    /// Удаляет самые холодные пары, пока кеш не уложится в `pipeline.lines_cache_memory_bytes`
+    fn evict_cold_pairs(
+        &mut self,
+        keep: Option<&KeyMarketType>,
+    ) {
+        let memory_budget = config::get().pipeline.lines_cache_memory_bytes;
+        let mut total = self.cache_size;
+        if total <= memory_budget {
+            self.cache_bytes.set(total as i64);
+            self.cache_pairs.set(self.pair_sizes.len() as i64);
+            return;
+        }
+
+        let mut evicted = Vec::new();
+        while total > memory_budget {
+            let Some((pair_key, size)) = self.pair_sizes.pop_lru() else { break };
+            if Some(&pair_key) == keep {
+                self.pair_sizes.put(pair_key, size);
+                if self.pair_sizes.len() == 1 {
+                    break;
+                }
+                continue;
+            }
+
+            total -= size;
+            evicted.push(pair_key);
+        }
+
+        self.store.update(|snapshot| {
+            for pair_key in evicted.iter() {
+                snapshot.remove(pair_key);
+                snapshot.remove(&Self::reversed(pair_key));
+            }
+        });
+
+        for pair_key in evicted.iter() {
+            self.initialization_keys.remove(&Self::reversed(pair_key));
+            self.initialization_keys.remove(pair_key);
+        }
+
+        self.cache_size = total;
+        self.cache_bytes.set(total as i64);
+        self.cache_pairs.set(self.pair_sizes.len() as i64);
+        tracing::info!("CacheAggregator -> вытеснено пар: {}, размер кеша: {} bytes", evicted.len(), total);
+    }
+
+    /// Размер линий пары в обоих направлениях
+    fn pair_size(
+        &self,
+        pair_key: &KeyMarketType,
+    ) -> usize {
+        [self.store.get(pair_key), self.store.get(&Self::reversed(pair_key))]
+            .iter()
+            .flatten()
+            .map(|history| history.get_size())
+            .sum()
+    }
+
+    /// Ключ пары без учёта направления
+    fn pair_key(
+        key: &KeyMarketType,
+    ) -> KeyMarketType {
+        if key.long_exchange <= key.short_exchange {
+            key.clone()
+        } else {
+            Self::reversed(key)
+        }
+    }
+
+    fn reversed(
+        key: &KeyMarketType,
+    ) -> KeyMarketType {
+        KeyMarketType::new(key.short_exchange, key.long_exchange, key.symbol.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use tokio::sync::mpsc;
+    use crate::models::{aggregator::KeyMarketType, exchange::ExchangeType, line::{Line, TimeFrame}};
+    use super::CacheAggregator;
+
+    fn line(
+        key: &KeyMarketType,
+        timestamp: i64
+    ) -> (Line, KeyMarketType) {
+        (Line::new(key.long_exchange, key.short_exchange, key.symbol.to_string(), 0.5, TimeFrame::One, timestamp), key.clone())
+    }
+
+    /// Время `AddLines` меряет `benches/lines_snapshot.rs`, здесь - что снимок не копирует нетронутые истории
+    #[test]
+    fn add_lines_shares_untouched_histories() {
+        let (_cache_tx, cache_rx) = mpsc::channel(1);
+        let (data_mapping_tx, _data_mapping_rx) = mpsc::channel(1);
+        let mut cache = CacheAggregator::new(cache_rx, data_mapping_tx, None);
+
+        let keys: Vec<KeyMarketType> = (0..1000)
+            .map(|i| KeyMarketType::new(ExchangeType::Bybit, ExchangeType::Gate, Arc::new(format!("coin{i}usdt"))))
+            .collect();
+        cache.add_lines(&keys.iter().map(|key| line(key, 0)).collect::<Vec<_>>());
+        let before = cache.store.load();
+
+        cache.add_lines(&[line(&keys[0], 60)]);
+        let after = cache.store.load();
+
+        assert!(!Arc::ptr_eq(&before[&keys[0]], &after[&keys[0]]));
+        assert_eq!(before[&keys[0]].iter().count(), 1);
+        assert_eq!(after[&keys[0]].iter().count(), 2);
+        assert!(keys[1..].iter().all(|key| Arc::ptr_eq(&before[key], &after[key])));
+    }
+}
@@ -0,0 +1,109 @@
+use std::{sync::OnceLock, time::{Duration, Instant}};
+use chrono::{DateTime, TimeZone, Timelike, Utc};
+use tokio::sync::watch;
+
+/// Время воспроизведения в Unix мкс. Есть только в режиме replay
+static REPLAY: OnceLock<Replay> = OnceLock::new();
+
+struct Replay {
+    now_us: watch::Sender<i64>,
+    /// Монотонное время процесса, соответствующее началу записи
+    started: (Instant, i64),
+}
+
+/// Переключает часы процесса на время записи. Вызывается один раз до запуска акторов
+pub fn start_replay(
+    start_us: i64
+) {
+    let (now_us, _) = watch::channel(start_us);
+    let replay = Replay {
+        now_us,
+        started: (Instant::now(), start_us),
+    };
+
+    if REPLAY.set(replay).is_err() {
+        tracing::warn!("Clock -> воспроизведение уже запущено");
+    }
+}
+
+/// Двигает время воспроизведения вперёд. Время назад не идёт
+pub fn advance(
+    now_us: i64
+) {
+    if let Some(replay) = REPLAY.get() {
+        replay.now_us.send_if_modified(|current| {
+            if now_us > *current {
+                *current = now_us;
+                return true;
+            }
+            false
+        });
+    }
+}
+
+/// Текущее время: системное или время записи при воспроизведении
+pub fn now() -> DateTime<Utc> {
+    match REPLAY.get() {
+        Some(replay) => Utc.timestamp_micros(*replay.now_us.borrow()).single().unwrap_or_default(),
+        None => system_now()
+    }
+}
+
+#[cfg(not(test))]
+fn system_now() -> DateTime<Utc> {
+    Utc::now()
+}
+
+/// В тестах системное время идёт от первого вызова по часам tokio, чтобы `start_paused`
+/// двигал минуты, свежесть стаканов и время получения событий вместе
+#[cfg(test)]
+fn system_now() -> DateTime<Utc> {
+    static ANCHOR: std::sync::LazyLock<(Instant, DateTime<Utc>)> = std::sync::LazyLock::new(|| (Instant::now(), Utc::now()));
+
+    let (anchor, anchor_now) = *ANCHOR;
+    let now = tokio::time::Instant::now().into_std();
+    match now.checked_duration_since(anchor) {
+        Some(elapsed) => anchor_now + chrono::Duration::from_std(elapsed).unwrap_or_default(),
+        None => anchor_now - chrono::Duration::from_std(anchor - now).unwrap_or_default(),
+    }
+}
+
+pub fn now_ms() -> i64 {
+    now().timestamp_millis()
+}
+
+/// Монотонное время для свежести стаканов. При воспроизведении идёт вместе со временем записи,
+/// в остальных случаях это часы tokio: вне тестов они совпадают с `Instant::now`
+pub fn instant() -> Instant {
+    match REPLAY.get() {
+        Some(replay) => {
+            let (started, start_us) = replay.started;
+            let elapsed = (*replay.now_us.borrow() - start_us).max(0) as u64;
+            started + Duration::from_micros(elapsed)
+        },
+        None => tokio::time::Instant::now().into_std()
+    }
+}
+
+/// Начало следующей минуты по текущим часам
+pub fn next_minute() -> DateTime<Utc> {
+    let now = now();
+    now.with_second(0).unwrap().with_nanosecond(0).unwrap() + chrono::Duration::minutes(1)
+}
+
+/// Ждёт момента `deadline` по текущим часам. При воспроизведении ждёт, пока запись до него дойдёт
+pub async fn sleep_until(
+    deadline: DateTime<Utc>
+) {
+    match REPLAY.get() {
+        Some(replay) => {
+            let deadline_us = deadline.timestamp_micros();
+            let mut now_us = replay.now_us.subscribe();
+            let _ = now_us.wait_for(|now_us| *now_us >= deadline_us).await;
+        },
+        None => {
+            let delay = (deadline - now()).to_std().unwrap_or_default();
+            tokio::time::sleep(delay).await;
+        }
+    }
+}
@@ -1,7 +1,29 @@
-use std::{collections::HashSet, sync::Arc, time::Duration};
+use std::{collections::HashMap, sync::Arc, time::Duration};
+use async_trait::async_trait;
 use itertools::Itertools;
-use tokio::sync::{Mutex, mpsc, oneshot, watch};
-use crate::{models::exchange::ExchangeType, services::{cache_aggregator::CacheAggregatorCmd, data_aggregator::DataAggregatorCmd, data_mapping::DataMappingCmd, exchange::{exchange_aggregator::ExchangeStoreCMD, exchange_channel_store::ExchangeChannelStoreCmd}}};
+use tokio::{sync::{mpsc, oneshot, watch}, task::JoinHandle};
+use tokio_util::sync::CancellationToken;
+use crate::{models::{exchange::ExchangeType, exchange_aggregator::BookData, websocket::Symbol}, services::{cache_aggregator::CacheAggregatorCmd, data_mapping::DataMappingCmd, exchange::{exchange_aggregator::BookUpdatesQueue, exchange_channel_store::{ExchangeChannelStoreCmd, ExchangeHandle}}, lines_store::LinesSnapshot, supervisor::Actor}};
+
+/// Спрос клиентов на стаканы бирж
+#[derive(Debug)]
+pub enum DataAccessLayerCmd {
+    AcquireBook {
+        exchange_id: ExchangeType,
+        symbol: Arc<Symbol>
+    },
+    ReleaseBook {
+        exchange_id: ExchangeType,
+        symbol: Arc<Symbol>
+    },
+}
+
+/// Подписка на стакан символа: количество потребителей и задача, которая пересылает стакан в DataAggregator.
+/// `task` есть только пока стакан подписан у биржи
+struct BookDemand {
+    consumers: usize,
+    task: Option<JoinHandle<()>>,
+}
 
 /// Извлекает конкретные данные из:
 /// 
@@ -10,55 +32,43 @@ use crate::{models::exchange::ExchangeType, services::{cache_aggregator::CacheAg
 /// 
 /// И после передаёт их в DataMapping
 pub struct DataAccessLayer {
+    data_access_layer_rx: mpsc::Receiver<DataAccessLayerCmd>,
     cache_aggregator_tx: mpsc::Sender<Arc<CacheAggregatorCmd>>,
-    data_mapping_tx: watch::Sender<DataMappingCmd>,
+    data_mapping_tx: mpsc::Sender<DataMappingCmd>,
     exchange_channel_store_tx: mpsc::Sender<ExchangeChannelStoreCmd>,
-    data_aggregator_tx: watch::Sender<DataAggregatorCmd>,
-    loaded_exchanges: Arc<Mutex<HashSet<ExchangeType>>>
+    book_updates: Arc<BookUpdatesQueue>,
+
+    demand: HashMap<(ExchangeType, Arc<Symbol>), BookDemand>,
 }
 
 impl DataAccessLayer {
     pub fn new(
+        data_access_layer_rx: mpsc::Receiver<DataAccessLayerCmd>,
         cache_aggregator_tx: mpsc::Sender<Arc<CacheAggregatorCmd>>,
-        data_mapping_tx: watch::Sender<DataMappingCmd>,
+        data_mapping_tx: mpsc::Sender<DataMappingCmd>,
         exchange_channel_store_tx: mpsc::Sender<ExchangeChannelStoreCmd>,
-        data_aggregator_tx: watch::Sender<DataAggregatorCmd>
-    ) -> Arc<Self> {
-        Arc::new(
-            Self { 
-                cache_aggregator_tx,
-                data_mapping_tx,
-                exchange_channel_store_tx,
-                data_aggregator_tx,
-                loaded_exchanges: Arc::new(Mutex::new(HashSet::new()))
-            }
-        )
-    }
+        book_updates: Arc<BookUpdatesQueue>
+    ) -> Self {
+        Self { 
+            data_access_layer_rx,
+            cache_aggregator_tx,
+            data_mapping_tx,
+            exchange_channel_store_tx,
+            book_updates,
 
-    pub async fn run(self: Arc<Self>) {
-        tokio::spawn(self.clone().from_exchange_aggregator());
-        tokio::spawn(self.clone().lines_from_cache_aggregator());
-    }
-
-    async fn lines_from_cache_aggregator(self: Arc<Self>) {        
-        let (tx, mut rx) = mpsc::channel(1);
-        
-        self.cache_aggregator_tx.send(Arc::new(
-            CacheAggregatorCmd::Subscribe { reply: tx }
-        )).await.ok();
-        
-        if let Some(mut watch_rx) = rx.recv().await {
-            while let Ok(_) = watch_rx.changed().await {
-                let data = watch_rx.borrow().clone();
-                let _ = self.data_mapping_tx.send(
-                    DataMappingCmd::LinesFromDataAccessLayer(data), 
-                );
-            }
+            demand: HashMap::new(),
         }
     }
+}
 
-    /// Этот метод обращаюся к exchange_aggregator_tx за данными
-    async fn from_exchange_aggregator(self: Arc<Self>) {
+/// Подписывает DataAggregator только на стаканы, которые запросили клиенты.
+/// Когда последний потребитель уходит, подписка на символ в ExchangeStore закрывается
+#[async_trait]
+impl Actor for DataAccessLayer {
+    async fn run(
+        &mut self,
+        shutdown: CancellationToken
+    ) {
         let (tx, rx) = oneshot::channel();
 
         if let Some(err) = self.exchange_channel_store_tx.send_timeout(
@@ -69,47 +79,131 @@ impl DataAccessLayer {
         ).await.err() {
             tracing::error!("{}", err);
         }
-        
-        if let Ok(mut watch_rx) = rx.await {
-            
-            while watch_rx.changed().await.is_ok() {
-                let data = watch_rx.borrow_and_update().clone();
-                for (exchange_id, exchange_aggregator_tx) in data.into_iter() {
-                    if self.clone().exists_exchange(exchange_id).await {
-                        continue;
-                    }
-                    let mut loaded_exchanges = self.loaded_exchanges.lock().await;
-                    loaded_exchanges.insert(exchange_id);
-
-                    // Подписываемся на обновления в ExchangeAggregator
-                    let data_aggregator_tx = self.data_aggregator_tx.clone();
-                    let (tx, mut rx) = mpsc::channel(1);
-                    exchange_aggregator_tx.send(ExchangeStoreCMD::Subscribe { reply: tx }).ok();
-
-                    if let Some(mut watch_aggregator_tx) = rx.recv().await {
-                        tokio::spawn(async move {
-                            while watch_aggregator_tx.changed().await.is_ok() {
-                                let exchange_data = watch_aggregator_tx.borrow_and_update().clone();
- 
-                                let _ = data_aggregator_tx.send(
-                                    DataAggregatorCmd::UpdateData { 
-                                        exchange_id, 
-                                        data: exchange_data
-                                    }, 
-                                );
+
+        let Ok(mut exchanges_rx) = rx.await else { return };
+        let mut lines_rx = self.subscribe_lines().await;
+
+        loop {
+            tokio::select! {
+                _ = shutdown.cancelled() => return,
+                Some(cmd) = self.data_access_layer_rx.recv() => {
+                    match cmd {
+                        DataAccessLayerCmd::AcquireBook { 
+                            exchange_id, 
+                            symbol 
+                        } => {
+                            let entry = self.demand
+                                .entry((exchange_id, symbol.clone()))
+                                .or_insert(BookDemand { consumers: 0, task: None });
+                            entry.consumers += 1;
+
+                            if entry.task.is_none() {
+                                let exchanges = exchanges_rx.borrow().clone();
+                                entry.task = Self::subscribe_book(&exchanges, exchange_id, symbol, self.book_updates.clone()).await;
                             }
-                        });
+                        },
+                        DataAccessLayerCmd::ReleaseBook { 
+                            exchange_id, 
+                            symbol 
+                        } => {
+                            let key = (exchange_id, symbol);
+                            if let Some(entry) = self.demand.get_mut(&key) {
+                                entry.consumers = entry.consumers.saturating_sub(1);
+                                if entry.consumers == 0
+                                    && let Some(task) = self.demand.remove(&key).and_then(|entry| entry.task) {
+                                    task.abort();
+
+                                    let exchange = exchanges_rx.borrow().get(&key.0).cloned();
+                                    if let Some(exchange) = exchange {
+                                        exchange.subscriptions.release(key.1).await;
+                                    }
+                                }
+                            }
+                        },
                     }
+                },
+                Ok(_) = exchanges_rx.changed() => {
+                    // Биржа могла зарегистрироваться позже, чем клиент запросил её стакан,
+                    // или перезапуститься: задача пересылки старого стакана завершается вместе с ним
+                    let exchanges = exchanges_rx.borrow_and_update().clone();
+                    let pending = self.demand
+                        .iter()
+                        .filter(|(_, entry)| entry.task.as_ref().is_none_or(|task| task.is_finished()))
+                        .map(|(key, _)| key.clone())
+                        .collect_vec();
+
+                    for (exchange_id, symbol) in pending {
+                        let task = Self::subscribe_book(&exchanges, exchange_id, symbol.clone(), self.book_updates.clone()).await;
+                        if let Some(entry) = self.demand.get_mut(&(exchange_id, symbol)) {
+                            entry.task = task;
+                        }
+                    }
+                },
+                data = Self::next_lines(&mut lines_rx) => {
+                    let _ = self.data_mapping_tx.send(
+                        DataMappingCmd::LinesFromDataAccessLayer(data), 
+                    ).await;
                 }
             }
         }
     }
+}
+
+impl DataAccessLayer {
+    async fn subscribe_lines(
+        &self
+    ) -> Option<watch::Receiver<Arc<LinesSnapshot>>> {
+        let (tx, mut rx) = mpsc::channel(1);
+        
+        self.cache_aggregator_tx.send(Arc::new(
+            CacheAggregatorCmd::Subscribe { reply: tx }
+        )).await.ok();
+        
+        rx.recv().await
+    }
+
+    /// Следующий снапшот линий из CacheAggregator. Без подписки никогда не завершается
+    async fn next_lines(
+        lines_rx: &mut Option<watch::Receiver<Arc<LinesSnapshot>>>
+    ) -> Arc<LinesSnapshot> {
+        if let Some(rx) = lines_rx.as_mut()
+            && rx.changed().await.is_ok() {
+            return rx.borrow().clone();
+        }
+
+        *lines_rx = None;
+        std::future::pending().await
+    }
+
+    async fn subscribe_book(
+        exchanges: &HashMap<ExchangeType, ExchangeHandle>,
+        exchange_id: ExchangeType,
+        symbol: Arc<Symbol>,
+        book_updates: Arc<BookUpdatesQueue>
+    ) -> Option<JoinHandle<()>> {
+        let exchange = exchanges.get(&exchange_id)?;
+        let book_rx = exchange.store.subscribe(symbol.clone()).await?;
+
+        // Стакан на бирже подписывается только пока он нужен клиентам
+        exchange.subscriptions.acquire(symbol).await;
+
+        Some(tokio::spawn(Self::forward_book(exchange_id, book_rx, book_updates)))
+    }
+
+    async fn forward_book(
+        exchange_id: ExchangeType,
+        mut book_rx: watch::Receiver<Arc<BookData>>,
+        book_updates: Arc<BookUpdatesQueue>
+    ) {
+        loop {
+            let data = book_rx.borrow_and_update().clone();
+            if !data.symbol.is_empty() {
+                book_updates.push((exchange_id, data.symbol.clone()), data);
+            }
 
-    async fn exists_exchange(
-        self: Arc<Self>,
-        exchange_id: ExchangeType
-    ) -> bool {
-        let loaded_exchanges = self.loaded_exchanges.lock().await;
-        loaded_exchanges.contains(&exchange_id)
+            if book_rx.changed().await.is_err() {
+                break;
+            }
+        }
     }
-}
\ No newline at end of file
+}
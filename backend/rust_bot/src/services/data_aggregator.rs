@@ -1,7 +1,8 @@
-use std::{collections::HashMap, sync::Arc, time::{Duration}};
-use chrono::{Timelike, Utc, Duration as ChronoDuration};
-use tokio::{sync::{mpsc, watch}, time::{Instant as TokioInstant, interval_at}};
-use crate::{models::{aggregator::{Quote, SpreadPair, Volume}, exchange::ExchangeType, exchange_aggregator::{BookData, BookDataWithArc}, line::{Line, TimeFrame}, orderbook::Snapshot, websocket::Symbol}, services::{cache_aggregator::CacheAggregatorCmd, data_mapping::DataMappingCmd}, storage::line_storage::add_new_lines};
+use std::{collections::HashMap, sync::Arc, time::{Duration, Instant}};
+use async_trait::async_trait;
+use tokio::sync::{broadcast, mpsc};
+use tokio_util::sync::CancellationToken;
+use crate::{config, models::{aggregator::{ExecutableSpread, ExecutableSpreads, KeyMarketType, Quote, SpreadPair}, exchange::ExchangeType, exchange_aggregator::{BookData, BookDataWithArc}, inventory::Inventory, line::{Line, TimeFrame}, websocket::{Symbol, normalize_symbol}}, services::{cache_aggregator::CacheAggregatorCmd, clock, data_mapping::ExchangesData, exchange::exchange_aggregator::BookUpdatesQueue, inventory_tracker::InventoryHandle, latency::LatencyHistogram, metrics::Metric, queue::CoalescingQueue, supervisor::Actor}, storage::line_storage::add_new_lines};
 
 #[derive(Clone)]
 pub enum DataAggregatorCmd {
@@ -9,11 +10,15 @@ pub enum DataAggregatorCmd {
         symbol: Arc<Symbol>,
         exchange_id: ExchangeType
     },
+    /// Символ убран из сессии биржи или биржа остановлена
+    MarketUnregister {
+        symbol: Arc<Symbol>,
+        exchange_id: ExchangeType
+    },
     UpdateData {
         exchange_id: ExchangeType,
         data: Arc<BookData>
     },
-    Default
 }
 
 #[derive(Debug, Clone)]
@@ -23,24 +28,37 @@ pub struct ExchangeBookData {
 
 /// <b>DataAggregator</b> Обьединяет данные с разных бирж
 pub struct DataAggregator {
-    pending_lines: HashMap<(ExchangeType, Arc<Symbol>), Arc<SpreadPair>>,
+    /// Последний спред пары бирж за текущую минуту, ключ - биржи в порядке `quotes`
+    pending_lines: HashMap<KeyMarketType, Arc<SpreadPair>>,
     markets: HashMap<Arc<Symbol>, HashMap<ExchangeType, ExchangeBookData>>,
 
     pub register_symbol_tx: mpsc::Sender<DataAggregatorCmd>,
     register_symbol_rx: mpsc::Receiver<DataAggregatorCmd>,
 
-    rx: watch::Receiver<DataAggregatorCmd>,
-    data_mapping_tx: watch::Sender<DataMappingCmd>,
+    /// Обновления стаканов от всех `ExchangeStore`, по одному состоянию на символ биржи
+    pub book_updates: Arc<BookUpdatesQueue>,
+    /// Положительные исполнимые спреды пар бирж символа после каждого обновления стакана
+    pub executable_spreads: broadcast::Sender<Arc<ExecutableSpreads>>,
+    /// Остатки на биржах для отметки исполнимых спредов
+    inventory: InventoryHandle,
+    books_queue: Arc<CoalescingQueue<Arc<Symbol>, ExchangesData>>,
     cache_aggregator_tx: mpsc::Sender<Arc<CacheAggregatorCmd>>,
+    /// Задержка от получения события до `DataAggregator` по биржам
+    pipeline_latency: HashMap<ExchangeType, Arc<LatencyHistogram>>,
+    /// Время записи батча линий в базу
+    db_latency: Arc<LatencyHistogram>,
+    /// Считается в `calculate_spread` на каждое обновление стакана
+    spreads: Arc<Metric>,
+    pending_gauge: Arc<Metric>,
 
     pool: Option<sqlx::PgPool>,
 }
 
 impl DataAggregator {
     pub fn new(
-        aggregator_rx: watch::Receiver<DataAggregatorCmd>,
-        data_mapping_tx: watch::Sender<DataMappingCmd>,
+        books_queue: Arc<CoalescingQueue<Arc<Symbol>, ExchangesData>>,
         cache_aggregator_tx: mpsc::Sender<Arc<CacheAggregatorCmd>>,
+        inventory: InventoryHandle,
 
         pool: Option<sqlx::PgPool>,
     ) -> Self {
@@ -54,48 +72,56 @@ impl DataAggregator {
             register_symbol_tx,
             register_symbol_rx,
 
-            rx: aggregator_rx,
-            data_mapping_tx,
+            book_updates: CoalescingQueue::new("data_aggregator.book_updates"),
+            executable_spreads: broadcast::channel(1024).0,
+            inventory,
+            books_queue,
             cache_aggregator_tx,
+            pipeline_latency: HashMap::new(),
+            db_latency: LatencyHistogram::register("db.batch"),
+            spreads: Metric::counter("data_aggregator_spreads_total", "Рассчитанные спреды пар бирж", &[]),
+            pending_gauge: Metric::gauge("data_aggregator_pending_lines", "Спреды, ожидающие записи в базу", &[]),
 
             pool,
         }
     }
-    
-    pub async fn run(
-        mut self, 
-    ) {
-        // Поток для отправки orderbooks клиентам, которые подписались на них, 
-        // но с конкретным токеном, а не всеми подряд
-        let now = Utc::now();
-        let next_min = now
-            .with_second(0).unwrap()
-            .with_nanosecond(0).unwrap()
-            + ChronoDuration::minutes(1);
-
-        let delay = next_min - Utc::now();
-        let delay_std = Duration::from_secs(delay.num_seconds() as u64);
-        let mut interval = interval_at(TokioInstant::now() + delay_std, Duration::from_secs(60));
+}
 
-        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+#[async_trait]
+impl Actor for DataAggregator {
+    async fn run(
+        &mut self,
+        shutdown: CancellationToken
+    ) {
+        // Спреды пишутся в базу в начале каждой минуты. При воспроизведении минуты идут по времени записи
+        let mut next_minute = clock::next_minute();
 
         loop {
             tokio::select! {
+                biased;
+                _ = shutdown.cancelled() => {
+                    // Спреды текущей минуты иначе потеряются
+                    tracing::info!("DataAggregator -> сохранение {} спредов перед остановкой", self.pending_lines.len());
+                    self.db_writer().await;
+                    return;
+                }
                 Some(cmd) = self.register_symbol_rx.recv() => {
                     self.handle_command(cmd).await;
                 }
-                Ok(_) = self.rx.changed() => {
-                    let cmd = self.rx.borrow().clone();
-                    self.handle_command(cmd).await;
+                ((exchange_id, _), data) = self.book_updates.pop() => {
+                    self.handle_command(DataAggregatorCmd::UpdateData { exchange_id, data }).await;
                 }
 
-                _ = interval.tick() => {
+                _ = clock::sleep_until(next_minute) => {
+                    next_minute = clock::next_minute();
                     self.db_writer().await;
                 },
             };
         }
     }
+}
 
+impl DataAggregator {
     async fn handle_command(
         &mut self, cmd: DataAggregatorCmd
     ) {
@@ -105,10 +131,7 @@ impl DataAggregator {
                 exchange_id 
             } => {
                 // Приводим symbol к общему формату symbolusdt
-                let symbol: String = symbol.chars()
-                    .filter(|c| *c != '_' && *c != '-')
-                    .map(|c| c.to_ascii_lowercase())
-                    .collect();
+                let symbol = normalize_symbol(&symbol);
 
                 let entry = self.markets
                     .entry(Arc::new(symbol))
@@ -118,66 +141,61 @@ impl DataAggregator {
                     data: None
                 });
             },
+            DataAggregatorCmd::MarketUnregister { 
+                symbol, 
+                exchange_id 
+            } => {
+                let symbol: Arc<Symbol> = Arc::new(normalize_symbol(&symbol));
+
+                self.pending_lines.retain(|key, _| key.symbol != symbol || (key.long_exchange != exchange_id && key.short_exchange != exchange_id));
+                self.pending_gauge.set(self.pending_lines.len() as i64);
+
+                let Some(exchanges) = self.markets.get_mut(&symbol) else { return };
+                if exchanges.remove(&exchange_id).is_none() {
+                    return;
+                }
+
+                if exchanges.is_empty() {
+                    self.markets.remove(&symbol);
+                }
+
+                // Клиенты символа перестают получать стакан снятой биржи
+                let snapshot_data = self.markets
+                    .get_mut(&symbol)
+                    .map(|exchanges| Self::snapshot_to_vec(exchanges, symbol.clone()))
+                    .unwrap_or_default();
+                self.books_queue.push(symbol, snapshot_data);
+            },
             DataAggregatorCmd::UpdateData { 
                 exchange_id,
                 data
             } => {
+                if let Some(time) = data.time {
+                    self.pipeline_latency
+                        .entry(exchange_id)
+                        .or_insert_with(|| LatencyHistogram::register(format!("pipeline.{}", exchange_id)))
+                        .record(clock::now_ms() - time.received_at);
+                }
+
                 if let Some(exchanges) = self.markets.get_mut(&data.symbol) {
                     Self::update_exchange_data(exchanges, exchange_id, data.clone());
                     let snapshot_data = Self::snapshot_to_vec(exchanges, data.symbol.clone());
-                    let _ = self.data_mapping_tx.send(DataMappingCmd::ExchangesDataToJsonPair(snapshot_data));
-
-                    // let volumes: Vec<Volume> = exchanges
-                    //     .iter()
-                    //     .filter_map(|(ex_id, data)| {
-                    //         data.data.as_ref().map(|arc| {
-                    //             Volume { 
-                    //                 exchange_id: *ex_id, 
-                    //                 value: arc.volume24h,
-                    //                 symbol: symbol.clone()
-                    //             }
-                    //         })
-                    //     })
-                    //     .collect();
-
-                    // self.data_mapping_tx.send_timeout(
-                    //     DataMappingCmd::VolumesToJson(
-                    //         volumes
-                    //     ), 
-                    //     Duration::from_millis(10)
-                    // ).await.ok();
-
-                    // let quotes: Vec<Option<Quote>> = exchanges
-                    //     .iter()
-                    //     .filter_map(|(ex_id, data)| {
-                    //         data.data.as_ref().map(|arc| {
-                    //             let snapshot = &arc.snapshot;
-                    //             if let Some(snapshot) = snapshot {
-                    //                 let best_ask = snapshot.a.iter()
-                    //                     .min_by_key(|(price, _)| *price)
-                    //                     .map(|(price, _)| *price as f64 / PRICE_TICK);
-
-                    //                 let best_bid = snapshot.b.iter()
-                    //                     .max_by_key(|(price, _)| *price)
-                    //                     .map(|(price, _)| *price as f64 / PRICE_TICK);
-
-                    //                 let quote = Quote {
-                    //                     exchange_id: Some(*ex_id),
-                    //                     symbol: Some(symbol.clone()),
-                    //                     ask: best_ask,
-                    //                     bid: best_bid,
-                    //                 };
-                    //                 return Some(quote);
-                    //             } else {
-                    //                 return Some(Quote::new());
-                    //             }
-                    //         })
-                    //     }).collect();
-
-                    // self.calculate_spread(quotes).await;
+
+                    // Считаются, только пока их кто-то слушает
+                    if self.executable_spreads.receiver_count() > 0 {
+                        let spreads = Self::executable_spreads(&snapshot_data, &self.inventory.snapshot());
+                        self.executable_spreads.send(Arc::new(ExecutableSpreads {
+                            symbol: data.symbol.clone(),
+                            spreads: spreads.into_iter().map(Arc::new).collect(),
+                            time: clock::now_ms(),
+                        })).ok();
+                    }
+
+                    let quotes = Self::quotes(&snapshot_data);
+                    self.books_queue.push(data.symbol.clone(), snapshot_data);
+                    self.calculate_spread(quotes);
                 }
             },
-            DataAggregatorCmd::Default => {}
         }
         
     }
@@ -195,7 +213,9 @@ impl DataAggregator {
                 BookDataWithArc {
                     snapshot: snapshot_arc,
                     last_price: data.last_price,
-                    volume24h: data.volume24h
+                    volume24h: data.volume24h,
+                    updated_at: data.updated_at,
+                    time: data.time
                 }
             );
             old_data.data = Some(new_data);
@@ -205,13 +225,20 @@ impl DataAggregator {
     fn snapshot_to_vec(
         exchanges: &mut HashMap<ExchangeType, ExchangeBookData>,
         symbol: Arc<Symbol>
-    ) -> Vec<(ExchangeType, Arc<Symbol>, (Option<Arc<Snapshot>>, Option<f64>))> {
-        let snapshot_data: Vec<(ExchangeType, Arc<Symbol>, (Option<Arc<Snapshot>>, Option<f64>))> = exchanges
+    ) -> ExchangesData {
+        let now = clock::instant();
+        // Стаканы без обновлений дольше этого не участвуют в спредах
+        let stale_after = config::get().pipeline.book_stale_after();
+
+        let snapshot_data: ExchangesData = exchanges
             .iter_mut()
             .filter_map(|(ex_id, data)| {
-                data.data.as_ref().map(|arc| {
+                // Устаревший стакан даёт ложный спред, поэтому пара с ним не считается
+                data.data.as_ref().filter(|arc| {
+                    arc.updated_at.is_some_and(|updated_at| now.duration_since(updated_at) <= stale_after)
+                }).map(|arc| {
                     let taken_snapshot = arc.snapshot.clone();
-                    (*ex_id, symbol.clone(), (taken_snapshot, arc.last_price))
+                    (*ex_id, symbol.clone(), (taken_snapshot, arc.last_price, arc.volume24h, arc.time))
                 })
             })
             .collect();
@@ -219,87 +246,160 @@ impl DataAggregator {
         return snapshot_data;
     }
 
-    async fn calculate_spread(
+    /// Спреды всех пар свежих стаканов символа в обе стороны на `pipeline.spread_notional`.
+    /// Пара без объёма в стаканах или с отрицательным спредом пропускается
+    fn executable_spreads(
+        snapshot_data: &ExchangesData,
+        inventory: &Inventory
+    ) -> Vec<ExecutableSpread> {
+        let config = config::get();
+        let notional = config.pipeline.spread_notional;
+        let now = clock::now_ms();
+        let mut spreads = Vec::new();
+
+        for (long_exchange, symbol, (long_book, _, _, long_time)) in snapshot_data {
+            let Some(long_book) = long_book else { continue };
+            let Some(best_ask) = long_book.a.keys().next() else { continue };
+            let qty = notional / best_ask.as_f64();
+
+            let Some(buy) = long_book.buy(qty).filter(|fill| fill.complete) else { continue };
+
+            for (short_exchange, _, (short_book, _, _, short_time)) in snapshot_data {
+                if short_exchange == long_exchange {
+                    continue;
+                }
+                let Some(short_book) = short_book else { continue };
+                let Some(sell) = short_book.sell(qty).filter(|fill| fill.complete) else { continue };
+
+                let spread_pct = (sell.price - buy.price) / ((sell.price + buy.price) / 2.0) * 100.0;
+                if spread_pct <= 0.0 {
+                    continue;
+                }
+
+                let skewed = match (long_time, short_time) {
+                    (Some(long_time), Some(short_time)) => long_time.skew(short_time) > config.pipeline.spread_max_skew_ms,
+                    _ => false
+                };
+
+                let mut spread = ExecutableSpread {
+                    key: KeyMarketType::new(*long_exchange, *short_exchange, symbol.clone()),
+                    qty,
+                    buy_price: buy.price,
+                    sell_price: sell.price,
+                    buy_limit: buy.worst,
+                    sell_limit: sell.worst,
+                    spread_pct,
+                    skewed,
+                    inventory: None,
+                    time: now,
+                };
+                spread.inventory = inventory.fit(&spread, now);
+                spreads.push(spread);
+            }
+        }
+
+        spreads
+    }
+
+    /// Лучшие цены стаканов `snapshot_to_vec`: устаревшие стаканы туда уже не попали
+    fn quotes(
+        snapshot_data: &ExchangesData
+    ) -> Vec<Quote> {
+        let mut quotes: Vec<Quote> = snapshot_data
+            .iter()
+            .filter_map(|(exchange_id, symbol, (snapshot, _, _, time))| {
+                let snapshot = snapshot.as_ref()?;
+                Some(Quote {
+                    exchange_id: Some(*exchange_id),
+                    symbol: Some(symbol.clone()),
+                    bid: snapshot.b.keys().next_back().map(|price| price.as_f64()),
+                    ask: snapshot.a.keys().next().map(|price| price.as_f64()),
+                    time: *time,
+                })
+            })
+            .collect();
+
+        // Пара бирж всегда считается в одном порядке и попадает в один ключ `pending_lines`
+        quotes.sort_by(|a, b| a.exchange_id.partial_cmp(&b.exchange_id).unwrap_or(std::cmp::Ordering::Equal));
+        quotes
+    }
+
+    /// Спреды лучших цен всех пар бирж символа. Последний спред пары за минуту пишется в `db_writer`
+    fn calculate_spread(
         &mut self,
-        quotes: Vec<Option<Quote>>,
+        quotes: Vec<Quote>,
     ) {
-        let data_mapping = self.data_mapping_tx.clone();
-
         for (i, long_quote) in quotes.iter().enumerate() {
             for short_quote in quotes.iter().skip(i+1) {
-                if let (Some(lq), Some(sq)) = (long_quote, short_quote) {
-                    if let (
-                        Some(symbol),
-                        Some(long_exchange),
-                        Some(long_ask),
-                        Some(_long_bid),
-
-                        Some(short_exchange),
-                        Some(_short_ask), 
-                        Some(short_bid),
-                    ) = (
-                        lq.symbol.clone(),
-                        lq.exchange_id,
-                        lq.ask, 
-                        lq.bid,
-
-                        sq.exchange_id,
-                        sq.ask,
-                        sq.bid
-                    ) {
-                        // Доделать для второй стороны
-                        
-                        // Long - Short
-                        let long_spread = Arc::new(self.spread_type(
-                            long_exchange,
-                            short_bid,
-
-                            short_exchange,
-                            long_ask,
-                            symbol.clone()
-                        ));
-
-                        let _ = data_mapping.send(
-                            DataMappingCmd::SpreadPairToJsonPair(long_spread.clone()), 
-                        );
-
-                        self.pending_lines.insert((long_exchange, symbol.clone()), long_spread);
-                    }
-                }
+                let Some(spread) = Self::spread_type(long_quote, short_quote) else {
+                    continue;
+                };
+
+                self.spreads.inc();
+                self.pending_lines.insert(
+                    KeyMarketType::new(spread.long_exchange, spread.short_exchange, spread.symbol.clone()),
+                    Arc::new(spread)
+                );
             }
         }
+
+        self.pending_gauge.set(self.pending_lines.len() as i64);
     }
 
-    /// Формотирует спред к нужному направлению
+    /// Спред в обе стороны: `long_spread` - покупка по ask `long_exchange` и продажа по bid `short_exchange`,
+    /// `short_spread` - наоборот. Без символа или лучших цен одной из бирж спреда нет
     fn spread_type(
-        &self,
-        
-        long_exchange: ExchangeType,
-        long_value: f64,
-        
-        short_exchange: ExchangeType,
-        short_value: f64,
-        symbol: Arc<Symbol>,
-    ) -> SpreadPair {
-        let mid_in_price = (long_value + short_value) / 2.0;
-        let spread_in_percent = (long_value - short_value) / mid_in_price * 100.0;
-
-        let mid_out_price = (short_value + long_value) / 2.0;
-        let spread_out_percent = (short_value - long_value) / mid_out_price * 100.0;
-
-        let now = Utc::now();
+        long_quote: &Quote,
+        short_quote: &Quote
+    ) -> Option<SpreadPair> {
+        let (
+            Some(symbol),
+            Some(long_exchange),
+            Some(long_ask),
+            Some(long_bid),
+
+            Some(short_exchange),
+            Some(short_ask),
+            Some(short_bid),
+        ) = (
+            long_quote.symbol.clone(),
+            long_quote.exchange_id,
+            long_quote.ask,
+            long_quote.bid,
+
+            short_quote.exchange_id,
+            short_quote.ask,
+            short_quote.bid
+        ) else {
+            return None;
+        };
+
+        let mid_in_price = (short_bid + long_ask) / 2.0;
+        let spread_in_percent = (short_bid - long_ask) / mid_in_price * 100.0;
+
+        let mid_out_price = (long_bid + short_ask) / 2.0;
+        let spread_out_percent = (long_bid - short_ask) / mid_out_price * 100.0;
+
+        let now = clock::now();
         let timestamp = now.timestamp() - (now.timestamp() % 60);
 
-        let spread = SpreadPair::new(
-            symbol.clone(),
-            long_exchange, 
-            spread_in_percent, 
-            short_exchange, 
-            spread_out_percent, 
-            timestamp
-        );
-
-        spread
+        let (long_time, short_time) = (long_quote.time, short_quote.time);
+        let skewed = match (long_time, short_time) {
+            (Some(long_time), Some(short_time)) => long_time.skew(&short_time) > config::get().pipeline.spread_max_skew_ms,
+            _ => false
+        };
+
+        Some(SpreadPair {
+            symbol,
+            long_exchange,
+            long_spread: spread_in_percent,
+            short_exchange,
+            short_spread: spread_out_percent,
+            timestamp,
+            long_time,
+            short_time,
+            skewed
+        })
     }
     
     /// Обрабатывает pending_lines и сохраняет в базу данных
@@ -310,7 +410,16 @@ impl DataAggregator {
         
         let mut lines = Vec::new();
 
-        for ((_, symbol), spread) in self.pending_lines.iter() {
+        // Спреды минуты уходят из очереди и при ошибке записи, и когда все они пропущены
+        let pending_lines = std::mem::take(&mut self.pending_lines);
+        self.pending_gauge.set(0);
+
+        for (key, spread) in pending_lines.into_iter() {
+            // Спред между отстающей и свежей биржей не пишется в историю
+            if spread.skewed {
+                continue;
+            }
+
             let long_line = Line::new(
                 spread.long_exchange, 
                 spread.short_exchange, 
@@ -320,7 +429,8 @@ impl DataAggregator {
                 spread.timestamp
             );
 
-            lines.push((long_line, (spread.long_exchange, spread.short_exchange, symbol.clone())));
+            let reversed = KeyMarketType::new(key.short_exchange, key.long_exchange, key.symbol.clone());
+            lines.push((long_line, key));
 
             let short_line = Line::new(
                 spread.short_exchange, 
@@ -331,11 +441,15 @@ impl DataAggregator {
                 spread.timestamp
             );
 
-            lines.push((short_line, (spread.short_exchange, spread.long_exchange, symbol.clone())));
+            lines.push((short_line, reversed));
         }
 
         if !lines.is_empty() {
-            match add_new_lines(&pool, &lines).await {
+            let started = Instant::now();
+            let result = add_new_lines(&pool, &lines).await;
+            self.db_latency.record(started.elapsed().as_millis() as i64);
+
+            match result {
                 Ok(_) => {
                     let cache_aggregator_tx = self.cache_aggregator_tx.clone();
                     if let Some(err) = cache_aggregator_tx.send_timeout(
@@ -345,12 +459,10 @@ impl DataAggregator {
                         tracing::error!("DataAggregator(DbWriter) -> {err}")
                     } 
                     
-                    self.pending_lines.clear();
                     tracing::info!("Данные отправлены");
                 },
                 Err(e) => {
                     tracing::error!("Ошибка отправки батча: {e}");
-                    self.pending_lines.clear();
                 }
             };
         }
@@ -1,286 +1,284 @@
 use std::{collections::{HashMap, HashSet, VecDeque}, sync::Arc};
+use async_trait::async_trait;
 use futures_util::future::join_all;
 use itertools::Itertools;
 use ordered_float::OrderedFloat;
-use serde::{Deserialize, Serialize};
 use serde_json::Value;
-use tokio::sync::{RwLock, mpsc, watch};
+use tokio::sync::mpsc;
+use tokio_util::sync::CancellationToken;
 
-use crate::{models::{aggregator::{JsonPairData, JsonPairUniqueId, KeyMarketType, SpreadPair, Volume}, data_mapping::{DataJson, SnapshotJson}, exchange::ExchangeType, line::Line, orderbook::Snapshot, websocket::{ChannelSubscription, ChannelType, Symbol, WsClientMessage, WsClientMsgResult}}, services::manager_transmitter::{ManagerTransmitterCmd, NotifyEvent}};
+use crate::{config, models::{aggregator::{JsonPairData, JsonPairUniqueId, KeyMarketType, Volume}, data_mapping::SnapshotJson, exchange::ExchangeType, line::{Line, LineHistory}, orderbook::{EventTime, Snapshot}, websocket::{ChannelSubscription, ChannelType, Symbol, WsClientMessage, WsClientMsgResult}}, services::{lines_store::LinesSnapshot, manager_transmitter::{ManagerTransmitterCmd, NotifyEvent}, queue::CoalescingQueue, supervisor::Actor}};
 
-const MANAGER_TRANSMITTER_TIMEOUT_DELAY: u64 = 10; // ms
+/// Стаканы всех бирж одного символа
+pub type ExchangesData = Vec<(ExchangeType, Arc<Symbol>, (Option<Arc<Snapshot>>, Option<f64>, Option<f64>, Option<EventTime>))>;
 
 #[derive(Clone)]
 pub enum DataMappingCmd {
-    #[allow(unused)]
-    /// Это команда приводит `lines` в формат JSON и соединяет попарно (`Long Lines & Short Lines`)
-    LinesFromDataAccessLayer(Arc<RwLock<Arc<HashMap<(ExchangeType, ExchangeType), HashMap<Arc<Symbol>, Arc<RwLock<VecDeque<Line>>>>>>>>),
-    LinesToJsonPair(Arc<RwLock<VecDeque<Line>>>, Arc<RwLock<VecDeque<Line>>>, Arc<Symbol>, ExchangeType, ExchangeType),
-    LinesFromDbToJsonPair(HashMap<(ExchangeType, ExchangeType, Arc<Symbol>), VecDeque<Line>>),
+    /// Это команда отправляет последние `lines` каждой пары как `UpdateLine` (`Long Line & Short Line`)
+    LinesFromDataAccessLayer(Arc<LinesSnapshot>),
+    LinesToJsonPair(Arc<LineHistory>, Arc<LineHistory>, Arc<Symbol>, ExchangeType, ExchangeType),
+    LinesFromDbToJsonPair(HashMap<KeyMarketType, VecDeque<Line>>),
     /// Это команда приводит `ExchangesData` в формат JSON и соединяет попарно (`Long data & Short data`)
-    ExchangesDataToJsonPair(Vec<(ExchangeType, Arc<Symbol>, (Option<Arc<Snapshot>>, Option<f64>))>),
-    /// Это команда приводит `SpreadPair` в формат JSON и соединяет попарно (`Long Spread & Short Spread`)
-    SpreadPairToJsonPair(Arc<SpreadPair>),
-    /// Это команда приводит `Volumes` в формат JSON и соединяет попарно (`Long Volume & Short Volume`)
-    VolumesToJson(Vec<Volume>),
-    Default
+    ExchangesDataToJsonPair(ExchangesData),
 }
 
 pub struct DataMapping {
-    pub data_mapping_tx: watch::Sender<DataMappingCmd>,
-    data_mapping_rx: watch::Receiver<DataMappingCmd>,
-    manager_transmitter_tx: mpsc::Sender<ManagerTransmitterCmd>
+    /// Команды без потерь: при переполнении отправитель ждёт
+    pub data_mapping_tx: mpsc::Sender<DataMappingCmd>,
+    data_mapping_rx: mpsc::Receiver<DataMappingCmd>,
+    /// Стаканы по символам: непрочитанное состояние символа заменяется новым
+    pub books_queue: Arc<CoalescingQueue<Arc<Symbol>, ExchangesData>>,
+    manager_transmitter_tx: mpsc::Sender<ManagerTransmitterCmd>,
+
+    /// Последние отправленные объёмы, чтобы не слать `Volume24h` на каждое обновление стакана
+    last_volumes: HashMap<(ExchangeType, Arc<Symbol>), f64>,
+}
+
+#[async_trait]
+impl Actor for DataMapping {
+    async fn run(
+        &mut self,
+        shutdown: CancellationToken
+    ) {
+        loop {
+            tokio::select! {
+                _ = shutdown.cancelled() => return,
+                Some(cmd) = self.data_mapping_rx.recv() => {
+                    self.handle_command(cmd).await;
+                }
+                (_, markets) = self.books_queue.pop() => {
+                    self.handle_command(DataMappingCmd::ExchangesDataToJsonPair(markets)).await;
+                }
+            }
+        }
+    }
 }
 
 impl DataMapping {
     pub fn new(
         manager_transmitter_tx: mpsc::Sender<ManagerTransmitterCmd>
     ) -> Self {
-        let (data_mapping_tx, data_mapping_rx) = watch::channel::<DataMappingCmd>(DataMappingCmd::Default);
-        Self { 
+        let (data_mapping_tx, data_mapping_rx) = mpsc::channel::<DataMappingCmd>(config::get().pipeline.data_mapping_queue);
+        Self {
             data_mapping_tx,
             data_mapping_rx,
-            manager_transmitter_tx
+            books_queue: CoalescingQueue::new("data_mapping.books"),
+            manager_transmitter_tx,
+
+            last_volumes: HashMap::new(),
         }
     }
 
-    pub fn run(mut self) {
-        tokio::spawn(async move {
-            while let Ok(_) = self.data_mapping_rx.changed().await {
-                let cmd = self.data_mapping_rx.borrow().clone();
-                
-                match cmd {
-                    DataMappingCmd::LinesFromDataAccessLayer(
-                        data
-                    ) => {
-                        let lines = data.read().await;
-                        for (i, ((long_exchange, _), data)) in lines.iter().enumerate() {
-                            for ((short_long_exchange, _), short_data) in lines.iter().skip(i+1) {
-                                for (symbol, long_vec) in data {
-                                    for (short_symbol, short_vec) in short_data {
-                                        let long_vec = long_vec.read().await;
-                                        let short_vec = short_vec.read().await;
-
-                                        if symbol == short_symbol {
-                                            let long_json_lines = Arc::new(self.lines_to_json(&long_vec));
-                                            let short_json_lines = Arc::new(self.lines_to_json(&short_vec));
-
-                                            // self.send_message_with_key(
-                                            //     ChannelType::Chart,
-                                            //     *long_exchange,
-                                            //     DataJson::LinesHistory(long_json_lines.clone()),
-                                            //     *short_long_exchange,
-                                            //     DataJson::LinesHistory(short_json_lines.clone()),
-                                            //     symbol.clone(),
-                                            //     JsonPairUniqueId::LinesHistory
-                                            // ).await;
-
-                                            // self.send_message_with_key(
-                                            //     ChannelType::Chart,
-                                            //     *short_long_exchange,
-                                            //     DataJson::LinesHistory(short_json_lines),
-                                            //     *long_exchange,
-                                            //     DataJson::LinesHistory(long_json_lines),
-                                            //     symbol.clone(),
-                                            //     JsonPairUniqueId::LinesHistory
-                                            // ).await;
-                                        }
-                                    }
-                                }
-                            }
-                        }
-                    },
-                    DataMappingCmd::LinesToJsonPair(
-                        long_data,
-                        short_data,
-                        symbol,
-                        long_exchange, 
-                        short_exchange
-                    ) => {
-                        let long_lines = long_data.read().await;
-                        let short_lines = short_data.read().await;
-
-                        let long_json_lines = Arc::new(self.lines_to_json(&long_lines));
-                        let short_json_lines = Arc::new(self.lines_to_json(&short_lines));
-
-                        // self.send_message_with_key(
-                        //     ChannelType::Chart,
-                        //     long_exchange,
-                        //     DataJson::LinesHistory(long_json_lines.clone()),
-                        //     short_exchange,
-                        //     DataJson::LinesHistory(short_json_lines.clone()),
-                        //     symbol.clone(),
-                        //     JsonPairUniqueId::LinesHistory
-                        // ).await;
-
-                        // self.send_message_with_key(
-                        //     ChannelType::Chart,
-                        //     short_exchange,
-                        //     DataJson::LinesHistory(short_json_lines),
-                        //     long_exchange,
-                        //     DataJson::LinesHistory(long_json_lines),
-                        //     symbol.clone(),
-                        //     JsonPairUniqueId::LinesHistory
-                        // ).await;
-                    },
-                    DataMappingCmd::LinesFromDbToJsonPair(lines) => {
-                        for (i, ((long_exchange, short_exchnage, symbol), long_lines)) in lines.iter().enumerate() {
-                            for (_, short_lines) in lines.iter().skip(i+1) {
-                                let long_lines = Arc::new(self.lines_to_json(&long_lines));
-                                let short_lines = Arc::new(self.lines_to_json(&short_lines));
-                                
-                                // self.send_message_with_key(
-                                //     ChannelType::Chart,
-                                //     *long_exchange,
-                                //     DataJson::LinesHistory(long_lines.clone()),
-                                //     *short_exchnage,
-                                //     DataJson::LinesHistory(short_lines.clone()),
-                                //     symbol.clone(),
-                                //     JsonPairUniqueId::LinesHistory
-                                // ).await;
-
-                                // self.send_message_with_key(
-                                //     ChannelType::Chart,
-                                //     *short_exchnage,
-                                //     DataJson::LinesHistory(short_lines),
-                                //     *long_exchange,
-                                //     DataJson::LinesHistory(long_lines),
-                                //     symbol.clone(),
-                                //     JsonPairUniqueId::LinesHistory
-                                // ).await;
-                            }
-                        }
-                    },
-                    DataMappingCmd::ExchangesDataToJsonPair(
-                        markets
-                    ) => {
-                        let mut futures = Vec::new();
-                        for (i, (long_ex_id, symbol, (long_snapshot, long_last_price))) in markets.iter().enumerate() {
-                            for (short_ex_id, short_symbol, (short_snapshot, short_last_price)) in markets.iter().skip(i+1) {
-                                let long_json_lines = self.snapshot_to_json(long_snapshot, &long_last_price);
-                                let short_json_lines = self.snapshot_to_json(short_snapshot, &short_last_price);
-
-                                if let (
-                                    Some(long), 
-                                    Some(short)
-                                ) = (
-                                    long_json_lines, 
-                                    short_json_lines
-                                ) { 
-                                    let long_arc = Arc::new(long);
-                                    let short_arc = Arc::new(short);
-                                    
-                                    // self.send_message_with_key(
-                                    //     ChannelType::OrderBook,
-                                    //     *long_ex_id,
-                                    //     JsonPairData::OrderBook { long: long_arc.clone(), short: short_arc.clone() },
-                                    //     *short_ex_id,
-                                    //     symbol.clone(),
-                                    //     JsonPairUniqueId::OrderBook,
-                                    // ).await;
-
-                                    // self.send_message_with_key(
-                                    //     ChannelType::OrderBook,
-                                    //     *short_ex_id,
-                                    //     JsonPairData::OrderBook { long: short_arc.clone(), short: long_arc.clone() },
-                                    //     *long_ex_id,
-                                    //     short_symbol.clone(),
-                                    //     JsonPairUniqueId::OrderBook,
-                                    // ).await;
-
-                                    futures.push(self.send_message_with_key(
-                                        ChannelType::OrderBook,
-                                        *long_ex_id,
-                                        JsonPairData::OrderBook { long: long_arc.clone(), short: short_arc.clone() },
-                                        *short_ex_id,
-                                        symbol.clone(),
-                                        JsonPairUniqueId::OrderBook,
-                                    ));
-
-                                    futures.push(self.send_message_with_key(
-                                        ChannelType::OrderBook,
-                                        *short_ex_id,
-                                        JsonPairData::OrderBook { long: short_arc.clone(), short: long_arc.clone() },
-                                        *long_ex_id,
-                                        short_symbol.clone(),
-                                        JsonPairUniqueId::OrderBook,
-                                    ));
-                                }
-                            }
-                        }
+    async fn handle_command(
+        &mut self,
+        cmd: DataMappingCmd
+    ) {
+        match cmd {
+            DataMappingCmd::LinesFromDataAccessLayer(
+                snapshot
+            ) => {
+                let mut futures = Vec::new();
+                for (key, long_history) in snapshot.iter() {
+                    let reversed = KeyMarketType::new(key.short_exchange, key.long_exchange, key.symbol.clone());
+                    let short_history = snapshot.get(&reversed);
+
+                    if let (
+                        Some(long_line),
+                        Some(short_line)
+                    ) = (
+                        long_history.iter().last(),
+                        short_history.and_then(|history| history.iter().last())
+                    ) {
+                        futures.push(self.send_message_with_key(
+                            ChannelType::Chart,
+                            key.long_exchange,
+                            JsonPairData::UpdateLine {
+                                long: self.line_to_json(long_line.timestamp, long_line.value),
+                                short: self.line_to_json(short_line.timestamp, short_line.value),
+                            },
+                            key.short_exchange,
+                            key.symbol.clone(),
+                            JsonPairUniqueId::UpdateLine
+                        ));
+                    }
+                }
 
-                        let _ = join_all(futures).await;
-                    },
-                    DataMappingCmd::SpreadPairToJsonPair(
-                        spread_pair
-                    ) => {
-                        let long = serde_json::json!({
-                            "value": spread_pair.long_spread,
-                            "time": spread_pair.timestamp.clone()
-                        });
-
-                        let short = serde_json::json!({
-                            "value": spread_pair.short_spread,
-                            "time": spread_pair.timestamp.clone()
-                        });
-
-                        // self.send_message_with_key(
-                        //     ChannelType::Chart,
-                        //     spread_pair.long_exchange,
-                        //     DataJson::UpdateLine(long),
-                        //     spread_pair.short_exchange,
-                        //     DataJson::UpdateLine(short),
-                        //     spread_pair.symbol.clone(),
-                        //     JsonPairUniqueId::UpdateLine
-                        // ).await;
-                    }, 
-                    DataMappingCmd::VolumesToJson(
-                        volumes
-                    ) => {
-                        for (i, long_vol) in volumes.iter().enumerate() {
-                            for short_vol in volumes.iter().skip(i+1) {
-                                // self.send_message_with_key(
-                                //     ChannelType::Chart,
-                                //     *&long_vol.exchange_id,
-                                //     DataJson::Volume24h(serde_json::to_value(long_vol).unwrap()),
-                                //     *&short_vol.exchange_id,
-                                //     DataJson::Volume24h(serde_json::to_value(short_vol).unwrap()),
-                                //     long_vol.symbol.clone(),
-                                //     JsonPairUniqueId::Volume24h
-                                // ).await;
-
-                                // self.send_message_with_key(
-                                //     ChannelType::Chart,
-                                //     *&short_vol.exchange_id,
-                                //     DataJson::Volume24h(serde_json::to_value(short_vol).unwrap()),
-                                //     *&long_vol.exchange_id,
-                                //     DataJson::Volume24h(serde_json::to_value(long_vol).unwrap()),
-                                //     short_vol.symbol.clone(),
-                                //     JsonPairUniqueId::Volume24h
-                                // ).await;
-                            }
+                let _ = join_all(futures).await;
+            },
+            DataMappingCmd::LinesToJsonPair(
+                long_data,
+                short_data,
+                symbol,
+                long_exchange,
+                short_exchange
+            ) => {
+                let long_json_lines = self.lines_to_json(long_data.iter());
+                let short_json_lines = self.lines_to_json(short_data.iter());
+
+                self.send_lines_history(long_exchange, long_json_lines, short_exchange, short_json_lines, symbol).await;
+            },
+            DataMappingCmd::LinesFromDbToJsonPair(lines) => {
+                for (key, long_lines) in lines.iter() {
+                    if key.long_exchange > key.short_exchange {
+                        continue;
+                    }
+
+                    let reversed = KeyMarketType::new(key.short_exchange, key.long_exchange, key.symbol.clone());
+                    if let Some(short_lines) = lines.get(&reversed) {
+                        let long_json_lines = self.lines_to_json(long_lines.iter());
+                        let short_json_lines = self.lines_to_json(short_lines.iter());
+
+                        self.send_lines_history(key.long_exchange, long_json_lines, key.short_exchange, short_json_lines, key.symbol.clone()).await;
+                    }
+                }
+            },
+            DataMappingCmd::ExchangesDataToJsonPair(
+                markets
+            ) => {
+                let mut futures = Vec::new();
+                let max_skew = config::get().pipeline.spread_max_skew_ms;
+                for (i, (long_ex_id, symbol, (long_snapshot, long_last_price, _, long_time))) in markets.iter().enumerate() {
+                    for (short_ex_id, short_symbol, (short_snapshot, short_last_price, _, short_time)) in markets.iter().skip(i+1) {
+                        let long_json_lines = self.snapshot_to_json(long_snapshot, &long_last_price, long_time);
+                        let short_json_lines = self.snapshot_to_json(short_snapshot, &short_last_price, short_time);
+                        let skewed = match (long_time, short_time) {
+                            (Some(long_time), Some(short_time)) => long_time.skew(short_time) > max_skew,
+                            _ => false
+                        };
+
+                        if let (
+                            Some(long),
+                            Some(short)
+                        ) = (
+                            long_json_lines,
+                            short_json_lines
+                        ) {
+                            let long_arc = Arc::new(long);
+                            let short_arc = Arc::new(short);
+
+                            futures.push(self.send_message_with_key(
+                                ChannelType::OrderBook,
+                                *long_ex_id,
+                                JsonPairData::OrderBook { long: long_arc.clone(), short: short_arc.clone(), skewed },
+                                *short_ex_id,
+                                symbol.clone(),
+                                JsonPairUniqueId::OrderBook,
+                            ));
+
+                            futures.push(self.send_message_with_key(
+                                ChannelType::OrderBook,
+                                *short_ex_id,
+                                JsonPairData::OrderBook { long: short_arc.clone(), short: long_arc.clone(), skewed },
+                                *long_ex_id,
+                                short_symbol.clone(),
+                                JsonPairUniqueId::OrderBook,
+                            ));
                         }
-                    }, 
-                    DataMappingCmd::Default => {}
+                    }
+                }
+
+                let _ = join_all(futures).await;
+
+                let mut volumes = Vec::new();
+                let mut changed = false;
+                for (exchange_id, symbol, (_, _, volume, _)) in markets.iter() {
+                    if let Some(volume) = volume {
+                        let last = self.last_volumes.insert((*exchange_id, symbol.clone()), *volume);
+                        changed |= last != Some(*volume);
+                    }
+
+                    volumes.push(Volume {
+                        exchange_id: *exchange_id,
+                        value: *volume,
+                        symbol: symbol.clone()
+                    });
+                }
+
+                if changed {
+                    self.send_volumes(volumes).await;
                 }
+            },
+        }
+    }
+
+    /// Отправляет историю линий в обе стороны пары
+    async fn send_lines_history(
+        &self,
+        long_exchange: ExchangeType,
+        long_json_lines: Vec<Value>,
+        short_exchange: ExchangeType,
+        short_json_lines: Vec<Value>,
+        symbol: Arc<Symbol>,
+    ) {
+        let _ = join_all([
+            self.send_message_with_key(
+                ChannelType::Chart,
+                long_exchange,
+                JsonPairData::LinesHistory { long: long_json_lines.clone(), short: short_json_lines.clone() },
+                short_exchange,
+                symbol.clone(),
+                JsonPairUniqueId::LinesHistory
+            ),
+            self.send_message_with_key(
+                ChannelType::Chart,
+                short_exchange,
+                JsonPairData::LinesHistory { long: short_json_lines, short: long_json_lines },
+                long_exchange,
+                symbol,
+                JsonPairUniqueId::LinesHistory
+            ),
+        ]).await;
+    }
+
+    async fn send_volumes(
+        &self,
+        volumes: Vec<Volume>
+    ) {
+        let mut futures = Vec::new();
+        for (i, long_vol) in volumes.iter().enumerate() {
+            for short_vol in volumes.iter().skip(i+1) {
+                let long = serde_json::to_value(long_vol).unwrap_or_default();
+                let short = serde_json::to_value(short_vol).unwrap_or_default();
+
+                futures.push(self.send_message_with_key(
+                    ChannelType::Chart,
+                    long_vol.exchange_id,
+                    JsonPairData::Volume24h { long: long.clone(), short: short.clone() },
+                    short_vol.exchange_id,
+                    long_vol.symbol.clone(),
+                    JsonPairUniqueId::Volume24h
+                ));
+
+                futures.push(self.send_message_with_key(
+                    ChannelType::Chart,
+                    short_vol.exchange_id,
+                    JsonPairData::Volume24h { long: short, short: long },
+                    long_vol.exchange_id,
+                    short_vol.symbol.clone(),
+                    JsonPairUniqueId::Volume24h
+                ));
             }
-        });
+        }
+
+        let _ = join_all(futures).await;
     }
 
     fn snapshot_to_json(
         &self,
         map: &Option<Arc<Snapshot>>,
-        last_price: &Option<f64>
+        last_price: &Option<f64>,
+        time: &Option<EventTime>
     ) -> Option<SnapshotJson> {
         if let (Some(snapshot), Some(last_price)) = (map, last_price) {
-            let snapshot_ui = snapshot.to_ui(6, *last_price);
+            let snapshot_ui = snapshot.to_ui(config::get().pipeline.ui_depth, *last_price);
             let asks_json: Vec<Value> = self.ask_bid_to_json(snapshot_ui.a);
             let bids_json: Vec<Value> = self.ask_bid_to_json(snapshot_ui.b);
-            
+
             let snapshot = SnapshotJson {
                 asks: asks_json,
                 bids: bids_json,
-                last_price: OrderedFloat(*last_price)
+                last_price: OrderedFloat(*last_price),
+                time: *time
             };
 
             return Some(snapshot);
@@ -303,22 +301,28 @@ impl DataMapping {
             }).collect()
     }
 
-    fn lines_to_json(
+    fn line_to_json(
         &self,
-        map: &VecDeque<Line>
+        timestamp: i64,
+        value: f64,
+    ) -> Value {
+        serde_json::json!({
+            "time": timestamp,
+            "value": value
+        })
+    }
+
+    fn lines_to_json<'a>(
+        &self,
+        lines: impl Iterator<Item = &'a Line>
     ) -> Vec<Value> {
         let mut seen = HashSet::new();
-        
-        map
-            .into_iter()
+
+        lines
             .filter(|line| seen.insert(line.timestamp))
             .sorted_by_key(|l| l.timestamp)
-            .map(|line| {
-                serde_json::json!({
-                    "time": line.timestamp,
-                    "value": line.value
-                })
-            }).collect()
+            .map(|line| self.line_to_json(line.timestamp, line.value))
+            .collect()
     }
 
     async fn send_message_with_key(
@@ -330,35 +334,37 @@ impl DataMapping {
         symbol: Arc<Symbol>,
         unique_id: JsonPairUniqueId,
     ) {
+        let long_market_type = KeyMarketType::new(long_exchange, short_exchange, symbol.clone());
+        let short_market_type = KeyMarketType::new(short_exchange, long_exchange, symbol.clone());
+
+        // Ключ должен совпадать с тем, что клиент получил при подписке в ClientAggregator::sub_index
+        let channel_key = match channel {
+            ChannelType::Chart => ChannelSubscription::Chart {
+                long_market_type,
+                short_market_type
+            },
+            _ => ChannelSubscription::OrderBook {
+                long_market_type,
+                short_market_type
+            },
+        };
+
         let msg = WsClientMessage {
             channel: channel,
-            result: WsClientMsgResult { 
-                data: Arc::new(data), 
+            result: WsClientMsgResult {
+                data: Arc::new(data),
                 symbol: symbol.clone(),
                 unique_id: unique_id
             },
         };
 
-        let channel_key = ChannelSubscription::OrderBook { 
-            long_market_type: KeyMarketType { 
-                long_exchange: long_exchange, 
-                short_exchange: short_exchange, 
-                symbol: symbol.clone()
-            }, 
-            short_market_type: KeyMarketType { 
-                long_exchange: short_exchange, 
-                short_exchange: long_exchange, 
-                symbol: symbol.clone()
-            }, 
-        };
-
         let _ = self.manager_transmitter_tx.send(
             ManagerTransmitterCmd::Notify(
                 NotifyEvent::PayloadJson(
-                    channel_key.clone(), 
-                    msg.clone()
+                    channel_key,
+                    msg
                 )
-            ), 
+            ),
         ).await;
     }
 }
@@ -1,23 +1,74 @@
-use std::{collections::{BTreeMap}, sync::Arc};
+use std::{collections::{BTreeMap}, sync::Arc, time::Duration};
 use rust_decimal::Decimal;
-use tokio::sync::{Mutex, mpsc, watch};
+use tokio::sync::Mutex;
 use tokio_tungstenite::tungstenite::Message;
 
-use crate::{models::{exchange::{PriceCache, TickerInfo}, orderbook::OrderBookEventData, websocket::Symbol}, services::exchange::exchange_aggregator::ExchangeStoreCMD};
+use crate::{config::ExchangeConfig, models::{exchange::{PriceCache, TickerInfo}, orderbook::{EventTime, OrderBookEventData}, websocket::Symbol}, services::exchange::exchange_aggregator::BookEventSender};
+
+/// Ограничения биржи на публичные WebSocket подписки
+#[derive(Debug, Clone, Copy)]
+pub struct ExchangeLimits {
+    /// Топиков в одном соединении
+    pub topics_per_connection: usize,
+    /// Топиков, которые занимает символ с тикером и стаканом
+    pub topics_per_symbol: usize,
+    /// Аргументов (топиков или символов) в одном сообщении подписки
+    pub args_per_message: usize,
+    /// Исходящих сообщений в секунду на соединение
+    pub messages_per_second: u32,
+    pub max_connections: usize,
+}
+
+impl ExchangeLimits {
+    /// Сколько символов помещается в одно соединение вместе с будущими подписками на стакан
+    pub fn symbols_per_connection(&self) -> usize {
+        (self.topics_per_connection / self.topics_per_symbol.max(1)).max(1)
+    }
+}
+
+/// Адреса биржи. Значения по умолчанию задаёт адаптер, конфиг может их переопределить
+#[derive(Debug, Clone)]
+pub struct Endpoints {
+    pub ws_url: String,
+    /// Базовый REST адрес без пути
+    pub rest_url: String,
+}
+
+impl Endpoints {
+    pub fn new(
+        ws_url: &str,
+        rest_url: &str,
+        config: &ExchangeConfig
+    ) -> Self {
+        Self {
+            ws_url: config.ws_url.clone().unwrap_or_else(|| ws_url.into()),
+            rest_url: config.rest_url.clone().unwrap_or_else(|| rest_url.into()).trim_end_matches('/').into(),
+        }
+    }
+}
+
+/// Heartbeat уровня приложения: биржи молча закрывают соединение без ping,
+/// а пропущенные ответы означают, что соединение мертво
+#[derive(Debug, Clone, Copy)]
+pub struct Heartbeat {
+    pub interval: Duration,
+    /// Сколько ответов подряд можно пропустить до переподключения
+    pub max_missed: u32,
+}
 
 #[async_trait::async_trait]
 pub trait ExchangeAdapter: Send + Sync + 'static {
-    fn ws_url(self: Arc<Self>,) -> &'static str;
+    fn ws_url(self: Arc<Self>,) -> String;
     fn requires_auth(self: Arc<Self>) -> bool;
     async fn auth_url(self: Arc<Self>, client: &reqwest::Client) -> Option<url::Url>;
     async fn get_api_key(self: Arc<Self>, client: &reqwest::Client) -> Result<String, reqwest::Error>;
     async fn get_tickers(self: Arc<Self>, client: &reqwest::Client) -> Option<Vec<TickerInfo>>;
-    async fn get_snapshot_spot_http(self: Arc<Self>, tickers: &Vec<TickerInfo>, client: &reqwest::Client, sender_data: watch::Sender<ExchangeStoreCMD>);
-    async fn parse_message(self: Arc<Self>, msg: String, snapshot_channel: mpsc::Sender<ExchangeStoreCMD>, sender_data: watch::Sender<ExchangeStoreCMD>);
-    async fn parse_tickers(self: Arc<Self>, msg: Arc<String>, sender_data: watch::Sender<ExchangeStoreCMD>);
-    async fn parse_orderbook(self: Arc<Self>, msg: Arc<String>, snapshot_channel: mpsc::Sender<ExchangeStoreCMD>, sender_data: watch::Sender<ExchangeStoreCMD>);
-    async fn handle_snapshot<'a>(self: Arc<Self>, data: Option<OrderBookEventData<'a>>, snapshot_channel: mpsc::Sender<ExchangeStoreCMD>, sender_data: watch::Sender<ExchangeStoreCMD>);
-    async fn handle_delta<'a>(self: Arc<Self>, data: Option<OrderBookEventData<'a>>, sender_data: watch::Sender<ExchangeStoreCMD>);
+    async fn get_snapshot_spot_http(self: Arc<Self>, tickers: &Vec<TickerInfo>, client: &reqwest::Client, sender_data: BookEventSender);
+    async fn parse_message(self: Arc<Self>, msg: String, sender_data: BookEventSender);
+    async fn parse_tickers(self: Arc<Self>, msg: Arc<String>, sender_data: BookEventSender);
+    async fn parse_orderbook(self: Arc<Self>, msg: Arc<String>, sender_data: BookEventSender);
+    async fn handle_snapshot<'a>(self: Arc<Self>, data: Option<OrderBookEventData<'a>>, time: EventTime, sender_data: BookEventSender);
+    async fn handle_delta<'a>(self: Arc<Self>, data: Option<OrderBookEventData<'a>>, time: EventTime, sender_data: BookEventSender);
     fn cache(&self) -> &Arc<Mutex<PriceCache>>;
     async fn is_valid_price(self: Arc<Self>, last_price: f64, symbol: &Symbol) -> bool {        
         let mut price_cache = self.cache().lock().await;
@@ -36,5 +87,16 @@ pub trait ExchangeAdapter: Send + Sync + 'static {
     fn is_valid_book(self: Arc<Self>, asks: &BTreeMap<Decimal, f64>, bids: &BTreeMap<Decimal, f64>) -> bool {
         asks.len() > 1 && bids.len() > 1
     }
-    fn create_subscribe_messages(self: Arc<Self>, symbol: Arc<Symbol>) -> Vec<Message>;
+    fn limits(self: Arc<Self>) -> ExchangeLimits;
+    fn heartbeat(self: Arc<Self>) -> Heartbeat;
+    fn create_ping_message(self: Arc<Self>, id: u64) -> Message;
+    /// Ответ на `create_ping_message`. Pong фреймы WebSocket считаются ответом всегда
+    fn is_pong(self: Arc<Self>, msg: &str) -> bool;
+    /// Тикеры подписываются всегда. Символы упаковываются в сообщения по `args_per_message`
+    fn create_ticker_subscribe_messages(self: Arc<Self>, symbols: &[Arc<Symbol>]) -> Vec<Message>;
+    /// Символ убран из конфига без перезапуска сессии
+    fn create_ticker_unsubscribe_messages(self: Arc<Self>, symbols: &[Arc<Symbol>]) -> Vec<Message>;
+    /// Стакан подписывается только пока он кому-то нужен, см. `SubscriptionManager`
+    fn create_depth_subscribe_messages(self: Arc<Self>, symbols: &[Arc<Symbol>]) -> Vec<Message>;
+    fn create_depth_unsubscribe_messages(self: Arc<Self>, symbols: &[Arc<Symbol>]) -> Vec<Message>;
 }
\ No newline at end of file
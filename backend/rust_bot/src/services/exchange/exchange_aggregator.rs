@@ -1,9 +1,11 @@
-use std::{collections::BTreeMap, num::NonZeroUsize, sync::Arc};
+use std::{collections::{BTreeMap, HashMap, HashSet}, num::NonZeroUsize, sync::Arc};
 
+use async_trait::async_trait;
 use lru::LruCache;
 use rust_decimal::{Decimal, prelude::FromPrimitive};
-use tokio::sync::{mpsc, watch};
-use crate::models::{exchange::ExchangeType, exchange_aggregator::BookData, orderbook::{BookEvent, Delta, Snapshot, SnapshotUi}, websocket::Symbol};
+use tokio::sync::{mpsc::{self, error::TrySendError}, oneshot, watch};
+use tokio_util::sync::CancellationToken;
+use crate::{config, models::{exchange::ExchangeType, exchange_aggregator::BookData, orderbook::{BookEvent, Delta, EventTime, Snapshot, SnapshotUi}, websocket::{Symbol, normalize_symbol}}, services::{clock, exchange::{exchange_health::{HEALTH_INTERVAL, HealthHandle}, subscription_manager::SubscriptionHandle}, latency::LatencyHistogram, metrics::Metric, queue::{CoalescingQueue, QueueStats}, supervisor::Actor}};
 
 impl Snapshot {
     pub fn to_ui(&self, 
@@ -61,174 +63,433 @@ pub fn parse_levels__<'a>(data: Vec<Vec<&'a str>>) -> BTreeMap<Decimal, f64> {
     values
 }
 
-#[derive(Debug, Clone)]
+/// Очередь обновлений стаканов для DataAggregator, по одному состоянию на символ биржи
+pub type BookUpdatesQueue = CoalescingQueue<(ExchangeType, Arc<Symbol>), Arc<BookData>>;
+
+/// Сколько событий применяется к стаканам перед публикацией изменённых символов
+const EVENTS_BATCH: usize = 256;
+
 pub enum ExchangeStoreCMD {
     Event(BookEvent),
     RegisterSymbol {
         symbol: Arc<Symbol>
     },
+    /// Символ убран из сессии биржи. Подписчики стакана остаются и получат его, если символ вернётся
+    UnregisterSymbol {
+        symbol: Arc<Symbol>
+    },
+    /// Текущий стакан символа целиком
+    GetBook {
+        symbol: Arc<Symbol>,
+        reply: oneshot::Sender<Option<BookData>>
+    },
+    /// Сбрасывает стакан до нового снапшота от биржи
+    ResetBook {
+        symbol: Arc<Symbol>
+    },
+    /// Подписка на стакан одного символа
     Subscribe {
-        reply: mpsc::Sender<watch::Receiver<Arc<BookData>>>
+        symbol: Arc<Symbol>,
+        reply: oneshot::Sender<watch::Receiver<Arc<BookData>>>
     },
-    Default
-} 
+}
+
+/// <b>BookEventSender</b> отправляет команды в `ExchangeStore` через ограниченную очередь.
+///
+/// При переполнении очереди:
+/// <br>• `Snapshot`, `Delta` и служебные команды не теряются - отправитель ждёт, и чтение сокета замедляется
+/// <br>• `TickerUpdate` отбрасывается - следующий тикер несёт полное состояние
+#[derive(Clone)]
+pub struct BookEventSender {
+    tx: mpsc::Sender<ExchangeStoreCMD>,
+    stats: Arc<QueueStats>,
+    parse_errors: Arc<Metric>,
+}
+
+impl BookEventSender {
+    pub fn channel(
+        exchange_id: ExchangeType
+    ) -> (Self, mpsc::Receiver<ExchangeStoreCMD>) {
+        let (tx, rx) = mpsc::channel(config::get().pipeline.exchange_store_queue);
+        let stats = QueueStats::register(format!("exchange_store.{}", exchange_id));
+        let parse_errors = Metric::counter("exchange_parse_errors_total", "Сообщения биржи, которые адаптер не смог разобрать", &[("exchange", &exchange_id.to_string())]);
+
+        (Self { tx, stats, parse_errors }, rx)
+    }
+
+    /// Сообщение биржи пропускается, соединение продолжает работать
+    pub fn parse_error(
+        &self,
+        error: impl std::fmt::Display
+    ) {
+        self.parse_errors.inc();
+        tracing::debug!("BookEventSender -> не удалось разобрать сообщение: {error}");
+    }
+
+    pub async fn send(
+        &self,
+        event: BookEvent
+    ) {
+        match event {
+            BookEvent::TickerUpdate { .. } => {
+                self.try_send(ExchangeStoreCMD::Event(event));
+            },
+            _ => {
+                self.send_lossless(ExchangeStoreCMD::Event(event)).await;
+            }
+        }
+    }
+
+    pub async fn register_symbol(
+        &self,
+        symbol: Arc<Symbol>
+    ) {
+        self.send_lossless(ExchangeStoreCMD::RegisterSymbol { symbol }).await;
+    }
+
+    pub async fn unregister_symbol(
+        &self,
+        symbol: Arc<Symbol>
+    ) {
+        self.send_lossless(ExchangeStoreCMD::UnregisterSymbol { symbol }).await;
+    }
+
+    pub async fn book(
+        &self,
+        symbol: Arc<Symbol>
+    ) -> Option<BookData> {
+        let (reply, rx) = oneshot::channel();
+        self.send_lossless(ExchangeStoreCMD::GetBook { symbol, reply }).await;
+
+        rx.await.ok().flatten()
+    }
+
+    pub async fn reset_book(
+        &self,
+        symbol: Arc<Symbol>
+    ) {
+        self.send_lossless(ExchangeStoreCMD::ResetBook { symbol }).await;
+    }
+
+    pub async fn subscribe(
+        &self,
+        symbol: Arc<Symbol>
+    ) -> Option<watch::Receiver<Arc<BookData>>> {
+        let (reply, rx) = oneshot::channel();
+        self.send_lossless(ExchangeStoreCMD::Subscribe { symbol, reply }).await;
+
+        rx.await.ok()
+    }
+
+    fn try_send(
+        &self,
+        cmd: ExchangeStoreCMD
+    ) {
+        match self.tx.try_send(cmd) {
+            Ok(_) => self.stats.add_sent(),
+            Err(TrySendError::Full(_)) => self.stats.add_dropped(),
+            Err(TrySendError::Closed(_)) => {}
+        }
+    }
+
+    async fn send_lossless(
+        &self,
+        cmd: ExchangeStoreCMD
+    ) {
+        match self.tx.try_send(cmd) {
+            Ok(_) => self.stats.add_sent(),
+            Err(TrySendError::Full(cmd)) => {
+                self.stats.add_backpressure();
+                if self.tx.send(cmd).await.is_ok() {
+                    self.stats.add_sent();
+                }
+            },
+            Err(TrySendError::Closed(_)) => {}
+        }
+    }
+}
 
 pub struct ExchangeStore {
     pub market_data: LruCache<Symbol, BookData>,
-    pub rx: watch::Receiver<ExchangeStoreCMD>,
-    pub register_channel_rx: mpsc::Receiver<ExchangeStoreCMD>,
-    
-    #[allow(unused)]
+    pub rx: mpsc::Receiver<ExchangeStoreCMD>,
+
     id: ExchangeType,
-    watch_tx: watch::Sender<Arc<BookData>>,
-    watch_rx: watch::Receiver<Arc<BookData>>
+    /// Переподписка после пропуска дельт. При воспроизведении её нет: стакан ждёт снапшота из записи
+    subscriptions: Option<SubscriptionHandle>,
+    /// Каналы стаканов по символам. Стакан без подписчиков обновляется, но не клонируется и не публикуется
+    books_tx: HashMap<Symbol, watch::Sender<Arc<BookData>>>,
+    /// Символы, изменённые в текущей пачке событий
+    changed: HashSet<Symbol>,
+
+    health: HealthHandle,
+    /// Сообщения и время последнего обновления символов с прошлого отчёта в `HealthMonitor`
+    messages: u64,
+    activity: HashMap<Symbol, i64>,
+    /// Задержка от биржевого времени события до получения
+    feed_latency: Arc<LatencyHistogram>,
+    book_updates: Arc<Metric>,
+    book_gaps: Arc<Metric>,
+    evictions: Arc<Metric>,
 }
 
 impl ExchangeStore {
     pub fn new(
-        rx: watch::Receiver<ExchangeStoreCMD>,
-        register_channel_rx: mpsc::Receiver<ExchangeStoreCMD>,
-        id: ExchangeType
+        rx: mpsc::Receiver<ExchangeStoreCMD>,
+        id: ExchangeType,
+        subscriptions: Option<SubscriptionHandle>,
+        health: HealthHandle
     ) -> Self {
-        let cache_capacity = std::env::var("ORDERBOOK_CACHE_CAPACITY")
-            .unwrap_or_else(|_| "1000".into())
-            .parse::<usize>()
-            .expect("ORDERBOOK_CACHE_CAPACITY must be a number");
-
+        let cache_capacity = config::get().pipeline.orderbook_cache_capacity;
         let market_data = LruCache::new(NonZeroUsize::new(cache_capacity).unwrap());
-        let (watch_tx, watch_rx) = watch::channel(Arc::new(BookData::new()));
 
         Self {
             market_data,
-
-            rx: rx,
-            watch_tx, watch_rx,
-            register_channel_rx,
+            rx,
 
             id,
+            subscriptions,
+            books_tx: HashMap::new(),
+            changed: HashSet::new(),
+
+            health,
+            messages: 0,
+            activity: HashMap::new(),
+            feed_latency: LatencyHistogram::register(format!("feed.{}", id)),
+            book_updates: Metric::counter("exchange_book_updates_total", "Снапшоты и дельты, применённые к стаканам", &[("exchange", &id.to_string())]),
+            book_gaps: Metric::counter("exchange_book_gaps_total", "Пропуски в последовательности дельт стакана", &[("exchange", &id.to_string())]),
+            evictions: Metric::counter("exchange_book_evictions_total", "Стаканы, вытесненные из LRU кеша ExchangeStore", &[("exchange", &id.to_string())]),
         }
     }
 
-    pub async fn set_data(
-        mut self,
+}
+
+/// Каждое событие применяется к стакану, а подписчикам символа уходит
+/// одно состояние за пачку событий
+#[async_trait]
+impl Actor for ExchangeStore {
+    async fn run(
+        &mut self,
+        shutdown: CancellationToken
     ) {
-        let last_version_id = 0;
+        let mut batch = Vec::with_capacity(EVENTS_BATCH);
+        let mut health_interval = tokio::time::interval(HEALTH_INTERVAL);
 
         loop {
             tokio::select! {
-                Some(cmd) = self.register_channel_rx.recv() => {
-                    match cmd {
-                        ExchangeStoreCMD::RegisterSymbol { 
-                            symbol 
-                        } => {                    
-                            // Разобрать с normilize_symbol;
-                            let symbol: String = symbol
-                                .chars()
-                                .filter(|c| *c != '_' && *c != '-')
-                                .map(|c| c.to_ascii_lowercase())
-                                .collect();
-                        
-                            self.market_data.put(symbol.clone(), BookData::new());
-                        },
-                        ExchangeStoreCMD::Event(event) => {
-                            match event {
-                                BookEvent::Snapshot { 
-                                    symbol, 
-                                    snapshot  
-                                } => {
-                                    self.handle_snaphsot(symbol, snapshot);
-                                },
-                                _ => {}
-                            }
+                _ = shutdown.cancelled() => return,
+                received = self.rx.recv_many(&mut batch, EVENTS_BATCH) => {
+                    if received == 0 {
+                        return;
+                    }
+
+                    for cmd in batch.drain(..) {
+                        if matches!(cmd, ExchangeStoreCMD::Event(_)) {
+                            self.messages += 1;
                         }
-                        _ => {}
+                        self.handle_command(cmd);
                     }
+
+                    self.mark_updated();
+                    self.publish_changed();
                 },
+                _ = health_interval.tick() => {
+                    self.health.activity(self.id, self.messages, std::mem::take(&mut self.activity));
+                    self.messages = 0;
+                }
+            }
+        }
+    }
+}
 
-                Ok(_) = self.rx.changed() => {
-                    let cmd = self.rx.borrow().clone();
-
-                    match cmd {
-                        ExchangeStoreCMD::Default => {}
-                        ExchangeStoreCMD::Event(event) => {
-                            match event {
-                                BookEvent::Snapshot { 
-                                    symbol, snapshot  
-                                } => {
-                                    self.handle_snaphsot(symbol, snapshot);
-                                }
-                                BookEvent::Delta { 
-                                    symbol, delta 
-                                } => {
-                                    self.handle_delta(symbol, delta, last_version_id);
-                                },
-                                BookEvent::TickerUpdate { 
-                                    symbol, last_price, volume 
-                                } => {
-                                    self.ticker_updater(symbol, last_price, volume);
-                                },
-                            }
-                        },
-                        ExchangeStoreCMD::Subscribe { 
-                            reply
-                        } => {             
-                            reply.send(self.watch_rx.clone()).await.ok();
-                        },
-                        _ => {}
+impl ExchangeStore {
+    /// Отмечает время обновления символов текущей пачки
+    fn mark_updated(
+        &mut self,
+    ) {
+        let now = clock::instant();
+        let now_ms = clock::now_ms();
+
+        for symbol in self.changed.iter() {
+            if let Some(data) = self.market_data.peek_mut(symbol) {
+                data.updated_at = Some(now);
+            }
+            self.activity.insert(symbol.clone(), now_ms);
+        }
+    }
+
+    fn handle_command(
+        &mut self,
+        cmd: ExchangeStoreCMD
+    ) {
+        match cmd {
+            ExchangeStoreCMD::RegisterSymbol { 
+                symbol 
+            } => {                    
+                let symbol = normalize_symbol(&symbol);
+
+                // `push` возвращает и заменённый стакан того же символа, это не вытеснение
+                if let Some((evicted, _)) = self.market_data.push(symbol.clone(), BookData::new()) {
+                    if evicted != symbol {
+                        self.evictions.inc();
                     }
                 }
+            },
+            ExchangeStoreCMD::UnregisterSymbol { 
+                symbol 
+            } => {
+                let symbol = normalize_symbol(&symbol);
+
+                self.market_data.pop(&symbol);
+                self.changed.remove(&symbol);
+                self.activity.remove(&symbol);
+            },
+            ExchangeStoreCMD::Event(event) => {
+                match event {
+                    BookEvent::Snapshot { 
+                        symbol, snapshot, time
+                    } => {
+                        self.record_latency(&time);
+                        self.book_updates.inc();
+                        self.handle_snaphsot(symbol, snapshot, time);
+                    }
+                    BookEvent::Delta { 
+                        symbol, delta, time
+                    } => {
+                        self.record_latency(&time);
+                        self.book_updates.inc();
+                        self.handle_delta(symbol, delta, time);
+                    },
+                    BookEvent::TickerUpdate { 
+                        symbol, last_price, volume, time
+                    } => {
+                        self.record_latency(&time);
+                        self.ticker_updater(symbol, last_price, volume, time);
+                    },
+                }
+            },
+            ExchangeStoreCMD::GetBook { 
+                symbol, 
+                reply 
+            } => {
+                let _ = reply.send(self.market_data.peek(&*symbol).cloned());
+            },
+            ExchangeStoreCMD::ResetBook { 
+                symbol 
+            } => {
+                if let Some(data) = self.market_data.peek_mut(&*symbol) {
+                    data.snapshot = None;
+                    self.changed.insert(symbol.to_string());
+                }
+            },
+            ExchangeStoreCMD::Subscribe { 
+                symbol,
+                reply
+            } => {
+                let rx = match self.books_tx.get(&*symbol) {
+                    Some(book_tx) => book_tx.subscribe(),
+                    None => {
+                        // Новый подписчик сразу получает текущее состояние стакана
+                        let data = self.market_data
+                            .peek(&*symbol)
+                            .map(|data| data.to_owned())
+                            .unwrap_or_else(BookData::new);
+
+                        let (book_tx, rx) = watch::channel(Arc::new(data));
+                        self.books_tx.insert(symbol.to_string(), book_tx);
+                        rx
+                    }
+                };
+
+                let _ = reply.send(rx);
+            },
+        }
+    }
+
+    fn publish_changed(
+        &mut self,
+    ) {
+        for symbol in self.changed.drain() {
+            let Some(book_tx) = self.books_tx.get(&symbol) else { continue };
+            if book_tx.receiver_count() == 0 {
+                self.books_tx.remove(&symbol);
+                continue;
+            }
+
+            if let Some(data) = self.market_data.peek(&symbol) {
+                book_tx.send_replace(Arc::new(data.to_owned()));
             }
         }
     }
 
+    fn record_latency(
+        &self,
+        time: &EventTime
+    ) {
+        if let Some(latency) = time.feed_latency() {
+            self.feed_latency.record(latency);
+        }
+    }
+
     fn handle_snaphsot(
         &mut self,
         symbol: Symbol,
-        snapshot: Snapshot
+        snapshot: Snapshot,
+        time: EventTime
     ) {
         if let Some(data) = self.market_data.get_mut(&*symbol) {
             data.snapshot = Some(snapshot);
-            data.symbol = Arc::new(symbol);
-            let _ = self.watch_tx.send(Arc::new(data.to_owned()));
+            data.time = Some(time);
+            data.symbol = Arc::new(symbol.clone());
+            self.changed.insert(symbol);
         }
     }
 
+    /// Дельта с версиями продолжает стакан, если начинается не позже следующей за `last_update_id` версии.
+    /// Уже учтённые дельты пропускаются, после пропуска стакан сбрасывается до нового снапшота.
+    /// Дельты без версий применяются по порядку прихода
     fn handle_delta(
         &mut self,
         symbol: Symbol,
         delta: Delta,
-        mut _last_version_id: u64
+        time: EventTime
     ) {
-        if let Some(data) = self.market_data.get_mut(&symbol) {
-            if let Some(snapshot) = &mut data.snapshot {
-                match snapshot.last_update_id {
-                    Some(_) => {
-                        let from_version = delta.from_version.unwrap();
-                        let to_version = delta.to_version.unwrap();
-                        _last_version_id = to_version + 1;  
-
-                        if _last_version_id == 0 {   
-                            return;
-                        }
-                        
-                        Self::handle_delta_data(delta, snapshot);
-                        
-                        if from_version != _last_version_id {
-                            tracing::error!("[OrderBookManager]: Packet loss detected");
-                            return;
-                        }
-                        let _ = self.watch_tx.send(Arc::new(data.to_owned())); 
-                    }
-                    None => {
-                        Self::handle_delta_data(delta, snapshot);
-                        let _ = self.watch_tx.send(Arc::new(data.to_owned()));
-                    }
-                } 
+        let Some(data) = self.market_data.get_mut(&symbol) else { return };
+        let Some(snapshot) = &mut data.snapshot else { return };
+
+        if let (Some(last_version), Some(from_version), Some(to_version)) = (snapshot.last_update_id, delta.from_version, delta.to_version) {
+            if to_version <= last_version {
+                return;
             }
+
+            if from_version > last_version + 1 {
+                self.book_gaps.inc();
+                tracing::warn!("ExchangeStore({}) -> {symbol}: пропущены версии {}..{}, стакан запрошен заново", self.id, last_version + 1, from_version - 1);
+                data.snapshot = None;
+                self.changed.insert(symbol.clone());
+                self.resync(symbol);
+                return;
+            }
+
+            snapshot.last_update_id = Some(to_version);
         }
+
+        Self::handle_delta_data(delta, snapshot);
+        data.time = Some(time);
+        self.changed.insert(symbol);
+    }
+
+    /// Переподписка на стакан, чтобы биржа прислала новый снапшот
+    fn resync(
+        &self,
+        symbol: Symbol
+    ) {
+        let Some(subscriptions) = self.subscriptions.clone() else { return };
+        tokio::spawn(async move {
+            subscriptions.resync(Arc::new(symbol)).await;
+        });
     }
 
-    fn handle_delta_data(
+    pub fn handle_delta_data(
         delta: Delta,
         snapshot: &mut Snapshot
     ) {
@@ -261,12 +522,191 @@ impl ExchangeStore {
         &mut self,
         symbol: Symbol,
         last_price: f64,
-        volume: f64
+        volume: f64,
+        time: EventTime
     ) {
         if let Some(data) = self.market_data.get_mut(&symbol) {
             data.last_price = Some(last_price);
             data.volume24h = Some(volume);
-            let _ = self.watch_tx.send(Arc::new(data.to_owned()));
+            data.time = Some(time);
+            self.changed.insert(symbol);
         }
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use std::{sync::atomic::Ordering, time::Duration};
+    use super::*;
+    use crate::{config::Config, services::{exchange::{exchange_health::HealthMonitor, subscription_manager::SubscriptionCmd}, supervisor::Supervisor}, tests::lock_config};
+
+    const DELTAS: usize = 10_000;
+
+    /// Очередь `ExchangeStore` меньше потока дельт, а подписчик стакана читает медленно:
+    /// отправитель ждёт вместо потерь, каждая дельта применяется, подписчик получает итоговый стакан
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn every_delta_is_applied_under_backpressure() {
+        let mut config = Config::default();
+        config.pipeline.exchange_store_queue = 8;
+        let _config = lock_config(config).await;
+
+        let exchange_id = ExchangeType::BinX;
+        let symbol = Arc::new(Symbol::from("stressusdt"));
+
+        let supervisor = Supervisor::new();
+        let (health_monitor, health) = HealthMonitor::new();
+        supervisor.spawn_actor("HealthMonitor", health_monitor);
+
+        let (sender, rx) = BookEventSender::channel(exchange_id);
+        let store = ExchangeStore::new(rx, exchange_id, None, health);
+
+        // Счётчики общие для процесса, поэтому сравниваются приращения
+        let stats = sender.stats.clone();
+        let book_updates = store.book_updates.clone();
+        let book_gaps = store.book_gaps.clone();
+        let sent = stats.sent.load(Ordering::Relaxed);
+        let dropped = stats.dropped.load(Ordering::Relaxed);
+        let updates = book_updates.get();
+        let gaps = book_gaps.get();
+
+        let producer = tokio::spawn({
+            let sender = sender.clone();
+            let symbol = symbol.clone();
+            async move {
+                sender.register_symbol(symbol.clone()).await;
+                sender.send(BookEvent::Snapshot {
+                    symbol: symbol.to_string(),
+                    snapshot: Snapshot { a: BTreeMap::new(), b: BTreeMap::new(), last_update_id: Some(0) },
+                    time: EventTime::received(None),
+                }).await;
+
+                // Версии идут подряд: под давлением очереди ни одна не теряется и не переставляется
+                for i in 0..DELTAS {
+                    sender.send(BookEvent::Delta {
+                        symbol: symbol.to_string(),
+                        delta: Delta {
+                            a: BTreeMap::from([(Decimal::from(1000 + i), 1.0)]),
+                            b: BTreeMap::new(),
+                            from_version: Some(i as u64 + 1),
+                            to_version: Some(i as u64 + 1),
+                        },
+                        time: EventTime::received(None),
+                    }).await;
+                }
+            }
+        });
+
+        // Хранилище запускается, только когда отправитель уже упёрся в полную очередь
+        while stats.backpressure.load(Ordering::Relaxed) == 0 {
+            tokio::time::sleep(Duration::from_millis(1)).await;
+        }
+        supervisor.spawn_actor("ExchangeStore", store);
+
+        let mut book_rx = sender.subscribe(symbol.clone()).await.unwrap();
+        let reader = tokio::spawn(async move {
+            let mut reads = 0;
+            loop {
+                let levels = book_rx.borrow_and_update().snapshot.as_ref().map_or(0, |snapshot| snapshot.a.len());
+                if levels == DELTAS {
+                    return reads;
+                }
+
+                reads += 1;
+                tokio::time::sleep(Duration::from_millis(5)).await;
+                if book_rx.changed().await.is_err() {
+                    return reads;
+                }
+            }
+        });
+
+        producer.await.unwrap();
+        let reads = tokio::time::timeout(Duration::from_secs(30), reader).await.unwrap().unwrap();
+
+        let book = sender.book(symbol.clone()).await.unwrap();
+        let snapshot = book.snapshot.unwrap();
+        assert_eq!(snapshot.a.len(), DELTAS);
+        assert!(snapshot.a.iter().enumerate().all(|(i, (price, volume))| *price == Decimal::from(1000 + i) && *volume == 1.0));
+        assert_eq!(snapshot.last_update_id, Some(DELTAS as u64));
+        assert!(reads < DELTAS, "медленный подписчик получает последнее состояние, а не каждую дельту");
+
+        // Регистрация, снапшот, дельты, подписка и запрос стакана
+        assert_eq!(stats.sent.load(Ordering::Relaxed) - sent, DELTAS as u64 + 4);
+        assert_eq!(stats.dropped.load(Ordering::Relaxed) - dropped, 0);
+        assert_eq!(book_updates.get() - updates, DELTAS as i64 + 1);
+        assert_eq!(book_gaps.get() - gaps, 0);
+
+        supervisor.shutdown();
+    }
+
+    fn versioned(
+        price: i64,
+        from_version: u64,
+        to_version: u64
+    ) -> ExchangeStoreCMD {
+        ExchangeStoreCMD::Event(BookEvent::Delta {
+            symbol: "versionusdt".into(),
+            delta: Delta {
+                a: BTreeMap::from([(Decimal::from(price), 1.0)]),
+                b: BTreeMap::new(),
+                from_version: Some(from_version),
+                to_version: Some(to_version),
+            },
+            time: EventTime::received(None),
+        })
+    }
+
+    fn levels(
+        store: &ExchangeStore
+    ) -> Option<Vec<i64>> {
+        let snapshot = store.market_data.peek("versionusdt")?.snapshot.as_ref()?;
+        Some(snapshot.a.keys().map(|price| price.to_string().parse().unwrap()).collect())
+    }
+
+    /// Дельты с версиями идут от `last_update_id` снапшота, старые пропускаются,
+    /// а пропуск версий сбрасывает стакан и переподписывает его
+    #[tokio::test]
+    async fn versioned_deltas_follow_snapshot_and_resync_on_gap() {
+        let _config = lock_config(Config::default()).await;
+        let (_, health) = HealthMonitor::new();
+        let (subscriptions, mut subscriptions_rx) = SubscriptionHandle::channel();
+        let (_sender, rx) = BookEventSender::channel(ExchangeType::Mexc);
+        let mut store = ExchangeStore::new(rx, ExchangeType::Mexc, Some(subscriptions), health);
+        let gaps = store.book_gaps.get();
+
+        store.handle_command(ExchangeStoreCMD::RegisterSymbol { symbol: Arc::new("versionusdt".into()) });
+        store.handle_command(ExchangeStoreCMD::Event(BookEvent::Snapshot {
+            symbol: "versionusdt".into(),
+            snapshot: Snapshot { a: BTreeMap::from([(Decimal::from(100), 1.0)]), b: BTreeMap::new(), last_update_id: Some(10) },
+            time: EventTime::received(None),
+        }));
+
+        // Первая дельта может начинаться до снапшота, если заканчивается после него
+        store.handle_command(versioned(101, 8, 11));
+        store.handle_command(versioned(102, 12, 14));
+        // Уже учтена в снапшоте
+        store.handle_command(versioned(99, 5, 9));
+        // Без версий применяется как есть
+        store.handle_command(ExchangeStoreCMD::Event(BookEvent::Delta {
+            symbol: "versionusdt".into(),
+            delta: Delta { a: BTreeMap::from([(Decimal::from(103), 1.0)]), b: BTreeMap::new(), from_version: None, to_version: None },
+            time: EventTime::received(None),
+        }));
+
+        assert_eq!(levels(&store), Some(vec![100, 101, 102, 103]));
+        assert_eq!(store.market_data.peek("versionusdt").unwrap().snapshot.as_ref().unwrap().last_update_id, Some(14));
+        assert_eq!(store.book_gaps.get() - gaps, 0);
+        assert!(subscriptions_rx.try_recv().is_err());
+
+        // Версия 15 потеряна
+        store.handle_command(versioned(104, 16, 17));
+        assert_eq!(levels(&store), None);
+        assert_eq!(store.book_gaps.get() - gaps, 1);
+
+        let cmd = tokio::time::timeout(Duration::from_secs(5), subscriptions_rx.recv()).await.unwrap().unwrap();
+        assert!(matches!(cmd, SubscriptionCmd::Resync { symbol, .. } if symbol.as_str() == "versionusdt"));
+
+        // До нового снапшота дельты не применяются
+        store.handle_command(versioned(105, 18, 18));
+        assert_eq!(levels(&store), None);
+        assert_eq!(store.book_gaps.get() - gaps, 1);
+    }
+}
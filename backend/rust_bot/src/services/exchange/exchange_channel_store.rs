@@ -1,25 +1,38 @@
 use std::{collections::HashMap};
+use async_trait::async_trait;
 use tokio::sync::{mpsc, oneshot, watch};
-use crate::{models::exchange::ExchangeType, services::exchange::exchange_aggregator::ExchangeStoreCMD};
+use tokio_util::sync::CancellationToken;
+use crate::{models::exchange::ExchangeType, services::{exchange::{exchange_aggregator::BookEventSender, subscription_manager::SubscriptionHandle}, supervisor::Actor}};
+
+/// Каналы одной биржи
+#[derive(Clone)]
+pub struct ExchangeHandle {
+    pub store: BookEventSender,
+    pub subscriptions: SubscriptionHandle,
+}
 
 pub enum ExchangeChannelStoreCmd {
     RegisterChannel {
         exchange_id: ExchangeType,
-        channel: watch::Sender<ExchangeStoreCMD>
+        channel: ExchangeHandle
+    },
+    /// Биржа остановлена, её каналы закрываются
+    RemoveExchange {
+        exchange_id: ExchangeType
     },
     
     GetExchangesChannel {
-        reply: oneshot::Sender<watch::Receiver<HashMap<ExchangeType, watch::Sender<ExchangeStoreCMD>>>>
+        reply: oneshot::Sender<watch::Receiver<HashMap<ExchangeType, ExchangeHandle>>>
     },
 }
 
 pub struct ExchangeChannelStore {
-    exchanges_channel: HashMap<ExchangeType, watch::Sender<ExchangeStoreCMD>>,
+    exchanges_channel: HashMap<ExchangeType, ExchangeHandle>,
 
     pub sender_channel: mpsc::Sender<ExchangeChannelStoreCmd>,
     receiver_channel: mpsc::Receiver<ExchangeChannelStoreCmd>,
-    watch: watch::Sender<HashMap<ExchangeType, watch::Sender<ExchangeStoreCMD>>>,
-    watch_rx: watch::Receiver<HashMap<ExchangeType, watch::Sender<ExchangeStoreCMD>>>
+    watch: watch::Sender<HashMap<ExchangeType, ExchangeHandle>>,
+    watch_rx: watch::Receiver<HashMap<ExchangeType, ExchangeHandle>>
 }
 
 impl ExchangeChannelStore {
@@ -35,10 +48,19 @@ impl ExchangeChannelStore {
             watch, watch_rx
         }
     }
+}
 
-    pub async fn run(mut self) {
+#[async_trait]
+impl Actor for ExchangeChannelStore {
+    async fn run(
+        &mut self,
+        shutdown: CancellationToken
+    ) {
         loop {
-            if let Some(cmd) = self.receiver_channel.recv().await {
+            if let Some(cmd) = tokio::select! {
+                cmd = self.receiver_channel.recv() => cmd,
+                _ = shutdown.cancelled() => return
+            } {
                 match cmd {
                     ExchangeChannelStoreCmd::RegisterChannel { 
                         exchange_id, 
@@ -47,6 +69,13 @@ impl ExchangeChannelStore {
                         self.exchanges_channel.insert(exchange_id, channel);
                         self.watch.send(self.exchanges_channel.clone()).ok();
                     },
+                    ExchangeChannelStoreCmd::RemoveExchange { 
+                        exchange_id 
+                    } => {
+                        if self.exchanges_channel.remove(&exchange_id).is_some() {
+                            self.watch.send(self.exchanges_channel.clone()).ok();
+                        }
+                    },
                     ExchangeChannelStoreCmd::GetExchangesChannel { 
                         reply 
                     } => {
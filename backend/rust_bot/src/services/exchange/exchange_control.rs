@@ -0,0 +1,243 @@
+use std::{collections::HashMap, path::PathBuf, sync::Arc, time::Duration};
+use anyhow::{anyhow, bail};
+use async_trait::async_trait;
+use tokio::sync::{mpsc, oneshot};
+use tokio_util::sync::CancellationToken;
+use tracing::info;
+use crate::{adapters::{bybit_adapter::BybitAdapter, gate_adapter::GateAdapter, kucoin_adapter::KuCoinAdapter}, config::{self, Config, ExchangeConfig, SUPPORTED_EXCHANGES}, models::exchange::ExchangeType, services::{data_aggregator::DataAggregatorCmd, exchange::{exchange_adapter::ExchangeAdapter, exchange_channel_store::ExchangeChannelStoreCmd, exchange_health::HealthHandle, exchange_setup::{ExchangeSession, ExchangeSetup, SessionStatus}}, supervisor::{Actor, Supervisor}}};
+
+/// Как часто проверяется время изменения файла конфигурации
+const CONFIG_WATCH_INTERVAL: Duration = Duration::from_secs(5);
+
+pub enum ExchangeControlCmd {
+    /// Перечитать файл конфигурации и применить изменения бирж, символов и порогов
+    Reload {
+        reply: oneshot::Sender<anyhow::Result<()>>
+    },
+    /// Состояние запущенных бирж
+    Status {
+        reply: oneshot::Sender<Vec<SessionStatus>>
+    },
+}
+
+#[derive(Clone)]
+pub struct ExchangeControlHandle {
+    tx: mpsc::Sender<ExchangeControlCmd>
+}
+
+impl ExchangeControlHandle {
+    /// Ошибка конфига не меняет запущенные биржи
+    pub async fn reload(
+        &self
+    ) -> anyhow::Result<()> {
+        let (reply, rx) = oneshot::channel();
+        self.tx
+            .send(ExchangeControlCmd::Reload { reply })
+            .await
+            .map_err(|_| anyhow!("ExchangeControl остановлен"))?;
+
+        rx.await.map_err(|_| anyhow!("ExchangeControl остановлен"))?
+    }
+
+    pub async fn status(
+        &self
+    ) -> Vec<SessionStatus> {
+        let (reply, rx) = oneshot::channel();
+        self.tx.send(ExchangeControlCmd::Status { reply }).await.ok();
+
+        rx.await.unwrap_or_default()
+    }
+}
+
+struct RunningExchange {
+    config: ExchangeConfig,
+    session: Arc<dyn ExchangeSession>,
+}
+
+/// <b>ExchangeControl</b> запускает и останавливает `ExchangeSetup` бирж по конфигу.
+///
+/// При перезагрузке:
+/// <br>• выключенная биржа останавливается, включённая запускается
+/// <br>• при смене адресов сессия биржи пересоздаётся
+/// <br>• у остальных бирж тикеры приводятся к новым фильтрам символов без переподключения
+pub struct ExchangeControl {
+    rx: mpsc::Receiver<ExchangeControlCmd>,
+    config_path: PathBuf,
+    exchanges: HashMap<ExchangeType, RunningExchange>,
+
+    data_aggregator_tx: mpsc::Sender<DataAggregatorCmd>,
+    exchange_channel_store_tx: mpsc::Sender<ExchangeChannelStoreCmd>,
+    health: HealthHandle,
+    /// Каждая сессия биржи получает дочерний supervisor и останавливается вместе с приложением
+    supervisor: Supervisor,
+}
+
+impl ExchangeControl {
+    pub fn new(
+        config_path: impl Into<PathBuf>,
+        data_aggregator_tx: mpsc::Sender<DataAggregatorCmd>,
+        exchange_channel_store_tx: mpsc::Sender<ExchangeChannelStoreCmd>,
+        health: HealthHandle,
+        supervisor: Supervisor
+    ) -> (Self, ExchangeControlHandle) {
+        let (tx, rx) = mpsc::channel(8);
+
+        let this = Self {
+            rx,
+            config_path: config_path.into(),
+            exchanges: HashMap::new(),
+
+            data_aggregator_tx,
+            exchange_channel_store_tx,
+            health,
+            supervisor,
+        };
+
+        (this, ExchangeControlHandle { tx })
+    }
+}
+
+#[async_trait]
+impl Actor for ExchangeControl {
+    async fn run(
+        &mut self,
+        shutdown: CancellationToken
+    ) {
+        // Биржи из конфига, загруженного при старте. После перезапуска - сверка с текущим
+        self.apply(&config::get()).await;
+
+        // Сессии бирж останавливаются своими дочерними supervisor
+        while let Some(cmd) = tokio::select! {
+            cmd = self.rx.recv() => cmd,
+            _ = shutdown.cancelled() => None
+        } {
+            match cmd {
+                ExchangeControlCmd::Reload { 
+                    reply 
+                } => {
+                    let _ = reply.send(self.reload().await);
+                },
+                ExchangeControlCmd::Status { 
+                    reply 
+                } => {
+                    let mut status = Vec::new();
+                    for running in self.exchanges.values() {
+                        status.push(running.session.clone().status().await);
+                    }
+
+                    let _ = reply.send(status);
+                },
+            }
+        }
+    }
+}
+
+impl ExchangeControl {
+    async fn reload(
+        &mut self
+    ) -> anyhow::Result<()> {
+        // Без файла `Config::load` вернул бы значения по умолчанию и включил бы другие биржи
+        if !self.config_path.exists() {
+            bail!("{} не найден", self.config_path.display());
+        }
+
+        let mut config = Config::load(&self.config_path)?;
+        for section in config.keep_startup_settings(&config::get()) {
+            tracing::warn!("Config -> {section} применится только после перезапуска");
+        }
+
+        config::set(config);
+        self.apply(&config::get()).await;
+
+        info!("Config -> перезагружен из {}", self.config_path.display());
+        Ok(())
+    }
+
+    async fn apply(
+        &mut self,
+        config: &Config
+    ) {
+        for exchange_id in SUPPORTED_EXCHANGES {
+            let exchange = config.exchange(exchange_id);
+
+            match self.exchanges.remove(&exchange_id) {
+                Some(running) if !exchange.enabled => {
+                    running.session.stop().await;
+                },
+                Some(running) if running.config.endpoints_changed(&exchange) => {
+                    running.session.stop().await;
+                    self.start(exchange_id, exchange);
+                },
+                Some(running) => {
+                    running.session.clone().sync_symbols().await;
+                    self.exchanges.insert(exchange_id, RunningExchange { config: exchange, ..running });
+                },
+                None if exchange.enabled => {
+                    self.start(exchange_id, exchange);
+                },
+                None => {}
+            }
+        }
+    }
+
+    fn start(
+        &mut self,
+        exchange_id: ExchangeType,
+        exchange: ExchangeConfig
+    ) {
+        let session = match exchange_id {
+            ExchangeType::Bybit => self.setup(exchange_id, BybitAdapter::new(&exchange)),
+            ExchangeType::Gate => self.setup(exchange_id, GateAdapter::new(&exchange)),
+            ExchangeType::KuCoin => self.setup(exchange_id, KuCoinAdapter::new(&exchange)),
+            // `Config::validate` не даёт включить биржу без адаптера
+            _ => return
+        };
+
+        self.exchanges.insert(exchange_id, RunningExchange { config: exchange, session });
+    }
+
+    fn setup<A: ExchangeAdapter + Send + Sync + 'static>(
+        &self,
+        exchange_id: ExchangeType,
+        adapter: Arc<A>
+    ) -> Arc<dyn ExchangeSession> {
+        let setup = ExchangeSetup::new(
+            exchange_id,
+            adapter,
+            self.data_aggregator_tx.clone(),
+            self.exchange_channel_store_tx.clone(),
+            self.health.clone(),
+            self.supervisor.child()
+        );
+        setup.clone().start();
+
+        setup
+    }
+}
+
+/// Перезагружает конфиг, когда меняется время изменения файла.
+/// Ошибка в файле пишется в лог, биржи продолжают работать с прежним конфигом
+pub async fn watch_config(
+    path: PathBuf,
+    control: ExchangeControlHandle
+) {
+    let modified = |path: &PathBuf| std::fs::metadata(path).and_then(|metadata| metadata.modified()).ok();
+
+    let mut last_modified = modified(&path);
+    let mut interval = tokio::time::interval(CONFIG_WATCH_INTERVAL);
+    interval.tick().await;
+
+    loop {
+        interval.tick().await;
+
+        let current = modified(&path);
+        if current.is_none() || current == last_modified {
+            continue;
+        }
+        last_modified = current;
+
+        if let Err(e) = control.reload().await {
+            tracing::error!("Config -> {e:#}");
+        }
+    }
+}
@@ -0,0 +1,273 @@
+use std::{collections::HashMap, sync::Arc, time::Duration};
+use async_trait::async_trait;
+use serde::Serialize;
+use tokio::sync::{mpsc, watch};
+use tokio_util::sync::CancellationToken;
+use crate::{config, models::{exchange::ExchangeType, websocket::Symbol}, services::{clock, supervisor::Actor}};
+
+/// Как часто пересчитываются статусы и публикуется `HealthReport`
+pub const HEALTH_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, Copy, Serialize, PartialEq, Eq)]
+#[serde(rename_all="snake_case")]
+pub enum FeedStatus {
+    /// Соединение открыто, данных ещё не было
+    Connected,
+    /// Соединение отправляет подписки
+    Subscribing,
+    Live,
+    /// Данные перестали приходить дольше `pipeline.book_stale_secs`
+    Stale,
+    Down,
+}
+
+pub enum HealthCmd {
+    /// Состояние одного соединения биржи из `ExchangeSetup`
+    Connection {
+        exchange_id: ExchangeType,
+        connection: usize,
+        status: FeedStatus
+    },
+    /// Активность `ExchangeStore` за `HEALTH_INTERVAL`: время последнего сообщения по символам в мс
+    Activity {
+        exchange_id: ExchangeType,
+        messages: u64,
+        symbols: HashMap<Symbol, i64>
+    },
+    /// Биржа остановлена при перезагрузке конфига
+    Remove {
+        exchange_id: ExchangeType
+    },
+}
+
+#[derive(Clone)]
+pub struct HealthHandle {
+    tx: mpsc::Sender<HealthCmd>,
+    report_rx: watch::Receiver<Arc<HealthReport>>,
+}
+
+impl HealthHandle {
+    pub async fn connection(
+        &self,
+        exchange_id: ExchangeType,
+        connection: usize,
+        status: FeedStatus
+    ) {
+        self.tx.send(HealthCmd::Connection { exchange_id, connection, status }).await.ok();
+    }
+
+    /// Не ждёт: отчёт о сообщениях можно потерять, следующий придёт через `HEALTH_INTERVAL`
+    pub fn activity(
+        &self,
+        exchange_id: ExchangeType,
+        messages: u64,
+        symbols: HashMap<Symbol, i64>
+    ) {
+        self.tx.try_send(HealthCmd::Activity { exchange_id, messages, symbols }).ok();
+    }
+
+    pub async fn remove(
+        &self,
+        exchange_id: ExchangeType
+    ) {
+        self.tx.send(HealthCmd::Remove { exchange_id }).await.ok();
+    }
+
+    pub fn subscribe(&self) -> watch::Receiver<Arc<HealthReport>> {
+        self.report_rx.clone()
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SymbolHealth {
+    pub status: FeedStatus,
+    /// Unix время последнего сообщения в мс
+    pub last_message: i64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ExchangeHealth {
+    pub status: FeedStatus,
+    pub last_message: Option<i64>,
+    /// Сообщений в секунду за последний `HEALTH_INTERVAL`
+    pub message_rate: f64,
+    pub reconnects: u64,
+    /// Статусы соединений по индексу
+    pub connections: HashMap<usize, FeedStatus>,
+    pub symbols: HashMap<Symbol, SymbolHealth>,
+}
+
+/// Статусы всех бирж. Символы в общем формате (`btcusdt`)
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct HealthReport {
+    pub exchanges: HashMap<ExchangeType, ExchangeHealth>,
+}
+
+impl HealthReport {
+    /// Статусы бирж только с одним символом
+    pub fn for_symbol(
+        &self,
+        symbol: &Symbol
+    ) -> Self {
+        let exchanges = self.exchanges
+            .iter()
+            .map(|(exchange_id, health)| {
+                let symbols = health.symbols
+                    .get(symbol)
+                    .map(|symbol_health| HashMap::from([(symbol.clone(), symbol_health.clone())]))
+                    .unwrap_or_default();
+
+                (*exchange_id, ExchangeHealth { symbols, ..health.clone() })
+            })
+            .collect();
+
+        Self { exchanges }
+    }
+}
+
+#[derive(Default)]
+struct ExchangeState {
+    connections: HashMap<usize, FeedStatus>,
+    reconnects: u64,
+    messages: u64,
+    last_message: Option<i64>,
+    symbols: HashMap<Symbol, i64>,
+}
+
+/// <b>HealthMonitor</b> собирает состояние соединений и поток сообщений бирж
+/// и публикует статусы бирж и символов в `HealthReport`
+pub struct HealthMonitor {
+    rx: mpsc::Receiver<HealthCmd>,
+    report_tx: watch::Sender<Arc<HealthReport>>,
+
+    exchanges: HashMap<ExchangeType, ExchangeState>,
+}
+
+impl HealthMonitor {
+    pub fn new() -> (Self, HealthHandle) {
+        let (tx, rx) = mpsc::channel(256);
+        let (report_tx, report_rx) = watch::channel(Arc::new(HealthReport::default()));
+
+        let this = Self {
+            rx,
+            report_tx,
+
+            exchanges: HashMap::new(),
+        };
+
+        (this, HealthHandle { tx, report_rx })
+    }
+}
+
+#[async_trait]
+impl Actor for HealthMonitor {
+    async fn run(
+        &mut self,
+        shutdown: CancellationToken
+    ) {
+        let mut interval = tokio::time::interval(HEALTH_INTERVAL);
+
+        loop {
+            tokio::select! {
+                _ = shutdown.cancelled() => return,
+                Some(cmd) = self.rx.recv() => {
+                    self.handle_command(cmd);
+                },
+                _ = interval.tick() => {
+                    self.publish();
+                }
+            }
+        }
+    }
+}
+
+impl HealthMonitor {
+    fn handle_command(
+        &mut self,
+        cmd: HealthCmd
+    ) {
+        match cmd {
+            HealthCmd::Connection {
+                exchange_id,
+                connection,
+                status
+            } => {
+                let state = self.exchanges.entry(exchange_id).or_default();
+
+                // Открытие после разрыва - переподключение
+                let previous = state.connections.insert(connection, status);
+                if status == FeedStatus::Connected && previous == Some(FeedStatus::Down) {
+                    state.reconnects += 1;
+                }
+            },
+            HealthCmd::Activity {
+                exchange_id,
+                messages,
+                symbols
+            } => {
+                let state = self.exchanges.entry(exchange_id).or_default();
+
+                state.messages += messages;
+                if let Some(last) = symbols.values().max() {
+                    state.last_message = state.last_message.max(Some(*last));
+                }
+                state.symbols.extend(symbols);
+            },
+            HealthCmd::Remove {
+                exchange_id
+            } => {
+                self.exchanges.remove(&exchange_id);
+            },
+        }
+    }
+
+    fn publish(
+        &mut self
+    ) {
+        let now = clock::now_ms();
+        let stale_after = config::get().pipeline.book_stale_after().as_millis() as i64;
+        let is_fresh = |last: i64| now - last <= stale_after;
+
+        let mut report = HealthReport::default();
+        for (exchange_id, state) in self.exchanges.iter_mut() {
+            let is_up = state.connections.values().any(|status| *status != FeedStatus::Down);
+
+            let status = if !is_up {
+                FeedStatus::Down
+            } else if state.last_message.is_some_and(is_fresh) {
+                FeedStatus::Live
+            } else if state.connections.values().any(|status| *status == FeedStatus::Subscribing) {
+                FeedStatus::Subscribing
+            } else if state.last_message.is_some() {
+                FeedStatus::Stale
+            } else {
+                FeedStatus::Connected
+            };
+
+            let symbols = state.symbols
+                .iter()
+                .map(|(symbol, last_message)| {
+                    let status = match status {
+                        FeedStatus::Down => FeedStatus::Down,
+                        _ if is_fresh(*last_message) => FeedStatus::Live,
+                        _ => FeedStatus::Stale
+                    };
+                    (symbol.clone(), SymbolHealth { status, last_message: *last_message })
+                })
+                .collect();
+
+            report.exchanges.insert(*exchange_id, ExchangeHealth {
+                status,
+                last_message: state.last_message,
+                message_rate: state.messages as f64 / HEALTH_INTERVAL.as_secs_f64(),
+                reconnects: state.reconnects,
+                connections: state.connections.clone(),
+                symbols,
+            });
+
+            state.messages = 0;
+        }
+
+        self.report_tx.send_replace(Arc::new(report));
+    }
+}
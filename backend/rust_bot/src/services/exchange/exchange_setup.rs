@@ -1,190 +1,565 @@
+use std::collections::{BTreeMap, HashMap, VecDeque};
 use std::sync::Arc;
-use std::time::Duration;
-use futures_util::{SinkExt, StreamExt};
-use tokio::sync::{mpsc, watch};
-use tokio_tungstenite::connect_async;
+use std::time::{Duration, Instant};
+use arc_swap::ArcSwapOption;
+use futures_util::{SinkExt, StreamExt, stream::SplitSink};
+use tokio::net::TcpStream;
+use tokio::sync::{Mutex, mpsc, oneshot};
+use tokio::time::MissedTickBehavior;
+use tokio_tungstenite::{MaybeTlsStream, WebSocketStream, connect_async};
 use tokio_tungstenite::tungstenite::Message;
+use tokio_util::sync::CancellationToken;
+use tokio_util::task::AbortOnDropHandle;
 use tracing::{info};
 use crate::models::exchange::TickerInfo;
-use crate::models::websocket::{WsCmd};
+use crate::config;
+use crate::models::websocket::{Symbol, WsCmd, normalize_symbol};
 use crate::models::{exchange::ExchangeType};
 use crate::services::data_aggregator::DataAggregatorCmd;
 use crate::services::exchange::exchange_adapter::ExchangeAdapter;
-use crate::services::exchange::exchange_aggregator::{ExchangeStore, ExchangeStoreCMD};
-use crate::services::exchange::exchange_channel_store::ExchangeChannelStoreCmd;
+use crate::services::exchange::exchange_aggregator::{BookEventSender, ExchangeStore};
+use crate::services::exchange::exchange_channel_store::{ExchangeChannelStoreCmd, ExchangeHandle};
+use crate::services::exchange::exchange_health::{FeedStatus, HealthHandle};
+use crate::services::exchange::subscription_manager::{SubscriptionHandle, SubscriptionManager};
+use crate::services::exchange::frame_recorder::FrameRecorder;
+use crate::services::latency::LatencyHistogram;
+use crate::services::metrics::Metric;
+use crate::services::supervisor::{Actor, Supervisor};
 
-const CHUNK_SIZE: usize = 50;
+/// Очередь команд подписки одного соединения
+const WS_CMD_QUEUE: usize = 64;
+/// Пауза перед переподключением
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+type WsStream = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+/// Причина завершения соединения
+#[derive(Debug, PartialEq, Eq)]
+enum ConnectionEnd {
+    Closed,
+    HeartbeatTimeout,
+    /// Соединение больше никому не нужно
+    CommandsClosed,
+}
+
+/// Соединение сессии и количество его тикеров
+struct SessionConnection {
+    cmd_tx: mpsc::Sender<WsCmd>,
+    symbols: usize,
+}
+
+/// Тикеры биржи и их распределение по соединениям.
+/// Соединения не закрываются, когда из них убирают все тикеры: индекс соединения общий с `SubscriptionManager`
+#[derive(Default)]
+struct Session {
+    /// Тикеры биржи из REST в символах биржи (`BTC_USDT`)
+    listed: Vec<Arc<Symbol>>,
+    /// Подписанные тикеры: общий формат -> символ биржи и индекс соединения
+    active: HashMap<Symbol, (Arc<Symbol>, usize)>,
+    connections: Vec<SessionConnection>,
+}
+
+/// Соединение сессии для API администратора
+#[derive(Debug, Clone)]
+pub struct ConnectionStatus {
+    pub index: usize,
+    pub symbols: usize,
+    /// Задача соединения завершилась
+    pub closed: bool,
+}
+
+/// Тикеры и соединения сессии биржи для API администратора
+#[derive(Debug, Clone)]
+pub struct SessionStatus {
+    pub exchange_id: ExchangeType,
+    /// Тикеры биржи из REST
+    pub listed: usize,
+    /// Тикеры, прошедшие фильтры конфига и подписанные
+    pub symbols: usize,
+    pub connections: Vec<ConnectionStatus>,
+}
+
+/// Соединение сессии под `Supervisor`. Тикеры и подписки хранятся здесь,
+/// поэтому после перезапуска соединение восстанавливает их
+struct WsConnection<A: ExchangeAdapter> {
+    setup: Arc<ExchangeSetup<A>>,
+    index: usize,
+    cmd_rx: mpsc::Receiver<WsCmd>,
+    /// Тикеры и активные подписки соединения по ключу `WsCmd`
+    tickers: Vec<Arc<Symbol>>,
+    subscriptions: HashMap<String, Vec<Message>>,
+}
+
+#[async_trait::async_trait]
+impl<A: ExchangeAdapter + Send + Sync + 'static> Actor for WsConnection<A> {
+    async fn run(
+        &mut self,
+        shutdown: CancellationToken
+    ) {
+        tokio::select! {
+            biased;
+            _ = shutdown.cancelled() => {},
+            _ = self.setup.clone().connect_ws(self.index, &mut self.cmd_rx, &mut self.tickers, &mut self.subscriptions) => {}
+        }
+    }
+}
+
+/// Сессия биржи, которой управляет `ExchangeControl`
+#[async_trait::async_trait]
+pub trait ExchangeSession: Send + Sync {
+    async fn status(self: Arc<Self>) -> SessionStatus;
+    /// Приводит подписанные тикеры к фильтрам текущего конфига
+    async fn sync_symbols(self: Arc<Self>);
+    /// Закрывает соединения и убирает биржу из `ExchangeChannelStore`, `DataAggregator` и `HealthMonitor`
+    async fn stop(self: Arc<Self>);
+}
 
 /// <b>ExchangeSetup</b> инициализирует WebSocket с помощью `ExchangeAdapter`
 pub struct ExchangeSetup<T: ExchangeAdapter> {
     pub adapter: Arc<T>,
     pub title: String,
-    pub enabled: bool,
     #[allow(unused)]
     pub ticker_tx: async_channel::Sender<(String, String)>,
     #[allow(unused)]
     pub ticker_rx: async_channel::Receiver<(String, String)>,
     pub client: reqwest::Client,
-    pub sender_data: watch::Sender<ExchangeStoreCMD>,
-    sender_data_queue_tx: mpsc::Sender<ExchangeStoreCMD>,
+    pub sender_data: BookEventSender,
+    pub subscriptions: SubscriptionHandle,
+    health: HealthHandle,
     exchange_id: ExchangeType,
+    session: Mutex<Session>,
+    /// Соединения, `ExchangeStore` и `SubscriptionManager` биржи. Останавливается в `stop`
+    supervisor: Supervisor,
+    /// Входящие сообщения всех соединений, кроме ответов на heartbeat
+    messages: Arc<Metric>,
+    reconnects: Arc<Metric>,
+    /// Время ответа биржи на heartbeat, общее для соединений биржи
+    heartbeat_rtt: Arc<LatencyHistogram>,
+    /// Запись сырых кадров, есть только пока она включена в конфиге биржи
+    recorder: ArcSwapOption<FrameRecorder>,
 
-    data_aggregator_tx: mpsc::Sender<DataAggregatorCmd>
+    data_aggregator_tx: mpsc::Sender<DataAggregatorCmd>,
+    exchange_channel_store_tx: mpsc::Sender<ExchangeChannelStoreCmd>
 }
 
 impl<A: ExchangeAdapter + Send + Sync + 'static> ExchangeSetup<A> {
     pub fn new(
         exchange_id: ExchangeType,
         adapter: Arc<A>,
-        enabled: bool,
         data_aggregator_tx: mpsc::Sender<DataAggregatorCmd>,
-        exchange_channel_store_tx: mpsc::Sender<ExchangeChannelStoreCmd>
+        exchange_channel_store_tx: mpsc::Sender<ExchangeChannelStoreCmd>,
+        health: HealthHandle,
+        supervisor: Supervisor
     ) -> Arc<Self> {
         let title = format!("{}Websocket", exchange_id);
+        let exchange = exchange_id.to_string();
+        let messages = Metric::counter("exchange_messages_total", "Входящие сообщения биржи", &[("exchange", &exchange)]);
+        let reconnects = Metric::counter("exchange_reconnects_total", "Переподключения соединений биржи", &[("exchange", &exchange)]);
+        let heartbeat_rtt = LatencyHistogram::register(format!("heartbeat.{}", exchange_id));
         let (ticker_tx, ticker_rx) = async_channel::bounded(64);
         let client = reqwest::Client::new();
-        let (sender_data, rx_data) = watch::channel(ExchangeStoreCMD::Default);
-        let (sender_data_queue_tx, sender_data_queue_rx) = mpsc::channel(CHUNK_SIZE);
-
-        let store = ExchangeStore::new(rx_data, sender_data_queue_rx, exchange_id);
-        let sender_data_cl = sender_data.clone();
-
-        tokio::spawn(async move {
-            exchange_channel_store_tx.send_timeout(
-                ExchangeChannelStoreCmd::RegisterChannel { 
-                    exchange_id, 
-                    channel: sender_data_cl
-                }, 
-                Duration::from_millis(10)
-            ).await.ok();
-            
-            store.set_data().await;
+        let (sender_data, rx_data) = BookEventSender::channel(exchange_id);
+
+        let (subscriptions, subscriptions_rx) = SubscriptionHandle::channel();
+        let store = ExchangeStore::new(rx_data, exchange_id, Some(subscriptions.clone()), health.clone());
+        supervisor.spawn_actor(
+            format!("{}SubscriptionManager", exchange_id),
+            SubscriptionManager::new(adapter.clone(), exchange_id, subscriptions_rx)
+        );
+
+        let handle = ExchangeHandle {
+            store: sender_data.clone(),
+            subscriptions: subscriptions.clone(),
+        };
+
+        tokio::spawn({
+            let exchange_channel_store_tx = exchange_channel_store_tx.clone();
+            async move {
+                let registered = exchange_channel_store_tx.send(
+                    ExchangeChannelStoreCmd::RegisterChannel { 
+                        exchange_id, 
+                        channel: handle
+                    }
+                ).await;
+
+                if registered.is_err() {
+                    tracing::error!("{} -> ExchangeChannelStore закрыт, биржа не зарегистрирована", exchange_id);
+                }
+            }
         });
+        supervisor.spawn_actor(format!("{}Store", exchange_id), store);
 
         let this = Arc::new(Self {
-            title, enabled,
+            title,
             ticker_tx, ticker_rx, client,
-            sender_data, sender_data_queue_tx,
-            exchange_id, data_aggregator_tx, adapter
+            sender_data, subscriptions, health,
+            exchange_id, session: Mutex::new(Session::default()), supervisor,
+            messages, reconnects, heartbeat_rtt, recorder: ArcSwapOption::empty(),
+            data_aggregator_tx, exchange_channel_store_tx, adapter
         });
+        this.sync_recorder();
 
         this
     }
 
     pub fn start(self: Arc<Self>) {
-        if !self.enabled {
-            tracing::warn!("{} disabled", self.title);
-            return;
-        }
-
         tokio::spawn({
             let this = self.clone();
+            let cancel = self.supervisor.token();
             async move {
-                let tickers = this.adapter.clone().get_tickers(&this.client).await;
-                if let Some(result) = tickers {                    
-                    let result = Arc::new(result);
-                    
-                    tokio::spawn({
-                        let this = self.clone();
-                        async move {
-                            this.try_run_ws_session(&result).await;
-                        }
-                    });
+                let tickers = tokio::select! {
+                    _ = cancel.cancelled() => return,
+                    tickers = this.adapter.clone().get_tickers(&this.client) => tickers
+                };
+
+                let Some(result) = tickers else {
+                    tracing::warn!("{} -> не удалось получить тикеры", this.title);
+                    return;
+                };
+
+                this.session.lock().await.listed = result
+                    .into_iter()
+                    .filter_map(|ticker_info| ticker_info.symbol)
+                    .map(Arc::new)
+                    .collect();
+
+                this.apply_symbols().await;
                     
-                    // // Загружаем снапшоты
-                    // tokio::spawn({
-                    //     let this = self.clone();
-                    //     let result = result.clone();
-                    //     let adapter = this.adapter.clone();
-                    //     async move {
-                    //      adapter.get_snapshot_spot_http(&result, &this.client, this.sender_data.clone()).await;
-                    //     }
-                    // });
-                }
+                // // Загружаем снапшоты
+                // tokio::spawn({
+                //     let this = self.clone();
+                //     let result = result.clone();
+                //     let adapter = this.adapter.clone();
+                //     async move {
+                //      adapter.get_snapshot_spot_http(&result, &this.client, this.sender_data.clone()).await;
+                //     }
+                // });
             }
         });
     }
 
-    async fn try_run_ws_session(
-        self: Arc<Self>,
-        tickers: &Vec<TickerInfo>
+    /// Убирает тикеры, которые запретил конфиг, и добавляет разрешённые:
+    /// сначала в соединения со свободным местом, затем в новые до `max_connections`
+    async fn apply_symbols(
+        self: Arc<Self>
     ) {
         let adapter = self.adapter.clone();
+        let limits = adapter.clone().limits();
+        let config = config::get();
 
-        for chunk in tickers.chunks(CHUNK_SIZE) {
-            let (cmd_tx, mut cmd_rx) = mpsc::channel::<WsCmd>(CHUNK_SIZE);
+        let mut session = self.session.lock().await;
+        if self.supervisor.token().is_cancelled() {
+            return;
+        }
 
-            for ticker_info in chunk {
-                let symbol = ticker_info.symbol.clone();
-                if let Some(symbol) = symbol {
-                    
-                    let symbol = Arc::new(symbol);
+        // Фильтры символов из конфига, символ в общем формате (`btcusdt`)
+        let allowed: HashMap<Symbol, Arc<Symbol>> = session.listed
+            .iter()
+            .map(|symbol| (normalize_symbol(symbol), symbol.clone()))
+            .filter(|(symbol, _)| config.allows_symbol(self.exchange_id, symbol))
+            .collect();
 
-                    // Регистрируем тикеры в exchange aggregator 
-                    // обьязательно используеться очередь, чтобы гарантировано зарегистрировать все тикеры
-                    let _ = self.sender_data_queue_tx.send(ExchangeStoreCMD::RegisterSymbol { symbol: symbol.clone() }).await;
+        let removed: Vec<Symbol> = session.active
+            .keys()
+            .filter(|symbol| !allowed.contains_key(*symbol))
+            .cloned()
+            .collect();
 
-                    // Регистрируем тикеры с exchange_id в общем аггрегаторе
-                    let _ = self.data_aggregator_tx.send(
-                        DataAggregatorCmd::MarketRegister { 
-                            symbol: symbol.clone(), 
-                            exchange_id: self.exchange_id 
-                        }
-                    ).await;
+        let mut removed_by_connection: HashMap<usize, Vec<Arc<Symbol>>> = HashMap::new();
+        for symbol in removed {
+            let Some((exchange_symbol, index)) = session.active.remove(&symbol) else { continue };
+            session.connections[index].symbols -= 1;
+
+            self.sender_data.unregister_symbol(exchange_symbol.clone()).await;
+            let _ = self.data_aggregator_tx.send(
+                DataAggregatorCmd::MarketUnregister { 
+                    symbol: exchange_symbol.clone(), 
+                    exchange_id: self.exchange_id 
+                }
+            ).await;
+
+            removed_by_connection.entry(index).or_default().push(exchange_symbol);
+        }
+
+        for (index, symbols) in removed_by_connection {
+            info!("{} -> отписка от тикеров: {}", self.title, symbols.len());
+            self.subscriptions.remove_symbols(index, symbols.clone()).await;
+            session.connections[index].cmd_tx.send(WsCmd::UnsubscribeTickers { symbols }).await.ok();
+        }
+
+        // Порядок тикеров как в ответе биржи
+        let added: Vec<(Symbol, Arc<Symbol>)> = session.listed
+            .iter()
+            .map(|symbol| (normalize_symbol(symbol), symbol.clone()))
+            .filter(|(symbol, _)| allowed.contains_key(symbol) && !session.active.contains_key(symbol))
+            .collect();
+
+        let mut added_by_connection: BTreeMap<usize, Vec<Arc<Symbol>>> = BTreeMap::new();
+        let mut skipped = 0;
+        for (symbol, exchange_symbol) in added {
+            let free = session.connections
+                .iter()
+                .position(|connection| connection.symbols < limits.symbols_per_connection());
 
-                    let messages = adapter.clone().create_subscribe_messages(symbol);
-                    cmd_tx.send(WsCmd::Subscribe(messages)).await.ok();
+            let index = match free {
+                Some(index) => index,
+                None if session.connections.len() < limits.max_connections => {
+                    let index = session.connections.len();
+                    let connection = self.clone().open_connection(index).await;
+                    session.connections.push(connection);
+                    index
+                },
+                None => {
+                    skipped += 1;
+                    continue;
                 }
+            };
+
+            session.connections[index].symbols += 1;
+            session.active.insert(symbol, (exchange_symbol.clone(), index));
+            added_by_connection.entry(index).or_default().push(exchange_symbol);
+        }
+
+        if skipped > 0 {
+            tracing::warn!("{} -> достигнут лимит соединений {}, пропущено тикеров: {}", self.title, limits.max_connections, skipped);
+        }
+
+        for (index, symbols) in added_by_connection {
+            for symbol in symbols.iter() {
+                // Регистрируем тикеры в exchange aggregator 
+                self.sender_data.register_symbol(symbol.clone()).await;
+
+                // Регистрируем тикеры с exchange_id в общем аггрегаторе
+                let _ = self.data_aggregator_tx.send(
+                    DataAggregatorCmd::MarketRegister { 
+                        symbol: symbol.clone(), 
+                        exchange_id: self.exchange_id 
+                    }
+                ).await;
             }
 
-            let this = self.clone();
-            tokio::spawn(async move {
-                this.connect_ws(&mut cmd_rx).await;
-            });
+            info!("{} -> подписка на тикеры: {}", self.title, symbols.len());
+
+            // Тикеры нужны всегда, стаканы подписывает SubscriptionManager по запросу
+            session.connections[index].cmd_tx.send(WsCmd::SubscribeTickers { symbols: symbols.clone() }).await.ok();
+            self.subscriptions.add_symbols(index, symbols).await;
         }
     }
 
+    /// Соединение работает до остановки сессии
+    async fn open_connection(
+        self: Arc<Self>,
+        index: usize
+    ) -> SessionConnection {
+        let (cmd_tx, cmd_rx) = mpsc::channel::<WsCmd>(WS_CMD_QUEUE);
+        self.subscriptions.add_connection(cmd_tx.clone()).await;
+
+        let name = format!("{}#{}", self.title, index);
+        self.supervisor.spawn_actor(name, WsConnection {
+            setup: self.clone(),
+            index,
+            cmd_rx,
+            tickers: Vec::new(),
+            subscriptions: HashMap::new(),
+        });
+
+        SessionConnection { cmd_tx, symbols: 0 }
+    }
+
+    /// Включает или выключает запись кадров по конфигу биржи
+    fn sync_recorder(
+        &self
+    ) {
+        let enabled = config::get().exchange(self.exchange_id).record;
+        if enabled == self.recorder.load().is_some() {
+            return;
+        }
+
+        if !enabled {
+            self.recorder.store(None);
+            info!("{} -> запись кадров выключена", self.title);
+            return;
+        }
+
+        match FrameRecorder::start(self.exchange_id) {
+            Ok(recorder) => {
+                self.recorder.store(Some(Arc::new(recorder)));
+                info!("{} -> запись кадров включена", self.title);
+            },
+            Err(e) => {
+                tracing::error!("{} -> не удалось включить запись кадров: {}", self.title, e);
+            }
+        }
+    }
+
+    /// Держит соединение открытым: после разрыва или пропущенных heartbeat
+    /// переподключается и восстанавливает все подписки соединения
     async fn connect_ws(
         self: Arc<Self>,
-        cmd_rx: &mut mpsc::Receiver<WsCmd>
+        index: usize,
+        cmd_rx: &mut mpsc::Receiver<WsCmd>,
+        tickers: &mut Vec<Arc<Symbol>>,
+        subscriptions: &mut HashMap<String, Vec<Message>>
     ) {
+        loop {
+            match self.clone().open_ws().await {
+                Some(ws_stream) => {
+                    info!("{} -> is running", self.title);
+                    self.health.connection(self.exchange_id, index, FeedStatus::Connected).await;
+
+                    let reason = self.clone().run_connection(index, ws_stream, cmd_rx, tickers, subscriptions).await;
+                    self.health.connection(self.exchange_id, index, FeedStatus::Down).await;
+                    if reason == ConnectionEnd::CommandsClosed {
+                        return;
+                    }
+
+                    self.reconnects.inc();
+                    tracing::warn!("{} -> соединение потеряно ({:?}), переподключение", self.title, reason);
+                },
+                None => {
+                    tracing::warn!("{} -> не удалось подключиться, повтор через {:?}", self.title, RECONNECT_DELAY);
+                }
+            }
+
+            tokio::time::sleep(RECONNECT_DELAY).await;
+        }
+    }
+
+    async fn open_ws(
+        self: Arc<Self>
+    ) -> Option<WsStream> {
         let adapter = self.adapter.clone();
-        let ws_url = adapter.clone().ws_url();
 
-        let mut ws_stream = Some(connect_async(ws_url).await.unwrap().0);
         if adapter.clone().requires_auth() {
-            let url = adapter.clone().auth_url(&self.client).await;
-            if let Some(ws_url) = url {
-                ws_stream = Some(connect_async(ws_url).await.unwrap().0);
-            }
+            let ws_url = adapter.clone().auth_url(&self.client).await?;
+            return connect_async(ws_url).await.ok().map(|(ws_stream, _)| ws_stream);
         }
 
-        if let Some(ws_stream) = ws_stream {
-            let (mut write, mut read) = ws_stream.split();
-            
-            info!("{} -> is running", self.title);
+        connect_async(adapter.ws_url()).await.ok().map(|(ws_stream, _)| ws_stream)
+    }
+
+    async fn run_connection(
+        self: Arc<Self>,
+        index: usize,
+        ws_stream: WsStream,
+        cmd_rx: &mut mpsc::Receiver<WsCmd>,
+        tickers: &mut Vec<Arc<Symbol>>,
+        subscriptions: &mut HashMap<String, Vec<Message>>
+    ) -> ConnectionEnd {
+        let adapter = self.adapter.clone();
+        let (write, mut read) = ws_stream.split();
+
+        self.health.connection(self.exchange_id, index, FeedStatus::Subscribing).await;
+        let mut resubscribe = match tickers.is_empty() {
+            true => Vec::new(),
+            false => adapter.clone().create_ticker_subscribe_messages(tickers)
+        };
+        resubscribe.extend(subscriptions.values().flatten().cloned());
+
+        let (paced_tx, paced_rx) = mpsc::unbounded_channel();
+        let (ping_tx, ping_rx) = mpsc::unbounded_channel();
+        let (resubscribed_tx, mut resubscribed_rx) = oneshot::channel();
+        // Писатель живёт, пока живёт соединение
+        let mut writer = AbortOnDropHandle::new(tokio::spawn(Self::write_ws(
+            write,
+            resubscribe.into(),
+            paced_rx,
+            ping_rx,
+            adapter.clone().limits().messages_per_second,
+            resubscribed_tx
+        )));
+
+        let heartbeat = adapter.clone().heartbeat();
+        let mut ping_interval = tokio::time::interval(heartbeat.interval);
+        ping_interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        ping_interval.tick().await;
+
+        let mut ping_id: u64 = 0;
+        let mut ping_sent_at: Option<Instant> = None;
+        let mut missed: u32 = 0;
+
+        loop {
+            tokio::select! {
+                cmd = cmd_rx.recv() => {
+                    let Some(cmd) = cmd else { return ConnectionEnd::CommandsClosed };
+
+                    let msgs = match cmd {
+                        WsCmd::Subscribe { key, messages } => {
+                            subscriptions.insert(key, messages.clone());
+                            messages
+                        },
+                        WsCmd::Unsubscribe { key, messages } => {
+                            subscriptions.remove(&key);
+                            messages
+                        },
+                        WsCmd::SubscribeTickers { symbols } => {
+                            let messages = adapter.clone().create_ticker_subscribe_messages(&symbols);
+                            tickers.extend(symbols);
+                            messages
+                        },
+                        WsCmd::UnsubscribeTickers { symbols } => {
+                            tickers.retain(|symbol| !symbols.contains(symbol));
+                            adapter.clone().create_ticker_unsubscribe_messages(&symbols)
+                        }
+                    };
+
+                    for msg in msgs {
+                        if paced_tx.send(msg).is_err() {
+                            return ConnectionEnd::Closed;
+                        }
+                    }
+                },
+                resubscribed = &mut resubscribed_rx, if !resubscribed_rx.is_terminated() => {
+                    if resubscribed.is_ok() {
+                        self.health.connection(self.exchange_id, index, FeedStatus::Connected).await;
+                    }
+                },
+                _ = &mut writer => {
+                    return ConnectionEnd::Closed;
+                },
+                _ = ping_interval.tick() => {
+                    if ping_sent_at.is_some() {
+                        missed += 1;
+                        tracing::warn!("{} -> нет ответа на heartbeat ({}/{})", self.title, missed, heartbeat.max_missed);
 
-            while let Some(cmd) = cmd_rx.recv().await {
-                match cmd {
-                    WsCmd::Subscribe(msgs) => {
-                        for msg in msgs {
-                            write.send(msg).await.ok();
+                        if missed >= heartbeat.max_missed {
+                            return ConnectionEnd::HeartbeatTimeout;
                         }
                     }
-                }
-            }
 
-            while let Some(result) = read.next().await {
-                if let Ok(msg) = result {
+                    ping_id += 1;
+                    if ping_tx.send(adapter.clone().create_ping_message(ping_id)).is_err() {
+                        return ConnectionEnd::Closed;
+                    }
+                    ping_sent_at = Some(Instant::now());
+                },
+                result = read.next() => {
+                    let Some(Ok(msg)) = result else { return ConnectionEnd::Closed };
+                    if let Some(recorder) = &*self.recorder.load() {
+                        recorder.record(index, &msg);
+                    }
+
+                    let is_pong = match &msg {
+                        Message::Text(text) => adapter.clone().is_pong(text),
+                        Message::Pong(_) => true,
+                        _ => false
+                    };
+
+                    if is_pong {
+                        if let Some(sent_at) = ping_sent_at.take() {
+                            missed = 0;
+                            self.heartbeat_rtt.record(sent_at.elapsed().as_millis() as i64);
+                        }
+                        continue;
+                    }
+                    self.messages.inc();
+
                     match msg {
                         Message::Text(channel) => {
-                            adapter.clone().parse_message(channel, self.sender_data_queue_tx.clone(), self.sender_data.clone()).await;
-                        },
-                        Message::Pong(pong) => {
-                            println!("{} ответил на Pong: {:?}", self.title, pong)
+                            adapter.clone().parse_message(channel, self.sender_data.clone()).await;
                         },
                         Message::Close(_) => {
-                            break;
+                            return ConnectionEnd::Closed;
                         }
                         _ => {}
                     }
@@ -193,6 +568,51 @@ impl<A: ExchangeAdapter + Send + Sync + 'static> ExchangeSetup<A> {
         }
     }
 
+    /// Пишет в сокет соединения. Подписки уходят не чаще `messages_per_second`, иначе биржа
+    /// разрывает соединение, а ping - сразу: ожидание лимита не задерживает ни чтение, ни heartbeat.
+    /// <br>`resubscribed` срабатывает, когда отправлены подписки, восстановленные после подключения
+    async fn write_ws(
+        mut write: SplitSink<WsStream, Message>,
+        mut pending: VecDeque<Message>,
+        mut paced_rx: mpsc::UnboundedReceiver<Message>,
+        mut ping_rx: mpsc::UnboundedReceiver<Message>,
+        messages_per_second: u32,
+        resubscribed: oneshot::Sender<()>
+    ) {
+        let mut send_interval = tokio::time::interval(Duration::from_secs(1) / messages_per_second.max(1));
+        send_interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+        let mut resubscribed = Some(resubscribed);
+        let mut resubscribing = pending.len();
+
+        loop {
+            if resubscribing == 0 && let Some(resubscribed) = resubscribed.take() {
+                let _ = resubscribed.send(());
+            }
+
+            tokio::select! {
+                biased;
+                msg = ping_rx.recv() => {
+                    let Some(msg) = msg else { return };
+                    if write.send(msg).await.is_err() {
+                        return;
+                    }
+                },
+                _ = send_interval.tick(), if !pending.is_empty() => {
+                    let Some(msg) = pending.pop_front() else { continue };
+                    resubscribing = resubscribing.saturating_sub(1);
+                    if write.send(msg).await.is_err() {
+                        return;
+                    }
+                },
+                msg = paced_rx.recv() => {
+                    let Some(msg) = msg else { return };
+                    pending.push_back(msg);
+                }
+            }
+        }
+    }
+
     async fn _run_ws_session(
         self: Arc<Self>,
         _tickers: &Vec<TickerInfo>
@@ -200,3 +620,60 @@ impl<A: ExchangeAdapter + Send + Sync + 'static> ExchangeSetup<A> {
 
     }
 }
+
+#[async_trait::async_trait]
+impl<A: ExchangeAdapter + Send + Sync + 'static> ExchangeSession for ExchangeSetup<A> {
+    async fn status(
+        self: Arc<Self>
+    ) -> SessionStatus {
+        let session = self.session.lock().await;
+
+        SessionStatus {
+            exchange_id: self.exchange_id,
+            listed: session.listed.len(),
+            symbols: session.active.len(),
+            connections: session.connections
+                .iter()
+                .enumerate()
+                .map(|(index, connection)| ConnectionStatus {
+                    index,
+                    symbols: connection.symbols,
+                    closed: connection.cmd_tx.is_closed(),
+                })
+                .collect(),
+        }
+    }
+
+    async fn sync_symbols(
+        self: Arc<Self>
+    ) {
+        self.sync_recorder();
+        self.apply_symbols().await;
+    }
+
+    async fn stop(
+        self: Arc<Self>
+    ) {
+        let mut session = self.session.lock().await;
+
+        for (_, (symbol, _)) in session.active.drain() {
+            let _ = self.data_aggregator_tx.send(
+                DataAggregatorCmd::MarketUnregister { 
+                    symbol, 
+                    exchange_id: self.exchange_id 
+                }
+            ).await;
+        }
+
+        self.exchange_channel_store_tx.send(
+            ExchangeChannelStoreCmd::RemoveExchange { 
+                exchange_id: self.exchange_id 
+            }
+        ).await.ok();
+
+        self.supervisor.shutdown();
+        self.health.remove(self.exchange_id).await;
+
+        info!("{} -> остановлен", self.title);
+    }
+}
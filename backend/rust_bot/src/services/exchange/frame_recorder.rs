@@ -0,0 +1,339 @@
+use std::{fs::{self, File, OpenOptions}, io::{self, BufReader, Read, Write}, path::{Path, PathBuf}, sync::{Arc, LazyLock, mpsc::{self, RecvTimeoutError, SyncSender, TrySendError}}, thread, time::{Duration, Instant}};
+use chrono::Utc;
+use flate2::{Compression, read::GzDecoder, write::GzEncoder};
+use tokio_tungstenite::tungstenite::Message;
+
+use crate::{config, models::exchange::ExchangeType, services::queue::QueueStats};
+
+/// Сигнатура в начале каждого файла записи
+pub const FRAME_MAGIC: &[u8; 4] = b"RBFR";
+/// Версия формата. Меняется при любом несовместимом изменении заголовка или записи
+pub const FRAME_FORMAT_VERSION: u16 = 1;
+
+/// Кадры, которые ждут записи. Переполнение - кадр отбрасывается, а не тормозит чтение сокета
+const RECORDER_QUEUE: usize = 8192;
+/// Как часто сжатые данные сбрасываются на диск
+const FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Общая точка отсчёта монотонного времени всех записей процесса и её wall-clock время в мкс
+static ANCHOR: LazyLock<(Instant, i64)> = LazyLock::new(|| (Instant::now(), Utc::now().timestamp_micros()));
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum FrameKind {
+    Text = 1,
+    Binary = 2,
+}
+
+impl FrameKind {
+    fn from_u8(
+        value: u8
+    ) -> Option<Self> {
+        match value {
+            1 => Some(FrameKind::Text),
+            2 => Some(FrameKind::Binary),
+            _ => None
+        }
+    }
+}
+
+/// Кадр в очереди на запись или прочитанный из файла
+#[derive(Debug, Clone)]
+pub struct Frame {
+    pub kind: FrameKind,
+    pub connection: u16,
+    pub mono_ns: u64,
+    pub wall_us: i64,
+    pub payload: Vec<u8>,
+}
+
+/// <b>FrameRecorder</b> пишет входящие кадры одной биржи в сжатые файлы, только дописывая их.
+///
+/// Файл `{recorder.dir}/{exchange}/{exchange}-{YYYYmmddTHHMMSS}.rbfr.gz` - один gzip поток,
+/// все числа little-endian. Данные сбрасываются каждую секунду, поэтому файл после аварийной
+/// остановки читается до последнего сброса.
+///
+/// Заголовок файла:
+/// <br>• `magic` 4 байта `RBFR`
+/// <br>• `version` u16, сейчас `FRAME_FORMAT_VERSION` = 1
+/// <br>• `exchange_len` u8 и `exchange` UTF-8 - имя биржи как в конфиге (`bybit`, `gate.io`)
+/// <br>• `anchor_wall_us` i64 - Unix время в мкс, соответствующее `mono_ns` = 0
+///
+/// Запись кадра:
+/// <br>• `kind` u8 - 1 текст, 2 бинарный
+/// <br>• `connection` u16 - индекс соединения сессии биржи
+/// <br>• `mono_ns` u64 - монотонное время получения в нс от точки отсчёта процесса
+/// <br>• `wall_us` i64 - Unix время получения в мкс
+/// <br>• `len` u32 и `payload` - кадр как пришёл от биржи
+///
+/// Новый файл начинается, когда несжатых данных больше `recorder.max_file_mb`
+pub struct FrameRecorder {
+    tx: SyncSender<Frame>,
+    stats: Arc<QueueStats>,
+}
+
+impl FrameRecorder {
+    /// Запускает поток записи. Поток дописывает файл и завершается, когда recorder удалён
+    pub fn start(
+        exchange_id: ExchangeType
+    ) -> io::Result<Self> {
+        let config = config::get();
+        let name = serde_json::to_string(&exchange_id)
+            .map(|name| name.trim_matches('"').to_string())
+            .unwrap_or_else(|_| exchange_id.to_string());
+
+        let writer = FrameWriter {
+            dir: config.recorder.dir.join(&name),
+            max_file_bytes: config.recorder.max_file_mb * 1024 * 1024,
+            name,
+            file: None,
+            written: 0,
+        };
+        fs::create_dir_all(&writer.dir)?;
+
+        let (tx, rx) = mpsc::sync_channel(RECORDER_QUEUE);
+        thread::Builder::new()
+            .name(format!("recorder-{}", writer.name))
+            .spawn(move || writer.run(rx))?;
+
+        Ok(Self {
+            tx,
+            stats: QueueStats::register(format!("recorder.{}", exchange_id)),
+        })
+    }
+
+    /// Ping, Pong и Close не записываются. Кадр копируется, запись на диск идёт в отдельном потоке
+    pub fn record(
+        &self,
+        connection: usize,
+        msg: &Message
+    ) {
+        let (kind, payload) = match msg {
+            Message::Text(text) => (FrameKind::Text, text.as_bytes().to_vec()),
+            Message::Binary(data) => (FrameKind::Binary, data.clone()),
+            _ => return
+        };
+
+        let (anchor, _) = *ANCHOR;
+        let frame = Frame {
+            kind,
+            connection: connection as u16,
+            mono_ns: anchor.elapsed().as_nanos() as u64,
+            wall_us: Utc::now().timestamp_micros(),
+            payload,
+        };
+
+        match self.tx.try_send(frame) {
+            Ok(_) => self.stats.add_sent(),
+            Err(TrySendError::Full(_)) => self.stats.add_dropped(),
+            Err(TrySendError::Disconnected(_)) => {}
+        }
+    }
+}
+
+struct FrameWriter {
+    dir: PathBuf,
+    name: String,
+    max_file_bytes: u64,
+    file: Option<GzEncoder<File>>,
+    /// Несжатые байты текущего файла
+    written: u64,
+}
+
+impl FrameWriter {
+    fn run(
+        mut self,
+        rx: mpsc::Receiver<Frame>
+    ) {
+        loop {
+            let result = match rx.recv_timeout(FLUSH_INTERVAL) {
+                Ok(frame) => self.write(&frame),
+                Err(RecvTimeoutError::Timeout) => self.flush(),
+                Err(RecvTimeoutError::Disconnected) => break
+            };
+
+            if let Err(e) = result {
+                tracing::error!("FrameRecorder({}) -> {e}, запись начнётся в новом файле", self.name);
+                self.file = None;
+            }
+        }
+
+        if let Some(file) = self.file.take()
+            && let Err(e) = file.finish() {
+            tracing::error!("FrameRecorder({}) -> {e}", self.name);
+        }
+    }
+
+    fn write(
+        &mut self,
+        frame: &Frame
+    ) -> io::Result<()> {
+        if self.file.is_none() || self.written >= self.max_file_bytes {
+            self.rotate()?;
+        }
+        let Some(file) = self.file.as_mut() else { return Ok(()) };
+
+        file.write_all(&[frame.kind as u8])?;
+        file.write_all(&frame.connection.to_le_bytes())?;
+        file.write_all(&frame.mono_ns.to_le_bytes())?;
+        file.write_all(&frame.wall_us.to_le_bytes())?;
+        file.write_all(&(frame.payload.len() as u32).to_le_bytes())?;
+        file.write_all(&frame.payload)?;
+
+        self.written += 1 + 2 + 8 + 8 + 4 + frame.payload.len() as u64;
+        Ok(())
+    }
+
+    fn flush(
+        &mut self
+    ) -> io::Result<()> {
+        match self.file.as_mut() {
+            Some(file) => file.flush(),
+            None => Ok(())
+        }
+    }
+
+    /// Закрывает текущий файл и открывает новый. Существующие файлы не перезаписываются
+    fn rotate(
+        &mut self
+    ) -> io::Result<()> {
+        if let Some(file) = self.file.take() {
+            file.finish()?;
+        }
+
+        let started = Utc::now().format("%Y%m%dT%H%M%S");
+        let mut path = self.dir.join(format!("{}-{}.rbfr.gz", self.name, started));
+        let mut suffix = 1;
+        while path.exists() {
+            path = self.dir.join(format!("{}-{}-{}.rbfr.gz", self.name, started, suffix));
+            suffix += 1;
+        }
+
+        let file = OpenOptions::new().write(true).create_new(true).open(&path)?;
+        let mut file = GzEncoder::new(file, Compression::fast());
+
+        let (_, anchor_wall_us) = *ANCHOR;
+        file.write_all(FRAME_MAGIC)?;
+        file.write_all(&FRAME_FORMAT_VERSION.to_le_bytes())?;
+        file.write_all(&[self.name.len() as u8])?;
+        file.write_all(self.name.as_bytes())?;
+        file.write_all(&anchor_wall_us.to_le_bytes())?;
+
+        tracing::info!("FrameRecorder({}) -> запись в {}", self.name, path.display());
+
+        self.file = Some(file);
+        self.written = 0;
+        Ok(())
+    }
+}
+
+/// <b>FrameReader</b> читает файл `FrameRecorder` по одному кадру.
+/// Файл, оборванный аварийной остановкой, читается до последнего целого кадра
+pub struct FrameReader {
+    pub path: PathBuf,
+    /// Имя биржи из заголовка, как в конфиге
+    pub exchange: String,
+    #[allow(unused)]
+    pub anchor_wall_us: i64,
+    input: GzDecoder<BufReader<File>>,
+}
+
+impl FrameReader {
+    pub fn open(
+        path: &Path
+    ) -> io::Result<Self> {
+        let mut input = GzDecoder::new(BufReader::new(File::open(path)?));
+
+        let mut magic = [0u8; 4];
+        input.read_exact(&mut magic)?;
+        if &magic != FRAME_MAGIC {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "не файл записи кадров"));
+        }
+
+        let version = u16::from_le_bytes(read_array(&mut input)?);
+        if version != FRAME_FORMAT_VERSION {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("версия формата {version}, поддерживается {FRAME_FORMAT_VERSION}")
+            ));
+        }
+
+        let [name_len] = read_array(&mut input)?;
+        let mut name = vec![0u8; name_len as usize];
+        input.read_exact(&mut name)?;
+        let exchange = String::from_utf8(name)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+        let anchor_wall_us = i64::from_le_bytes(read_array(&mut input)?);
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            exchange,
+            anchor_wall_us,
+            input,
+        })
+    }
+
+    /// Биржа по имени из заголовка
+    pub fn exchange_id(&self) -> Option<ExchangeType> {
+        serde_json::from_value(serde_json::Value::String(self.exchange.clone())).ok()
+    }
+
+    /// `None` - файл закончился
+    pub fn next_frame(
+        &mut self
+    ) -> io::Result<Option<Frame>> {
+        let mut kind = [0u8; 1];
+        match self.input.read(&mut kind) {
+            Ok(0) => return Ok(None),
+            Ok(_) => {},
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(self.truncated()),
+            Err(e) => return Err(e)
+        }
+
+        match self.read_frame(kind[0]) {
+            Ok(frame) => Ok(Some(frame)),
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => Ok(self.truncated()),
+            Err(e) => Err(e)
+        }
+    }
+
+    fn read_frame(
+        &mut self,
+        kind: u8
+    ) -> io::Result<Frame> {
+        let kind = FrameKind::from_u8(kind)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, format!("неизвестный тип кадра {kind}")))?;
+
+        let connection = u16::from_le_bytes(read_array(&mut self.input)?);
+        let mono_ns = u64::from_le_bytes(read_array(&mut self.input)?);
+        let wall_us = i64::from_le_bytes(read_array(&mut self.input)?);
+        let len = u32::from_le_bytes(read_array(&mut self.input)?);
+
+        let mut payload = vec![0u8; len as usize];
+        self.input.read_exact(&mut payload)?;
+
+        Ok(Frame {
+            kind,
+            connection,
+            mono_ns,
+            wall_us,
+            payload,
+        })
+    }
+
+    fn truncated(
+        &self
+    ) -> Option<Frame> {
+        tracing::warn!("FrameReader -> {} оборван, последний кадр пропущен", self.path.display());
+        None
+    }
+}
+
+fn read_array<const N: usize>(
+    input: &mut impl Read
+) -> io::Result<[u8; N]> {
+    let mut buf = [0u8; N];
+    input.read_exact(&mut buf)?;
+    Ok(buf)
+}
@@ -0,0 +1,358 @@
+use std::{collections::{HashMap, HashSet}, path::{Path, PathBuf}, sync::Arc, time::Duration};
+use anyhow::{Context, anyhow, bail};
+use async_trait::async_trait;
+use tokio::{sync::{mpsc, oneshot}, time::Instant};
+use tokio_util::sync::CancellationToken;
+use tracing::info;
+use crate::{adapters::{bybit_adapter::BybitAdapter, gate_adapter::GateAdapter, kucoin_adapter::KuCoinAdapter}, config, models::{exchange::ExchangeType, orderbook::BookEvent, websocket::{Symbol, normalize_symbol}}, services::{clock, data_aggregator::DataAggregatorCmd, exchange::{exchange_adapter::ExchangeAdapter, exchange_aggregator::{BookEventSender, BookUpdatesQueue, ExchangeStore, ExchangeStoreCMD}, exchange_health::HealthHandle, frame_recorder::{Frame, FrameKind, FrameReader}}, supervisor::{Actor, Supervisor}}};
+
+/// Как часто проверяется, что `DataAggregator` разобрал все стаканы
+const DRAIN_POLL: Duration = Duration::from_millis(1);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReplaySpeed {
+    /// Без пауз между кадрами
+    Fast,
+    /// Паузы между кадрами как при записи
+    Realtime,
+}
+
+/// Аргументы `rust_bot replay [--realtime] <файл или каталог>...`
+#[derive(Debug, Clone)]
+pub struct ReplayArgs {
+    pub paths: Vec<PathBuf>,
+    pub speed: ReplaySpeed,
+}
+
+impl ReplayArgs {
+    /// `None` - приложение запущено без подкоманды `replay`
+    pub fn parse(
+        mut args: impl Iterator<Item = String>
+    ) -> anyhow::Result<Option<Self>> {
+        if args.next().as_deref() != Some("replay") {
+            return Ok(None);
+        }
+
+        let mut speed = ReplaySpeed::Fast;
+        let mut paths = Vec::new();
+        for arg in args {
+            match arg.as_str() {
+                "--realtime" => speed = ReplaySpeed::Realtime,
+                "--fast" => speed = ReplaySpeed::Fast,
+                flag if flag.starts_with("--") => bail!("неизвестный флаг {flag}"),
+                path => paths.push(PathBuf::from(path)),
+            }
+        }
+
+        if paths.is_empty() {
+            bail!("usage: rust_bot replay [--fast|--realtime] <файл .rbfr.gz или каталог>...");
+        }
+
+        Ok(Some(Self { paths, speed }))
+    }
+}
+
+/// Кадры всех файлов записи по порядку времени получения
+pub struct RecordedFrames {
+    /// Файл и его следующий кадр. Порядок файлов решает при равном времени
+    readers: Vec<(FrameReader, ExchangeType, Option<Frame>)>,
+}
+
+impl RecordedFrames {
+    /// Каталоги читаются рекурсивно, берутся файлы `*.rbfr.gz`
+    pub fn open(
+        paths: &[PathBuf]
+    ) -> anyhow::Result<Self> {
+        let mut files = Vec::new();
+        for path in paths {
+            collect_files(path, &mut files)?;
+        }
+        files.sort();
+
+        let mut readers = Vec::new();
+        for file in files {
+            let mut reader = FrameReader::open(&file).with_context(|| file.display().to_string())?;
+            let exchange_id = reader.exchange_id()
+                .ok_or_else(|| anyhow!("{}: неизвестная биржа {}", file.display(), reader.exchange))?;
+            let next = reader.next_frame().with_context(|| file.display().to_string())?;
+
+            readers.push((reader, exchange_id, next));
+        }
+
+        if readers.iter().all(|(_, _, next)| next.is_none()) {
+            bail!("в записи нет кадров");
+        }
+
+        Ok(Self { readers })
+    }
+
+    /// Время первого кадра записи в Unix мкс
+    pub fn start_us(&self) -> i64 {
+        self.readers
+            .iter()
+            .filter_map(|(_, _, next)| next.as_ref().map(|frame| frame.wall_us))
+            .min()
+            .unwrap_or_default()
+    }
+
+    pub fn exchanges(&self) -> HashSet<ExchangeType> {
+        self.readers.iter().map(|(_, exchange_id, _)| *exchange_id).collect()
+    }
+
+    /// Следующий по времени кадр. Ошибка чтения закрывает только свой файл
+    pub fn next(
+        &mut self
+    ) -> Option<(ExchangeType, Frame)> {
+        let (reader, exchange_id, next) = self.readers
+            .iter_mut()
+            .filter(|(_, _, next)| next.is_some())
+            .min_by_key(|(_, _, next)| next.as_ref().map(|frame| frame.wall_us))?;
+
+        let frame = next.take()?;
+        *next = reader.next_frame().unwrap_or_else(|e| {
+            tracing::error!("Replay -> {}: {e}", reader.path.display());
+            None
+        });
+
+        Some((*exchange_id, frame))
+    }
+}
+
+/// Адаптер биржи записи с адресами из конфига. Соединений он не открывает
+pub fn replay_adapter(
+    exchange_id: ExchangeType
+) -> anyhow::Result<Arc<dyn ExchangeAdapter>> {
+    let exchange = config::get().exchange(exchange_id);
+    let adapter: Arc<dyn ExchangeAdapter> = match exchange_id {
+        ExchangeType::Bybit => BybitAdapter::new(&exchange),
+        ExchangeType::Gate => GateAdapter::new(&exchange),
+        ExchangeType::KuCoin => KuCoinAdapter::new(&exchange),
+        _ => bail!("для {} нет адаптера", exchange_id)
+    };
+
+    Ok(adapter)
+}
+
+fn collect_files(
+    path: &Path,
+    files: &mut Vec<PathBuf>
+) -> anyhow::Result<()> {
+    if !path.is_dir() {
+        files.push(path.to_path_buf());
+        return Ok(());
+    }
+
+    for entry in std::fs::read_dir(path).with_context(|| path.display().to_string())? {
+        let path = entry?.path();
+        if path.is_dir() {
+            collect_files(&path, files)?;
+        } else if path.to_string_lossy().ends_with(".rbfr.gz") {
+            files.push(path);
+        }
+    }
+
+    Ok(())
+}
+
+/// Биржа записи: её адаптер и `ExchangeStore`.
+/// События адаптера идут в `ExchangeStore` через `Replay`, который регистрирует новые символы
+struct ReplayExchange {
+    adapter: Arc<dyn ExchangeAdapter>,
+    sender_data: BookEventSender,
+    events_rx: mpsc::Receiver<ExchangeStoreCMD>,
+    store_tx: mpsc::Sender<ExchangeStoreCMD>,
+    /// Символы, зарегистрированные в `ExchangeStore` и `DataAggregator`
+    symbols: HashSet<Symbol>,
+}
+
+/// <b>Replay</b> подаёт записанные кадры в настоящие адаптеры вместо WebSocket бирж.
+///
+/// Путь данных тот же: `parse_message` -> `ExchangeStore` -> `DataAggregator` -> `DataMapping`.
+/// Часы процесса идут по времени получения кадров, поэтому минуты `db_writer` и свежесть стаканов
+/// считаются по записи. Стакан после каждого кадра передаётся в `DataAggregator` сразу, без подписки
+/// клиента, а перед каждой новой минутой записи `Replay` ждёт, пока `DataAggregator` разберёт все стаканы.
+/// Поэтому при одинаковой записи и конфиге спреды получаются одинаковыми на любой скорости.
+///
+/// Символы регистрируются по первому событию с ними, фильтры символов конфига применяются.
+/// После последнего кадра приложение останавливается, `DataAggregator` записывает последнюю минуту
+pub struct Replay {
+    frames: RecordedFrames,
+    speed: ReplaySpeed,
+    exchanges: HashMap<ExchangeType, ReplayExchange>,
+
+    data_aggregator_tx: mpsc::Sender<DataAggregatorCmd>,
+    book_updates: Arc<BookUpdatesQueue>,
+    supervisor: Supervisor,
+
+    frames_total: u64,
+    /// Начало воспроизведения: монотонное время и время записи
+    started: Option<(Instant, i64)>,
+    minute: i64,
+}
+
+impl Replay {
+    /// Создаёт адаптеры и запускает `ExchangeStore` бирж записи.
+    /// Часы процесса уже должны идти по записи, см. `clock::start_replay`
+    pub fn new(
+        frames: RecordedFrames,
+        speed: ReplaySpeed,
+        data_aggregator_tx: mpsc::Sender<DataAggregatorCmd>,
+        book_updates: Arc<BookUpdatesQueue>,
+        health: HealthHandle,
+        supervisor: Supervisor
+    ) -> anyhow::Result<Self> {
+        let config = config::get();
+
+        let mut exchanges = HashMap::new();
+        for exchange_id in frames.exchanges() {
+            let adapter = replay_adapter(exchange_id)?;
+            let (sender_data, events_rx) = BookEventSender::channel(exchange_id);
+            let (store_tx, store_rx) = mpsc::channel(config.pipeline.exchange_store_queue);
+            supervisor.spawn_actor(
+                format!("{}Store", exchange_id),
+                ExchangeStore::new(store_rx, exchange_id, None, health.clone())
+            );
+
+            exchanges.insert(exchange_id, ReplayExchange {
+                adapter,
+                sender_data,
+                events_rx,
+                store_tx,
+                symbols: HashSet::new(),
+            });
+        }
+
+        let minute = frames.start_us() / 60_000_000;
+        Ok(Self {
+            frames,
+            speed,
+            exchanges,
+
+            data_aggregator_tx,
+            book_updates,
+            supervisor,
+
+            frames_total: 0,
+            started: None,
+            minute,
+        })
+    }
+}
+
+#[async_trait]
+impl Actor for Replay {
+    async fn run(
+        &mut self,
+        shutdown: CancellationToken
+    ) {
+        let (started, start_us) = *self.started.get_or_insert((Instant::now(), self.frames.start_us()));
+        info!("Replay -> воспроизведение {:?}, биржи: {:?}", self.speed, self.exchanges.keys());
+
+        while let Some((exchange_id, frame)) = self.frames.next() {
+            if self.speed == ReplaySpeed::Realtime {
+                let offset = Duration::from_micros((frame.wall_us - start_us).max(0) as u64);
+                tokio::select! {
+                    _ = shutdown.cancelled() => return,
+                    _ = tokio::time::sleep_until(started + offset) => {}
+                }
+            } else if shutdown.is_cancelled() {
+                return;
+            }
+
+            // Стаканы прошлой минуты должны попасть в её батч
+            let minute = frame.wall_us / 60_000_000;
+            if minute != self.minute {
+                self.minute = minute;
+                self.drain(&shutdown).await;
+            }
+
+            clock::advance(frame.wall_us);
+            self.replay_frame(exchange_id, frame).await;
+            self.frames_total += 1;
+        }
+
+        self.drain(&shutdown).await;
+        info!(
+            "Replay -> воспроизведено кадров: {} за {:?}, время записи {}",
+            self.frames_total, started.elapsed(), clock::now()
+        );
+
+        self.supervisor.shutdown();
+    }
+}
+
+impl Replay {
+    async fn replay_frame(
+        &mut self,
+        exchange_id: ExchangeType,
+        frame: Frame
+    ) {
+        // Бинарные кадры адаптеры не разбирают и при live соединении
+        if frame.kind != FrameKind::Text {
+            return;
+        }
+        let Ok(text) = String::from_utf8(frame.payload) else { return };
+
+        let Some(exchange) = self.exchanges.get_mut(&exchange_id) else { return };
+        if exchange.adapter.clone().is_pong(&text) {
+            return;
+        }
+        exchange.adapter.clone().parse_message(text, exchange.sender_data.clone()).await;
+
+        let mut changed = Vec::new();
+        while let Ok(cmd) = exchange.events_rx.try_recv() {
+            let ExchangeStoreCMD::Event(event) = &cmd else { continue };
+            let symbol = match event {
+                BookEvent::Snapshot { symbol, .. }
+                | BookEvent::Delta { symbol, .. }
+                | BookEvent::TickerUpdate { symbol, .. } => symbol.clone(),
+            };
+
+            if !exchange.symbols.contains(&symbol) {
+                let normalized = normalize_symbol(&symbol);
+
+                if !config::get().allows_symbol(exchange_id, &normalized) {
+                    continue;
+                }
+
+                let _ = exchange.store_tx.send(ExchangeStoreCMD::RegisterSymbol { symbol: Arc::new(symbol.clone()) }).await;
+                let _ = self.data_aggregator_tx.send(
+                    DataAggregatorCmd::MarketRegister {
+                        symbol: Arc::new(symbol.clone()),
+                        exchange_id
+                    }
+                ).await;
+                exchange.symbols.insert(symbol.clone());
+            }
+
+            let _ = exchange.store_tx.send(cmd).await;
+            if !changed.contains(&symbol) {
+                changed.push(symbol);
+            }
+        }
+
+        // Стакан после кадра, а не последнее состояние к моменту чтения подписчиком
+        for symbol in changed {
+            let (reply, rx) = oneshot::channel();
+            let _ = exchange.store_tx.send(ExchangeStoreCMD::GetBook { symbol: Arc::new(symbol), reply }).await;
+
+            let Ok(Some(mut data)) = rx.await else { continue };
+            if data.symbol.is_empty() {
+                continue;
+            }
+
+            data.updated_at = Some(clock::instant());
+            self.book_updates.push((exchange_id, data.symbol.clone()), Arc::new(data));
+        }
+    }
+
+    /// Ждёт, пока `DataAggregator` заберёт все стаканы из очереди
+    async fn drain(
+        &self,
+        shutdown: &CancellationToken
+    ) {
+        while !self.book_updates.is_empty() && !shutdown.is_cancelled() {
+            tokio::time::sleep(DRAIN_POLL).await;
+        }
+    }
+}
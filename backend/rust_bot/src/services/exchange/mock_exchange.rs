@@ -0,0 +1,711 @@
+use std::{collections::{BTreeMap, HashMap}, net::SocketAddr, path::PathBuf, sync::{Arc, Mutex, atomic::{AtomicBool, Ordering}}, time::Duration};
+use anyhow::{Context, bail};
+use futures_util::{SinkExt, StreamExt, stream::SplitSink};
+use serde::Deserialize;
+use tokio::net::{TcpListener, TcpStream};
+use tokio_tungstenite::{WebSocketStream, accept_async, tungstenite::Message};
+use tokio_util::sync::CancellationToken;
+use tracing::info;
+use wiremock::{Mock, MockServer, ResponseTemplate, matchers::{method, path}};
+
+use crate::{config::ExchangeConfig, models::exchange::ExchangeType, services::exchange::mock_trading::{MockTrading, MockTradingOptions}};
+
+const USAGE: &str = "usage: rust_bot mock-exchange <bybit|gate.io|kucoin> [options]
+
+options:
+    --symbols <A,B>        символы в формате биржи (по умолчанию BTCUSDT, BTC_USDT или BTC-USDT)
+    --script <file.json>   сценарий WebSocket, список шагов MockStep (по умолчанию снапшот и тикер на подписку)
+    --rest-bind <addr>     адрес REST (по умолчанию 127.0.0.1:0)
+    --ws-bind <addr>       адрес WebSocket (по умолчанию 127.0.0.1:0)
+
+торговля (bybit и gate.io), включается ключом:
+    --api-key <key>        ключ, которым должны быть подписаны запросы
+    --api-secret <secret>
+    --private-ws-bind <addr>  адрес приватного WebSocket (по умолчанию 127.0.0.1:0)
+    --fill-ratio <0..1>    доля рыночного и IOC ордера, исполняемая сразу (по умолчанию 1)
+    --fill-price <price>   цена исполнения рыночных ордеров (по умолчанию 100)
+    --balances <A=n,B=n>   остатки счёта, например USDT=1000,BTC=1. Без них остатки не ведутся
+                           и ордера не проверяются на средства";
+
+/// Уровень стакана `[цена, объём]`
+pub type MockLevel = [f64; 2];
+
+/// Шаг сценария WebSocket. Сообщения собираются в формате биржи, которую изображает сервер
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum MockStep {
+    /// Ждать от клиента сообщение с подстрокой, например подписку на символ.
+    /// Учитываются сообщения текущего соединения
+    WaitFor {
+        contains: String,
+    },
+    Snapshot {
+        symbol: String,
+        asks: Vec<MockLevel>,
+        bids: Vec<MockLevel>,
+    },
+    /// Объём 0 удаляет уровень
+    Delta {
+        symbol: String,
+        asks: Vec<MockLevel>,
+        bids: Vec<MockLevel>,
+    },
+    Ticker {
+        symbol: String,
+        last_price: f64,
+        volume: f64,
+    },
+    /// Кадр как есть
+    Raw {
+        text: String,
+    },
+    /// Оборванный JSON, который адаптер биржи примет за стакан символа
+    Malformed {
+        symbol: String,
+    },
+    Sleep {
+        ms: u64,
+    },
+    /// Закрыть соединение. Сценарий продолжается на следующем соединении
+    Disconnect,
+    /// Отвечать ли на ping. Без ответов клиент переподключается по heartbeat
+    Pongs {
+        enabled: bool,
+    },
+}
+
+impl MockStep {
+    /// Сценарий по умолчанию: на подписку символа - снапшот стакана и тикер
+    pub fn default_script(
+        symbols: &[String]
+    ) -> Vec<MockStep> {
+        symbols
+            .iter()
+            .flat_map(|symbol| [
+                MockStep::WaitFor { contains: symbol.clone() },
+                MockStep::Snapshot {
+                    symbol: symbol.clone(),
+                    asks: vec![[100.1, 1.0], [100.2, 2.0], [100.5, 5.0]],
+                    bids: vec![[99.9, 1.0], [99.8, 2.0], [99.5, 5.0]],
+                },
+                MockStep::Ticker { symbol: symbol.clone(), last_price: 100.0, volume: 1_000_000.0 },
+            ])
+            .collect()
+    }
+}
+
+/// Состояние сервера, общее для всех соединений WebSocket
+struct MockState {
+    exchange_id: ExchangeType,
+    script: Vec<MockStep>,
+    /// Следующий шаг сценария. После переподключения сценарий не начинается заново
+    cursor: Mutex<usize>,
+    pongs: AtomicBool,
+    /// Текстовые сообщения клиентов по порядку
+    received: Mutex<Vec<String>>,
+    connections: Mutex<u64>,
+}
+
+impl MockState {
+    fn current(
+        &self
+    ) -> Option<MockStep> {
+        let cursor = *self.cursor.lock().unwrap();
+        self.script.get(cursor).cloned()
+    }
+
+    fn advance(
+        &self
+    ) {
+        *self.cursor.lock().unwrap() += 1;
+    }
+}
+
+/// <b>MockExchange</b> - биржа в процессе для интеграционных прогонов без сети.
+///
+/// <br>• REST на `wiremock`: Bybit `/v5/market/tickers`, Gate `/spot/currency_pairs` и `/spot/order_book`,
+/// KuCoin `/api/v1/bullet-public` и `/api/v1/market/allTickers`
+/// <br>• WebSocket проигрывает сценарий `MockStep` и отвечает на ping в формате биржи
+/// <br>• `exchange_config()` - секция конфига, которая направляет `ExchangeSetup` на сервер
+/// <br>• `enable_trading` добавляет приватный API с проверкой подписей, см. `MockTrading`
+///
+/// Сервер останавливается при drop
+pub struct MockExchange {
+    exchange_id: ExchangeType,
+    rest: MockServer,
+    ws_addr: SocketAddr,
+    state: Arc<MockState>,
+    trading: Option<MockTrading>,
+    token: CancellationToken,
+}
+
+impl MockExchange {
+    /// Поддерживаются Bybit, Gate и KuCoin. `symbols` в формате биржи: `BTCUSDT`, `BTC_USDT`, `BTC-USDT`
+    #[allow(unused)]
+    pub async fn start(
+        exchange_id: ExchangeType,
+        symbols: &[String],
+        script: Vec<MockStep>
+    ) -> anyhow::Result<Self> {
+        let rest = TcpListener::bind("127.0.0.1:0").await?;
+        let ws = TcpListener::bind("127.0.0.1:0").await?;
+        Self::start_on(exchange_id, symbols, script, rest.into_std()?, ws).await
+    }
+
+    /// То же на заранее открытых сокетах
+    pub async fn start_on(
+        exchange_id: ExchangeType,
+        symbols: &[String],
+        script: Vec<MockStep>,
+        rest_listener: std::net::TcpListener,
+        ws_listener: TcpListener
+    ) -> anyhow::Result<Self> {
+        if !matches!(exchange_id, ExchangeType::Bybit | ExchangeType::Gate | ExchangeType::KuCoin) {
+            bail!("mock сервер не поддерживает {exchange_id}");
+        }
+
+        let ws_addr = ws_listener.local_addr()?;
+        let rest = MockServer::builder().listener(rest_listener).start().await;
+        mount_rest(exchange_id, symbols, &rest, &format!("ws://{ws_addr}")).await;
+
+        let state = Arc::new(MockState {
+            exchange_id,
+            script,
+            cursor: Mutex::new(0),
+            pongs: AtomicBool::new(true),
+            received: Mutex::new(Vec::new()),
+            connections: Mutex::new(0),
+        });
+        let token = CancellationToken::new();
+
+        tokio::spawn(accept_loop(ws_listener, state.clone(), token.clone()));
+
+        Ok(Self { exchange_id, rest, ws_addr, state, trading: None, token })
+    }
+
+    /// Приватный API на том же REST сервере и отдельном WebSocket
+    pub async fn enable_trading(
+        &mut self,
+        options: MockTradingOptions,
+        private_ws_listener: TcpListener
+    ) -> anyhow::Result<()> {
+        let trading = MockTrading::start(self.exchange_id, options, &self.rest, private_ws_listener, self.token.child_token()).await?;
+        self.trading = Some(trading);
+        Ok(())
+    }
+
+    pub fn rest_url(&self) -> String {
+        self.rest.uri()
+    }
+
+    pub fn ws_url(&self) -> String {
+        format!("ws://{}", self.ws_addr)
+    }
+
+    /// Включённая биржа с адресами сервера
+    #[allow(unused)]
+    pub fn exchange_config(
+        &self
+    ) -> ExchangeConfig {
+        ExchangeConfig {
+            enabled: true,
+            ws_url: Some(self.ws_url()),
+            rest_url: Some(self.rest_url()),
+            ..Default::default()
+        }
+    }
+
+    /// Секция для `config.toml`
+    pub fn config_snippet(
+        &self
+    ) -> String {
+        let section = serde_json::to_string(&self.exchange_id).unwrap_or_default();
+        let mut snippet = format!("[exchanges.{section}]\nenabled = true\nws_url = \"{}\"\nrest_url = \"{}\"", self.ws_url(), self.rest_url());
+
+        if let Some(trading) = &self.trading {
+            snippet.push_str(&format!(
+                "\n\n[exchanges.{section}.trading]\nenabled = true\nrest_url = \"{}\"\nws_url = \"{}\"",
+                self.rest_url(),
+                trading.ws_url()
+            ));
+        }
+
+        snippet
+    }
+
+    /// Текстовые сообщения, которые клиенты прислали по WebSocket
+    pub fn received(&self) -> Vec<String> {
+        self.state.received.lock().unwrap().clone()
+    }
+
+    /// Сколько раз клиенты подключались по WebSocket
+    pub fn connections(&self) -> u64 {
+        *self.state.connections.lock().unwrap()
+    }
+
+    /// Сценарий проигран до конца
+    pub fn script_done(&self) -> bool {
+        self.state.current().is_none()
+    }
+
+    pub fn trading(&self) -> Option<&MockTrading> {
+        self.trading.as_ref()
+    }
+}
+
+impl Drop for MockExchange {
+    fn drop(&mut self) {
+        self.token.cancel();
+    }
+}
+
+async fn mount_rest(
+    exchange_id: ExchangeType,
+    symbols: &[String],
+    server: &MockServer,
+    ws_url: &str
+) {
+    let respond = |body: serde_json::Value| ResponseTemplate::new(200).set_body_json(body);
+
+    match exchange_id {
+        ExchangeType::Bybit => {
+            let list: Vec<_> = symbols.iter().map(|symbol| serde_json::json!({ "symbol": symbol })).collect();
+            Mock::given(method("GET"))
+                .and(path("/v5/market/tickers"))
+                .respond_with(respond(serde_json::json!({
+                    "retCode": 0,
+                    "retMsg": "OK",
+                    "result": { "category": "spot", "list": list }
+                })))
+                .mount(server)
+                .await;
+        },
+        ExchangeType::Gate => {
+            let pairs: Vec<_> = symbols.iter().map(|symbol| serde_json::json!({ "id": symbol })).collect();
+            Mock::given(method("GET"))
+                .and(path("/spot/currency_pairs"))
+                .respond_with(respond(serde_json::json!(pairs)))
+                .mount(server)
+                .await;
+            Mock::given(method("GET"))
+                .and(path("/spot/order_book"))
+                .respond_with(respond(serde_json::json!({
+                    "current": 0,
+                    "update": 0,
+                    "asks": [],
+                    "bids": []
+                })))
+                .mount(server)
+                .await;
+        },
+        ExchangeType::KuCoin => {
+            Mock::given(method("POST"))
+                .and(path("/api/v1/bullet-public"))
+                .respond_with(respond(serde_json::json!({
+                    "code": "200000",
+                    "data": {
+                        "token": "mock-token",
+                        "instanceServers": [{
+                            "endpoint": ws_url,
+                            "protocol": "websocket",
+                            "encrypt": false,
+                            "pingInterval": 18000,
+                            "pingTimeout": 10000
+                        }]
+                    }
+                })))
+                .mount(server)
+                .await;
+
+            let tickers: Vec<_> = symbols.iter().map(|symbol| serde_json::json!({ "symbol": symbol })).collect();
+            Mock::given(method("GET"))
+                .and(path("/api/v1/market/allTickers"))
+                .respond_with(respond(serde_json::json!({
+                    "code": "200000",
+                    "data": { "time": chrono::Utc::now().timestamp_millis(), "ticker": tickers }
+                })))
+                .mount(server)
+                .await;
+        },
+        _ => {}
+    }
+}
+
+async fn accept_loop(
+    listener: TcpListener,
+    state: Arc<MockState>,
+    token: CancellationToken
+) {
+    loop {
+        let stream = tokio::select! {
+            _ = token.cancelled() => return,
+            accepted = listener.accept() => match accepted {
+                Ok((stream, _)) => stream,
+                Err(e) => {
+                    tracing::warn!("MockExchange -> accept: {e}");
+                    continue;
+                }
+            }
+        };
+
+        // Сценарий общий, поэтому соединения обслуживаются по очереди
+        tokio::select! {
+            _ = token.cancelled() => return,
+            _ = serve_connection(stream, state.clone()) => {}
+        }
+    }
+}
+
+type WsWrite = SplitSink<WebSocketStream<TcpStream>, Message>;
+
+async fn serve_connection(
+    stream: TcpStream,
+    state: Arc<MockState>
+) {
+    let Ok(ws_stream) = accept_async(stream).await else { return };
+    *state.connections.lock().unwrap() += 1;
+
+    let (mut write, mut read) = ws_stream.split();
+    let mut received = Vec::new();
+
+    if state.exchange_id == ExchangeType::KuCoin {
+        let welcome = serde_json::json!({ "id": "mock", "type": "welcome" }).to_string();
+        if write.send(Message::Text(welcome)).await.is_err() {
+            return;
+        }
+    }
+
+    loop {
+        let step = state.current();
+
+        match step {
+            Some(MockStep::WaitFor { contains }) if received.iter().any(|msg: &String| msg.contains(&contains)) => {
+                state.advance();
+                continue;
+            },
+            Some(MockStep::WaitFor { .. }) => {},
+            Some(MockStep::Sleep { ms }) => {
+                let sleep = tokio::time::sleep(Duration::from_millis(ms));
+                tokio::pin!(sleep);
+                loop {
+                    tokio::select! {
+                        _ = &mut sleep => break,
+                        msg = read.next() => {
+                            if !handle_incoming(msg, &mut write, &state, &mut received).await {
+                                return;
+                            }
+                        }
+                    }
+                }
+                state.advance();
+                continue;
+            },
+            Some(MockStep::Disconnect) => {
+                state.advance();
+                write.send(Message::Close(None)).await.ok();
+                return;
+            },
+            Some(MockStep::Pongs { enabled }) => {
+                state.pongs.store(enabled, Ordering::Relaxed);
+                state.advance();
+                continue;
+            },
+            Some(step) => {
+                state.advance();
+                if let Some(text) = render(state.exchange_id, &step)
+                    && write.send(Message::Text(text)).await.is_err() {
+                    return;
+                }
+                continue;
+            },
+            None => {}
+        }
+
+        // Ждём сообщения клиента: подписку для `WaitFor` или ping после конца сценария
+        if !handle_incoming(read.next().await, &mut write, &state, &mut received).await {
+            return;
+        }
+    }
+}
+
+/// `false` - соединение закрыто
+async fn handle_incoming(
+    msg: Option<Result<Message, tokio_tungstenite::tungstenite::Error>>,
+    write: &mut WsWrite,
+    state: &MockState,
+    received: &mut Vec<String>
+) -> bool {
+    let text = match msg {
+        Some(Ok(Message::Text(text))) => text,
+        Some(Ok(Message::Close(_))) | Some(Err(_)) | None => return false,
+        Some(Ok(_)) => return true,
+    };
+
+    state.received.lock().unwrap().push(text.clone());
+
+    if state.pongs.load(Ordering::Relaxed)
+        && let Some(pong) = pong(state.exchange_id, &text)
+        && write.send(Message::Text(pong)).await.is_err() {
+        return false;
+    }
+
+    received.push(text);
+    true
+}
+
+/// Ответ на ping приложения в формате биржи
+fn pong(
+    exchange_id: ExchangeType,
+    msg: &str
+) -> Option<String> {
+    let json: serde_json::Value = serde_json::from_str(msg).ok()?;
+    let now_ms = chrono::Utc::now().timestamp_millis();
+
+    match exchange_id {
+        ExchangeType::Bybit if json["op"] == "ping" => Some(serde_json::json!({
+            "success": true,
+            "ret_msg": "pong",
+            "conn_id": "mock",
+            "req_id": json["req_id"],
+            "op": "ping"
+        }).to_string()),
+        ExchangeType::Gate if json["channel"] == "spot.ping" => Some(serde_json::json!({
+            "time": now_ms / 1000,
+            "time_ms": now_ms,
+            "id": json["id"],
+            "channel": "spot.pong",
+            "event": "",
+            "result": null
+        }).to_string()),
+        ExchangeType::KuCoin if json["type"] == "ping" => Some(serde_json::json!({
+            "id": json["id"],
+            "type": "pong"
+        }).to_string()),
+        _ => None
+    }
+}
+
+fn levels(
+    levels: &[MockLevel]
+) -> Vec<[String; 2]> {
+    levels.iter().map(|[price, qty]| [price.to_string(), qty.to_string()]).collect()
+}
+
+/// Сообщение шага в формате биржи. `None` - шаг не отправляет кадр
+fn render(
+    exchange_id: ExchangeType,
+    step: &MockStep
+) -> Option<String> {
+    let now_ms = chrono::Utc::now().timestamp_millis();
+
+    let json = match (exchange_id, step) {
+        (_, MockStep::Raw { text }) => return Some(text.clone()),
+
+        (ExchangeType::Bybit, MockStep::Snapshot { symbol, asks, bids } | MockStep::Delta { symbol, asks, bids }) => serde_json::json!({
+            "topic": format!("orderbook.50.{symbol}"),
+            "type": if matches!(step, MockStep::Snapshot { .. }) { "snapshot" } else { "delta" },
+            "ts": now_ms,
+            "cts": now_ms,
+            "data": { "s": symbol, "a": levels(asks), "b": levels(bids), "u": 1, "seq": 1 }
+        }),
+        (ExchangeType::Bybit, MockStep::Ticker { symbol, last_price, volume }) => serde_json::json!({
+            "topic": format!("tickers.{symbol}"),
+            "type": "snapshot",
+            "ts": now_ms,
+            "data": { "symbol": symbol, "lastPrice": last_price.to_string(), "turnover24h": volume.to_string() }
+        }),
+        (ExchangeType::Bybit, MockStep::Malformed { symbol }) => {
+            return Some(format!("{{\"topic\":\"orderbook.50.{symbol}\",\"type\":\"snapshot\",\"data\":{{\"s\":"));
+        },
+
+        (ExchangeType::Gate, MockStep::Snapshot { symbol, asks, bids }) => serde_json::json!({
+            "time": now_ms / 1000,
+            "time_ms": now_ms,
+            "channel": "spot.order_book",
+            "event": "update",
+            "result": { "t": now_ms, "lastUpdateId": 1, "s": symbol, "asks": levels(asks), "bids": levels(bids) }
+        }),
+        (ExchangeType::Gate, MockStep::Delta { symbol, asks, bids }) => serde_json::json!({
+            "time": now_ms / 1000,
+            "time_ms": now_ms,
+            "channel": "spot.order_book_update",
+            "event": "update",
+            "result": { "t": now_ms, "U": 1, "u": 1, "s": symbol, "a": levels(asks), "b": levels(bids) }
+        }),
+        (ExchangeType::Gate, MockStep::Ticker { symbol, last_price, volume }) => serde_json::json!({
+            "time": now_ms / 1000,
+            "time_ms": now_ms,
+            "channel": "spot.tickers",
+            "event": "update",
+            "result": { "currency_pair": symbol, "last": last_price.to_string(), "quote_volume": volume.to_string() }
+        }),
+        (ExchangeType::Gate, MockStep::Malformed { symbol }) => {
+            return Some(format!("{{\"channel\":\"spot.order_book\",\"event\":\"update\",\"result\":{{\"s\":\"{symbol}\",\"asks\":"));
+        },
+
+        (ExchangeType::KuCoin, MockStep::Snapshot { symbol, asks, bids } | MockStep::Delta { symbol, asks, bids }) => serde_json::json!({
+            "type": "message",
+            "topic": format!("/spotMarket/level2Depth50:{symbol}"),
+            "subject": "level2",
+            "data": { "asks": levels(asks), "bids": levels(bids), "timestamp": now_ms }
+        }),
+        (ExchangeType::KuCoin, MockStep::Ticker { symbol, last_price, volume }) => serde_json::json!({
+            "type": "message",
+            "topic": format!("/market/ticker:{symbol}"),
+            "subject": "trade.ticker",
+            "data": { "price": last_price.to_string(), "size": volume.to_string(), "time": now_ms }
+        }),
+        (ExchangeType::KuCoin, MockStep::Malformed { symbol }) => {
+            return Some(format!("{{\"type\":\"message\",\"topic\":\"/spotMarket/level2Depth50:{symbol}\",\"data\":"));
+        },
+
+        _ => return None
+    };
+
+    Some(json.to_string())
+}
+
+/// Аргументы `rust_bot mock-exchange`
+#[derive(Debug, Clone)]
+pub struct MockExchangeArgs {
+    pub exchange_id: ExchangeType,
+    pub symbols: Vec<String>,
+    pub script: Option<PathBuf>,
+    pub rest_bind: SocketAddr,
+    pub ws_bind: SocketAddr,
+    pub trading: Option<MockTradingOptions>,
+    pub private_ws_bind: SocketAddr,
+}
+
+impl MockExchangeArgs {
+    /// `None` - приложение запущено без подкоманды `mock-exchange`
+    pub fn parse(
+        mut args: impl Iterator<Item = String>
+    ) -> anyhow::Result<Option<Self>> {
+        if args.next().as_deref() != Some("mock-exchange") {
+            return Ok(None);
+        }
+
+        let exchange = args.next().context(USAGE)?;
+        let exchange_id = serde_json::from_value::<ExchangeType>(serde_json::Value::String(exchange.clone()))
+            .with_context(|| format!("неизвестная биржа {exchange}\n{USAGE}"))?;
+
+        let mut flags = HashMap::new();
+        while let Some(arg) = args.next() {
+            let Some(flag) = arg.strip_prefix("--") else {
+                bail!("лишний аргумент {arg}\n{USAGE}");
+            };
+            let value = args.next().with_context(|| format!("--{flag} без значения\n{USAGE}"))?;
+            flags.insert(flag.to_string(), value);
+        }
+
+        let mut take = |flag: &str| flags.remove(flag);
+        let addr = |flag: &str, value: Option<String>| -> anyhow::Result<SocketAddr> {
+            value
+                .unwrap_or_else(|| "127.0.0.1:0".into())
+                .parse()
+                .with_context(|| format!("--{flag}: неверный адрес"))
+        };
+
+        let symbols = match take("symbols") {
+            Some(symbols) => symbols.split(',').map(|symbol| symbol.trim().to_string()).filter(|symbol| !symbol.is_empty()).collect(),
+            None => vec![match exchange_id {
+                ExchangeType::Gate => "BTC_USDT",
+                ExchangeType::KuCoin => "BTC-USDT",
+                _ => "BTCUSDT",
+            }.to_string()],
+        };
+
+        let number = |flag: &str, value: Option<String>, default: f64| -> anyhow::Result<f64> {
+            value.map_or(Ok(default), |value| value.parse().with_context(|| format!("--{flag}: неверное число")))
+        };
+
+        let trading = match (take("api-key"), take("api-secret")) {
+            (Some(api_key), Some(api_secret)) => Some(MockTradingOptions {
+                api_key,
+                api_secret,
+                fill_ratio: number("fill-ratio", take("fill-ratio"), 1.0)?,
+                fill_price: number("fill-price", take("fill-price"), 100.0)?,
+                balances: take("balances").map(|balances| parse_balances(&balances)).transpose()?,
+                reject_cancels: false,
+            }),
+            (None, None) => None,
+            _ => bail!("--api-key и --api-secret задаются вместе\n{USAGE}"),
+        };
+
+        let args = Self {
+            exchange_id,
+            symbols,
+            script: take("script").map(PathBuf::from),
+            rest_bind: addr("rest-bind", take("rest-bind"))?,
+            ws_bind: addr("ws-bind", take("ws-bind"))?,
+            trading,
+            private_ws_bind: addr("private-ws-bind", take("private-ws-bind"))?,
+        };
+
+        if let Some(flag) = flags.keys().next() {
+            bail!("неизвестный флаг --{flag}\n{USAGE}");
+        }
+
+        Ok(Some(args))
+    }
+}
+
+/// `USDT=1000,BTC=1` как остатки по валютам в верхнем регистре
+fn parse_balances(
+    value: &str
+) -> anyhow::Result<BTreeMap<String, f64>> {
+    value
+        .split(',')
+        .filter(|pair| !pair.trim().is_empty())
+        .map(|pair| {
+            let (asset, amount) = pair.split_once('=').with_context(|| format!("--balances: `{pair}` без `=`"))?;
+            let amount = amount.trim().parse::<f64>().with_context(|| format!("--balances: неверное число в `{pair}`"))?;
+            Ok((asset.trim().to_ascii_uppercase(), amount))
+        })
+        .collect()
+}
+
+/// Запускает mock биржу и держит её до Ctrl+C
+pub async fn run(
+    args: MockExchangeArgs
+) -> anyhow::Result<()> {
+    let script = match &args.script {
+        Some(file) => {
+            let text = std::fs::read_to_string(file).with_context(|| file.display().to_string())?;
+            serde_json::from_str::<Vec<MockStep>>(&text).with_context(|| file.display().to_string())?
+        },
+        None => MockStep::default_script(&args.symbols),
+    };
+
+    let rest = std::net::TcpListener::bind(args.rest_bind).with_context(|| format!("REST {}", args.rest_bind))?;
+    rest.set_nonblocking(true)?;
+    let ws = TcpListener::bind(args.ws_bind).await.with_context(|| format!("WebSocket {}", args.ws_bind))?;
+
+    let mut mock = MockExchange::start_on(args.exchange_id, &args.symbols, script, rest, ws).await?;
+    if let Some(options) = args.trading.clone() {
+        let private_ws = TcpListener::bind(args.private_ws_bind).await.with_context(|| format!("приватный WebSocket {}", args.private_ws_bind))?;
+        mock.enable_trading(options, private_ws).await?;
+    }
+    info!(
+        "MockExchange -> {}: REST {}, WebSocket {}, символы {:?}\n{}",
+        args.exchange_id,
+        mock.rest_url(),
+        mock.ws_url(),
+        args.symbols,
+        mock.config_snippet()
+    );
+
+    tokio::signal::ctrl_c().await?;
+    info!(
+        "MockExchange -> соединений {}, сообщений от клиентов {}, сценарий {}",
+        mock.connections(),
+        mock.received().len(),
+        if mock.script_done() { "проигран" } else { "не закончен" }
+    );
+    if let Some(trading) = mock.trading() {
+        info!("MockExchange -> ордеров {}, отклонено подписей {}", trading.orders(), trading.rejected_signatures());
+    }
+
+    Ok(())
+}
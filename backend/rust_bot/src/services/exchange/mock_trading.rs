@@ -0,0 +1,835 @@
+use std::{collections::{BTreeMap, HashSet}, sync::{Arc, Mutex, atomic::{AtomicU64, Ordering}}};
+use futures_util::{SinkExt, StreamExt};
+use serde_json::{Value, json};
+use tokio::{net::{TcpListener, TcpStream}, sync::broadcast};
+use tokio_tungstenite::{accept_async, tungstenite::Message};
+use tokio_util::sync::CancellationToken;
+use wiremock::{Mock, MockServer, Request, Respond, ResponseTemplate, matchers::path_regex};
+
+use crate::{adapters::{bybit_trading::BybitTrading, gate_trading::GateTrading}, models::exchange::ExchangeType, services::exchange::trading_adapter::{Credentials, split_symbol}};
+
+/// Комиссия mock биржи, доля суммы сделки
+const MOCK_FEE_RATE: f64 = 0.001;
+/// Допустимое расхождение времени подписи Gate, в секундах
+const GATE_TIME_WINDOW_SECS: i64 = 60;
+
+/// Как mock биржа исполняет ордера
+#[derive(Debug, Clone)]
+pub struct MockTradingOptions {
+    pub api_key: String,
+    pub api_secret: String,
+    /// Доля рыночного или IOC ордера, которая исполняется сразу, остаток отменяется.
+    /// GTC ордер с `fill_ratio` < 1 остаётся в стакане
+    pub fill_ratio: f64,
+    /// Цена исполнения рыночных ордеров, лимитные исполняются по своей цене
+    pub fill_price: f64,
+    /// Начальные остатки по валютам. `None` - остатки не ведутся и ордера не проверяются на средства
+    pub balances: Option<BTreeMap<String, f64>>,
+    /// Отмена отвечает ошибкой сервера, как у перегруженной биржи. Итог ордера тогда есть только в `get_order`
+    pub reject_cancels: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum MockOrderStatus {
+    Open,
+    Filled,
+    Cancelled,
+}
+
+#[derive(Debug, Clone)]
+struct MockOrder {
+    id: u64,
+    /// `orderLinkId` Bybit или `text` Gate как есть
+    client_order_id: String,
+    /// Символ в формате биржи
+    symbol: String,
+    buy: bool,
+    market: bool,
+    ioc: bool,
+    /// Количество в запросе: у рыночной покупки Gate - сумма в котируемой валюте
+    amount: f64,
+    qty: f64,
+    price: f64,
+    status: MockOrderStatus,
+    filled: f64,
+    filled_total: f64,
+    updated_ms: i64,
+}
+
+impl MockOrder {
+    fn avg_price(&self) -> f64 {
+        if self.filled > 0.0 { self.filled_total / self.filled } else { 0.0 }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum MockPrivateEvent {
+    Order(MockOrder),
+    Trade {
+        order: MockOrder,
+        trade_id: u64,
+        qty: f64,
+        price: f64,
+        fee_asset: String,
+        fee: f64,
+    },
+    Balance {
+        asset: String,
+        free: f64,
+    },
+}
+
+/// Ордера и ключ mock биржи, общие для REST и приватных соединений
+struct TradingState {
+    exchange_id: ExchangeType,
+    credentials: Credentials,
+    options: MockTradingOptions,
+    /// По бирже id, поиск по client order id берёт последний ордер
+    orders: Mutex<BTreeMap<u64, MockOrder>>,
+    next_id: AtomicU64,
+    events: broadcast::Sender<MockPrivateEvent>,
+    rejected_signatures: AtomicU64,
+    /// Ордера в стакане средства не блокируют: остаток меняется только исполнениями
+    balances: Option<Mutex<BTreeMap<String, f64>>>,
+}
+
+impl TradingState {
+    fn find(
+        &self,
+        id: &str
+    ) -> Option<MockOrder> {
+        let orders = self.orders.lock().unwrap();
+        orders
+            .values()
+            .rev()
+            .find(|order| order.client_order_id == id || order.id.to_string() == id)
+            .cloned()
+    }
+
+    /// Валюты символа в формате биржи (`BTCUSDT`, `BTC_USDT`)
+    fn assets(
+        symbol: &str
+    ) -> Option<(String, String)> {
+        split_symbol(&symbol.replace('_', ""))
+    }
+
+    /// Хватает ли средств на ордер: котируемой валюты на покупку по цене ордера или `fill_price`
+    /// для рыночного, базовой на продажу
+    fn covers(
+        &self,
+        order: &MockOrder
+    ) -> bool {
+        let Some(balances) = &self.balances else { return true };
+        let Some((base, quote)) = Self::assets(&order.symbol) else { return false };
+
+        let (asset, required) = if order.buy {
+            (quote, order.qty * if order.market { self.options.fill_price } else { order.price })
+        } else {
+            (base, order.qty)
+        };
+        balances.lock().unwrap().get(&asset).copied().unwrap_or_default() >= required
+    }
+
+    fn balances(&self) -> Vec<(String, f64)> {
+        self.balances
+            .as_ref()
+            .map(|balances| balances.lock().unwrap().iter().map(|(asset, free)| (asset.clone(), *free)).collect())
+            .unwrap_or_default()
+    }
+
+    /// Комиссия сделки и её валюта. Как на настоящих биржах, Bybit берёт комиссию покупки
+    /// в базовой валюте, остальные сделки платят в котируемой
+    fn fee(
+        &self,
+        order: &MockOrder,
+        qty: f64,
+        price: f64
+    ) -> (String, f64) {
+        let (base, quote) = Self::assets(&order.symbol).unwrap_or_default();
+        if self.exchange_id == ExchangeType::Bybit && order.buy {
+            (base, qty * MOCK_FEE_RATE)
+        } else {
+            (quote, qty * price * MOCK_FEE_RATE)
+        }
+    }
+
+    /// Сделка двигает базовую и котируемую валюту, комиссия списывается с той, в которой взята
+    fn settle(
+        &self,
+        order: &MockOrder,
+        qty: f64,
+        price: f64
+    ) {
+        let Some(balances) = &self.balances else { return };
+        let Some((base, quote)) = Self::assets(&order.symbol) else { return };
+        let notional = qty * price;
+        let (fee_asset, fee) = self.fee(order, qty, price);
+
+        let changed = {
+            let mut balances = balances.lock().unwrap();
+            let (mut base_change, mut quote_change) = if order.buy { (qty, -notional) } else { (-qty, notional) };
+            if fee_asset == base {
+                base_change -= fee;
+            } else {
+                quote_change -= fee;
+            }
+            [(base, base_change), (quote, quote_change)].map(|(asset, change)| {
+                let free = balances.entry(asset.clone()).or_default();
+                *free += change;
+                (asset, *free)
+            })
+        };
+
+        for (asset, free) in changed {
+            self.events.send(MockPrivateEvent::Balance { asset, free }).ok();
+        }
+    }
+
+    /// Новый ордер и немедленное исполнение по `fill_ratio`
+    fn create(
+        &self,
+        mut order: MockOrder
+    ) -> MockOrder {
+        order.id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        order.updated_ms = chrono::Utc::now().timestamp_millis();
+        self.orders.lock().unwrap().insert(order.id, order.clone());
+        self.events.send(MockPrivateEvent::Order(order.clone())).ok();
+
+        let ratio = self.options.fill_ratio.clamp(0.0, 1.0);
+        if ratio > 0.0 && (order.market || order.ioc || ratio >= 1.0) {
+            let price = if order.market { self.options.fill_price } else { order.price };
+            let qty = order.qty * ratio;
+            self.fill(&mut order, qty, price);
+        }
+
+        if order.status == MockOrderStatus::Open && (order.market || order.ioc) {
+            order.status = MockOrderStatus::Cancelled;
+            self.update(&order);
+        }
+
+        order
+    }
+
+    fn fill(
+        &self,
+        order: &mut MockOrder,
+        qty: f64,
+        price: f64
+    ) {
+        order.filled += qty;
+        order.filled_total += qty * price;
+        if order.filled >= order.qty * (1.0 - 1e-9) {
+            order.status = MockOrderStatus::Filled;
+        }
+        self.settle(order, qty, price);
+
+        let (fee_asset, fee) = self.fee(order, qty, price);
+        self.events.send(MockPrivateEvent::Trade {
+            order: order.clone(),
+            trade_id: self.next_id.fetch_add(1, Ordering::Relaxed),
+            qty,
+            price,
+            fee_asset,
+            fee,
+        }).ok();
+        self.update(order);
+    }
+
+    fn update(
+        &self,
+        order: &MockOrder
+    ) {
+        let mut order = order.clone();
+        order.updated_ms = chrono::Utc::now().timestamp_millis();
+        self.orders.lock().unwrap().insert(order.id, order.clone());
+        self.events.send(MockPrivateEvent::Order(order)).ok();
+    }
+
+    fn cancel(
+        &self,
+        id: &str
+    ) -> Option<MockOrder> {
+        let mut order = self.find(id).filter(|order| order.status == MockOrderStatus::Open)?;
+        order.status = MockOrderStatus::Cancelled;
+        self.update(&order);
+        Some(order)
+    }
+}
+
+/// <b>MockTrading</b> приватный API mock биржи: подписанные REST запросы на ордера и приватный WebSocket.
+///
+/// <br>• Подпись каждого запроса проверяется тем же кодом, которым её ставят `BybitTrading` и `GateTrading`,
+/// неверная подпись получает ответ биржи с кодом ошибки авторизации
+/// <br>• Рыночные и IOC ордера исполняются сразу на `fill_ratio`, исполнения и статусы уходят в приватный поток
+/// <br>• С `balances` ордер без средств отклоняется, исполнения меняют остатки и отправляют их в приватный поток
+pub struct MockTrading {
+    state: Arc<TradingState>,
+    ws_url: String,
+}
+
+impl MockTrading {
+    pub async fn start(
+        exchange_id: ExchangeType,
+        options: MockTradingOptions,
+        rest: &MockServer,
+        ws_listener: TcpListener,
+        token: CancellationToken
+    ) -> anyhow::Result<Self> {
+        let ws_url = format!("ws://{}", ws_listener.local_addr()?);
+        let (events, _) = broadcast::channel(1024);
+
+        let state = Arc::new(TradingState {
+            exchange_id,
+            credentials: Credentials { api_key: options.api_key.clone(), api_secret: options.api_secret.clone() },
+            orders: Mutex::new(BTreeMap::new()),
+            next_id: AtomicU64::new(1_000_000),
+            events,
+            rejected_signatures: AtomicU64::new(0),
+            balances: options.balances.clone().map(Mutex::new),
+            options,
+        });
+
+        let paths = match exchange_id {
+            ExchangeType::Bybit => "^/v5/(order|account)/",
+            ExchangeType::Gate => "^/spot/(orders|accounts)",
+            _ => anyhow::bail!("mock торговля не поддерживает {exchange_id}"),
+        };
+        Mock::given(path_regex(paths))
+            .respond_with(TradingResponder { state: state.clone() })
+            .mount(rest)
+            .await;
+
+        tokio::spawn(accept_private(ws_listener, state.clone(), token));
+
+        Ok(Self { state, ws_url })
+    }
+
+    pub fn ws_url(&self) -> &str {
+        &self.ws_url
+    }
+
+    /// Запросы и подписки, отклонённые из-за ключа или подписи
+    pub fn rejected_signatures(&self) -> u64 {
+        self.state.rejected_signatures.load(Ordering::Relaxed)
+    }
+
+    pub fn orders(&self) -> usize {
+        self.state.orders.lock().unwrap().len()
+    }
+}
+
+struct TradingResponder {
+    state: Arc<TradingState>,
+}
+
+impl Respond for TradingResponder {
+    fn respond(
+        &self,
+        request: &Request
+    ) -> ResponseTemplate {
+        match self.state.exchange_id {
+            ExchangeType::Bybit => bybit_rest(&self.state, request),
+            _ => gate_rest(&self.state, request),
+        }
+    }
+}
+
+fn header<'a>(
+    request: &'a Request,
+    name: &str
+) -> &'a str {
+    request.headers.get(name).and_then(|value| value.to_str().ok()).unwrap_or_default()
+}
+
+fn query_param(
+    request: &Request,
+    name: &str
+) -> Option<String> {
+    request.url.query_pairs().find(|(key, _)| key == name).map(|(_, value)| value.into_owned())
+}
+
+fn number(
+    value: &Value
+) -> f64 {
+    value.as_str().and_then(|value| value.parse().ok()).or_else(|| value.as_f64()).unwrap_or_default()
+}
+
+fn bybit_rest(
+    state: &TradingState,
+    request: &Request
+) -> ResponseTemplate {
+    let respond = |code: i64, message: &str, result: Value| ResponseTemplate::new(200).set_body_json(json!({
+        "retCode": code,
+        "retMsg": message,
+        "result": result,
+        "retExtInfo": {},
+        "time": chrono::Utc::now().timestamp_millis()
+    }));
+
+    let timestamp: i64 = header(request, "X-BAPI-TIMESTAMP").parse().unwrap_or_default();
+    let recv_window: u64 = header(request, "X-BAPI-RECV-WINDOW").parse().unwrap_or(5000);
+    let body = String::from_utf8_lossy(&request.body).into_owned();
+    let payload = if request.method.as_str() == "GET" { request.url.query().unwrap_or_default().to_string() } else { body.clone() };
+
+    if header(request, "X-BAPI-API-KEY") != state.credentials.api_key {
+        state.rejected_signatures.fetch_add(1, Ordering::Relaxed);
+        return respond(10003, "API key is invalid.", json!({}));
+    }
+    if (chrono::Utc::now().timestamp_millis() - timestamp).unsigned_abs() > recv_window {
+        state.rejected_signatures.fetch_add(1, Ordering::Relaxed);
+        return respond(10002, "invalid request, please check your server timestamp or recv_window param", json!({}));
+    }
+    if header(request, "X-BAPI-SIGN") != BybitTrading::sign(&state.credentials, timestamp, recv_window, &payload) {
+        state.rejected_signatures.fetch_add(1, Ordering::Relaxed);
+        return respond(10004, "error sign! origin_string[...]", json!({}));
+    }
+
+    let params: Value = serde_json::from_str(&body).unwrap_or_default();
+
+    match (request.method.as_str(), request.url.path()) {
+        ("POST", "/v5/order/create") => {
+            let client_order_id = params["orderLinkId"].as_str().unwrap_or_default().to_string();
+            if !client_order_id.is_empty() && state.find(&client_order_id).is_some() {
+                return respond(110072, "OrderLinkedID is duplicate", json!({}));
+            }
+
+            let market = params["orderType"] == "Market";
+            if !market && number(&params["price"]) <= 0.0 {
+                return respond(10001, "params error: price is required", json!({}));
+            }
+
+            let order = MockOrder {
+                id: 0,
+                client_order_id,
+                symbol: params["symbol"].as_str().unwrap_or_default().to_string(),
+                buy: params["side"] == "Buy",
+                market,
+                ioc: params["timeInForce"] == "IOC",
+                amount: number(&params["qty"]),
+                qty: number(&params["qty"]),
+                price: number(&params["price"]),
+                status: MockOrderStatus::Open,
+                filled: 0.0,
+                filled_total: 0.0,
+                updated_ms: 0,
+            };
+            if !state.covers(&order) {
+                return respond(170131, "Insufficient balance.", json!({}));
+            }
+
+            let order = state.create(order);
+            respond(0, "OK", json!({ "orderId": order.id.to_string(), "orderLinkId": order.client_order_id }))
+        },
+        ("POST", "/v5/order/cancel") if state.options.reject_cancels => respond(10016, "Internal server error.", json!({})),
+        ("POST", "/v5/order/cancel") => {
+            let id = params["orderLinkId"].as_str().or(params["orderId"].as_str()).unwrap_or_default();
+            match state.cancel(id) {
+                Some(order) => respond(0, "OK", json!({ "orderId": order.id.to_string(), "orderLinkId": order.client_order_id })),
+                None => respond(170213, "Order does not exist.", json!({})),
+            }
+        },
+        ("GET", "/v5/order/realtime" | "/v5/order/history") => {
+            let id = query_param(request, "orderLinkId").or_else(|| query_param(request, "orderId")).unwrap_or_default();
+            let list: Vec<Value> = state.find(&id).map(|order| bybit_order(&order)).into_iter().collect();
+            respond(0, "OK", json!({ "category": "spot", "list": list }))
+        },
+        ("GET", "/v5/account/wallet-balance") => {
+            let coins: Vec<Value> = state.balances().into_iter().map(|(asset, free)| bybit_coin(&asset, free)).collect();
+            respond(0, "OK", json!({ "list": [{ "accountType": "UNIFIED", "coin": coins }] }))
+        },
+        _ => ResponseTemplate::new(404),
+    }
+}
+
+fn bybit_order(
+    order: &MockOrder
+) -> Value {
+    json!({
+        "category": "spot",
+        "orderId": order.id.to_string(),
+        "orderLinkId": order.client_order_id,
+        "symbol": order.symbol,
+        "side": if order.buy { "Buy" } else { "Sell" },
+        "orderType": if order.market { "Market" } else { "Limit" },
+        "price": order.price.to_string(),
+        "qty": order.qty.to_string(),
+        "orderStatus": match order.status {
+            MockOrderStatus::Open if order.filled > 0.0 => "PartiallyFilled",
+            MockOrderStatus::Open => "New",
+            MockOrderStatus::Filled => "Filled",
+            MockOrderStatus::Cancelled if order.filled > 0.0 => "PartiallyFilledCanceled",
+            MockOrderStatus::Cancelled => "Cancelled",
+        },
+        "cumExecQty": order.filled.to_string(),
+        "cumExecValue": order.filled_total.to_string(),
+        "avgPrice": order.avg_price().to_string(),
+        "leavesQty": (order.qty - order.filled).max(0.0).to_string(),
+        "updatedTime": order.updated_ms.to_string(),
+    })
+}
+
+fn bybit_coin(
+    asset: &str,
+    free: f64
+) -> Value {
+    json!({ "coin": asset, "walletBalance": free.to_string(), "locked": "0" })
+}
+
+fn gate_rest(
+    state: &TradingState,
+    request: &Request
+) -> ResponseTemplate {
+    let error = |status: u16, label: &str, message: &str| ResponseTemplate::new(status).set_body_json(json!({ "label": label, "message": message }));
+
+    let timestamp: i64 = header(request, "Timestamp").parse().unwrap_or_default();
+    let query = request.url.query().unwrap_or_default();
+    let signature = GateTrading::sign(&state.credentials, request.method.as_str(), request.url.path(), query, &request.body, timestamp);
+
+    if header(request, "KEY") != state.credentials.api_key {
+        state.rejected_signatures.fetch_add(1, Ordering::Relaxed);
+        return error(401, "INVALID_KEY", "Invalid key provided");
+    }
+    if (chrono::Utc::now().timestamp() - timestamp).abs() > GATE_TIME_WINDOW_SECS {
+        state.rejected_signatures.fetch_add(1, Ordering::Relaxed);
+        return error(401, "REQUEST_EXPIRED", "gap between request Timestamp and server time exceeds 60");
+    }
+    if header(request, "SIGN") != signature {
+        state.rejected_signatures.fetch_add(1, Ordering::Relaxed);
+        return error(401, "INVALID_SIGNATURE", "Signature mismatch");
+    }
+
+    if request.url.path() == "/spot/accounts" {
+        let accounts: Vec<Value> = state
+            .balances()
+            .into_iter()
+            .map(|(asset, free)| json!({ "currency": asset, "available": free.to_string(), "locked": "0" }))
+            .collect();
+        return ResponseTemplate::new(200).set_body_json(accounts);
+    }
+
+    let order_id = request.url.path().strip_prefix("/spot/orders/").unwrap_or_default().to_string();
+
+    match (request.method.as_str(), order_id.is_empty()) {
+        ("POST", true) => {
+            let Ok(params) = serde_json::from_slice::<Value>(&request.body) else {
+                return error(400, "INVALID_PARAM_VALUE", "invalid json");
+            };
+            let text = params["text"].as_str().unwrap_or_default().to_string();
+            if !text.is_empty() && !text.starts_with("t-") {
+                return error(400, "INVALID_PARAM_VALUE", "text must start with t-");
+            }
+
+            let market = params["type"] == "market";
+            let buy = params["side"] == "buy";
+            let amount = number(&params["amount"]);
+            let price = number(&params["price"]);
+            if !market && price <= 0.0 {
+                return error(400, "INVALID_PARAM_VALUE", "price is required");
+            }
+
+            let order = MockOrder {
+                id: 0,
+                client_order_id: text,
+                symbol: params["currency_pair"].as_str().unwrap_or_default().to_string(),
+                buy,
+                market,
+                ioc: params["time_in_force"] == "ioc",
+                amount,
+                qty: if market && buy { amount / state.options.fill_price } else { amount },
+                price,
+                status: MockOrderStatus::Open,
+                filled: 0.0,
+                filled_total: 0.0,
+                updated_ms: 0,
+            };
+            if !state.covers(&order) {
+                return error(400, "BALANCE_NOT_ENOUGH", "Not enough balance");
+            }
+
+            let order = state.create(order);
+            ResponseTemplate::new(201).set_body_json(gate_order(&order, None))
+        },
+        ("GET", false) => match state.find(&order_id) {
+            Some(order) => ResponseTemplate::new(200).set_body_json(gate_order(&order, None)),
+            None => error(404, "ORDER_NOT_FOUND", "Order not found"),
+        },
+        ("DELETE", false) if state.options.reject_cancels => error(500, "SERVER_ERROR", "Internal server error"),
+        ("DELETE", false) => match state.cancel(&order_id) {
+            Some(order) => ResponseTemplate::new(200).set_body_json(gate_order(&order, None)),
+            None => error(404, "ORDER_NOT_FOUND", "Order not found"),
+        },
+        _ => ResponseTemplate::new(404),
+    }
+}
+
+/// Ордер в ответе REST, для канала `spot.orders` с `event` вместо `status`
+fn gate_order(
+    order: &MockOrder,
+    event: Option<&str>
+) -> Value {
+    let mut value = json!({
+        "id": order.id.to_string(),
+        "text": order.client_order_id,
+        "currency_pair": order.symbol,
+        "type": if order.market { "market" } else { "limit" },
+        "side": if order.buy { "buy" } else { "sell" },
+        "amount": order.amount.to_string(),
+        "price": order.price.to_string(),
+        "time_in_force": if order.ioc || order.market { "ioc" } else { "gtc" },
+        "left": (order.qty - order.filled).max(0.0).to_string(),
+        "filled_amount": order.filled.to_string(),
+        "filled_total": order.filled_total.to_string(),
+        "avg_deal_price": order.avg_price().to_string(),
+        "finish_as": match order.status {
+            MockOrderStatus::Open => "open",
+            MockOrderStatus::Filled => "filled",
+            MockOrderStatus::Cancelled if order.ioc || order.market => "ioc",
+            MockOrderStatus::Cancelled => "cancelled",
+        },
+        "update_time_ms": order.updated_ms,
+    });
+
+    match event {
+        Some(event) => value["event"] = event.into(),
+        None => value["status"] = match order.status {
+            MockOrderStatus::Open => "open",
+            MockOrderStatus::Filled => "closed",
+            MockOrderStatus::Cancelled => "cancelled",
+        }.into(),
+    }
+
+    value
+}
+
+async fn accept_private(
+    listener: TcpListener,
+    state: Arc<TradingState>,
+    token: CancellationToken
+) {
+    loop {
+        tokio::select! {
+            _ = token.cancelled() => return,
+            accepted = listener.accept() => match accepted {
+                Ok((stream, _)) => {
+                    tokio::spawn(serve_private(stream, state.clone(), token.clone()));
+                },
+                Err(e) => tracing::warn!("MockExchange -> приватный accept: {e}"),
+            }
+        }
+    }
+}
+
+/// Приватное соединение: сначала авторизация, потом события подписанных каналов
+async fn serve_private(
+    stream: TcpStream,
+    state: Arc<TradingState>,
+    token: CancellationToken
+) {
+    let Ok(ws_stream) = accept_async(stream).await else { return };
+    let (mut write, mut read) = ws_stream.split();
+    let mut events = state.events.subscribe();
+    let mut authorized = false;
+    let mut channels = HashSet::new();
+
+    loop {
+        tokio::select! {
+            _ = token.cancelled() => return,
+            msg = read.next() => {
+                let text = match msg {
+                    Some(Ok(Message::Text(text))) => text,
+                    Some(Ok(Message::Close(_))) | Some(Err(_)) | None => return,
+                    Some(Ok(_)) => continue,
+                };
+                let Ok(json) = serde_json::from_str::<Value>(&text) else { continue };
+
+                let reply = match state.exchange_id {
+                    ExchangeType::Bybit => bybit_private_request(&state, &json, &mut authorized, &mut channels),
+                    _ => gate_private_request(&state, &json, &mut channels),
+                };
+                if let Some(reply) = reply && write.send(Message::Text(reply.to_string())).await.is_err() {
+                    return;
+                }
+            },
+            event = events.recv() => {
+                let Ok(event) = event else { continue };
+                let frame = match state.exchange_id {
+                    ExchangeType::Bybit if authorized => bybit_private_event(&event, &channels),
+                    ExchangeType::Gate => gate_private_event(&event, &channels),
+                    _ => None,
+                };
+                if let Some(frame) = frame && write.send(Message::Text(frame.to_string())).await.is_err() {
+                    return;
+                }
+            }
+        }
+    }
+}
+
+fn bybit_private_request(
+    state: &TradingState,
+    json: &Value,
+    authorized: &mut bool,
+    channels: &mut HashSet<String>
+) -> Option<Value> {
+    let op = json["op"].as_str()?;
+    let respond = |success: bool, message: &str| json!({
+        "success": success,
+        "ret_msg": message,
+        "op": op,
+        "req_id": json["req_id"],
+        "conn_id": "mock"
+    });
+
+    match op {
+        "auth" => {
+            let args = json["args"].as_array().cloned().unwrap_or_default();
+            let expires = args.get(1).and_then(Value::as_i64).unwrap_or_default();
+            let valid = args.first().and_then(Value::as_str) == Some(state.credentials.api_key.as_str())
+                && expires > chrono::Utc::now().timestamp_millis()
+                && args.get(2).and_then(Value::as_str) == Some(BybitTrading::sign_ws(&state.credentials, expires).as_str());
+
+            *authorized = valid;
+            if !valid {
+                state.rejected_signatures.fetch_add(1, Ordering::Relaxed);
+                return Some(respond(false, "Params Error"));
+            }
+            Some(respond(true, ""))
+        },
+        "subscribe" if !*authorized => Some(respond(false, "Request not authorized")),
+        "subscribe" => {
+            for topic in json["args"].as_array().into_iter().flatten().filter_map(Value::as_str) {
+                channels.insert(topic.split('.').next().unwrap_or(topic).to_string());
+            }
+            Some(respond(true, ""))
+        },
+        "ping" => Some(json!({ "op": "pong", "req_id": json["req_id"], "args": [chrono::Utc::now().timestamp_millis().to_string()], "conn_id": "mock" })),
+        _ => None
+    }
+}
+
+fn bybit_private_event(
+    event: &MockPrivateEvent,
+    channels: &HashSet<String>
+) -> Option<Value> {
+    let now_ms = chrono::Utc::now().timestamp_millis();
+
+    match event {
+        MockPrivateEvent::Order(order) if channels.contains("order") => Some(json!({
+            "id": format!("order-{}", order.id),
+            "topic": "order.spot",
+            "creationTime": now_ms,
+            "data": [bybit_order(order)]
+        })),
+        MockPrivateEvent::Trade { order, trade_id, qty, price, fee_asset, fee } if channels.contains("execution") => Some(json!({
+            "id": format!("execution-{trade_id}"),
+            "topic": "execution.spot",
+            "creationTime": now_ms,
+            "data": [{
+                "category": "spot",
+                "symbol": order.symbol,
+                "side": if order.buy { "Buy" } else { "Sell" },
+                "orderId": order.id.to_string(),
+                "orderLinkId": order.client_order_id,
+                "execId": trade_id.to_string(),
+                "execPrice": price.to_string(),
+                "execQty": qty.to_string(),
+                "execFee": fee.to_string(),
+                "feeCurrency": fee_asset,
+                "execType": "Trade",
+                "execTime": now_ms.to_string()
+            }]
+        })),
+        MockPrivateEvent::Balance { asset, free } if channels.contains("wallet") => Some(json!({
+            "id": format!("wallet-{asset}-{now_ms}"),
+            "topic": "wallet",
+            "creationTime": now_ms,
+            "data": [{ "accountType": "UNIFIED", "coin": [bybit_coin(asset, *free)] }]
+        })),
+        _ => None
+    }
+}
+
+fn gate_private_request(
+    state: &TradingState,
+    json: &Value,
+    channels: &mut HashSet<String>
+) -> Option<Value> {
+    let channel = json["channel"].as_str()?;
+    let now = chrono::Utc::now();
+
+    if channel == "spot.ping" {
+        return Some(json!({ "time": now.timestamp(), "time_ms": now.timestamp_millis(), "id": json["id"], "channel": "spot.pong", "event": "", "result": null }));
+    }
+
+    let event = json["event"].as_str()?;
+    let time = json["time"].as_i64().unwrap_or_default();
+    let auth = &json["auth"];
+    let valid = auth["KEY"].as_str() == Some(state.credentials.api_key.as_str())
+        && (now.timestamp() - time).abs() <= GATE_TIME_WINDOW_SECS
+        && auth["SIGN"].as_str() == Some(GateTrading::sign_ws(&state.credentials, channel, event, time).as_str());
+
+    if !valid {
+        state.rejected_signatures.fetch_add(1, Ordering::Relaxed);
+        return Some(json!({
+            "time": now.timestamp(),
+            "channel": channel,
+            "event": event,
+            "error": { "code": 2, "message": "signature not valid" },
+            "result": { "status": "fail" }
+        }));
+    }
+
+    match event {
+        "subscribe" => channels.insert(channel.to_string()),
+        "unsubscribe" => channels.remove(channel),
+        _ => false,
+    };
+
+    Some(json!({ "time": now.timestamp(), "channel": channel, "event": event, "error": null, "result": { "status": "success" } }))
+}
+
+fn gate_private_event(
+    event: &MockPrivateEvent,
+    channels: &HashSet<String>
+) -> Option<Value> {
+    let now = chrono::Utc::now();
+    let frame = |channel: &str, result: Value| json!({
+        "time": now.timestamp(),
+        "time_ms": now.timestamp_millis(),
+        "channel": channel,
+        "event": "update",
+        "result": [result]
+    });
+
+    match event {
+        MockPrivateEvent::Order(order) if channels.contains("spot.orders") => {
+            let stage = match order.status {
+                MockOrderStatus::Open if order.filled > 0.0 => "update",
+                MockOrderStatus::Open => "put",
+                _ => "finish",
+            };
+            Some(frame("spot.orders", gate_order(order, Some(stage))))
+        },
+        MockPrivateEvent::Trade { order, trade_id, qty, price, fee_asset, fee } if channels.contains("spot.usertrades") => Some(frame("spot.usertrades", json!({
+            "id": trade_id,
+            "order_id": order.id.to_string(),
+            "text": order.client_order_id,
+            "currency_pair": order.symbol,
+            "side": if order.buy { "buy" } else { "sell" },
+            "role": "taker",
+            "amount": qty.to_string(),
+            "price": price.to_string(),
+            "fee": fee.to_string(),
+            "fee_currency": fee_asset,
+            "create_time_ms": format!("{:.6}", now.timestamp_millis() as f64 / 1000.0)
+        }))),
+        MockPrivateEvent::Balance { asset, free } if channels.contains("spot.balances") => Some(frame("spot.balances", json!({
+            "timestamp_ms": now.timestamp_millis().to_string(),
+            "currency": asset,
+            "available": free.to_string(),
+            "freeze": "0",
+            "total": free.to_string()
+        }))),
+        _ => None
+    }
+}
@@ -1,5 +1,12 @@
-pub mod exchanges_run;
+pub mod exchange_control;
 pub mod exchange_aggregator;
 pub mod exchange_setup;
 pub mod exchange_adapter;
-pub mod exchange_channel_store;
\ No newline at end of file
+pub mod exchange_channel_store;
+pub mod subscription_manager;
+pub mod exchange_health;
+pub mod frame_recorder;
+pub mod frame_replay;
+pub mod mock_exchange;
+pub mod trading_adapter;
+pub mod mock_trading;
\ No newline at end of file
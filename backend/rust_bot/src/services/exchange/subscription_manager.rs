@@ -0,0 +1,387 @@
+use std::{collections::HashMap, sync::Arc, time::{Duration, Instant}};
+use async_trait::async_trait;
+use tokio::sync::{mpsc, oneshot};
+use tokio_util::sync::CancellationToken;
+use crate::{config, models::{exchange::ExchangeType, websocket::{Symbol, WsCmd, normalize_symbol}}, services::{exchange::exchange_adapter::ExchangeAdapter, supervisor::Actor}};
+
+/// Как часто проверяются подписки без потребителей и нагрузка соединений
+const SWEEP_INTERVAL: Duration = Duration::from_secs(5);
+/// Разница в количестве подписок на стаканы между соединениями, после которой подписки переносятся
+const REBALANCE_THRESHOLD: usize = 10;
+
+pub enum SubscriptionCmd {
+    /// Новое соединение биржи, индекс соединения - порядок добавления
+    AddConnection {
+        cmd_tx: mpsc::Sender<WsCmd>
+    },
+    /// Тикеры, на которые подписано соединение `connection`
+    AddSymbols {
+        connection: usize,
+        symbols: Vec<Arc<Symbol>>
+    },
+    /// Тикеры, убранные из соединения `connection`. Спрос на их стаканы сохраняется
+    RemoveSymbols {
+        connection: usize,
+        symbols: Vec<Arc<Symbol>>
+    },
+    Acquire {
+        symbol: Arc<Symbol>
+    },
+    Release {
+        symbol: Arc<Symbol>
+    },
+    /// Переподписка на стакан, чтобы биржа прислала новый снапшот. `false` - стакан не подписан
+    Resync {
+        symbol: Arc<Symbol>,
+        reply: oneshot::Sender<bool>
+    },
+}
+
+#[derive(Clone)]
+pub struct SubscriptionHandle {
+    tx: mpsc::Sender<SubscriptionCmd>
+}
+
+impl SubscriptionHandle {
+    pub fn channel() -> (Self, mpsc::Receiver<SubscriptionCmd>) {
+        let (tx, rx) = mpsc::channel(64);
+        (Self { tx }, rx)
+    }
+
+    pub async fn add_connection(
+        &self,
+        cmd_tx: mpsc::Sender<WsCmd>
+    ) {
+        self.tx.send(SubscriptionCmd::AddConnection { cmd_tx }).await.ok();
+    }
+
+    pub async fn add_symbols(
+        &self,
+        connection: usize,
+        symbols: Vec<Arc<Symbol>>
+    ) {
+        self.tx.send(SubscriptionCmd::AddSymbols { connection, symbols }).await.ok();
+    }
+
+    pub async fn remove_symbols(
+        &self,
+        connection: usize,
+        symbols: Vec<Arc<Symbol>>
+    ) {
+        self.tx.send(SubscriptionCmd::RemoveSymbols { connection, symbols }).await.ok();
+    }
+
+    /// Символ в общем формате (`btcusdt`)
+    pub async fn acquire(
+        &self,
+        symbol: Arc<Symbol>
+    ) {
+        self.tx.send(SubscriptionCmd::Acquire { symbol }).await.ok();
+    }
+
+    pub async fn release(
+        &self,
+        symbol: Arc<Symbol>
+    ) {
+        self.tx.send(SubscriptionCmd::Release { symbol }).await.ok();
+    }
+
+    pub async fn resync(
+        &self,
+        symbol: Arc<Symbol>
+    ) -> bool {
+        let (reply, rx) = oneshot::channel();
+        self.tx.send(SubscriptionCmd::Resync { symbol, reply }).await.ok();
+
+        rx.await.unwrap_or(false)
+    }
+}
+
+struct Connection {
+    cmd_tx: mpsc::Sender<WsCmd>,
+    depth_count: usize,
+    /// Место под стаканы зарезервировано по одному на тикер соединения, см. `ExchangeLimits::topics_per_symbol`
+    depth_capacity: usize,
+}
+
+struct DepthSubscription {
+    consumers: usize,
+    /// Соединение, в котором оформлена подписка. `None` - биржа ещё не прислала тикер или соединение
+    connection: Option<usize>,
+    idle_since: Option<Instant>,
+}
+
+/// <b>SubscriptionManager</b> подписывается на стакан символа биржи, когда он нужен первому потребителю,
+/// и отписывается после `idle_grace` без потребителей. Тикеры подписываются всегда в `ExchangeSetup`.
+///
+/// Новые подписки уходят в наименее нагруженное соединение, а при перекосе подписки переносятся
+pub struct SubscriptionManager<A: ExchangeAdapter> {
+    adapter: Arc<A>,
+    exchange_id: ExchangeType,
+    rx: mpsc::Receiver<SubscriptionCmd>,
+
+    connections: Vec<Connection>,
+    /// Общий формат символа -> символ биржи
+    symbols: HashMap<Symbol, Arc<Symbol>>,
+    depth: HashMap<Symbol, DepthSubscription>,
+}
+
+impl<A: ExchangeAdapter> SubscriptionManager<A> {
+    pub fn new(
+        adapter: Arc<A>,
+        exchange_id: ExchangeType,
+        rx: mpsc::Receiver<SubscriptionCmd>
+    ) -> Self {
+        Self {
+            adapter,
+            exchange_id,
+            rx,
+
+            connections: Vec::new(),
+            symbols: HashMap::new(),
+            depth: HashMap::new(),
+        }
+    }
+
+}
+
+/// Работает до остановки сессии биржи
+#[async_trait]
+impl<A: ExchangeAdapter + Send + Sync + 'static> Actor for SubscriptionManager<A> {
+    async fn run(
+        &mut self,
+        cancel: CancellationToken
+    ) {
+        let mut interval = tokio::time::interval(SWEEP_INTERVAL);
+
+        loop {
+            tokio::select! {
+                _ = cancel.cancelled() => {
+                    return;
+                },
+                Some(cmd) = self.rx.recv() => {
+                    self.handle_command(cmd).await;
+                },
+                _ = interval.tick() => {
+                    self.unsubscribe_idle().await;
+                    self.rebalance().await;
+                }
+            }
+        }
+    }
+}
+
+impl<A: ExchangeAdapter> SubscriptionManager<A> {
+    async fn handle_command(
+        &mut self,
+        cmd: SubscriptionCmd
+    ) {
+        match cmd {
+            SubscriptionCmd::AddConnection {
+                cmd_tx
+            } => {
+                self.connections.push(Connection { cmd_tx, depth_count: 0, depth_capacity: 0 });
+            },
+            SubscriptionCmd::AddSymbols {
+                connection,
+                symbols
+            } => {
+                let Some(entry) = self.connections.get_mut(connection) else { return };
+                entry.depth_capacity += symbols.len();
+                for symbol in symbols {
+                    self.symbols.insert(normalize_symbol(&symbol), symbol);
+                }
+
+                self.subscribe_pending().await;
+            },
+            SubscriptionCmd::RemoveSymbols {
+                connection,
+                symbols
+            } => {
+                let symbols_len = symbols.len();
+                for symbol in symbols {
+                    let symbol = normalize_symbol(&symbol);
+
+                    // Стакан отписывается сразу, подписка ждёт возвращения символа
+                    let subscribed = self.depth
+                        .get_mut(&symbol)
+                        .and_then(|subscription| subscription.connection.take());
+                    if let Some(index) = subscribed {
+                        self.unsubscribe(&symbol, index).await;
+                    }
+
+                    self.symbols.remove(&symbol);
+                }
+
+                if let Some(entry) = self.connections.get_mut(connection) {
+                    entry.depth_capacity = entry.depth_capacity.saturating_sub(symbols_len);
+                }
+            },
+            SubscriptionCmd::Acquire {
+                symbol
+            } => {
+                let symbol = normalize_symbol(&symbol);
+                let subscription = self.depth
+                    .entry(symbol.clone())
+                    .or_insert(DepthSubscription { consumers: 0, connection: None, idle_since: None });
+
+                subscription.consumers += 1;
+                subscription.idle_since = None;
+
+                if subscription.connection.is_none() {
+                    self.subscribe(&symbol).await;
+                }
+            },
+            SubscriptionCmd::Release {
+                symbol
+            } => {
+                let symbol = normalize_symbol(&symbol);
+                if let Some(subscription) = self.depth.get_mut(&symbol) {
+                    subscription.consumers = subscription.consumers.saturating_sub(1);
+                    if subscription.consumers == 0 {
+                        subscription.idle_since = Some(Instant::now());
+                    }
+                }
+            },
+            SubscriptionCmd::Resync {
+                symbol,
+                reply
+            } => {
+                let symbol = normalize_symbol(&symbol);
+                let subscribed = self.depth
+                    .get(&symbol)
+                    .and_then(|subscription| subscription.connection);
+
+                if let Some(index) = subscribed {
+                    self.unsubscribe(&symbol, index).await;
+                    if let Some(subscription) = self.depth.get_mut(&symbol) {
+                        subscription.connection = None;
+                    }
+                    self.subscribe(&symbol).await;
+                }
+
+                let _ = reply.send(subscribed.is_some());
+            },
+        }
+    }
+
+    /// Подписки, запрошенные до появления соединения или тикера
+    async fn subscribe_pending(
+        &mut self
+    ) {
+        let pending: Vec<Symbol> = self.depth
+            .iter()
+            .filter(|(symbol, subscription)| subscription.connection.is_none() && self.symbols.contains_key(*symbol))
+            .map(|(symbol, _)| symbol.clone())
+            .collect();
+
+        for symbol in pending {
+            self.subscribe(&symbol).await;
+        }
+    }
+
+    /// Оформляет подписку на стакан в наименее нагруженном соединении со свободным местом
+    async fn subscribe(
+        &mut self,
+        symbol: &Symbol
+    ) {
+        let Some(exchange_symbol) = self.symbols.get(symbol).cloned() else { return };
+        let Some(index) = self.least_loaded() else { return };
+
+        let messages = self.adapter.clone().create_depth_subscribe_messages(&[exchange_symbol]);
+        let connection = &mut self.connections[index];
+        if connection.cmd_tx.send(WsCmd::Subscribe { key: symbol.clone(), messages }).await.is_ok() {
+            connection.depth_count += 1;
+            if let Some(subscription) = self.depth.get_mut(symbol) {
+                subscription.connection = Some(index);
+            }
+        }
+    }
+
+    async fn unsubscribe(
+        &mut self,
+        symbol: &Symbol,
+        index: usize
+    ) {
+        let Some(exchange_symbol) = self.symbols.get(symbol).cloned() else { return };
+
+        let messages = self.adapter.clone().create_depth_unsubscribe_messages(&[exchange_symbol]);
+        let connection = &mut self.connections[index];
+        connection.cmd_tx.send(WsCmd::Unsubscribe { key: symbol.clone(), messages }).await.ok();
+        connection.depth_count = connection.depth_count.saturating_sub(1);
+    }
+
+    async fn unsubscribe_idle(
+        &mut self
+    ) {
+        let idle_grace = config::get().pipeline.subscription_idle_grace();
+        let idle: Vec<(Symbol, Option<usize>)> = self.depth
+            .iter()
+            .filter(|(_, subscription)| {
+                subscription.consumers == 0
+                    && subscription.idle_since.is_some_and(|since| since.elapsed() >= idle_grace)
+            })
+            .map(|(symbol, subscription)| (symbol.clone(), subscription.connection))
+            .collect();
+
+        for (symbol, connection) in idle {
+            self.depth.remove(&symbol);
+            if let Some(index) = connection {
+                self.unsubscribe(&symbol, index).await;
+            }
+        }
+
+        if !self.depth.is_empty() {
+            tracing::debug!("{} -> подписок на стаканы: {}", self.exchange_id, self.depth.len());
+        }
+    }
+
+    /// Переносит подписки из самого нагруженного соединения в самое свободное
+    async fn rebalance(
+        &mut self
+    ) {
+        loop {
+            let (
+                Some(busiest),
+                Some(least)
+            ) = (
+                self.most_loaded(),
+                self.least_loaded()
+            ) else { return };
+
+            if self.connections[busiest].depth_count - self.connections[least].depth_count <= REBALANCE_THRESHOLD {
+                return;
+            }
+
+            let symbol = self.depth
+                .iter()
+                .find(|(_, subscription)| subscription.connection == Some(busiest))
+                .map(|(symbol, _)| symbol.clone());
+
+            let Some(symbol) = symbol else { return };
+
+            self.unsubscribe(&symbol, busiest).await;
+            if let Some(subscription) = self.depth.get_mut(&symbol) {
+                subscription.connection = None;
+            }
+            self.subscribe(&symbol).await;
+        }
+    }
+
+    fn least_loaded(&self) -> Option<usize> {
+        self.connections
+            .iter()
+            .enumerate()
+            .filter(|(_, connection)| !connection.cmd_tx.is_closed() && connection.depth_count < connection.depth_capacity)
+            .min_by_key(|(_, connection)| connection.depth_count)
+            .map(|(index, _)| index)
+    }
+
+    fn most_loaded(&self) -> Option<usize> {
+        self.connections
+            .iter()
+            .enumerate()
+            .max_by_key(|(_, connection)| connection.depth_count)
+            .map(|(index, _)| index)
+    }
+}
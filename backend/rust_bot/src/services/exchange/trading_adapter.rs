@@ -0,0 +1,195 @@
+use std::{fmt, sync::Arc};
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha256, Sha512};
+use tokio_tungstenite::tungstenite::Message;
+
+use crate::{config::TradingConfig, models::{exchange::ExchangeType, inventory::Balance, order::{ClientOrderId, FillReport, OrderRequest, OrderUpdate}, websocket::Symbol}, services::exchange::exchange_adapter::Heartbeat};
+
+/// <b>TradingError</b> ошибка приватного API. Коды бирж сводятся к общим вариантам,
+/// по которым вызывающий решает, повторять ли запрос
+#[derive(Debug, Clone, PartialEq)]
+pub enum TradingError {
+    /// Торговля на бирже не включена в конфиге
+    NotConfigured(ExchangeType),
+    /// Неверный ключ, подпись или время запроса
+    Auth(String),
+    InsufficientBalance(String),
+    /// Биржа отклонила параметры ордера
+    InvalidOrder(String),
+    /// Ордер с таким client order id уже есть
+    DuplicateOrder(String),
+    /// Ордер не прошёл предторговые проверки `RiskManager` и не отправлялся на биржу
+    Risk(String),
+    OrderNotFound(String),
+    RateLimited(String),
+    /// Остальные отказы биржи с её кодом
+    Exchange { code: String, message: String },
+    /// Запрос мог не дойти или ответ потерян: состояние ордера неизвестно
+    Network(String),
+    /// Ответ не разобран
+    Decode(String),
+}
+
+impl TradingError {
+    /// Ордер мог быть создан, нужно спросить биржу по client order id
+    pub fn is_unknown_outcome(&self) -> bool {
+        matches!(self, Self::Network(_) | Self::Decode(_))
+    }
+}
+
+impl fmt::Display for TradingError {
+    fn fmt(
+        &self,
+        f: &mut fmt::Formatter<'_>
+    ) -> fmt::Result {
+        match self {
+            Self::NotConfigured(exchange_id) => write!(f, "торговля на {exchange_id} не включена"),
+            Self::Auth(message) => write!(f, "ошибка авторизации: {message}"),
+            Self::InsufficientBalance(message) => write!(f, "недостаточно средств: {message}"),
+            Self::InvalidOrder(message) => write!(f, "неверный ордер: {message}"),
+            Self::DuplicateOrder(message) => write!(f, "ордер уже существует: {message}"),
+            Self::Risk(message) => write!(f, "отклонён риск-проверкой: {message}"),
+            Self::OrderNotFound(message) => write!(f, "ордер не найден: {message}"),
+            Self::RateLimited(message) => write!(f, "превышен лимит запросов: {message}"),
+            Self::Exchange { code, message } => write!(f, "биржа ответила {code}: {message}"),
+            Self::Network(message) => write!(f, "сеть: {message}"),
+            Self::Decode(message) => write!(f, "неверный ответ биржи: {message}"),
+        }
+    }
+}
+
+impl std::error::Error for TradingError {}
+
+impl From<reqwest::Error> for TradingError {
+    fn from(
+        e: reqwest::Error
+    ) -> Self {
+        if e.is_decode() {
+            Self::Decode(e.to_string())
+        } else {
+            Self::Network(e.to_string())
+        }
+    }
+}
+
+/// Ключ API биржи. Секрет не выводится в лог
+#[derive(Clone)]
+pub struct Credentials {
+    pub api_key: String,
+    pub api_secret: String,
+}
+
+impl fmt::Debug for Credentials {
+    fn fmt(
+        &self,
+        f: &mut fmt::Formatter<'_>
+    ) -> fmt::Result {
+        f.debug_struct("Credentials").field("api_key", &self.api_key).finish_non_exhaustive()
+    }
+}
+
+/// Адреса приватного API: боевые или testnet по `trading.testnet`, конфиг может их переопределить
+#[derive(Debug, Clone)]
+pub struct TradingEndpoints {
+    /// Базовый REST адрес, путь запроса добавляется к нему
+    pub rest_url: String,
+    pub ws_url: String,
+}
+
+impl TradingEndpoints {
+    /// `mainnet` и `testnet` - пары `(rest, ws)`
+    pub fn new(
+        mainnet: (&str, &str),
+        testnet: (&str, &str),
+        config: &TradingConfig
+    ) -> Self {
+        let (rest_url, ws_url) = if config.testnet { testnet } else { mainnet };
+        Self {
+            rest_url: config.rest_url.clone().unwrap_or_else(|| rest_url.into()).trim_end_matches('/').into(),
+            ws_url: config.ws_url.clone().unwrap_or_else(|| ws_url.into()),
+        }
+    }
+}
+
+/// Событие приватного потока
+#[derive(Debug, Clone)]
+pub enum PrivateEvent {
+    Order(OrderUpdate),
+    Fill(FillReport),
+    /// Новый остаток валюты целиком, не изменение
+    Balance(Balance),
+}
+
+#[async_trait::async_trait]
+pub trait TradingAdapter: Send + Sync + 'static {
+    fn exchange_id(&self) -> ExchangeType;
+    /// Ответ биржи на создание. Если ответ потерян, ордер находится `get_order` по тому же `client_order_id`
+    async fn place_order(self: Arc<Self>, client: &reqwest::Client, request: &OrderRequest) -> Result<OrderUpdate, TradingError>;
+    /// Биржа приняла отмену. Ордер мог успеть исполниться, итог - в `get_order` или приватном потоке
+    async fn cancel_order(self: Arc<Self>, client: &reqwest::Client, symbol: &Symbol, client_order_id: &ClientOrderId) -> Result<(), TradingError>;
+    /// `None` - биржа не знает ордер с таким id
+    async fn get_order(self: Arc<Self>, client: &reqwest::Client, symbol: &Symbol, client_order_id: &ClientOrderId) -> Result<Option<OrderUpdate>, TradingError>;
+    /// Остатки спотового счёта по всем валютам
+    async fn get_balances(self: Arc<Self>, client: &reqwest::Client) -> Result<Vec<Balance>, TradingError>;
+    fn private_ws_url(self: Arc<Self>) -> String;
+    /// Авторизация и подписки на ордера, исполнения и остатки сразу после подключения
+    fn create_private_subscribe_messages(self: Arc<Self>) -> Vec<Message>;
+    /// Ошибка авторизации - `Err`, служебные сообщения - пустой список
+    fn parse_private_message(self: Arc<Self>, msg: &str) -> Result<Vec<PrivateEvent>, TradingError>;
+    fn heartbeat(self: Arc<Self>) -> Heartbeat;
+    fn create_ping_message(self: Arc<Self>, id: u64) -> Message;
+}
+
+pub fn hmac_sha256_hex(
+    secret: &str,
+    payload: &str
+) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC принимает ключ любой длины");
+    mac.update(payload.as_bytes());
+    hex::encode(mac.finalize().into_bytes())
+}
+
+pub fn hmac_sha512_hex(
+    secret: &str,
+    payload: &str
+) -> String {
+    let mut mac = Hmac::<Sha512>::new_from_slice(secret.as_bytes()).expect("HMAC принимает ключ любой длины");
+    mac.update(payload.as_bytes());
+    hex::encode(mac.finalize().into_bytes())
+}
+
+pub fn sha512_hex(
+    payload: &[u8]
+) -> String {
+    hex::encode(Sha512::digest(payload))
+}
+
+/// Символ в общем формате (`btcusdt`) как `(BTC, USDT)`. Котируемые валюты - те, что торгует бот
+pub fn split_symbol(
+    symbol: &str
+) -> Option<(String, String)> {
+    const QUOTES: [&str; 4] = ["usdt", "usdc", "btc", "eth"];
+
+    let symbol = symbol.to_ascii_lowercase();
+    QUOTES
+        .iter()
+        .find_map(|quote| symbol.strip_suffix(quote).filter(|base| !base.is_empty()).map(|base| (base.to_ascii_uppercase(), quote.to_ascii_uppercase())))
+}
+
+/// Число для тела запроса без экспоненты и лишних нулей
+pub fn format_decimal(
+    value: f64
+) -> String {
+    let text = format!("{value:.10}");
+    text.trim_end_matches('0').trim_end_matches('.').to_string()
+}
+
+/// Строковое число из ответа биржи, пустая строка - 0
+pub fn parse_decimal(
+    value: &str
+) -> Result<f64, TradingError> {
+    if value.is_empty() {
+        return Ok(0.0);
+    }
+    value.parse().map_err(|_| TradingError::Decode(format!("`{value}` не число")))
+}
@@ -0,0 +1,221 @@
+use std::{collections::{HashMap, HashSet}, sync::Arc, time::Duration};
+use async_trait::async_trait;
+use tokio::{sync::{broadcast, watch}, task::JoinSet};
+use tokio_util::sync::CancellationToken;
+use tracing::info;
+
+use crate::{config::{self, TRADING_EXCHANGES}, models::{exchange::ExchangeType, inventory::{Balance, Inventory}}, services::{clock, exchange::trading_adapter::TradingError, metrics::Metric, order_gateway::{OrderEvent, OrderGatewayHandle}, supervisor::Actor}};
+
+const TRACKER_NAME: &str = "InventoryTracker";
+
+/// Как часто остатки бирж запрашиваются через REST
+const POLL_INTERVAL: Duration = Duration::from_secs(15);
+/// Остатки без опроса и обновлений дольше трёх интервалов не используются
+const STALE_AFTER_MS: i64 = 3 * POLL_INTERVAL.as_millis() as i64;
+
+#[derive(Clone)]
+pub struct InventoryHandle {
+    inventory_rx: watch::Receiver<Arc<Inventory>>,
+}
+
+impl InventoryHandle {
+    /// Последние известные остатки
+    pub fn snapshot(&self) -> Arc<Inventory> {
+        self.inventory_rx.borrow().clone()
+    }
+}
+
+/// <b>InventoryTracker</b> остатки на биржах с `trading.enabled`.
+///
+/// <br>• Каждые `POLL_INTERVAL` и после переподключения приватного потока остатки биржи
+/// запрашиваются целиком через `OrderGateway`. У биржи не больше одного опроса, запрошенный во время
+/// опроса начнётся после него
+/// <br>• Между опросами валюты обновляются из приватных потоков. Опрос, начатый раньше последнего
+/// обновления из потока, отбрасывается: его остатки могут быть старше
+/// <br>• `DataAggregator` отмечает по ним, хватает ли средств на исполнимые спреды
+pub struct InventoryTracker {
+    orders: OrderGatewayHandle,
+    inventory_tx: watch::Sender<Arc<Inventory>>,
+    inventory: Inventory,
+    /// Биржа, время начала опроса в мс и его результат
+    polls: JoinSet<(ExchangeType, i64, Result<Vec<Balance>, TradingError>)>,
+    /// Биржи с опросом в работе
+    polling: HashSet<ExchangeType>,
+    /// Биржи, которые опросить снова после текущего опроса
+    repoll: HashSet<ExchangeType>,
+    /// Время последнего обновления из приватного потока в мс
+    streamed_at: HashMap<ExchangeType, i64>,
+
+    poll_errors: Arc<Metric>,
+}
+
+impl InventoryTracker {
+    pub fn new(
+        orders: OrderGatewayHandle
+    ) -> (Self, InventoryHandle) {
+        let inventory = Inventory::new(STALE_AFTER_MS);
+        let (inventory_tx, inventory_rx) = watch::channel(Arc::new(inventory.clone()));
+
+        let this = Self {
+            orders,
+            inventory_tx,
+            inventory,
+            polls: JoinSet::new(),
+            polling: HashSet::new(),
+            repoll: HashSet::new(),
+            streamed_at: HashMap::new(),
+
+            poll_errors: Metric::counter("inventory_poll_errors_total", "Неудачные запросы остатков бирж", &[]),
+        };
+
+        (this, InventoryHandle { inventory_rx })
+    }
+}
+
+#[async_trait]
+impl Actor for InventoryTracker {
+    async fn run(
+        &mut self,
+        shutdown: CancellationToken
+    ) {
+        let mut events = self.orders.subscribe();
+        let mut poll = tokio::time::interval(POLL_INTERVAL);
+
+        info!("{} -> is running", TRACKER_NAME);
+
+        loop {
+            tokio::select! {
+                _ = shutdown.cancelled() => break,
+                _ = poll.tick() => {
+                    let config = config::get();
+                    for exchange_id in TRADING_EXCHANGES.into_iter().filter(|exchange_id| config.exchange(*exchange_id).trading.enabled) {
+                        self.poll(exchange_id);
+                    }
+                },
+                event = events.recv() => match event {
+                    Ok(OrderEvent::Balance(balance)) => self.on_balance(balance),
+                    Ok(OrderEvent::StreamConnected(exchange_id)) => self.poll(exchange_id),
+                    Ok(_) => {},
+                    // Пропущенные остатки вернёт следующий опрос
+                    Err(broadcast::error::RecvError::Lagged(skipped)) => tracing::debug!("{} -> пропущено событий: {skipped}", TRACKER_NAME),
+                    Err(broadcast::error::RecvError::Closed) => break,
+                },
+                Some(result) = self.polls.join_next() => {
+                    let Ok((exchange_id, started_at, result)) = result else { continue };
+                    self.on_polled(exchange_id, started_at, result);
+                },
+            }
+        }
+
+        self.polls.shutdown().await;
+    }
+}
+
+impl InventoryTracker {
+    fn poll(
+        &mut self,
+        exchange_id: ExchangeType
+    ) {
+        if !self.polling.insert(exchange_id) {
+            self.repoll.insert(exchange_id);
+            return;
+        }
+
+        let orders = self.orders.clone();
+        let started_at = clock::now_ms();
+        self.polls.spawn(async move { (exchange_id, started_at, orders.balances(exchange_id).await) });
+    }
+
+    fn on_balance(
+        &mut self,
+        balance: Balance
+    ) {
+        let exchange_id = balance.exchange_id;
+        let now = clock::now_ms();
+        if self.inventory.update(balance, now) {
+            self.streamed_at.insert(exchange_id, now);
+            self.publish();
+        }
+    }
+
+    fn on_polled(
+        &mut self,
+        exchange_id: ExchangeType,
+        started_at: i64,
+        result: Result<Vec<Balance>, TradingError>
+    ) {
+        self.polling.remove(&exchange_id);
+        if self.repoll.remove(&exchange_id) {
+            self.poll(exchange_id);
+        }
+
+        match result {
+            Ok(_) if self.streamed_at.get(&exchange_id).is_some_and(|streamed_at| started_at <= *streamed_at) => {
+                tracing::debug!("{} -> остатки {} старше обновления из потока", TRACKER_NAME, exchange_id);
+                return;
+            },
+            Ok(balances) => {
+                let known = self.inventory.exchanges.contains_key(&exchange_id);
+                self.inventory.replace(exchange_id, balances, clock::now_ms());
+                if !known {
+                    info!("{} -> остатки {} получены", TRACKER_NAME, exchange_id);
+                }
+            },
+            // Торговля на бирже выключена: её остатки больше не нужны
+            Err(TradingError::NotConfigured(_)) => {
+                self.inventory.remove(exchange_id);
+                self.streamed_at.remove(&exchange_id);
+            },
+            // Прежние остатки остаются, пока не устареют
+            Err(e) => {
+                self.poll_errors.inc();
+                tracing::warn!("{} -> остатки {}: {e}", TRACKER_NAME, exchange_id);
+                return;
+            },
+        }
+
+        self.publish();
+    }
+
+    fn publish(&self) {
+        self.inventory_tx.send_replace(Arc::new(self.inventory.clone()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::sync::mpsc;
+    use crate::{config::Config, models::{exchange::ExchangeType, inventory::Balance}, services::{clock, order_gateway::OrderGateway, risk_manager::RiskManager}, tests::lock_config};
+    use super::InventoryTracker;
+
+    fn usdt(
+        free: f64
+    ) -> Balance {
+        Balance { exchange_id: ExchangeType::Bybit, asset: "USDT".into(), free, locked: 0.0, time: clock::now_ms() }
+    }
+
+    /// Второй опрос ждёт первый, а ответ опроса, начатого до обновления из потока, не затирает его
+    #[tokio::test]
+    async fn poll_started_before_stream_update_is_dropped() {
+        let _config = lock_config(Config::default()).await;
+        let (_risk, risk) = RiskManager::new(mpsc::channel(1).0, None);
+        let (_gateway, orders) = OrderGateway::new(risk);
+        let (mut tracker, inventory) = InventoryTracker::new(orders);
+
+        tracker.poll(ExchangeType::Bybit);
+        tracker.poll(ExchangeType::Bybit);
+        assert_eq!(tracker.polls.len(), 1);
+        assert!(tracker.repoll.contains(&ExchangeType::Bybit));
+
+        let now = clock::now_ms();
+        tracker.on_polled(ExchangeType::Bybit, now - 2000, Ok(vec![usdt(100.0)]));
+        assert_eq!(tracker.polls.len(), 2, "отложенный опрос начинается после первого");
+        tracker.on_balance(usdt(40.0));
+
+        tracker.on_polled(ExchangeType::Bybit, now - 1000, Ok(vec![usdt(100.0)]));
+        assert_eq!(inventory.snapshot().free(ExchangeType::Bybit, "USDT", clock::now_ms()), Some(40.0));
+
+        tracker.on_polled(ExchangeType::Bybit, clock::now_ms() + 1, Ok(vec![usdt(70.0)]));
+        assert_eq!(inventory.snapshot().free(ExchangeType::Bybit, "USDT", clock::now_ms()), Some(70.0));
+    }
+}
@@ -0,0 +1,108 @@
+use std::{sync::{Arc, LazyLock, Mutex, atomic::{AtomicU64, Ordering}}, time::Duration};
+
+static LATENCY_HISTOGRAMS: LazyLock<Mutex<Vec<Arc<LatencyHistogram>>>> = LazyLock::new(|| Mutex::new(Vec::new()));
+
+/// Верхние границы корзин в мс, последняя корзина - всё, что больше
+pub const LATENCY_BUCKETS: [u64; 12] = [1, 2, 5, 10, 20, 50, 100, 200, 500, 1000, 2000, 5000];
+
+/// <b>LatencyHistogram</b> распределение задержек в мс по `LATENCY_BUCKETS`
+#[derive(Debug, Default)]
+pub struct LatencyHistogram {
+    pub name: String,
+    pub buckets: [AtomicU64; LATENCY_BUCKETS.len() + 1],
+    pub count: AtomicU64,
+    /// Сумма задержек в мс
+    pub sum: AtomicU64,
+}
+
+impl LatencyHistogram {
+    /// Создаёт гистограмму и регистрирует её для `report_latency`.
+    /// Гистограмма с тем же именем возвращается существующей
+    pub fn register(
+        name: impl Into<String>
+    ) -> Arc<Self> {
+        let name = name.into();
+        let mut all = LATENCY_HISTOGRAMS.lock().unwrap();
+        if let Some(histogram) = all.iter().find(|histogram| histogram.name == name) {
+            return histogram.clone();
+        }
+
+        let histogram = Arc::new(Self {
+            name,
+            ..Default::default()
+        });
+
+        all.push(histogram.clone());
+        histogram
+    }
+
+    pub fn all() -> Vec<Arc<Self>> {
+        LATENCY_HISTOGRAMS.lock().unwrap().clone()
+    }
+
+    /// Отрицательная задержка - расхождение часов с биржей, она считается нулевой
+    pub fn record(
+        &self,
+        latency_ms: i64
+    ) {
+        let latency_ms = latency_ms.max(0) as u64;
+        let bucket = LATENCY_BUCKETS
+            .iter()
+            .position(|bound| latency_ms <= *bound)
+            .unwrap_or(LATENCY_BUCKETS.len());
+
+        self.buckets[bucket].fetch_add(1, Ordering::Relaxed);
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.sum.fetch_add(latency_ms, Ordering::Relaxed);
+    }
+
+    /// Верхняя граница корзины, в которую попадает квантиль `q`
+    pub fn quantile(
+        &self,
+        q: f64
+    ) -> Option<u64> {
+        let count = self.count.load(Ordering::Relaxed);
+        if count == 0 {
+            return None;
+        }
+
+        let target = (count as f64 * q).ceil() as u64;
+        let mut seen = 0;
+        for (index, bucket) in self.buckets.iter().enumerate() {
+            seen += bucket.load(Ordering::Relaxed);
+            if seen >= target {
+                return Some(LATENCY_BUCKETS.get(index).copied().unwrap_or(u64::MAX));
+            }
+        }
+
+        None
+    }
+}
+
+/// Периодически пишет квантили всех гистограмм в лог
+pub async fn report_latency(
+    period: Duration
+) {
+    let mut interval = tokio::time::interval(period);
+    interval.tick().await;
+
+    loop {
+        interval.tick().await;
+
+        for histogram in LatencyHistogram::all() {
+            let count = histogram.count.load(Ordering::Relaxed);
+            if count == 0 {
+                continue;
+            }
+
+            tracing::info!(
+                histogram = histogram.name.as_str(),
+                count,
+                avg_ms = histogram.sum.load(Ordering::Relaxed) / count,
+                p50_ms = histogram.quantile(0.5),
+                p99_ms = histogram.quantile(0.99),
+                "Latency"
+            );
+        }
+    }
+}
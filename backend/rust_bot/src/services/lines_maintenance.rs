@@ -0,0 +1,128 @@
+use std::time::Instant;
+use async_trait::async_trait;
+use chrono::Utc;
+use tokio_util::sync::CancellationToken;
+use crate::{config::{self, LinesConfig}, models::line::TimeFrame, services::supervisor::Actor, storage::lines_retention::{downsample_lines, drop_expired_partitions, ensure_partitions}};
+
+const DAY: i64 = 86_400;
+/// Сколько партиций создаётся заранее, чтобы вставки не попадали в default партицию
+const PARTITIONS_AHEAD: i64 = 3;
+
+#[derive(Debug, Clone, Copy)]
+pub struct RetentionPolicy {
+    pub timeframe: TimeFrame,
+    /// Размер одной партиции в секундах
+    pub partition_span: i64,
+    /// `None` - хранить всегда
+    pub keep_days: Option<i64>,
+}
+
+/// <b>LinesMaintenance</b> обслуживает `storage.lines`: создаёт партиции заранее, 
+/// сжимает `1m` линии в `1h` и удаляет партиции старше срока хранения.
+/// Сроки хранения берутся из `lines` при каждом запуске обслуживания
+pub struct LinesMaintenance {
+    pool: Option<sqlx::PgPool>,
+}
+
+impl LinesMaintenance {
+    pub fn new(
+        pool: Option<sqlx::PgPool>,
+    ) -> Self {
+        Self {
+            pool,
+        }
+    }
+
+    fn policies(
+        lines: &LinesConfig
+    ) -> [RetentionPolicy; 2] {
+        // 0 означает хранить всегда
+        let keep_days = |days: u32| (days > 0).then_some(days as i64);
+
+        [
+            RetentionPolicy {
+                timeframe: TimeFrame::One,
+                partition_span: DAY,
+                keep_days: keep_days(lines.retention_1m_days),
+            },
+            RetentionPolicy {
+                timeframe: TimeFrame::OneHour,
+                partition_span: 30 * DAY,
+                keep_days: keep_days(lines.retention_1h_days),
+            },
+        ]
+    }
+}
+
+#[async_trait]
+impl Actor for LinesMaintenance {
+    async fn run(
+        &mut self,
+        shutdown: CancellationToken
+    ) {
+        if self.pool.is_none() {
+            tracing::warn!("LinesMaintenance -> disabled, no database connection");
+            return;
+        }
+
+        let mut interval = tokio::time::interval(config::get().lines.maintenance_interval());
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+        loop {
+            tokio::select! {
+                _ = shutdown.cancelled() => return,
+                _ = interval.tick() => {}
+            }
+            self.maintain().await;
+        }
+    }
+}
+
+impl LinesMaintenance {
+    async fn maintain(
+        &self,
+    ) {
+        let started = Instant::now();
+        let now = Utc::now().timestamp();
+        let policies = Self::policies(&config::get().lines);
+
+        for policy in &policies {
+            if let Err(e) = ensure_partitions(&self.pool, policy.timeframe, policy.partition_span, now, PARTITIONS_AHEAD).await {
+                tracing::error!("LinesMaintenance({}) -> не удалось создать партиции: {e}", policy.timeframe.as_str());
+            }
+        }
+
+        let downsampled = match downsample_lines(&self.pool, now).await {
+            Ok(rows) => rows,
+            Err(e) => {
+                tracing::error!("LinesMaintenance -> ошибка downsampling: {e}");
+                0
+            }
+        };
+
+        for policy in &policies {
+            let Some(keep_days) = policy.keep_days else { continue };
+            let cutoff = now - keep_days * DAY;
+
+            match drop_expired_partitions(&self.pool, policy.timeframe, policy.partition_span, cutoff).await {
+                Ok((partitions, rows)) => {
+                    tracing::info!(
+                        timeframe = policy.timeframe.as_str(),
+                        dropped_partitions = partitions,
+                        removed_rows = rows,
+                        "LinesMaintenance -> retention"
+                    );
+                },
+                Err(e) => {
+                    tracing::error!("LinesMaintenance({}) -> ошибка удаления партиций: {e}", policy.timeframe.as_str());
+                }
+            }
+        }
+
+        tracing::info!(
+            downsampled_rows = downsampled,
+            elapsed_ms = started.elapsed().as_millis() as u64,
+            "LinesMaintenance -> завершено"
+        );
+    }
+}
@@ -0,0 +1,59 @@
+use std::sync::Arc;
+use arc_swap::ArcSwap;
+use tokio::sync::watch;
+use crate::models::{aggregator::KeyMarketType, line::LineHistory};
+
+/// Неизменяемый снимок истории линий. `im::HashMap` клонируется за O(1) и 
+/// делит структуру с предыдущей версией, поэтому обновление одного ключа не зависит от количества ключей
+pub type LinesSnapshot = im::HashMap<KeyMarketType, Arc<LineHistory>>;
+
+/// <b>LinesStore</b> хранит историю линий и публикует её без блокировок:
+/// читатели получают `Arc<LinesSnapshot>`, который после публикации не меняется
+#[derive(Clone)]
+pub struct LinesStore {
+    snapshot: Arc<ArcSwap<LinesSnapshot>>,
+    watch_tx: watch::Sender<Arc<LinesSnapshot>>,
+}
+
+impl LinesStore {
+    pub fn new() -> Self {
+        let snapshot = Arc::new(LinesSnapshot::new());
+        let (watch_tx, _) = watch::channel(snapshot.clone());
+
+        Self {
+            snapshot: Arc::new(ArcSwap::new(snapshot)),
+            watch_tx,
+        }
+    }
+
+    pub fn load(&self) -> Arc<LinesSnapshot> {
+        self.snapshot.load_full()
+    }
+
+    pub fn get(
+        &self,
+        key: &KeyMarketType
+    ) -> Option<Arc<LineHistory>> {
+        self.snapshot.load().get(key).cloned()
+    }
+
+    pub fn subscribe(&self) -> watch::Receiver<Arc<LinesSnapshot>> {
+        self.watch_tx.subscribe()
+    }
+
+    /// Применяет изменения к копии снимка и публикует её. 
+    /// Писатель должен быть один (`CacheAggregator`), иначе параллельные изменения перезапишут друг друга
+    pub fn update(
+        &self,
+        apply: impl FnOnce(&mut LinesSnapshot)
+    ) {
+        let mut next = self.snapshot.load().as_ref().clone();
+        apply(&mut next);
+        self.snapshot.store(Arc::new(next));
+    }
+
+    /// Оповещает подписчиков о текущем снимке
+    pub fn publish(&self) {
+        self.watch_tx.send_replace(self.load());
+    }
+}
@@ -1,6 +1,8 @@
 use std::{sync::Arc, time::Duration};
+use async_trait::async_trait;
 use tokio::sync::{mpsc};
-use crate::{models::{aggregator::{ClientAggregatorUse}, websocket::{ChannelSubscription, WsClientMessage}}, services::cache_aggregator::CacheAggregatorCmd, transport::client_aggregator::ClientAggregatorCmd};
+use tokio_util::sync::CancellationToken;
+use crate::{models::{aggregator::{ClientAggregatorUse}, websocket::{ChannelSubscription, WsClientMessage}}, services::{cache_aggregator::CacheAggregatorCmd, supervisor::Actor}, transport::client_aggregator::ClientAggregatorCmd};
 
 const TIMEOUT_DELAY: u64 = 30;
 
@@ -19,29 +21,37 @@ pub enum ManagerTransmitterCmd {
     Notify(NotifyEvent),
 }
 
-#[derive(Clone)]
 /// <b>ManagerTransmitter</b> ожидает обработанные данные и затем просто их отсылает далее
 pub struct ManagerTransmitter {
+    notify_rx: mpsc::Receiver<ManagerTransmitterCmd>,
     client_aggregator_chart_tx: mpsc::Sender<Arc<ClientAggregatorCmd>>,
     cache_aggregator_tx: mpsc::Sender<Arc<CacheAggregatorCmd>>,
 }
 
 impl ManagerTransmitter {
     pub fn new(
+        notify_rx: mpsc::Receiver<ManagerTransmitterCmd>,
         client_aggregator_chart_tx: mpsc::Sender<Arc<ClientAggregatorCmd>>,
         cache_aggregator_tx: mpsc::Sender<Arc<CacheAggregatorCmd>>,
     ) -> Self {
         Self { 
+            notify_rx,
             client_aggregator_chart_tx,
             cache_aggregator_tx,
         }
     }
+}
 
-    pub async fn run(
-        self, 
-        mut notify_rx: mpsc::Receiver<ManagerTransmitterCmd>,
+#[async_trait]
+impl Actor for ManagerTransmitter {
+    async fn run(
+        &mut self,
+        shutdown: CancellationToken
     ) {
-        while let Some(cmd) = notify_rx.recv().await {
+        while let Some(cmd) = tokio::select! {
+            cmd = self.notify_rx.recv() => cmd,
+            _ = shutdown.cancelled() => None
+        } {
             match cmd {
                 ManagerTransmitterCmd::Notify(event) => {
                     match event {
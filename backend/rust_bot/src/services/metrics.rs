@@ -0,0 +1,333 @@
+use std::{fmt::Write as _, sync::{Arc, LazyLock, Mutex, atomic::{AtomicI64, Ordering}}, time::Duration};
+use tokio::{io::{AsyncReadExt, AsyncWriteExt}, net::{TcpListener, TcpStream}};
+use tracing::info;
+
+use crate::{config, services::{latency::{LATENCY_BUCKETS, LatencyHistogram}, queue::QueueStats}};
+
+static METRICS: LazyLock<Mutex<Vec<Arc<Metric>>>> = LazyLock::new(|| Mutex::new(Vec::new()));
+
+const METRICS_NAME: &str = "Metrics";
+/// Сколько ждать заголовки запроса, иначе соединение закрывается
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MetricKind {
+    /// Только растёт, Prometheus считает по нему `rate`
+    Counter,
+    /// Текущее значение
+    Gauge,
+}
+
+impl MetricKind {
+    fn as_str(&self) -> &'static str {
+        match self {
+            MetricKind::Counter => "counter",
+            MetricKind::Gauge => "gauge",
+        }
+    }
+}
+
+/// <b>Metric</b> одна серия Prometheus: имя и набор меток.
+/// Повторная регистрация с теми же именем и метками возвращает ту же серию,
+/// поэтому перезапуск сессии биржи продолжает её счётчики
+#[derive(Debug)]
+pub struct Metric {
+    pub name: &'static str,
+    pub help: &'static str,
+    pub kind: MetricKind,
+    pub labels: Vec<(&'static str, String)>,
+    value: AtomicI64,
+}
+
+impl Metric {
+    pub fn counter(
+        name: &'static str,
+        help: &'static str,
+        labels: &[(&'static str, &str)]
+    ) -> Arc<Self> {
+        Self::register(name, help, MetricKind::Counter, labels)
+    }
+
+    pub fn gauge(
+        name: &'static str,
+        help: &'static str,
+        labels: &[(&'static str, &str)]
+    ) -> Arc<Self> {
+        Self::register(name, help, MetricKind::Gauge, labels)
+    }
+
+    fn register(
+        name: &'static str,
+        help: &'static str,
+        kind: MetricKind,
+        labels: &[(&'static str, &str)]
+    ) -> Arc<Self> {
+        let labels: Vec<(&'static str, String)> = labels
+            .iter()
+            .map(|(key, value)| (*key, value.to_string()))
+            .collect();
+
+        let mut metrics = METRICS.lock().unwrap();
+        if let Some(metric) = metrics.iter().find(|metric| metric.name == name && metric.labels == labels) {
+            return metric.clone();
+        }
+
+        let metric = Arc::new(Self {
+            name,
+            help,
+            kind,
+            labels,
+            value: AtomicI64::new(0),
+        });
+
+        metrics.push(metric.clone());
+        metric
+    }
+
+    pub fn inc(&self) {
+        self.add(1);
+    }
+
+    pub fn add(
+        &self,
+        value: i64
+    ) {
+        self.value.fetch_add(value, Ordering::Relaxed);
+    }
+
+    pub fn set(
+        &self,
+        value: i64
+    ) {
+        self.value.store(value, Ordering::Relaxed);
+    }
+
+    pub fn get(&self) -> i64 {
+        self.value.load(Ordering::Relaxed)
+    }
+}
+
+/// Все метрики, счётчики очередей и гистограммы задержек в текстовом формате Prometheus 0.0.4
+pub fn render() -> String {
+    let mut out = String::new();
+
+    let mut metrics = METRICS.lock().unwrap().clone();
+    // Серии одного имени идут подряд под одним HELP/TYPE
+    metrics.sort_by_key(|metric| metric.name);
+
+    let mut family = "";
+    for metric in metrics.iter() {
+        if metric.name != family {
+            family = metric.name;
+            header(&mut out, metric.name, metric.help, metric.kind.as_str());
+        }
+
+        let labels: Vec<(&str, &str)> = metric.labels
+            .iter()
+            .map(|(key, value)| (*key, value.as_str()))
+            .collect();
+        sample(&mut out, metric.name, &labels, metric.get());
+    }
+
+    let queues = QueueStats::all();
+    header(&mut out, "queue_messages_total", "Сообщения очередей рыночных данных по результату", "counter");
+    for stats in queues.iter() {
+        for (result, value) in [
+            ("sent", &stats.sent),
+            ("coalesced", &stats.coalesced),
+            ("dropped", &stats.dropped),
+        ] {
+            sample(&mut out, "queue_messages_total", &[("queue", &stats.name), ("result", result)], value.load(Ordering::Relaxed));
+        }
+    }
+
+    header(&mut out, "queue_backpressure_total", "Сколько раз отправитель ждал освобождения очереди", "counter");
+    for stats in queues.iter() {
+        sample(&mut out, "queue_backpressure_total", &[("queue", &stats.name)], stats.backpressure.load(Ordering::Relaxed));
+    }
+
+    header(&mut out, "latency_ms", "Задержки бирж, конвейера и записи в базу в мс", "histogram");
+    for histogram in LatencyHistogram::all() {
+        let name = histogram.name.as_str();
+
+        let mut cumulative = 0;
+        for (index, bucket) in histogram.buckets.iter().enumerate() {
+            cumulative += bucket.load(Ordering::Relaxed);
+            let le = LATENCY_BUCKETS
+                .get(index)
+                .map(|bound| bound.to_string())
+                .unwrap_or_else(|| "+Inf".into());
+
+            sample(&mut out, "latency_ms_bucket", &[("histogram", name), ("le", &le)], cumulative);
+        }
+
+        sample(&mut out, "latency_ms_sum", &[("histogram", name)], histogram.sum.load(Ordering::Relaxed));
+        sample(&mut out, "latency_ms_count", &[("histogram", name)], histogram.count.load(Ordering::Relaxed));
+    }
+
+    out
+}
+
+fn header(
+    out: &mut String,
+    name: &str,
+    help: &str,
+    kind: &str
+) {
+    let _ = writeln!(out, "# HELP {name} {help}");
+    let _ = writeln!(out, "# TYPE {name} {kind}");
+}
+
+fn sample(
+    out: &mut String,
+    name: &str,
+    labels: &[(&str, &str)],
+    value: impl std::fmt::Display
+) {
+    out.push_str(name);
+
+    if !labels.is_empty() {
+        out.push('{');
+        for (index, (key, label)) in labels.iter().enumerate() {
+            if index > 0 {
+                out.push(',');
+            }
+            let label = label.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n");
+            let _ = write!(out, "{key}=\"{label}\"");
+        }
+        out.push('}');
+    }
+
+    let _ = writeln!(out, " {value}");
+}
+
+/// HTTP endpoint `GET /metrics`. Запрос читается только до конца заголовков
+pub async fn serve() {
+    let config = config::get();
+    if !config.metrics.enabled {
+        return;
+    }
+
+    let listener = match TcpListener::bind(&config.metrics.bind).await {
+        Ok(listener) => listener,
+        Err(e) => {
+            tracing::error!("{} -> не удалось открыть {}: {}", METRICS_NAME, config.metrics.bind, e);
+            return;
+        }
+    };
+
+    info!("{} -> is running", METRICS_NAME);
+
+    while let Ok((stream, _)) = listener.accept().await {
+        tokio::spawn(handle_request(stream));
+    }
+}
+
+async fn handle_request(
+    mut stream: TcpStream
+) {
+    let mut request = Vec::new();
+    let mut buf = [0u8; 1024];
+
+    let read_headers = async {
+        while !request.windows(4).any(|window| window == b"\r\n\r\n") {
+            match stream.read(&mut buf).await {
+                Ok(0) | Err(_) => return false,
+                Ok(n) => request.extend_from_slice(&buf[..n]),
+            }
+
+            if request.len() > 8 * 1024 {
+                return false;
+            }
+        }
+        true
+    };
+
+    // Клиент без конца заголовков не держит задачу бесконечно
+    if !tokio::time::timeout(REQUEST_TIMEOUT, read_headers).await.unwrap_or(false) {
+        return;
+    }
+
+    let request_line = String::from_utf8_lossy(&request);
+    let mut parts = request_line.split_whitespace();
+    let (status, content_type, body) = match (parts.next(), parts.next()) {
+        (Some("GET"), Some("/metrics")) => ("200 OK", "text/plain; version=0.0.4; charset=utf-8", render()),
+        _ => ("404 Not Found", "text/plain; charset=utf-8", "not found\n".to_string()),
+    };
+
+    let response = format!(
+        "HTTP/1.1 {status}\r\nContent-Type: {content_type}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    );
+
+    stream.write_all(response.as_bytes()).await.ok();
+    stream.shutdown().await.ok();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Ответ `handle_request` на `request` целиком, до закрытия соединения сервером
+    async fn scrape(
+        request: &[u8]
+    ) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap()).await.unwrap();
+        let (stream, _) = listener.accept().await.unwrap();
+        let server = tokio::spawn(handle_request(stream));
+
+        client.write_all(request).await.unwrap();
+        let mut response = String::new();
+        client.read_to_string(&mut response).await.unwrap();
+        server.await.unwrap();
+
+        response
+    }
+
+    #[test]
+    fn render_groups_series_and_escapes_labels() {
+        let first = Metric::counter("test_render_total", "Тестовый счётчик", &[("exchange", "bybit")]);
+        let second = Metric::counter("test_render_total", "Тестовый счётчик", &[("exchange", "gate\"io")]);
+        let gauge = Metric::gauge("test_render_gauge", "Тестовое значение", &[]);
+        first.add(3);
+        second.inc();
+        gauge.set(-7);
+
+        let out = render();
+        assert_eq!(out.matches("# TYPE test_render_total counter").count(), 1);
+        assert!(out.contains("# HELP test_render_total Тестовый счётчик\n"));
+        assert!(out.contains("test_render_total{exchange=\"bybit\"} 3\n"));
+        assert!(out.contains("test_render_total{exchange=\"gate\\\"io\"} 1\n"));
+        assert!(out.contains("# TYPE test_render_gauge gauge\ntest_render_gauge -7\n"));
+        assert!(out.contains("# TYPE latency_ms histogram"));
+    }
+
+    #[tokio::test]
+    async fn get_metrics_returns_render() {
+        Metric::counter("test_scrape_total", "Счётчик для запроса", &[]).inc();
+
+        let response = scrape(b"GET /metrics HTTP/1.1\r\nHost: localhost\r\n\r\n").await;
+        let (head, body) = response.split_once("\r\n\r\n").unwrap();
+        assert!(head.starts_with("HTTP/1.1 200 OK"));
+        assert!(head.contains("Content-Type: text/plain; version=0.0.4"));
+        assert!(head.contains(&format!("Content-Length: {}", body.len())));
+        assert!(body.contains("test_scrape_total 1\n"));
+    }
+
+    #[tokio::test]
+    async fn unknown_path_is_not_found() {
+        let response = scrape(b"GET /other HTTP/1.1\r\n\r\n").await;
+        assert!(response.starts_with("HTTP/1.1 404 Not Found"));
+    }
+
+    /// Время на паузе: таймаут чтения наступает, как только клиент замолкает
+    #[tokio::test(start_paused = true)]
+    async fn unfinished_request_is_closed_after_timeout() {
+        let started = tokio::time::Instant::now();
+        let response = scrape(b"GET /metrics HTTP/1.1\r\n").await;
+
+        assert!(response.is_empty());
+        assert!(started.elapsed() >= REQUEST_TIMEOUT);
+    }
+}
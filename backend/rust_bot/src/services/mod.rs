@@ -3,4 +3,18 @@ pub mod cache_aggregator;
 pub mod manager_transmitter;
 pub mod data_access_layer;
 pub mod data_mapping;
-pub mod exchange;
\ No newline at end of file
+pub mod exchange;
+pub mod lines_maintenance;
+pub mod lines_store;
+pub mod queue;
+pub mod latency;
+pub mod supervisor;
+pub mod metrics;
+pub mod clock;
+pub mod backtest;
+
+pub mod paper_trading;
+pub mod order_gateway;
+pub mod arbitrage_executor;
+pub mod inventory_tracker;
+pub mod risk_manager;
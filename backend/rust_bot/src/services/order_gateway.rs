@@ -0,0 +1,878 @@
+use std::{collections::{HashMap, HashSet, VecDeque}, sync::Arc, time::Duration};
+use async_trait::async_trait;
+use futures_util::{SinkExt, StreamExt};
+use tokio::sync::{broadcast, mpsc, oneshot};
+use tokio_tungstenite::{connect_async, tungstenite::Message};
+use tokio_util::sync::CancellationToken;
+use tracing::info;
+
+use crate::{adapters::{bybit_trading::BybitTrading, gate_trading::GateTrading}, config::{self, TRADING_EXCHANGES}, models::{exchange::ExchangeType, inventory::Balance, order::{ClientOrderId, FillReport, OrderRequest, OrderState, OrderStatus, OrderType, OrderUpdate, is_valid_client_order_id}, risk::{Exposure, KillSwitches}}, services::{clock, exchange::trading_adapter::{PrivateEvent, TradingAdapter, TradingError, split_symbol}, metrics::Metric, risk_manager::RiskHandle, supervisor::Actor}};
+
+const GATEWAY_NAME: &str = "OrderGateway";
+
+/// Как часто незавершённые ордера сверяются с биржей через REST
+const SYNC_INTERVAL: Duration = Duration::from_secs(10);
+/// Сколько завершённые ордера хранятся в памяти
+const ORDER_RETENTION_MS: i64 = 3_600_000;
+const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(60);
+
+pub enum OrderGatewayCmd {
+    Place {
+        request: OrderRequest,
+        reply: oneshot::Sender<Result<OrderState, TradingError>>
+    },
+    Cancel {
+        client_order_id: ClientOrderId,
+        reply: oneshot::Sender<Result<OrderState, TradingError>>
+    },
+    Order {
+        client_order_id: ClientOrderId,
+        reply: oneshot::Sender<Option<OrderState>>
+    },
+    Orders {
+        reply: oneshot::Sender<Vec<OrderState>>
+    },
+    /// Сверка ордера с биржей через REST
+    Sync {
+        client_order_id: ClientOrderId,
+        reply: oneshot::Sender<Option<OrderState>>
+    },
+    Balances {
+        exchange_id: ExchangeType,
+        reply: oneshot::Sender<Result<Vec<Balance>, TradingError>>
+    },
+}
+
+/// Изменение ордера, исполнение или остаток, рассылается всем подписчикам `OrderGatewayHandle::subscribe`
+#[allow(unused)]
+#[derive(Debug, Clone)]
+pub enum OrderEvent {
+    Order(OrderState),
+    Fill(FillReport),
+    Balance(Balance),
+    /// Приватный поток биржи переподключился: его обновления за время разрыва потеряны
+    StreamConnected(ExchangeType),
+}
+
+#[derive(Clone)]
+pub struct OrderGatewayHandle {
+    tx: mpsc::Sender<OrderGatewayCmd>,
+    events: broadcast::Sender<OrderEvent>,
+}
+
+impl OrderGatewayHandle {
+    /// Повтор с тем же `client_order_id` возвращает уже известный ордер, второй не создаётся
+    pub async fn place(
+        &self,
+        request: OrderRequest
+    ) -> Result<OrderState, TradingError> {
+        let (reply, rx) = oneshot::channel();
+        self.tx.send(OrderGatewayCmd::Place { request, reply }).await.ok();
+        rx.await.unwrap_or_else(|_| Err(TradingError::Network(format!("{GATEWAY_NAME} не ответил"))))
+    }
+
+    /// Состояние после отмены: ордер мог успеть исполниться
+    pub async fn cancel(
+        &self,
+        client_order_id: ClientOrderId
+    ) -> Result<OrderState, TradingError> {
+        let (reply, rx) = oneshot::channel();
+        self.tx.send(OrderGatewayCmd::Cancel { client_order_id, reply }).await.ok();
+        rx.await.unwrap_or_else(|_| Err(TradingError::Network(format!("{GATEWAY_NAME} не ответил"))))
+    }
+
+    pub async fn order(
+        &self,
+        client_order_id: ClientOrderId
+    ) -> Option<OrderState> {
+        let (reply, rx) = oneshot::channel();
+        self.tx.send(OrderGatewayCmd::Order { client_order_id, reply }).await.ok();
+        rx.await.ok().flatten()
+    }
+
+    /// Состояние после сверки с биржей. Если биржа не ответила - последнее известное
+    pub async fn sync(
+        &self,
+        client_order_id: ClientOrderId
+    ) -> Option<OrderState> {
+        let (reply, rx) = oneshot::channel();
+        self.tx.send(OrderGatewayCmd::Sync { client_order_id, reply }).await.ok();
+        rx.await.ok().flatten()
+    }
+
+    pub async fn orders(&self) -> Vec<OrderState> {
+        let (reply, rx) = oneshot::channel();
+        self.tx.send(OrderGatewayCmd::Orders { reply }).await.ok();
+        rx.await.unwrap_or_default()
+    }
+
+    /// Остатки счёта биржи через REST
+    pub async fn balances(
+        &self,
+        exchange_id: ExchangeType
+    ) -> Result<Vec<Balance>, TradingError> {
+        let (reply, rx) = oneshot::channel();
+        self.tx.send(OrderGatewayCmd::Balances { exchange_id, reply }).await.ok();
+        rx.await.unwrap_or_else(|_| Err(TradingError::Network(format!("{GATEWAY_NAME} не ответил"))))
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<OrderEvent> {
+        self.events.subscribe()
+    }
+}
+
+/// Результаты запросов REST и сообщения приватных потоков, которые приходят в актор из задач
+enum GatewayEvent {
+    /// Ответ `RiskManager`: цена, по которой оценён ордер
+    Checked {
+        client_order_id: ClientOrderId,
+        result: Result<f64, TradingError>,
+        reply: oneshot::Sender<Result<OrderState, TradingError>>
+    },
+    Placed {
+        client_order_id: ClientOrderId,
+        result: Result<OrderUpdate, TradingError>,
+        reply: oneshot::Sender<Result<OrderState, TradingError>>
+    },
+    Cancelled {
+        client_order_id: ClientOrderId,
+        result: Result<Option<OrderUpdate>, TradingError>,
+        reply: oneshot::Sender<Result<OrderState, TradingError>>
+    },
+    Synced {
+        client_order_id: ClientOrderId,
+        result: Result<Option<OrderUpdate>, TradingError>,
+        /// Ответ на `OrderGatewayCmd::Sync`, у периодической сверки его нет
+        reply: Option<oneshot::Sender<Option<OrderState>>>
+    },
+    Stream(PrivateEvent),
+    /// Поток переподключился: обновления за время разрыва потеряны
+    StreamConnected(ExchangeType),
+}
+
+struct TradingExchange {
+    adapter: Arc<dyn TradingAdapter>,
+    client: reqwest::Client,
+}
+
+struct TrackedOrder {
+    state: OrderState,
+    /// Исполнения приходят повторно после переподключения потока
+    trade_ids: HashSet<String>,
+    fills_qty: f64,
+    fills_notional: f64,
+    /// Цена оценки `RiskManager`. До проверки ордер не входит в открытую сумму
+    risk_price: Option<f64>,
+}
+
+/// <b>OrderGateway</b> ордера на биржах через приватные API адаптеров `TradingAdapter`.
+///
+/// <br>• Ордер создаётся с `client_order_id`. Если ответ биржи потерян, ордер ищется по этому id
+/// и отправляется повторно, только если биржа его не знает
+/// <br>• Статусы ордеров и исполнения приходят из приватных потоков, после переподключения
+/// и каждые `SYNC_INTERVAL` незавершённые ордера сверяются через REST
+/// <br>• Изменения ордеров и остатков рассылаются подписчикам `OrderEvent`
+/// <br>• Перед отправкой ордер проходит проверки `RiskManager` с открытой суммой `Exposure`.
+/// Проверки идут по одной, поэтому ордер, прошедший проверку, уже учтён в следующей
+/// <br>• После включения kill switch незавершённые ордера на выключенных биржах отменяются
+///
+/// Биржи с `trading.enabled` берутся из конфига при старте
+pub struct OrderGateway {
+    rx: mpsc::Receiver<OrderGatewayCmd>,
+    events: broadcast::Sender<OrderEvent>,
+    gateway_tx: mpsc::Sender<GatewayEvent>,
+    gateway_rx: mpsc::Receiver<GatewayEvent>,
+
+    exchanges: HashMap<ExchangeType, TradingExchange>,
+    orders: HashMap<ClientOrderId, TrackedOrder>,
+    risk: RiskHandle,
+    /// Ордера в очереди на проверку
+    checks: VecDeque<(ClientOrderId, oneshot::Sender<Result<OrderState, TradingError>>)>,
+    checking: bool,
+
+    placed: Arc<Metric>,
+    rejected: Arc<Metric>,
+    fills: Arc<Metric>,
+}
+
+impl OrderGateway {
+    pub fn new(
+        risk: RiskHandle
+    ) -> (Self, OrderGatewayHandle) {
+        let (tx, rx) = mpsc::channel(256);
+        let (gateway_tx, gateway_rx) = mpsc::channel(1024);
+        let (events, _) = broadcast::channel(1024);
+
+        let config = config::get();
+        let mut exchanges = HashMap::new();
+        for exchange_id in TRADING_EXCHANGES {
+            let exchange = config.exchange(exchange_id);
+            if !exchange.trading.enabled {
+                continue;
+            }
+
+            let adapter: Option<Arc<dyn TradingAdapter>> = match exchange_id {
+                ExchangeType::Bybit => BybitTrading::new(&exchange).map(|adapter| adapter as _),
+                ExchangeType::Gate => GateTrading::new(&exchange).map(|adapter| adapter as _),
+                _ => None
+            };
+            // `Config::validate` не пропускает торговлю без ключа
+            let Some(adapter) = adapter else { continue };
+
+            let client = reqwest::Client::builder()
+                .timeout(exchange.trading.request_timeout())
+                .build()
+                .unwrap_or_default();
+            exchanges.insert(exchange_id, TradingExchange { adapter, client });
+        }
+
+        let this = Self {
+            rx,
+            events: events.clone(),
+            gateway_tx,
+            gateway_rx,
+
+            exchanges,
+            orders: HashMap::new(),
+            risk,
+            checks: VecDeque::new(),
+            checking: false,
+
+            placed: Metric::counter("orders_placed_total", "Ордера, принятые биржами", &[]),
+            rejected: Metric::counter("orders_rejected_total", "Ордера, отклонённые биржами или проверками", &[]),
+            fills: Metric::counter("order_fills_total", "Исполнения ордеров из приватных потоков", &[]),
+        };
+
+        (this, OrderGatewayHandle { tx, events })
+    }
+}
+
+#[async_trait]
+impl Actor for OrderGateway {
+    async fn run(
+        &mut self,
+        shutdown: CancellationToken
+    ) {
+        if self.exchanges.is_empty() {
+            info!("{} -> торговля не включена ни на одной бирже", GATEWAY_NAME);
+        }
+
+        // Потоки живут, пока работает актор: после паники они запускаются заново
+        let streams = shutdown.child_token();
+        let _streams_guard = streams.clone().drop_guard();
+        for exchange in self.exchanges.values() {
+            tokio::spawn(private_stream(exchange.adapter.clone(), self.gateway_tx.clone(), streams.clone()));
+        }
+
+        info!("{} -> is running, биржи {:?}", GATEWAY_NAME, self.exchanges.keys().collect::<Vec<_>>());
+        let mut sync = tokio::time::interval(SYNC_INTERVAL);
+        let mut kill_switches = self.risk.kill_switches();
+        // Switch, включённые до перезапуска, тоже отменяют найденные ордера
+        kill_switches.mark_changed();
+
+        loop {
+            tokio::select! {
+                _ = shutdown.cancelled() => break,
+                Some(cmd) = self.rx.recv() => self.handle_cmd(cmd),
+                Some(event) = self.gateway_rx.recv() => self.handle_event(event),
+                Ok(()) = kill_switches.changed() => {
+                    let switches = kill_switches.borrow_and_update().clone();
+                    self.cancel_blocked(&switches);
+                },
+                _ = sync.tick() => {
+                    self.sync(None);
+                    self.prune();
+                }
+            }
+        }
+    }
+}
+
+impl OrderGateway {
+    fn handle_cmd(
+        &mut self,
+        cmd: OrderGatewayCmd
+    ) {
+        match cmd {
+            OrderGatewayCmd::Place {
+                request,
+                reply
+            } => {
+                self.place(request, reply);
+            },
+            OrderGatewayCmd::Cancel {
+                client_order_id,
+                reply
+            } => {
+                self.cancel(client_order_id, reply);
+            },
+            OrderGatewayCmd::Order {
+                client_order_id,
+                reply
+            } => {
+                reply.send(self.orders.get(&client_order_id).map(|order| order.state.clone())).ok();
+            },
+            OrderGatewayCmd::Orders {
+                reply
+            } => {
+                let mut orders: Vec<OrderState> = self.orders.values().map(|order| order.state.clone()).collect();
+                orders.sort_by_key(|order| order.created_at);
+                reply.send(orders).ok();
+            },
+            OrderGatewayCmd::Sync {
+                client_order_id,
+                reply
+            } => {
+                let Some(order) = self.orders.get(&client_order_id) else {
+                    reply.send(None).ok();
+                    return;
+                };
+                let Some(exchange) = self.exchanges.get(&order.state.request.exchange_id) else {
+                    reply.send(Some(order.state.clone())).ok();
+                    return;
+                };
+
+                let adapter = exchange.adapter.clone();
+                let client = exchange.client.clone();
+                let symbol = order.state.request.symbol.clone();
+                let gateway_tx = self.gateway_tx.clone();
+                tokio::spawn(async move {
+                    let result = adapter.get_order(&client, &symbol, &client_order_id).await;
+                    gateway_tx.send(GatewayEvent::Synced { client_order_id, result, reply: Some(reply) }).await.ok();
+                });
+            },
+            OrderGatewayCmd::Balances {
+                exchange_id,
+                reply
+            } => {
+                let Some(exchange) = self.exchanges.get(&exchange_id) else {
+                    reply.send(Err(TradingError::NotConfigured(exchange_id))).ok();
+                    return;
+                };
+
+                let adapter = exchange.adapter.clone();
+                let client = exchange.client.clone();
+                tokio::spawn(async move {
+                    reply.send(adapter.get_balances(&client).await).ok();
+                });
+            },
+        }
+    }
+
+    fn place(
+        &mut self,
+        request: OrderRequest,
+        reply: oneshot::Sender<Result<OrderState, TradingError>>
+    ) {
+        if let Some(order) = self.orders.get(&request.client_order_id) {
+            let same = order.state.request.exchange_id == request.exchange_id
+                && order.state.request.symbol == request.symbol
+                && order.state.request.side == request.side
+                && order.state.request.qty == request.qty
+                && order.state.request.price == request.price;
+            let result = if same { Ok(order.state.clone()) } else { Err(TradingError::DuplicateOrder(request.client_order_id)) };
+            reply.send(result).ok();
+            return;
+        }
+
+        if let Err(e) = validate(&request) {
+            self.rejected.inc();
+            reply.send(Err(e)).ok();
+            return;
+        }
+
+        if !self.exchanges.contains_key(&request.exchange_id) {
+            self.rejected.inc();
+            reply.send(Err(TradingError::NotConfigured(request.exchange_id))).ok();
+            return;
+        }
+
+        let client_order_id = request.client_order_id.clone();
+        self.orders.insert(client_order_id.clone(), TrackedOrder {
+            state: OrderState::new(request),
+            trade_ids: HashSet::new(),
+            fills_qty: 0.0,
+            fills_notional: 0.0,
+            risk_price: None,
+        });
+
+        self.checks.push_back((client_order_id, reply));
+        self.next_check();
+    }
+
+    /// Следующий ордер из очереди уходит в `RiskManager`, когда ответ на предыдущий уже учтён
+    fn next_check(&mut self) {
+        if self.checking {
+            return;
+        }
+        let Some((client_order_id, reply)) = self.checks.pop_front() else { return };
+        let Some(order) = self.orders.get(&client_order_id) else { return };
+
+        let request = order.state.request.clone();
+        let exposure = self.exposure(&request);
+        let risk = self.risk.clone();
+        let gateway_tx = self.gateway_tx.clone();
+        self.checking = true;
+
+        tokio::spawn(async move {
+            let result = risk.check(request, exposure).await;
+            gateway_tx.send(GatewayEvent::Checked { client_order_id, result, reply }).await.ok();
+        });
+    }
+
+    /// Неисполненный объём незавершённых ордеров, прошедших проверку, по цене их оценки
+    fn exposure(
+        &self,
+        request: &OrderRequest
+    ) -> Exposure {
+        let mut exposure = Exposure::default();
+
+        for order in self.orders.values() {
+            let state = &order.state;
+            if state.status.is_final() || state.request.client_order_id == request.client_order_id {
+                continue;
+            }
+            exposure.open_legs += 1;
+
+            let Some(price) = order.risk_price else { continue };
+            let notional = (state.request.qty - state.filled_qty).max(0.0) * price;
+            if state.request.symbol == request.symbol {
+                exposure.symbol_notional += notional;
+            }
+            if state.request.exchange_id == request.exchange_id {
+                exposure.exchange_notional += notional;
+            }
+        }
+
+        exposure
+    }
+
+    fn cancel(
+        &mut self,
+        client_order_id: ClientOrderId,
+        reply: oneshot::Sender<Result<OrderState, TradingError>>
+    ) {
+        let Some(order) = self.orders.get(&client_order_id) else {
+            reply.send(Err(TradingError::OrderNotFound(client_order_id))).ok();
+            return;
+        };
+
+        if order.state.status.is_final() {
+            reply.send(Ok(order.state.clone())).ok();
+            return;
+        }
+
+        let Some(exchange) = self.exchanges.get(&order.state.request.exchange_id) else {
+            reply.send(Err(TradingError::NotConfigured(order.state.request.exchange_id))).ok();
+            return;
+        };
+
+        let adapter = exchange.adapter.clone();
+        let client = exchange.client.clone();
+        let symbol = order.state.request.symbol.clone();
+        let gateway_tx = self.gateway_tx.clone();
+
+        tokio::spawn(async move {
+            // Ордер, который биржа не нашла, мог исполниться до отмены: итог берётся из `get_order`
+            let result = match adapter.clone().cancel_order(&client, &symbol, &client_order_id).await {
+                Ok(()) | Err(TradingError::OrderNotFound(_)) => adapter.get_order(&client, &symbol, &client_order_id).await,
+                Err(e) => Err(e),
+            };
+            gateway_tx.send(GatewayEvent::Cancelled { client_order_id, result, reply }).await.ok();
+        });
+    }
+
+    /// Ответы на отмену никто не ждёт: итог придёт в `OrderEvent`
+    fn cancel_blocked(
+        &mut self,
+        switches: &KillSwitches
+    ) {
+        let blocked: Vec<ClientOrderId> = self.orders
+            .iter()
+            .filter(|(_, order)| !order.state.status.is_final() && !order.state.request.reduce_only && switches.blocking(order.state.request.exchange_id).is_some())
+            .map(|(client_order_id, _)| client_order_id.clone())
+            .collect();
+
+        for client_order_id in blocked {
+            tracing::warn!("{} -> {client_order_id}: отмена по kill switch", GATEWAY_NAME);
+            let (reply, _) = oneshot::channel();
+            self.cancel(client_order_id, reply);
+        }
+    }
+
+    fn handle_event(
+        &mut self,
+        event: GatewayEvent
+    ) {
+        match event {
+            GatewayEvent::Checked {
+                client_order_id,
+                result,
+                reply
+            } => {
+                self.checking = false;
+
+                match result {
+                    Ok(price) => {
+                        let order = self.orders.get_mut(&client_order_id);
+                        let exchange = order.as_ref().and_then(|order| self.exchanges.get(&order.state.request.exchange_id));
+                        if let (Some(order), Some(exchange)) = (order, exchange) {
+                            order.risk_price = Some(price);
+
+                            let adapter = exchange.adapter.clone();
+                            let client = exchange.client.clone();
+                            let request = order.state.request.clone();
+                            let gateway_tx = self.gateway_tx.clone();
+                            tokio::spawn(async move {
+                                let result = place_idempotent(adapter, &client, &request).await;
+                                gateway_tx.send(GatewayEvent::Placed { client_order_id, result, reply }).await.ok();
+                            });
+                        }
+                    },
+                    Err(e) => self.handle_event(GatewayEvent::Placed { client_order_id, result: Err(e), reply }),
+                }
+
+                self.next_check();
+            },
+            GatewayEvent::Placed {
+                client_order_id,
+                result,
+                reply
+            } => {
+                let Some(order) = self.orders.get_mut(&client_order_id) else { return };
+
+                match result {
+                    Ok(update) => {
+                        self.placed.inc();
+                        order.state.apply(&update);
+                        info!(
+                            "{} -> {} {} {:?} {} по {:?}: {:?}",
+                            GATEWAY_NAME,
+                            order.state.request.exchange_id,
+                            order.state.request.symbol,
+                            order.state.request.side,
+                            order.state.request.qty,
+                            order.state.request.price,
+                            order.state.status
+                        );
+                        reply.send(Ok(order.state.clone())).ok();
+                    },
+                    // Ордер мог остаться на бирже: он остаётся `Pending` и сверяется по `SYNC_INTERVAL`
+                    Err(e) if e.is_unknown_outcome() => {
+                        order.state.error = Some(e.to_string());
+                        tracing::warn!("{} -> {client_order_id}: состояние неизвестно: {e}", GATEWAY_NAME);
+                        reply.send(Err(e)).ok();
+                    },
+                    Err(e) => {
+                        self.rejected.inc();
+                        order.state.status = OrderStatus::Rejected;
+                        order.state.error = Some(e.to_string());
+                        order.state.updated_at = clock::now_ms();
+                        tracing::warn!("{} -> {client_order_id} отклонён: {e}", GATEWAY_NAME);
+                        reply.send(Err(e)).ok();
+                    },
+                }
+
+                let state = order.state.clone();
+                self.events.send(OrderEvent::Order(state)).ok();
+            },
+            GatewayEvent::Cancelled {
+                client_order_id,
+                result,
+                reply
+            } => {
+                match result {
+                    Ok(update) => {
+                        let update = update.unwrap_or_else(|| self.not_found_update(&client_order_id));
+                        self.apply_update(update);
+                        let state = self.orders.get(&client_order_id).map(|order| order.state.clone());
+                        reply.send(state.ok_or(TradingError::OrderNotFound(client_order_id))).ok();
+                    },
+                    Err(e) => {
+                        reply.send(Err(e)).ok();
+                    },
+                }
+            },
+            GatewayEvent::Synced {
+                client_order_id,
+                result,
+                reply
+            } => {
+                match result {
+                    Ok(Some(update)) => self.apply_update(update),
+                    // Ответ на создание потерян, и биржа ордер не знает
+                    Ok(None) => {
+                        let stale = self.orders
+                            .get(&client_order_id)
+                            .is_some_and(|order| order.state.status == OrderStatus::Pending && order.state.error.is_some());
+                        if stale {
+                            let update = self.not_found_update(&client_order_id);
+                            self.apply_update(update);
+                        }
+                    },
+                    Err(e) => tracing::warn!("{} -> сверка {client_order_id}: {e}", GATEWAY_NAME),
+                }
+
+                if let Some(reply) = reply {
+                    reply.send(self.orders.get(&client_order_id).map(|order| order.state.clone())).ok();
+                }
+            },
+            GatewayEvent::Stream(PrivateEvent::Order(update)) => {
+                self.apply_update(update);
+            },
+            GatewayEvent::Stream(PrivateEvent::Fill(fill)) => {
+                self.apply_fill(fill);
+            },
+            GatewayEvent::Stream(PrivateEvent::Balance(balance)) => {
+                self.events.send(OrderEvent::Balance(balance)).ok();
+            },
+            GatewayEvent::StreamConnected(exchange_id) => {
+                self.sync(Some(exchange_id));
+                self.events.send(OrderEvent::StreamConnected(exchange_id)).ok();
+            },
+        }
+    }
+
+    /// Ордер, которого нет на бирже, считается отклонённым
+    fn not_found_update(
+        &self,
+        client_order_id: &ClientOrderId
+    ) -> OrderUpdate {
+        let order = self.orders.get(client_order_id);
+
+        OrderUpdate {
+            exchange_id: order.map(|order| order.state.request.exchange_id).unwrap_or(ExchangeType::Bybit),
+            client_order_id: client_order_id.clone(),
+            order_id: None,
+            status: match order {
+                Some(order) if order.state.filled_qty > 0.0 => OrderStatus::Cancelled,
+                Some(order) if order.state.status == OrderStatus::Pending => OrderStatus::Rejected,
+                _ => OrderStatus::Cancelled,
+            },
+            filled_qty: order.map(|order| order.state.filled_qty).unwrap_or_default(),
+            avg_price: None,
+            time: clock::now_ms(),
+        }
+    }
+
+    /// Чужие ордера (созданные вне бота) пропускаются
+    fn apply_update(
+        &mut self,
+        update: OrderUpdate
+    ) {
+        let Some(order) = self.orders.get_mut(&update.client_order_id) else { return };
+        if order.state.request.exchange_id != update.exchange_id {
+            return;
+        }
+
+        if order.state.apply(&update) {
+            self.events.send(OrderEvent::Order(order.state.clone())).ok();
+        }
+    }
+
+    /// Исполнения двигают объём ордера, даже если статус из потока ордеров ещё не пришёл
+    fn apply_fill(
+        &mut self,
+        fill: FillReport
+    ) {
+        let Some(order) = self.orders.get_mut(&fill.client_order_id) else { return };
+        if order.state.request.exchange_id != fill.exchange_id || !order.trade_ids.insert(fill.trade_id.clone()) {
+            return;
+        }
+
+        self.fills.inc();
+        order.fills_qty += fill.qty;
+        order.fills_notional += fill.qty * fill.price;
+        // Биржа без валюты комиссии берёт её в котируемой
+        let fee_currency = fill.fee_currency
+            .as_ref()
+            .map(|currency| currency.to_ascii_uppercase())
+            .or_else(|| split_symbol(&fill.symbol).map(|(_, quote)| quote))
+            .unwrap_or_default();
+        *order.state.fees.entry(fee_currency).or_default() += fill.fee;
+
+        if order.fills_qty > order.state.filled_qty {
+            let status = if order.fills_qty >= order.state.request.qty * (1.0 - 1e-9) { OrderStatus::Filled } else { OrderStatus::PartiallyFilled };
+            let update = OrderUpdate {
+                exchange_id: fill.exchange_id,
+                client_order_id: fill.client_order_id.clone(),
+                order_id: Some(fill.order_id.clone()),
+                status: if order.state.status.is_final() { order.state.status } else { status },
+                filled_qty: order.fills_qty,
+                avg_price: Some(order.fills_notional / order.fills_qty),
+                time: fill.time,
+            };
+            order.state.apply(&update);
+        }
+
+        let state = order.state.clone();
+        self.events.send(OrderEvent::Fill(fill)).ok();
+        self.events.send(OrderEvent::Order(state)).ok();
+    }
+
+    /// Незавершённые ордера биржи (или всех бирж) через REST
+    fn sync(
+        &self,
+        exchange_id: Option<ExchangeType>
+    ) {
+        let now = clock::now_ms();
+
+        for order in self.orders.values() {
+            let request = &order.state.request;
+            let due = exchange_id.is_some() || now - order.state.updated_at >= SYNC_INTERVAL.as_millis() as i64;
+            if order.state.status.is_final() || !due || exchange_id.is_some_and(|exchange_id| exchange_id != request.exchange_id) {
+                continue;
+            }
+            let Some(exchange) = self.exchanges.get(&request.exchange_id) else { continue };
+
+            let adapter = exchange.adapter.clone();
+            let client = exchange.client.clone();
+            let gateway_tx = self.gateway_tx.clone();
+            let symbol = request.symbol.clone();
+            let client_order_id = request.client_order_id.clone();
+
+            tokio::spawn(async move {
+                let result = adapter.get_order(&client, &symbol, &client_order_id).await;
+                gateway_tx.send(GatewayEvent::Synced { client_order_id, result, reply: None }).await.ok();
+            });
+        }
+    }
+
+    fn prune(&mut self) {
+        let now = clock::now_ms();
+        self.orders.retain(|_, order| !order.state.status.is_final() || now - order.state.updated_at < ORDER_RETENTION_MS);
+    }
+}
+
+fn validate(
+    request: &OrderRequest
+) -> Result<(), TradingError> {
+    if !is_valid_client_order_id(&request.client_order_id) {
+        return Err(TradingError::InvalidOrder(format!("client_order_id `{}`: до 28 символов, латиница, цифры, - и _", request.client_order_id)));
+    }
+
+    if !(request.qty.is_finite() && request.qty > 0.0) {
+        return Err(TradingError::InvalidOrder("количество должно быть положительным".into()));
+    }
+
+    if let Some(price) = request.price && !(price.is_finite() && price > 0.0) {
+        return Err(TradingError::InvalidOrder("цена должна быть положительной".into()));
+    }
+
+    if request.order_type == OrderType::Limit && request.price.is_none() {
+        return Err(TradingError::InvalidOrder("лимитный ордер без цены".into()));
+    }
+
+    Ok(())
+}
+
+/// Создание с восстановлением после потерянного ответа: биржу спрашивают по `client_order_id`,
+/// и повторная отправка идёт, только если ордера там нет
+async fn place_idempotent(
+    adapter: Arc<dyn TradingAdapter>,
+    client: &reqwest::Client,
+    request: &OrderRequest
+) -> Result<OrderUpdate, TradingError> {
+    const ATTEMPTS: usize = 2;
+
+    let mut last_error = None;
+    for _ in 0..ATTEMPTS {
+        match adapter.clone().place_order(client, request).await {
+            Ok(update) => return Ok(update),
+            Err(e) if e.is_unknown_outcome() || matches!(e, TradingError::DuplicateOrder(_)) => {
+                match adapter.clone().get_order(client, &request.symbol, &request.client_order_id).await {
+                    Ok(Some(update)) => return Ok(update),
+                    Ok(None) if e.is_unknown_outcome() => last_error = Some(e),
+                    Ok(None) => return Err(e),
+                    // Биржа недоступна: состояние ордера так и неизвестно
+                    Err(_) => return Err(e),
+                }
+            },
+            Err(e) => return Err(e),
+        }
+    }
+
+    Err(last_error.unwrap_or_else(|| TradingError::Network("нет попыток".into())))
+}
+
+/// Приватный поток биржи: авторизация, подписки, heartbeat и переподключение с нарастающей паузой
+async fn private_stream(
+    adapter: Arc<dyn TradingAdapter>,
+    gateway_tx: mpsc::Sender<GatewayEvent>,
+    token: CancellationToken
+) {
+    let exchange_id = adapter.exchange_id();
+    let reconnects = Metric::counter("order_stream_reconnects_total", "Переподключения приватных потоков бирж", &[("exchange", &exchange_id.to_string())]);
+    let mut delay = Duration::from_secs(1);
+
+    while !token.is_cancelled() {
+        let url = adapter.clone().private_ws_url();
+
+        match connect_async(&url).await {
+            Ok((ws_stream, _)) => {
+                let (mut write, mut read) = ws_stream.split();
+                for msg in adapter.clone().create_private_subscribe_messages() {
+                    write.send(msg).await.ok();
+                }
+                gateway_tx.send(GatewayEvent::StreamConnected(exchange_id)).await.ok();
+                info!("{} -> приватный поток {} подключён", GATEWAY_NAME, exchange_id);
+
+                let heartbeat = adapter.clone().heartbeat();
+                let mut ping = tokio::time::interval(heartbeat.interval);
+                ping.tick().await;
+                let mut missed = 0;
+                let mut ping_id = 0;
+
+                loop {
+                    tokio::select! {
+                        _ = token.cancelled() => {
+                            write.send(Message::Close(None)).await.ok();
+                            return;
+                        },
+                        _ = ping.tick() => {
+                            if missed >= heartbeat.max_missed {
+                                tracing::warn!("{} -> приватный поток {}: нет ответа на ping", GATEWAY_NAME, exchange_id);
+                                break;
+                            }
+                            missed += 1;
+                            ping_id += 1;
+                            if write.send(adapter.clone().create_ping_message(ping_id)).await.is_err() {
+                                break;
+                            }
+                        },
+                        msg = read.next() => {
+                            let text = match msg {
+                                Some(Ok(Message::Text(text))) => text,
+                                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                                Some(Ok(_)) => continue,
+                            };
+                            missed = 0;
+
+                            match adapter.clone().parse_private_message(&text) {
+                                Ok(events) => {
+                                    delay = Duration::from_secs(1);
+                                    for event in events {
+                                        gateway_tx.send(GatewayEvent::Stream(event)).await.ok();
+                                    }
+                                },
+                                Err(TradingError::Auth(message)) => {
+                                    tracing::error!("{} -> приватный поток {}: {}", GATEWAY_NAME, exchange_id, TradingError::Auth(message));
+                                    break;
+                                },
+                                Err(e) => tracing::warn!("{} -> приватный поток {}: {e}", GATEWAY_NAME, exchange_id),
+                            }
+                        }
+                    }
+                }
+            },
+            Err(e) => tracing::warn!("{} -> приватный поток {}: {e}", GATEWAY_NAME, exchange_id),
+        }
+
+        reconnects.inc();
+        tokio::select! {
+            _ = token.cancelled() => return,
+            _ = tokio::time::sleep(delay) => {}
+        }
+        delay = (delay * 2).min(MAX_RECONNECT_DELAY);
+    }
+}
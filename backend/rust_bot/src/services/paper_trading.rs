@@ -0,0 +1,689 @@
+use std::{collections::HashMap, sync::Arc};
+use async_trait::async_trait;
+use tokio::{sync::{mpsc, oneshot, watch}, time::Instant};
+use tokio_util::sync::CancellationToken;
+use tracing::info;
+
+use crate::{config, models::{aggregator::KeyMarketType, exchange::ExchangeType, exchange_aggregator::BookData, orderbook::{Fill, Snapshot}, paper::{CloseReason, FillSide, PaperEvent, PaperFill, PaperMark, PaperPosition, PaperRequest, PaperStatus, PositionId}, websocket::{ClientId, Symbol}}, services::{clock, data_access_layer::DataAccessLayerCmd, exchange::exchange_channel_store::{ExchangeChannelStoreCmd, ExchangeHandle}, metrics::Metric, supervisor::Actor}, storage::paper_storage};
+
+/// Сколько последних позиций пользователя отправляется в ответ на `subscribe`
+const HISTORY_LIMIT: i64 = 50;
+
+pub enum PaperCmd {
+    Subscribe {
+        client_id: ClientId,
+        user_id: String,
+        tx: mpsc::Sender<Arc<PaperEvent>>
+    },
+    Unsubscribe {
+        client_id: ClientId
+    },
+    /// Ошибка запроса уходит в `tx`, даже если клиент не подписан
+    Request {
+        user_id: String,
+        request: PaperRequest,
+        tx: mpsc::Sender<Arc<PaperEvent>>
+    },
+}
+
+#[derive(Clone)]
+pub struct PaperHandle {
+    tx: mpsc::Sender<PaperCmd>,
+}
+
+impl PaperHandle {
+    pub async fn request(
+        &self,
+        client_id: ClientId,
+        user_id: String,
+        request: PaperRequest,
+        events: &mpsc::Sender<Arc<PaperEvent>>
+    ) {
+        let cmd = match request {
+            PaperRequest::Subscribe => PaperCmd::Subscribe { client_id, user_id, tx: events.clone() },
+            request => PaperCmd::Request { user_id, request, tx: events.clone() },
+        };
+        self.tx.send(cmd).await.ok();
+    }
+
+    pub async fn unsubscribe(
+        &self,
+        client_id: ClientId
+    ) {
+        self.tx.send(PaperCmd::Unsubscribe { client_id }).await.ok();
+    }
+}
+
+struct PaperClient {
+    user_id: String,
+    tx: mpsc::Sender<Arc<PaperEvent>>,
+}
+
+/// Открытие ждёт стаканы ног: до первого запроса пары биржи их не подписывают
+struct PendingOpen {
+    tx: mpsc::Sender<Arc<PaperEvent>>,
+    user_id: String,
+    key: KeyMarketType,
+    notional: f64,
+    take_profit_pct: Option<f64>,
+    stop_loss_pct: Option<f64>,
+    deadline: Instant,
+}
+
+/// Исполнение обеих ног по стаканам
+struct LegFills {
+    long: Fill,
+    short: Fill,
+}
+
+/// <b>PaperTrading</b> бумажные арбитражные позиции клиентов.
+///
+/// <br>• Открытие: покупка на long бирже по ask и продажа на short бирже по bid на `notional`, проходом по уровням текущих стаканов
+/// <br>• Переоценка каждые `paper.mark_interval_ms`: PnL закрытия сейчас по стаканам с комиссиями входа и выхода
+/// <br>• Закрытие клиентом или по `take_profit_pct` / `stop_loss_pct` от `notional`
+///
+/// Стаканы ног запрашиваются через `DataAccessLayer`, пока позиция открыта.
+/// Позиции и исполнения пишутся в `storage.paper_positions` и `storage.paper_fills`, открытые продолжают переоцениваться после перезапуска
+pub struct PaperTrading {
+    rx: mpsc::Receiver<PaperCmd>,
+    data_access_layer_tx: mpsc::Sender<DataAccessLayerCmd>,
+    exchange_channel_store_tx: mpsc::Sender<ExchangeChannelStoreCmd>,
+    pool: Option<sqlx::PgPool>,
+
+    clients: HashMap<ClientId, PaperClient>,
+    positions: HashMap<PositionId, PaperPosition>,
+    pending: Vec<PendingOpen>,
+    next_id: PositionId,
+    /// Открытые позиции загружены из базы
+    loaded: bool,
+
+    opened: Arc<Metric>,
+    closed: Arc<Metric>,
+    open_gauge: Arc<Metric>,
+}
+
+impl PaperTrading {
+    pub fn new(
+        data_access_layer_tx: mpsc::Sender<DataAccessLayerCmd>,
+        exchange_channel_store_tx: mpsc::Sender<ExchangeChannelStoreCmd>,
+        pool: Option<sqlx::PgPool>
+    ) -> (Self, PaperHandle) {
+        let (tx, rx) = mpsc::channel(256);
+
+        let this = Self {
+            rx,
+            data_access_layer_tx,
+            exchange_channel_store_tx,
+            pool,
+
+            clients: HashMap::new(),
+            positions: HashMap::new(),
+            pending: Vec::new(),
+            next_id: 0,
+            loaded: false,
+
+            opened: Metric::counter("paper_positions_opened_total", "Открытые бумажные позиции", &[]),
+            closed: Metric::counter("paper_positions_closed_total", "Закрытые бумажные позиции", &[]),
+            open_gauge: Metric::gauge("paper_positions_open", "Бумажные позиции, открытые сейчас", &[]),
+        };
+
+        (this, PaperHandle { tx })
+    }
+}
+
+#[async_trait]
+impl Actor for PaperTrading {
+    async fn run(
+        &mut self,
+        shutdown: CancellationToken
+    ) {
+        let (tx, rx) = oneshot::channel();
+        self.exchange_channel_store_tx.send(ExchangeChannelStoreCmd::GetExchangesChannel { reply: tx }).await.ok();
+        let Ok(exchanges_rx) = rx.await else { return };
+
+        // После перезапуска актора позиции уже в памяти, их стаканы запрошены
+        if !self.loaded {
+            self.load().await;
+        }
+
+        let mut interval = tokio::time::interval(config::get().paper.mark_interval());
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+        loop {
+            tokio::select! {
+                _ = shutdown.cancelled() => return,
+                Some(cmd) = self.rx.recv() => {
+                    self.handle_cmd(cmd, &exchanges_rx).await;
+                },
+                _ = interval.tick() => {
+                    self.retry_pending(&exchanges_rx).await;
+                    self.mark_all(&exchanges_rx).await;
+                }
+            }
+        }
+    }
+}
+
+impl PaperTrading {
+    async fn load(
+        &mut self
+    ) {
+        match paper_storage::get_max_position_id(&self.pool).await {
+            Ok(id) => self.next_id = id,
+            Err(e) => tracing::error!("PaperTrading -> id позиций: {e}"),
+        }
+
+        match paper_storage::get_open_positions(&self.pool).await {
+            Ok(positions) => {
+                for position in positions {
+                    self.acquire_books(&Self::key_of(&position)).await;
+                    self.positions.insert(position.id, position);
+                }
+            },
+            Err(e) => tracing::error!("PaperTrading -> открытые позиции: {e}"),
+        }
+
+        self.loaded = true;
+        self.open_gauge.set(self.positions.len() as i64);
+        info!("PaperTrading -> открытых позиций {}", self.positions.len());
+    }
+
+    async fn handle_cmd(
+        &mut self,
+        cmd: PaperCmd,
+        exchanges_rx: &watch::Receiver<HashMap<ExchangeType, ExchangeHandle>>
+    ) {
+        match cmd {
+            PaperCmd::Subscribe {
+                client_id,
+                user_id,
+                tx
+            } => {
+                let positions = self.user_positions(&user_id).await;
+                tx.try_send(Arc::new(PaperEvent::Positions { positions })).ok();
+                self.clients.insert(client_id, PaperClient { user_id, tx });
+            },
+            PaperCmd::Unsubscribe {
+                client_id
+            } => {
+                self.clients.remove(&client_id);
+            },
+            PaperCmd::Request {
+                user_id,
+                request,
+                tx
+            } => {
+                let result = match request {
+                    PaperRequest::Subscribe => Ok(()),
+                    PaperRequest::Open {
+                        long_exchange,
+                        short_exchange,
+                        ticker,
+                        notional,
+                        take_profit_pct,
+                        stop_loss_pct
+                    } => {
+                        let symbol = Arc::new(format!("{}usdt", ticker.to_lowercase()));
+                        let key = KeyMarketType::new(long_exchange, short_exchange, symbol);
+                        self.request_open(tx.clone(), user_id, key, notional, take_profit_pct, stop_loss_pct, exchanges_rx).await
+                    },
+                    PaperRequest::Close {
+                        position_id
+                    } => self.request_close(&user_id, position_id, exchanges_rx).await,
+                };
+
+                if let Err(message) = result {
+                    Self::send_error(&tx, message);
+                }
+            },
+        }
+    }
+
+    /// Открытые позиции из памяти с оценкой, закрытые из базы
+    async fn user_positions(
+        &self,
+        user_id: &str
+    ) -> Vec<PaperPosition> {
+        let mut positions: Vec<PaperPosition> = self.positions
+            .values()
+            .filter(|position| position.user_id == user_id)
+            .cloned()
+            .collect();
+
+        match paper_storage::get_user_positions(&self.pool, user_id, HISTORY_LIMIT).await {
+            Ok(stored) => positions.extend(stored.into_iter().filter(|position| position.status == PaperStatus::Closed)),
+            Err(e) => tracing::error!("PaperTrading -> позиции {user_id}: {e}"),
+        }
+
+        positions.sort_by_key(|position| std::cmp::Reverse(position.opened_at));
+        positions
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    async fn request_open(
+        &mut self,
+        tx: mpsc::Sender<Arc<PaperEvent>>,
+        user_id: String,
+        key: KeyMarketType,
+        notional: f64,
+        take_profit_pct: Option<f64>,
+        stop_loss_pct: Option<f64>,
+        exchanges_rx: &watch::Receiver<HashMap<ExchangeType, ExchangeHandle>>
+    ) -> Result<(), String> {
+        let settings = config::get().paper.clone();
+
+        if key.long_exchange == key.short_exchange {
+            return Err("биржи ног должны различаться".into());
+        }
+        if !(notional.is_finite() && notional > 0.0 && notional <= settings.max_notional) {
+            return Err(format!("notional должен быть от 0 до {}", settings.max_notional));
+        }
+        if [take_profit_pct, stop_loss_pct].iter().flatten().any(|pct| !(pct.is_finite() && *pct > 0.0)) {
+            return Err("takeProfitPct и stopLossPct должны быть положительными".into());
+        }
+
+        let open = self.positions.values().filter(|position| position.user_id == user_id).count()
+            + self.pending.iter().filter(|pending| pending.user_id == user_id).count();
+        if open >= settings.max_open_per_user {
+            return Err(format!("не больше {} открытых позиций", settings.max_open_per_user));
+        }
+
+        self.acquire_books(&key).await;
+        let pending = PendingOpen {
+            tx,
+            user_id,
+            key,
+            notional,
+            take_profit_pct,
+            stop_loss_pct,
+            deadline: Instant::now() + settings.open_timeout(),
+        };
+
+        // Стаканы уже есть, если пару смотрят другие клиенты
+        if let Some(pending) = self.try_open(pending, exchanges_rx).await {
+            self.pending.push(pending);
+        }
+
+        Ok(())
+    }
+
+    async fn retry_pending(
+        &mut self,
+        exchanges_rx: &watch::Receiver<HashMap<ExchangeType, ExchangeHandle>>
+    ) {
+        for pending in std::mem::take(&mut self.pending) {
+            if let Some(pending) = self.try_open(pending, exchanges_rx).await {
+                self.pending.push(pending);
+            }
+        }
+    }
+
+    /// `Some` - стаканов ещё нет, открытие повторится на следующей переоценке
+    async fn try_open(
+        &mut self,
+        pending: PendingOpen,
+        exchanges_rx: &watch::Receiver<HashMap<ExchangeType, ExchangeHandle>>
+    ) -> Option<PendingOpen> {
+        let Some((long, short)) = Self::books(exchanges_rx, &pending.key).await else {
+            if Instant::now() < pending.deadline {
+                return Some(pending);
+            }
+
+            self.release_books(&pending.key).await;
+            Self::send_error(&pending.tx, format!("нет свежих стаканов {}", Self::describe(&pending.key)));
+            return None;
+        };
+
+        let fee_rate = config::get().paper.fee_rate();
+        let opened = Self::open_fills(&long, &short, pending.notional);
+        let Some((qty, fills)) = opened else {
+            self.release_books(&pending.key).await;
+            Self::send_error(&pending.tx, format!("не хватает объёма стаканов {} на {}", Self::describe(&pending.key), pending.notional));
+            return None;
+        };
+
+        self.next_id += 1;
+        let now_ms = clock::now_ms();
+        let long_fee = fee_rate * qty * fills.long.price;
+        let short_fee = fee_rate * qty * fills.short.price;
+
+        let position = PaperPosition {
+            id: self.next_id,
+            user_id: pending.user_id,
+            symbol: pending.key.symbol.to_string(),
+            long_exchange: pending.key.long_exchange,
+            short_exchange: pending.key.short_exchange,
+            status: PaperStatus::Open,
+            notional: pending.notional,
+            qty,
+            long_entry: fills.long.price,
+            short_entry: fills.short.price,
+            entry_spread_pct: Self::spread_pct(fills.short.price, fills.long.price),
+            take_profit_pct: pending.take_profit_pct,
+            stop_loss_pct: pending.stop_loss_pct,
+            fees: long_fee + short_fee,
+            opened_at: now_ms,
+            closed_at: None,
+            long_exit: None,
+            short_exit: None,
+            exit_spread_pct: None,
+            realized_pnl: None,
+            close_reason: None,
+
+            mark: None,
+        };
+
+        let fills = [
+            PaperFill {
+                position_id: position.id,
+                exchange: position.long_exchange,
+                side: FillSide::Buy,
+                qty,
+                price: fills.long.price,
+                best_price: fills.long.best,
+                fee: long_fee,
+                timestamp: now_ms,
+            },
+            PaperFill {
+                position_id: position.id,
+                exchange: position.short_exchange,
+                side: FillSide::Sell,
+                qty,
+                price: fills.short.price,
+                best_price: fills.short.best,
+                fee: short_fee,
+                timestamp: now_ms,
+            },
+        ];
+
+        if let Err(e) = paper_storage::add_position(&self.pool, &position, &fills).await {
+            tracing::error!("PaperTrading -> позиция {}: {e}", position.id);
+        }
+
+        info!(
+            "PaperTrading -> {} открыл #{} {} на {} по спреду {:.4}%",
+            position.user_id,
+            position.id,
+            Self::describe(&pending.key),
+            position.notional,
+            position.entry_spread_pct
+        );
+        self.opened.inc();
+
+        for fill in fills {
+            self.publish(&position.user_id, PaperEvent::Fill { fill });
+        }
+        self.publish(&position.user_id, PaperEvent::Position { position: Box::new(position.clone()) });
+        self.positions.insert(position.id, position);
+        self.open_gauge.set(self.positions.len() as i64);
+
+        None
+    }
+
+    async fn request_close(
+        &mut self,
+        user_id: &str,
+        position_id: PositionId,
+        exchanges_rx: &watch::Receiver<HashMap<ExchangeType, ExchangeHandle>>
+    ) -> Result<(), String> {
+        let Some(position) = self.positions.get(&position_id).filter(|position| position.user_id == user_id) else {
+            return Err(format!("нет открытой позиции #{position_id}"));
+        };
+
+        let key = Self::key_of(position);
+        let Some((long, short)) = Self::books(exchanges_rx, &key).await else {
+            return Err(format!("нет свежих стаканов {}", Self::describe(&key)));
+        };
+
+        self.close(position_id, &long, &short, CloseReason::Manual).await;
+        Ok(())
+    }
+
+    /// Переоценка открытых позиций и правила закрытия
+    async fn mark_all(
+        &mut self,
+        exchanges_rx: &watch::Receiver<HashMap<ExchangeType, ExchangeHandle>>
+    ) {
+        let ids: Vec<PositionId> = self.positions.keys().copied().collect();
+
+        for id in ids {
+            let Some(position) = self.positions.get(&id) else { continue };
+            let key = Self::key_of(position);
+
+            // Без стаканов позиция сохраняет прошлую оценку, правила не проверяются
+            let Some((long, short)) = Self::books(exchanges_rx, &key).await else { continue };
+            let Some(fills) = Self::exit_fills(&long, &short, position.qty) else { continue };
+
+            let fee_rate = config::get().paper.fee_rate();
+            let (gross, exit_fees) = Self::exit_pnl(position, &fills, fee_rate);
+            let mark = PaperMark {
+                exit_spread_pct: Self::spread_pct(fills.short.price, fills.long.price),
+                unrealized_pnl: gross - position.fees - exit_fees,
+                updated_at: clock::now_ms(),
+            };
+
+            let pnl_pct = mark.unrealized_pnl / position.notional * 100.0;
+            let reason = if position.take_profit_pct.is_some_and(|pct| pnl_pct >= pct) {
+                Some(CloseReason::TakeProfit)
+            } else if position.stop_loss_pct.is_some_and(|pct| pnl_pct <= -pct) {
+                Some(CloseReason::StopLoss)
+            } else {
+                None
+            };
+
+            match reason {
+                Some(reason) => self.close(id, &long, &short, reason).await,
+                None => {
+                    let Some(position) = self.positions.get_mut(&id) else { continue };
+                    position.mark = Some(mark);
+
+                    let event = PaperEvent::Position { position: Box::new(position.clone()) };
+                    let user_id = position.user_id.clone();
+                    self.publish(&user_id, event);
+                }
+            }
+        }
+    }
+
+    async fn close(
+        &mut self,
+        position_id: PositionId,
+        long: &Snapshot,
+        short: &Snapshot,
+        reason: CloseReason
+    ) {
+        let Some(mut position) = self.positions.remove(&position_id) else { return };
+        let fee_rate = config::get().paper.fee_rate();
+
+        // Нехватка объёма исполняется по худшему уровню, позиция закрывается в любом случае
+        let Some(fills) = Self::exit_fills(long, short, position.qty) else {
+            self.positions.insert(position_id, position);
+            return;
+        };
+
+        let now_ms = clock::now_ms();
+        let (gross, exit_fees) = Self::exit_pnl(&position, &fills, fee_rate);
+        let long_fee = fee_rate * position.qty * fills.long.price;
+        let short_fee = fee_rate * position.qty * fills.short.price;
+
+        position.status = PaperStatus::Closed;
+        position.fees += exit_fees;
+        position.closed_at = Some(now_ms);
+        position.long_exit = Some(fills.long.price);
+        position.short_exit = Some(fills.short.price);
+        position.exit_spread_pct = Some(Self::spread_pct(fills.short.price, fills.long.price));
+        position.realized_pnl = Some(gross - position.fees);
+        position.close_reason = Some(reason);
+        position.mark = None;
+
+        let fills = [
+            PaperFill {
+                position_id,
+                exchange: position.long_exchange,
+                side: FillSide::Sell,
+                qty: position.qty,
+                price: fills.long.price,
+                best_price: fills.long.best,
+                fee: long_fee,
+                timestamp: now_ms,
+            },
+            PaperFill {
+                position_id,
+                exchange: position.short_exchange,
+                side: FillSide::Buy,
+                qty: position.qty,
+                price: fills.short.price,
+                best_price: fills.short.best,
+                fee: short_fee,
+                timestamp: now_ms,
+            },
+        ];
+
+        if let Err(e) = paper_storage::close_position(&self.pool, &position, &fills).await {
+            tracing::error!("PaperTrading -> закрытие #{position_id}: {e}");
+        }
+
+        info!(
+            "PaperTrading -> {} закрыл #{} ({}), PnL {:.4}",
+            position.user_id,
+            position_id,
+            reason.as_str(),
+            position.realized_pnl.unwrap_or_default()
+        );
+        self.closed.inc();
+        self.open_gauge.set(self.positions.len() as i64);
+        self.release_books(&Self::key_of(&position)).await;
+
+        for fill in fills {
+            self.publish(&position.user_id, PaperEvent::Fill { fill });
+        }
+        let user_id = position.user_id.clone();
+        self.publish(&user_id, PaperEvent::Position { position: Box::new(position) });
+    }
+
+    /// Количество по лучшему ask long биржи и исполнение обеих ног. `None` - не хватает объёма
+    fn open_fills(
+        long: &Snapshot,
+        short: &Snapshot,
+        notional: f64
+    ) -> Option<(f64, LegFills)> {
+        let best_ask = long.a.keys().next()?.as_f64();
+        let qty = notional / best_ask;
+
+        let long_fill = long.buy(qty)?;
+        let short_fill = short.sell(qty)?;
+        if !long_fill.complete || !short_fill.complete {
+            return None;
+        }
+
+        Some((qty, LegFills { long: long_fill, short: short_fill }))
+    }
+
+    /// Продажа на long бирже по bid и покупка на short бирже по ask
+    fn exit_fills(
+        long: &Snapshot,
+        short: &Snapshot,
+        qty: f64
+    ) -> Option<LegFills> {
+        Some(LegFills {
+            long: long.sell(qty)?,
+            short: short.buy(qty)?,
+        })
+    }
+
+    /// PnL ног без комиссий и комиссии выхода
+    fn exit_pnl(
+        position: &PaperPosition,
+        fills: &LegFills,
+        fee_rate: f64
+    ) -> (f64, f64) {
+        let gross = position.qty * (fills.long.price - position.long_entry)
+            + position.qty * (position.short_entry - fills.short.price);
+        let exit_fees = fee_rate * position.qty * (fills.long.price + fills.short.price);
+
+        (gross, exit_fees)
+    }
+
+    fn spread_pct(
+        short_price: f64,
+        long_price: f64
+    ) -> f64 {
+        (short_price - long_price) / ((short_price + long_price) / 2.0) * 100.0
+    }
+
+    /// Свежие стаканы обеих ног
+    async fn books(
+        exchanges_rx: &watch::Receiver<HashMap<ExchangeType, ExchangeHandle>>,
+        key: &KeyMarketType
+    ) -> Option<(Snapshot, Snapshot)> {
+        let (long, short) = {
+            let exchanges = exchanges_rx.borrow();
+            (exchanges.get(&key.long_exchange)?.store.clone(), exchanges.get(&key.short_exchange)?.store.clone())
+        };
+
+        Some((
+            Self::fresh(long.book(key.symbol.clone()).await?)?,
+            Self::fresh(short.book(key.symbol.clone()).await?)?,
+        ))
+    }
+
+    /// Стакан без обновлений дольше `pipeline.book_stale_secs` не исполняет сделки
+    fn fresh(
+        data: BookData
+    ) -> Option<Snapshot> {
+        let stale_after = config::get().pipeline.book_stale_after();
+        data.updated_at.filter(|updated_at| clock::instant().duration_since(*updated_at) <= stale_after)?;
+
+        data.snapshot
+    }
+
+    async fn acquire_books(
+        &self,
+        key: &KeyMarketType
+    ) {
+        for exchange_id in [key.long_exchange, key.short_exchange] {
+            self.data_access_layer_tx.send(DataAccessLayerCmd::AcquireBook { exchange_id, symbol: key.symbol.clone() }).await.ok();
+        }
+    }
+
+    async fn release_books(
+        &self,
+        key: &KeyMarketType
+    ) {
+        for exchange_id in [key.long_exchange, key.short_exchange] {
+            self.data_access_layer_tx.send(DataAccessLayerCmd::ReleaseBook { exchange_id, symbol: key.symbol.clone() }).await.ok();
+        }
+    }
+
+    fn key_of(
+        position: &PaperPosition
+    ) -> KeyMarketType {
+        KeyMarketType::new(position.long_exchange, position.short_exchange, Arc::new(Symbol::from(position.symbol.as_str())))
+    }
+
+    fn describe(
+        key: &KeyMarketType
+    ) -> String {
+        format!("{} {}/{}", key.symbol, key.long_exchange, key.short_exchange)
+    }
+
+    /// Всем соединениям пользователя. Переполненная очередь клиента пропускает сообщение:
+    /// следующая переоценка несёт полное состояние позиции
+    fn publish(
+        &self,
+        user_id: &str,
+        event: PaperEvent
+    ) {
+        let event = Arc::new(event);
+        for client in self.clients.values().filter(|client| client.user_id == user_id) {
+            client.tx.try_send(event.clone()).ok();
+        }
+    }
+
+    fn send_error(
+        tx: &mpsc::Sender<Arc<PaperEvent>>,
+        message: String
+    ) {
+        tx.try_send(Arc::new(PaperEvent::Error { message })).ok();
+    }
+}
@@ -0,0 +1,158 @@
+use std::{collections::{HashMap, VecDeque}, hash::Hash, sync::{Arc, LazyLock, Mutex, atomic::{AtomicU64, Ordering}}, time::Duration};
+use tokio::sync::Notify;
+
+static QUEUE_STATS: LazyLock<Mutex<Vec<Arc<QueueStats>>>> = LazyLock::new(|| Mutex::new(Vec::new()));
+
+/// <b>QueueStats</b> счётчики очереди на пути рыночных данных
+#[derive(Debug, Default)]
+pub struct QueueStats {
+    pub name: String,
+    /// Принятые сообщения
+    pub sent: AtomicU64,
+    /// Сообщения, заменённые более свежим значением по тому же ключу
+    pub coalesced: AtomicU64,
+    /// Сообщения, отброшенные из-за переполнения очереди
+    pub dropped: AtomicU64,
+    /// Сколько раз отправитель ждал освобождения очереди
+    pub backpressure: AtomicU64,
+}
+
+impl QueueStats {
+    /// Создаёт счётчики и регистрирует их для `report_stats`.
+    /// Очередь с тем же именем, например после перезапуска биржи, продолжает прежние счётчики
+    pub fn register(
+        name: impl Into<String>
+    ) -> Arc<Self> {
+        let name = name.into();
+        let mut all = QUEUE_STATS.lock().unwrap();
+        if let Some(stats) = all.iter().find(|stats| stats.name == name) {
+            return stats.clone();
+        }
+
+        let stats = Arc::new(Self {
+            name,
+            ..Default::default()
+        });
+
+        all.push(stats.clone());
+        stats
+    }
+
+    pub fn all() -> Vec<Arc<Self>> {
+        QUEUE_STATS.lock().unwrap().clone()
+    }
+
+    pub fn add_sent(&self) {
+        self.sent.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn add_coalesced(&self) {
+        self.coalesced.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn add_dropped(&self) {
+        self.dropped.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn add_backpressure(&self) {
+        self.backpressure.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Периодически пишет счётчики всех очередей в лог
+pub async fn report_stats(
+    period: Duration
+) {
+    let mut interval = tokio::time::interval(period);
+    interval.tick().await;
+
+    loop {
+        interval.tick().await;
+
+        for stats in QueueStats::all() {
+            tracing::info!(
+                queue = stats.name.as_str(),
+                sent = stats.sent.load(Ordering::Relaxed),
+                coalesced = stats.coalesced.load(Ordering::Relaxed),
+                dropped = stats.dropped.load(Ordering::Relaxed),
+                backpressure = stats.backpressure.load(Ordering::Relaxed),
+                "Queue stats"
+            );
+        }
+    }
+}
+
+struct CoalescingInner<K, V> {
+    order: VecDeque<K>,
+    values: HashMap<K, V>,
+}
+
+/// <b>CoalescingQueue</b> очередь с одним значением на ключ: новое значение заменяет ещё не прочитанное,
+/// а ключ сохраняет место в очереди. Размер ограничен количеством ключей (символов),
+/// поэтому медленный читатель получает последнее состояние каждого символа без роста памяти.
+///
+/// Читатель должен быть один
+pub struct CoalescingQueue<K, V> {
+    inner: Mutex<CoalescingInner<K, V>>,
+    notify: Notify,
+    stats: Arc<QueueStats>,
+}
+
+impl<K: Eq + Hash + Clone, V> CoalescingQueue<K, V> {
+    pub fn new(
+        name: impl Into<String>
+    ) -> Arc<Self> {
+        Arc::new(Self {
+            inner: Mutex::new(CoalescingInner {
+                order: VecDeque::new(),
+                values: HashMap::new(),
+            }),
+            notify: Notify::new(),
+            stats: QueueStats::register(name),
+        })
+    }
+
+    pub fn push(
+        &self,
+        key: K,
+        value: V
+    ) {
+        {
+            let mut inner = self.inner.lock().unwrap();
+            if inner.values.insert(key.clone(), value).is_some() {
+                self.stats.add_coalesced();
+            } else {
+                inner.order.push_back(key);
+            }
+        }
+
+        self.stats.add_sent();
+        self.notify.notify_one();
+    }
+
+    pub fn try_pop(&self) -> Option<(K, V)> {
+        let mut inner = self.inner.lock().unwrap();
+        while let Some(key) = inner.order.pop_front() {
+            if let Some(value) = inner.values.remove(&key) {
+                return Some((key, value));
+            }
+        }
+
+        None
+    }
+
+    /// Нет непрочитанных значений
+    pub fn is_empty(&self) -> bool {
+        self.inner.lock().unwrap().values.is_empty()
+    }
+
+    pub async fn pop(&self) -> (K, V) {
+        loop {
+            if let Some(item) = self.try_pop() {
+                return item;
+            }
+
+            self.notify.notified().await;
+        }
+    }
+}
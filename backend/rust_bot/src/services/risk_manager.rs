@@ -0,0 +1,533 @@
+use std::{collections::{HashMap, VecDeque}, sync::Arc};
+use async_trait::async_trait;
+use tokio::sync::{mpsc, oneshot, watch};
+use tokio_util::sync::CancellationToken;
+use tracing::info;
+
+use crate::{config::{self, RiskConfig}, models::{exchange::ExchangeType, order::OrderRequest, risk::{Exposure, KillScope, KillSource, KillSwitch, KillSwitches, RiskBreach, RiskCheck, RiskStatus}, websocket::Symbol}, services::{clock, exchange::{exchange_channel_store::{ExchangeChannelStoreCmd, ExchangeHandle}, trading_adapter::TradingError}, metrics::Metric, supervisor::Actor}, storage::{execution_storage, risk_storage}};
+
+const RISK_NAME: &str = "RiskManager";
+
+/// Сколько последних нарушений отдаёт `status`
+const RECENT_LIMIT: usize = 100;
+const DAY_MS: i64 = 86_400_000;
+
+pub enum RiskCmd {
+    Check {
+        request: OrderRequest,
+        exposure: Exposure,
+        /// Цена, по которой оценён ордер
+        reply: oneshot::Sender<Result<f64, RiskBreach>>
+    },
+    /// Включает или выключает kill switch
+    KillSwitch {
+        scope: KillScope,
+        active: bool,
+        reason: String,
+        reply: oneshot::Sender<Vec<KillSwitch>>
+    },
+    /// Результат завершённого исполнения для суточного убытка
+    Realized {
+        cash_flow: f64
+    },
+    Status {
+        reply: oneshot::Sender<RiskStatus>
+    },
+}
+
+#[derive(Clone)]
+pub struct RiskHandle {
+    tx: mpsc::Sender<RiskCmd>,
+    switches_rx: watch::Receiver<Arc<KillSwitches>>,
+}
+
+impl RiskHandle {
+    /// Цена, по которой оценён ордер: лимитная или середина стакана для рыночного.
+    /// Без ответа ордер отклоняется
+    pub async fn check(
+        &self,
+        request: OrderRequest,
+        exposure: Exposure
+    ) -> Result<f64, TradingError> {
+        let (reply, rx) = oneshot::channel();
+        self.tx.send(RiskCmd::Check { request, exposure, reply }).await.ok();
+
+        match rx.await {
+            Ok(result) => result.map_err(|breach| TradingError::Risk(breach.to_string())),
+            Err(_) => Err(TradingError::Risk(format!("{RISK_NAME} не ответил"))),
+        }
+    }
+
+    /// Включённые switch после изменения
+    pub async fn set_kill_switch(
+        &self,
+        scope: KillScope,
+        active: bool,
+        reason: String
+    ) -> Vec<KillSwitch> {
+        let (reply, rx) = oneshot::channel();
+        self.tx.send(RiskCmd::KillSwitch { scope, active, reason, reply }).await.ok();
+        rx.await.unwrap_or_default()
+    }
+
+    pub async fn realized(
+        &self,
+        cash_flow: f64
+    ) {
+        self.tx.send(RiskCmd::Realized { cash_flow }).await.ok();
+    }
+
+    pub async fn status(&self) -> Option<RiskStatus> {
+        let (reply, rx) = oneshot::channel();
+        self.tx.send(RiskCmd::Status { reply }).await.ok();
+        rx.await.ok()
+    }
+
+    pub fn kill_switches(&self) -> watch::Receiver<Arc<KillSwitches>> {
+        self.switches_rx.clone()
+    }
+
+    /// Торговля на бирже выключена kill switch
+    pub fn is_blocked(
+        &self,
+        exchange_id: ExchangeType
+    ) -> bool {
+        self.switches_rx.borrow().blocking(exchange_id).is_some()
+    }
+}
+
+/// Середина стакана биржи и сколько мс он не обновлялся
+struct BookView {
+    mid: Option<f64>,
+    age_ms: i64,
+}
+
+/// <b>RiskManager</b> предторговые проверки каждого ордера `OrderGateway` по `risk`.
+///
+/// <br>• Kill switch: общий или по бирже, из админки или автоматически при нарушении
+/// <br>• Суточный убыток исполнений: при `max_daily_loss` включается общий switch
+/// <br>• Незавершённые ордера не больше `max_open_legs`
+/// <br>• Стакан биржи ордера не старше `max_book_age_ms`
+/// <br>• Сумма ордера в пределах общего лимита, а вместе с незавершёнными ордерами `Exposure` -
+/// в пределах лимитов символа и биржи
+/// <br>• Цена не дальше `price_band_pct` от средней середины свежих стаканов всех бирж
+///
+/// Ордера `reduce_only` проходят без kill switch, суточного убытка, лимита незавершённых ордеров и лимитов
+/// символа и биржи, а устаревший стакан биржи им нужен только для оценки цены.
+/// Нарушения общего лимита суммы и цены выключают биржу ордера при `kill_on_breach`. Нарушения пишутся
+/// в `storage.risk_breaches`, включённые switch - в `storage.risk_kill_switches` и переживают перезапуск
+pub struct RiskManager {
+    rx: mpsc::Receiver<RiskCmd>,
+    exchange_channel_store_tx: mpsc::Sender<ExchangeChannelStoreCmd>,
+    switches_tx: watch::Sender<Arc<KillSwitches>>,
+    pool: Option<sqlx::PgPool>,
+
+    switches: KillSwitches,
+    /// Сутки UTC, к которым относится `daily_pnl`
+    day: i64,
+    daily_pnl: f64,
+    breaches: VecDeque<RiskBreach>,
+    loaded: bool,
+
+    rejected: Arc<Metric>,
+}
+
+impl RiskManager {
+    pub fn new(
+        exchange_channel_store_tx: mpsc::Sender<ExchangeChannelStoreCmd>,
+        pool: Option<sqlx::PgPool>
+    ) -> (Self, RiskHandle) {
+        let (tx, rx) = mpsc::channel(256);
+        let (switches_tx, switches_rx) = watch::channel(Arc::new(KillSwitches::default()));
+
+        let this = Self {
+            rx,
+            exchange_channel_store_tx,
+            switches_tx,
+            pool,
+
+            switches: KillSwitches::default(),
+            day: 0,
+            daily_pnl: 0.0,
+            breaches: VecDeque::new(),
+            loaded: false,
+
+            rejected: Metric::counter("risk_rejected_total", "Ордера, отклонённые риск-проверками", &[]),
+        };
+
+        (this, RiskHandle { tx, switches_rx })
+    }
+}
+
+#[async_trait]
+impl Actor for RiskManager {
+    async fn run(
+        &mut self,
+        shutdown: CancellationToken
+    ) {
+        let (tx, rx) = oneshot::channel();
+        self.exchange_channel_store_tx.send(ExchangeChannelStoreCmd::GetExchangesChannel { reply: tx }).await.ok();
+        let Ok(exchanges_rx) = rx.await else { return };
+
+        if !self.loaded {
+            self.load().await;
+        }
+
+        info!("{} -> is running", RISK_NAME);
+
+        loop {
+            tokio::select! {
+                _ = shutdown.cancelled() => return,
+                Some(cmd) = self.rx.recv() => self.handle_cmd(cmd, &exchanges_rx).await,
+            }
+        }
+    }
+}
+
+impl RiskManager {
+    async fn load(
+        &mut self
+    ) {
+        match risk_storage::get_kill_switches(&self.pool).await {
+            Ok(switches) => {
+                for switch in switches.iter() {
+                    tracing::warn!("{} -> kill switch {} включён с {}: {}", RISK_NAME, switch.scope, switch.activated_at, switch.reason);
+                }
+                self.switches.active = switches;
+                self.publish();
+            },
+            Err(e) => tracing::error!("{} -> kill switch: {e}", RISK_NAME),
+        }
+
+        self.day = clock::now_ms().div_euclid(DAY_MS);
+        match execution_storage::get_realized_since(&self.pool, self.day * DAY_MS).await {
+            Ok(realized) => self.daily_pnl = realized,
+            Err(e) => tracing::error!("{} -> результат за сутки: {e}", RISK_NAME),
+        }
+
+        self.loaded = true;
+    }
+
+    async fn handle_cmd(
+        &mut self,
+        cmd: RiskCmd,
+        exchanges_rx: &watch::Receiver<HashMap<ExchangeType, ExchangeHandle>>
+    ) {
+        match cmd {
+            RiskCmd::Check {
+                request,
+                exposure,
+                reply
+            } => {
+                let result = self.check(&request, exposure, exchanges_rx).await;
+                if result.is_err() {
+                    self.rejected.inc();
+                }
+                reply.send(result).ok();
+            },
+            RiskCmd::KillSwitch {
+                scope,
+                active,
+                reason,
+                reply
+            } => {
+                if active {
+                    self.activate(scope, reason, KillSource::Admin).await;
+                } else {
+                    self.deactivate(scope).await;
+                }
+                reply.send(self.switches.active.clone()).ok();
+            },
+            RiskCmd::Realized {
+                cash_flow
+            } => {
+                self.roll_day();
+                self.daily_pnl += cash_flow;
+
+                let max_daily_loss = config::get().risk.max_daily_loss;
+                let global = self.switches.active.iter().any(|switch| switch.scope == KillScope::Global);
+                if -self.daily_pnl >= max_daily_loss && !global {
+                    let breach = self.daily_loss_breach(None, max_daily_loss);
+                    self.record(breach).await;
+                }
+            },
+            RiskCmd::Status {
+                reply
+            } => {
+                self.roll_day();
+                reply.send(RiskStatus {
+                    kill_switches: self.switches.active.clone(),
+                    daily_pnl: self.daily_pnl,
+                    max_daily_loss: config::get().risk.max_daily_loss,
+                    breaches: self.breaches.iter().cloned().collect(),
+                }).ok();
+            },
+        }
+    }
+
+    /// Проверки по порядку, первое нарушение отклоняет ордер
+    async fn check(
+        &mut self,
+        request: &OrderRequest,
+        exposure: Exposure,
+        exchanges_rx: &watch::Receiver<HashMap<ExchangeType, ExchangeHandle>>
+    ) -> Result<f64, RiskBreach> {
+        let config = config::get();
+        let risk = &config.risk;
+
+        // Откат лишнего объёма уменьшает риск, поэтому остановки торговли его не держат
+        if !request.reduce_only {
+            // Ордера под включённым switch - следствие прошлого нарушения, в журнал не пишутся
+            if let Some(switch) = self.switches.blocking(request.exchange_id) {
+                return Err(Self::breach(request, RiskCheck::KillSwitch, 1.0, 0.0, format!("торговля выключена ({}): {}", switch.scope, switch.reason)));
+            }
+
+            self.roll_day();
+            if -self.daily_pnl >= risk.max_daily_loss {
+                let breach = self.daily_loss_breach(Some(request), risk.max_daily_loss);
+                return self.reject(breach).await;
+            }
+
+            if exposure.open_legs >= risk.max_open_legs {
+                let breach = Self::breach(request, RiskCheck::OpenLegs, exposure.open_legs as f64, risk.max_open_legs as f64, format!("незавершённых ордеров {}, лимит {}", exposure.open_legs, risk.max_open_legs));
+                return self.reject(breach).await;
+            }
+        }
+
+        let books = Self::books(exchanges_rx, &Arc::new(request.symbol.clone())).await;
+        let max_age_ms = risk.max_book_age_ms as i64;
+        let own = books.get(&request.exchange_id);
+        let fresh = own.filter(|book| book.age_ms <= max_age_ms).and_then(|book| book.mid);
+        // Откату устаревший стакан нужен только для оценки цены
+        let estimate = match request.reduce_only {
+            true => request.price.or(fresh).or(own.and_then(|book| book.mid)),
+            false => fresh.map(|mid| request.price.unwrap_or(mid)),
+        };
+        let Some(price) = estimate else {
+            let (value, message) = match books.get(&request.exchange_id) {
+                Some(book) if book.mid.is_some() => (book.age_ms as f64, format!("стакан {} {} не обновлялся {} мс", request.exchange_id, request.symbol, book.age_ms)),
+                _ => (f64::INFINITY, format!("нет стакана {} {}", request.exchange_id, request.symbol)),
+            };
+            let breach = Self::breach(request, RiskCheck::StaleBook, value, max_age_ms as f64, message);
+            return self.reject(breach).await;
+        };
+
+        let notional = request.qty * price;
+        if let Some(breach) = Self::notional_breach(risk, request, notional, exposure) {
+            return self.reject(breach).await;
+        }
+
+        // Рыночный ордер без оценки цены исполняется по стакану, его цена не проверяется
+        let mids: Vec<f64> = books.values().filter(|book| book.age_ms <= max_age_ms).filter_map(|book| book.mid).collect();
+        if let Some(price) = request.price && !mids.is_empty() {
+            let mid = mids.iter().sum::<f64>() / mids.len() as f64;
+            let deviation_pct = (price - mid).abs() / mid * 100.0;
+            if deviation_pct > risk.price_band_pct {
+                let breach = Self::breach(
+                    request,
+                    RiskCheck::PriceBand,
+                    deviation_pct,
+                    risk.price_band_pct,
+                    format!("цена {price} отличается от середины {mid:.6} по {} биржам на {deviation_pct:.2}%", mids.len())
+                );
+                return self.reject(breach).await;
+            }
+        }
+
+        Ok(price)
+    }
+
+    /// Общий лимит ограничивает сам ордер, лимиты символа и биржи - его вместе с незавершёнными ордерами.
+    /// Откат лишнего объёма уменьшает позицию, поэтому лимиты символа и биржи его не держат
+    fn notional_breach(
+        risk: &RiskConfig,
+        request: &OrderRequest,
+        notional: f64,
+        exposure: Exposure
+    ) -> Option<RiskBreach> {
+        if notional > risk.max_order_notional {
+            let message = format!("сумма ордера {notional:.2}, лимит {:.2}", risk.max_order_notional);
+            return Some(Self::breach(request, RiskCheck::OrderNotional, notional, risk.max_order_notional, message));
+        }
+
+        if request.reduce_only {
+            return None;
+        }
+
+        if let Some(&limit) = risk.symbol_max_notional.get(&request.symbol) {
+            let total = exposure.symbol_notional + notional;
+            if total > limit {
+                let message = format!("сумма ордеров {} с этим {total:.2}, лимит {limit:.2}", request.symbol);
+                return Some(Self::breach(request, RiskCheck::SymbolNotional, total, limit, message));
+            }
+        }
+
+        if let Some(&limit) = risk.exchange_max_notional.get(&request.exchange_id) {
+            let total = exposure.exchange_notional + notional;
+            if total > limit {
+                let message = format!("сумма ордеров на {} с этим {total:.2}, лимит {limit:.2}", request.exchange_id);
+                return Some(Self::breach(request, RiskCheck::ExchangeNotional, total, limit, message));
+            }
+        }
+
+        None
+    }
+
+    /// Стаканы символа на всех запущенных биржах
+    async fn books(
+        exchanges_rx: &watch::Receiver<HashMap<ExchangeType, ExchangeHandle>>,
+        symbol: &Arc<Symbol>
+    ) -> HashMap<ExchangeType, BookView> {
+        let stores: Vec<_> = exchanges_rx.borrow().iter().map(|(exchange_id, handle)| (*exchange_id, handle.store.clone())).collect();
+        let now = clock::instant();
+        let mut books = HashMap::new();
+
+        for (exchange_id, store) in stores {
+            let Some(book) = store.book(symbol.clone()).await else { continue };
+            let Some(updated_at) = book.updated_at else { continue };
+
+            let mid = book.snapshot.as_ref().and_then(|snapshot| {
+                let ask = snapshot.a.keys().next()?.as_f64();
+                let bid = snapshot.b.keys().next_back()?.as_f64();
+                Some((ask + bid) / 2.0)
+            });
+            books.insert(exchange_id, BookView {
+                mid,
+                age_ms: now.saturating_duration_since(updated_at).as_millis() as i64,
+            });
+        }
+
+        books
+    }
+
+    fn breach(
+        request: &OrderRequest,
+        check: RiskCheck,
+        value: f64,
+        limit: f64,
+        message: String
+    ) -> RiskBreach {
+        RiskBreach {
+            check,
+            exchange_id: Some(request.exchange_id),
+            symbol: Some(request.symbol.clone()),
+            client_order_id: Some(request.client_order_id.clone()),
+            value,
+            limit,
+            message,
+            killed: None,
+            time: clock::now_ms(),
+        }
+    }
+
+    fn daily_loss_breach(
+        &self,
+        request: Option<&OrderRequest>,
+        max_daily_loss: f64
+    ) -> RiskBreach {
+        let message = format!("убыток за сутки {:.2}, лимит {max_daily_loss:.2}", -self.daily_pnl);
+        let mut breach = match request {
+            Some(request) => Self::breach(request, RiskCheck::DailyLoss, -self.daily_pnl, max_daily_loss, message),
+            None => RiskBreach {
+                check: RiskCheck::DailyLoss,
+                exchange_id: None,
+                symbol: None,
+                client_order_id: None,
+                value: -self.daily_pnl,
+                limit: max_daily_loss,
+                message,
+                killed: None,
+                time: clock::now_ms(),
+            },
+        };
+        breach.killed = Some(KillScope::Global);
+        breach
+    }
+
+    async fn reject<T>(
+        &mut self,
+        mut breach: RiskBreach
+    ) -> Result<T, RiskBreach> {
+        if breach.check.is_fat_finger() && config::get().risk.kill_on_breach {
+            breach.killed = breach.exchange_id.map(KillScope::Exchange);
+        }
+
+        self.record(breach.clone()).await;
+        Err(breach)
+    }
+
+    /// Нарушение в журнал и, если оно выключает торговлю, включение switch
+    async fn record(
+        &mut self,
+        breach: RiskBreach
+    ) {
+        tracing::warn!(
+            "{} -> {} {} {}: {}",
+            RISK_NAME,
+            breach.check.as_str(),
+            breach.exchange_id.map(|exchange_id| exchange_id.to_string()).unwrap_or_default(),
+            breach.client_order_id.clone().unwrap_or_default(),
+            breach.message
+        );
+
+        if let Err(e) = risk_storage::add_breach(&self.pool, &breach).await {
+            tracing::error!("{} -> запись нарушения: {e}", RISK_NAME);
+        }
+
+        if let Some(scope) = breach.killed {
+            self.activate(scope, breach.to_string(), KillSource::Breach).await;
+        }
+
+        self.breaches.push_front(breach);
+        self.breaches.truncate(RECENT_LIMIT);
+    }
+
+    /// Повторное включение обновляет причину
+    async fn activate(
+        &mut self,
+        scope: KillScope,
+        reason: String,
+        source: KillSource
+    ) {
+        let switch = KillSwitch { scope, reason, source, activated_at: clock::now_ms() };
+        tracing::error!("{} -> kill switch {} включён ({}): {}", RISK_NAME, scope, source.as_str(), switch.reason);
+
+        if let Err(e) = risk_storage::set_kill_switch(&self.pool, &switch).await {
+            tracing::error!("{} -> запись kill switch: {e}", RISK_NAME);
+        }
+
+        self.switches.active.retain(|active| active.scope != scope);
+        self.switches.active.push(switch);
+        self.publish();
+    }
+
+    async fn deactivate(
+        &mut self,
+        scope: KillScope
+    ) {
+        let before = self.switches.active.len();
+        self.switches.active.retain(|active| active.scope != scope);
+        if self.switches.active.len() == before {
+            return;
+        }
+
+        info!("{} -> kill switch {} выключен", RISK_NAME, scope);
+        if let Err(e) = risk_storage::remove_kill_switch(&self.pool, scope).await {
+            tracing::error!("{} -> удаление kill switch: {e}", RISK_NAME);
+        }
+        self.publish();
+    }
+
+    fn publish(&self) {
+        self.switches_tx.send_replace(Arc::new(self.switches.clone()));
+    }
+
+    /// Суточный результат начинается заново в полночь UTC
+    fn roll_day(&mut self) {
+        let day = clock::now_ms().div_euclid(DAY_MS);
+        if day != self.day {
+            self.day = day;
+            self.daily_pnl = 0.0;
+        }
+    }
+}
@@ -0,0 +1,178 @@
+use std::{any::Any, future::Future, sync::Arc, time::{Duration, Instant}};
+use async_trait::async_trait;
+use tokio::sync::Mutex;
+use tokio_util::{sync::CancellationToken, task::TaskTracker};
+
+/// Первая пауза перед перезапуском упавшей задачи
+const RESTART_BACKOFF_MIN: Duration = Duration::from_secs(1);
+/// Максимальная пауза. Задача, проработавшая дольше, перезапускается с минимальной паузой
+const RESTART_BACKOFF_MAX: Duration = Duration::from_secs(60);
+
+/// <b>Actor</b> задача с состоянием под надзором `Supervisor`.
+/// После паники `run` вызывается снова с тем же состоянием и теми же каналами
+#[async_trait]
+pub trait Actor: Send + 'static {
+    /// Работает до отмены `shutdown`, после неё доделывает то, что нельзя потерять
+    async fn run(
+        &mut self,
+        shutdown: CancellationToken
+    );
+}
+
+/// <b>Supervisor</b> запускает акторы и задачи, перезапускает их после паники
+/// и останавливает их по сигналу завершения
+#[derive(Clone)]
+pub struct Supervisor {
+    shutdown: CancellationToken,
+    tracker: TaskTracker,
+}
+
+impl Supervisor {
+    pub fn new() -> Self {
+        Self {
+            shutdown: CancellationToken::new(),
+            tracker: TaskTracker::new(),
+        }
+    }
+
+    /// Дочерний supervisor останавливается вместе с родителем или сам по `shutdown`.
+    /// Его задачи учитываются в `wait` родителя
+    pub fn child(&self) -> Self {
+        Self {
+            shutdown: self.shutdown.child_token(),
+            tracker: self.tracker.clone(),
+        }
+    }
+
+    pub fn token(&self) -> CancellationToken {
+        self.shutdown.clone()
+    }
+
+    pub fn shutdown(&self) {
+        self.shutdown.cancel();
+    }
+
+    /// Запускает актор. Он сам решает, как завершиться после отмены токена
+    pub fn spawn_actor<A: Actor>(
+        &self,
+        name: impl Into<String>,
+        actor: A
+    ) {
+        let actor = Arc::new(Mutex::new(actor));
+        let shutdown = self.shutdown.clone();
+
+        self.supervise(name.into(), move || {
+            let actor = actor.clone();
+            let shutdown = shutdown.clone();
+            async move {
+                actor.lock().await.run(shutdown).await;
+            }
+        });
+    }
+
+    /// Запускает задачу без состояния. После паники `factory` создаёт её заново,
+    /// при завершении работы задача просто прерывается
+    pub fn spawn_task<F, Fut>(
+        &self,
+        name: impl Into<String>,
+        factory: F
+    )
+    where
+        F: Fn() -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        let shutdown = self.shutdown.clone();
+
+        self.supervise(name.into(), move || {
+            let task = factory();
+            let shutdown = shutdown.clone();
+            async move {
+                tokio::select! {
+                    _ = shutdown.cancelled() => {},
+                    _ = task => {}
+                }
+            }
+        });
+    }
+
+    /// Задача без перезапуска, которую `wait` дождётся. Например, соединение клиента
+    pub fn track<Fut>(
+        &self,
+        task: Fut
+    )
+    where
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        self.tracker.spawn(task);
+    }
+
+    /// Ждёт завершения всех задач. `false` - не успели за `timeout`
+    pub async fn wait(
+        &self,
+        timeout: Duration
+    ) -> bool {
+        self.tracker.close();
+        tokio::time::timeout(timeout, self.tracker.wait()).await.is_ok()
+    }
+
+    fn supervise<F, Fut>(
+        &self,
+        name: String,
+        mut start: F
+    )
+    where
+        F: FnMut() -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        let shutdown = self.shutdown.clone();
+
+        self.tracker.spawn(async move {
+            let mut backoff = RESTART_BACKOFF_MIN;
+
+            loop {
+                let started = Instant::now();
+                let result = tokio::spawn(start()).await;
+
+                match result {
+                    Ok(()) => {
+                        if !shutdown.is_cancelled() {
+                            tracing::info!("Supervisor -> {} завершился", name);
+                        }
+                        return;
+                    },
+                    Err(e) if e.is_panic() => {
+                        if shutdown.is_cancelled() {
+                            tracing::error!("Supervisor -> {} упал при остановке: {}", name, panic_message(e.into_panic()));
+                            return;
+                        }
+
+                        if started.elapsed() >= RESTART_BACKOFF_MAX {
+                            backoff = RESTART_BACKOFF_MIN;
+                        }
+                        tracing::error!("Supervisor -> {} упал: {}, перезапуск через {:?}", name, panic_message(e.into_panic()), backoff);
+                    },
+                    // Рантайм останавливается
+                    Err(_) => return
+                }
+
+                tokio::select! {
+                    _ = shutdown.cancelled() => return,
+                    _ = tokio::time::sleep(backoff) => {}
+                }
+                backoff = (backoff * 2).min(RESTART_BACKOFF_MAX);
+            }
+        });
+    }
+}
+
+fn panic_message(
+    payload: Box<dyn Any + Send>
+) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "неизвестная паника".into()
+    }
+}
@@ -0,0 +1,148 @@
+use crate::models::execution::{Execution, ExecutionId, ExecutionStatus, JournalEntry};
+
+/// Наибольший id исполнения, новые исполнения нумеруются после него
+pub async fn get_max_execution_id(
+    pool: &Option<sqlx::PgPool>
+) -> Result<ExecutionId, sqlx::Error> {
+    if let Some(pool) = pool {
+        let (id,) = sqlx::query_as::<_, (i64,)>(
+            "SELECT COALESCE(MAX(id), 0) FROM storage.executions"
+        )
+        .fetch_one(pool)
+        .await?;
+
+        return Ok(id);
+    }
+
+    Ok(0)
+}
+
+/// Исполнения, оборванные остановкой процесса. Их ордера уже не отслеживаются
+pub async fn interrupt_running(
+    pool: &Option<sqlx::PgPool>
+) -> Result<u64, sqlx::Error> {
+    if let Some(pool) = pool {
+        let result = sqlx::query(
+            "UPDATE storage.executions SET status = $1 WHERE status = $2"
+        )
+        .bind(ExecutionStatus::Interrupted.as_str())
+        .bind(ExecutionStatus::Running.as_str())
+        .execute(pool)
+        .await?;
+
+        return Ok(result.rows_affected());
+    }
+
+    Ok(0)
+}
+
+pub async fn add_execution(
+    pool: &Option<sqlx::PgPool>,
+    execution: &Execution,
+) -> Result<(), sqlx::Error> {
+    if let Some(pool) = pool {
+        sqlx::query(
+            r#"
+            INSERT INTO storage.executions (id, symbol, long_exchange, short_exchange, status, threshold_pct, spread_pct,
+                qty, buy_price, sell_price, long_qty, short_qty, cash_flow, fees, started_at, finished_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16)
+            "#
+        )
+        .bind(execution.id)
+        .bind(&execution.symbol)
+        .bind(execution.long_exchange)
+        .bind(execution.short_exchange)
+        .bind(execution.status.as_str())
+        .bind(execution.threshold_pct)
+        .bind(execution.spread_pct)
+        .bind(execution.qty)
+        .bind(execution.buy_price)
+        .bind(execution.sell_price)
+        .bind(execution.long_qty)
+        .bind(execution.short_qty)
+        .bind(execution.cash_flow)
+        .bind(execution.fees)
+        .bind(execution.started_at)
+        .bind(execution.finished_at)
+        .execute(pool)
+        .await?;
+    }
+
+    Ok(())
+}
+
+/// Итог исполнения: статус, объёмы ног и суммы
+pub async fn finish_execution(
+    pool: &Option<sqlx::PgPool>,
+    execution: &Execution,
+) -> Result<(), sqlx::Error> {
+    if let Some(pool) = pool {
+        sqlx::query(
+            r#"
+            UPDATE storage.executions
+            SET status = $2, long_qty = $3, short_qty = $4, cash_flow = $5, fees = $6, finished_at = $7
+            WHERE id = $1
+            "#
+        )
+        .bind(execution.id)
+        .bind(execution.status.as_str())
+        .bind(execution.long_qty)
+        .bind(execution.short_qty)
+        .bind(execution.cash_flow)
+        .bind(execution.fees)
+        .bind(execution.finished_at)
+        .execute(pool)
+        .await?;
+    }
+
+    Ok(())
+}
+
+pub async fn add_journal_entry(
+    pool: &Option<sqlx::PgPool>,
+    execution_id: ExecutionId,
+    entry: &JournalEntry,
+) -> Result<(), sqlx::Error> {
+    if let Some(pool) = pool {
+        sqlx::query(
+            r#"
+            INSERT INTO storage.execution_journal (execution_id, decision, exchange, side, client_order_id, qty, price, note, timestamp)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+            "#
+        )
+        .bind(execution_id)
+        .bind(entry.decision.as_str())
+        .bind(entry.exchange_id)
+        .bind(entry.side.map(|side| side.as_str()))
+        .bind(&entry.client_order_id)
+        .bind(entry.qty)
+        .bind(entry.price)
+        .bind(&entry.note)
+        .bind(entry.time)
+        .execute(pool)
+        .await?;
+    }
+
+    Ok(())
+}
+
+/// Результат исполнений с равными ногами, завершённых начиная с `since` (мс Unix)
+pub async fn get_realized_since(
+    pool: &Option<sqlx::PgPool>,
+    since: i64,
+) -> Result<f64, sqlx::Error> {
+    if let Some(pool) = pool {
+        let (realized,) = sqlx::query_as::<_, (f64,)>(
+            "SELECT COALESCE(SUM(cash_flow), 0) FROM storage.executions WHERE finished_at >= $1 AND status IN ($2, $3)"
+        )
+        .bind(since)
+        .bind(ExecutionStatus::Hedged.as_str())
+        .bind(ExecutionStatus::Unwound.as_str())
+        .fetch_one(pool)
+        .await?;
+
+        return Ok(realized);
+    }
+
+    Ok(0.0)
+}
@@ -2,28 +2,33 @@ use std::{collections::{HashMap, VecDeque}, sync::Arc};
 
 use sqlx::QueryBuilder;
 
-use crate::models::{exchange::ExchangeType, line::Line, websocket::Symbol};
+use crate::models::{aggregator::KeyMarketType, exchange::ExchangeType, line::Line, websocket::Symbol};
 
 pub async fn get_spread_history(
     pool: &Option<sqlx::PgPool>, 
     symbol: &str, 
     long_exchange: ExchangeType,
     short_exchange: ExchangeType,
-) -> Result<HashMap<(ExchangeType, ExchangeType, Arc<Symbol>), VecDeque<Line>>, sqlx::Error> {    
+) -> Result<HashMap<KeyMarketType, VecDeque<Line>>, sqlx::Error> {    
     if let Some(pool) = pool {
         
         let lines: Vec<Line> = sqlx::query_as::<_, Line>(
             r#"
             SELECT timestamp, long_exchange, short_exchange, symbol, timeframe, value
-            FROM storage.lines
-            WHERE symbol = $1 
-                AND (
-                    (long_exchange = $2 AND short_exchange = $3)
-                    OR
-                    (long_exchange = $3 AND short_exchange = $2)
-                )
+            FROM (
+                SELECT timestamp, long_exchange, short_exchange, symbol, timeframe, value
+                FROM storage.lines
+                WHERE symbol = $1 
+                    AND timeframe = '1m'
+                    AND (
+                        (long_exchange = $2 AND short_exchange = $3)
+                        OR
+                        (long_exchange = $3 AND short_exchange = $2)
+                    )
+                ORDER BY timestamp DESC
+                LIMIT 200
+            ) recent
             ORDER BY timestamp ASC
-            LIMIT 200
             "#
         )
         .bind(symbol)
@@ -34,7 +39,7 @@ pub async fn get_spread_history(
 
         let mut history_map = HashMap::new();
         for line in lines.clone() {
-            let key = (line.long_exchange, line.short_exchange, Arc::new(symbol.to_string()));
+            let key = KeyMarketType::new(line.long_exchange, line.short_exchange, Arc::new(symbol.to_string()));
             history_map
                 .entry(key)
                 .or_insert_with(VecDeque::new)
@@ -47,9 +52,67 @@ pub async fn get_spread_history(
     Ok(HashMap::new())
 }
 
+/// Возвращает пары с наибольшим количеством `1m` линий начиная с `since`
+pub async fn get_active_pairs(
+    pool: &Option<sqlx::PgPool>,
+    since: i64,
+    limit: i64,
+) -> Result<Vec<(ExchangeType, ExchangeType, Symbol)>, sqlx::Error> {
+    if let Some(pool) = pool {
+        let pairs = sqlx::query_as::<_, (ExchangeType, ExchangeType, Symbol)>(
+            r#"
+            SELECT long_exchange, short_exchange, symbol
+            FROM storage.lines
+            WHERE timeframe = '1m' AND timestamp >= $1
+            GROUP BY long_exchange, short_exchange, symbol
+            ORDER BY count(*) DESC, max(timestamp) DESC
+            LIMIT $2
+            "#
+        )
+        .bind(since)
+        .bind(limit)
+        .fetch_all(pool)
+        .await?;
+
+        return Ok(pairs);
+    }
+
+    Ok(Vec::new())
+}
+
+/// Все `1m` линии символа за `[from, to]` в обоих направлениях, по возрастанию времени
+pub async fn get_lines_range(
+    pool: &Option<sqlx::PgPool>,
+    symbol: &str,
+    from: i64,
+    to: i64,
+) -> Result<Vec<Line>, sqlx::Error> {
+    if let Some(pool) = pool {
+        let lines = sqlx::query_as::<_, Line>(
+            r#"
+            SELECT timestamp, long_exchange, short_exchange, symbol, timeframe, value
+            FROM storage.lines
+            WHERE symbol = $1
+                AND timeframe = '1m'
+                AND timestamp BETWEEN $2 AND $3
+            ORDER BY timestamp ASC
+            "#
+        )
+        .bind(symbol)
+        .bind(from)
+        .bind(to)
+        .fetch_all(pool)
+        .await?;
+
+        return Ok(lines);
+    }
+
+    Ok(Vec::new())
+}
+
 pub async fn add_new_lines(
     pool: &Option<sqlx::PgPool>, 
-    lines: &Vec<(Line, (ExchangeType, ExchangeType, Arc<Symbol>))>,
+    lines: &Vec<(Line, KeyMarketType)>,
 ) -> Result<(), sqlx::Error> {
     if let Some(pool) = pool {
         let mut builder = QueryBuilder::new(
@@ -57,12 +120,12 @@ pub async fn add_new_lines(
         );
 
         for chunk in lines.chunks(1000) {
-            builder.push_values(chunk.iter(), |mut b, (line, (_, _, symbol))| {
+            builder.push_values(chunk.iter(), |mut b, (line, key)| {
                 b.push_bind(line.timestamp)
                     .push_bind(line.long_exchange)
                     .push_bind(line.short_exchange)
-                    .push_bind(symbol.to_string())
-                    .push_bind(line.timeframe.clone())
+                    .push_bind(key.symbol.to_string())
+                    .push_bind(line.timeframe)
                     .push_bind(line.value);
             });
         }
@@ -0,0 +1,155 @@
+use chrono::NaiveDate;
+
+use crate::models::line::TimeFrame;
+
+/// Создаёт партиции `storage.lines_<tf>_p<YYYYMMDD>` на `ahead` интервалов вперёд, начиная с текущего
+pub async fn ensure_partitions(
+    pool: &Option<sqlx::PgPool>,
+    timeframe: TimeFrame,
+    span: i64,
+    now: i64,
+    ahead: i64,
+) -> Result<(), sqlx::Error> {
+    if let Some(pool) = pool {
+        let first = now - now.rem_euclid(span);
+
+        for i in 0..=ahead {
+            let range_from = first + i * span;
+            sqlx::query("SELECT storage.ensure_lines_partition($1, $2, $3)")
+                .bind(timeframe.as_str())
+                .bind(range_from)
+                .bind(range_from + span)
+                .execute(pool)
+                .await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Агрегирует закрытые часы `1m` линий в `1h` (среднее значение за час).
+/// Повторный запуск по тому же часу перезаписывает значение, поэтому операция идемпотентна.
+/// Последний агрегированный час считается заново: `1m` линия, записанная после прошлого запуска, в него попадёт
+/// 
+/// Возвращает количество записанных `1h` строк
+pub async fn downsample_lines(
+    pool: &Option<sqlx::PgPool>,
+    now: i64,
+) -> Result<u64, sqlx::Error> {
+    if let Some(pool) = pool {
+        let hour = TimeFrame::OneHour.seconds();
+        let end = now - now.rem_euclid(hour);
+
+        let last_hour = sqlx::query_scalar::<_, Option<i64>>(
+            "SELECT max(timestamp) FROM storage.lines WHERE timeframe = '1h'"
+        )
+        .fetch_one(pool)
+        .await?;
+
+        let start = match last_hour {
+            Some(last_hour) => last_hour,
+            None => {
+                let first_line = sqlx::query_scalar::<_, Option<i64>>(
+                    "SELECT min(timestamp) FROM storage.lines WHERE timeframe = '1m'"
+                )
+                .fetch_one(pool)
+                .await?;
+
+                match first_line {
+                    Some(first_line) => first_line - first_line.rem_euclid(hour),
+                    None => return Ok(0)
+                }
+            }
+        };
+
+        if start >= end {
+            return Ok(0);
+        }
+
+        let result = sqlx::query(
+            r#"
+            INSERT INTO storage.lines (timestamp, long_exchange, short_exchange, symbol, timeframe, value)
+            SELECT timestamp - timestamp % $3, long_exchange, short_exchange, symbol, '1h', avg(value)
+            FROM storage.lines
+            WHERE timeframe = '1m'
+                AND timestamp >= $1
+                AND timestamp < $2
+            GROUP BY 1, long_exchange, short_exchange, symbol
+            ON CONFLICT (timeframe, timestamp, long_exchange, short_exchange, symbol)
+            DO UPDATE SET value = EXCLUDED.value
+            "#
+        )
+        .bind(start)
+        .bind(end)
+        .bind(hour)
+        .execute(pool)
+        .await?;
+
+        return Ok(result.rows_affected());
+    }
+
+    Ok(0)
+}
+
+/// Удаляет партиции `timeframe`, которые целиком старше `cutoff`, 
+/// и устаревшие строки из default партиции.
+/// 
+/// Возвращает `(удалённые партиции, удалённые строки)`
+pub async fn drop_expired_partitions(
+    pool: &Option<sqlx::PgPool>,
+    timeframe: TimeFrame,
+    span: i64,
+    cutoff: i64,
+) -> Result<(u64, u64), sqlx::Error> {
+    let mut dropped_partitions = 0;
+    let mut removed_rows = 0;
+
+    if let Some(pool) = pool {
+        let parent = format!("lines_{}", timeframe.as_str());
+        let prefix = format!("{}_p", parent);
+
+        let partitions = sqlx::query_scalar::<_, String>(
+            r#"
+            SELECT child.relname::TEXT
+            FROM pg_inherits
+            JOIN pg_class child ON child.oid = pg_inherits.inhrelid
+            JOIN pg_class parent ON parent.oid = pg_inherits.inhparent
+            JOIN pg_namespace ns ON ns.oid = parent.relnamespace
+            WHERE ns.nspname = 'storage' AND parent.relname = $1
+            "#
+        )
+        .bind(&parent)
+        .fetch_all(pool)
+        .await?;
+
+        for partition in partitions {
+            let Some(date) = partition.strip_prefix(&prefix) else { continue };
+            let Ok(date) = NaiveDate::parse_from_str(date, "%Y%m%d") else { continue };
+            let Some(range_from) = date.and_hms_opt(0, 0, 0).map(|d| d.and_utc().timestamp()) else { continue };
+
+            if range_from + span > cutoff {
+                continue;
+            }
+
+            let rows = sqlx::query_scalar::<_, i64>(&format!("SELECT count(*) FROM storage.{partition}"))
+                .fetch_one(pool)
+                .await?;
+
+            sqlx::query(&format!("DROP TABLE storage.{partition}"))
+                .execute(pool)
+                .await?;
+
+            dropped_partitions += 1;
+            removed_rows += rows as u64;
+        }
+
+        let result = sqlx::query(&format!("DELETE FROM storage.{parent}_default WHERE timestamp < $1"))
+            .bind(cutoff)
+            .execute(pool)
+            .await?;
+
+        removed_rows += result.rows_affected();
+    }
+
+    Ok((dropped_partitions, removed_rows))
+}
@@ -1,2 +1,6 @@
 pub mod pool;
-pub mod line_storage;
\ No newline at end of file
+pub mod line_storage;
+pub mod lines_retention;
+pub mod paper_storage;
+pub mod execution_storage;
+pub mod risk_storage;
@@ -0,0 +1,165 @@
+use crate::models::paper::{PaperFill, PaperPosition, PaperStatus, PositionId};
+
+const POSITION_COLUMNS: &str = "id, user_id, symbol, long_exchange, short_exchange, status, notional, qty, long_entry, short_entry, \
+    entry_spread_pct, take_profit_pct, stop_loss_pct, fees, opened_at, closed_at, long_exit, short_exit, exit_spread_pct, realized_pnl, close_reason";
+
+/// Наибольший id позиции, новые позиции нумеруются после него
+pub async fn get_max_position_id(
+    pool: &Option<sqlx::PgPool>
+) -> Result<PositionId, sqlx::Error> {
+    if let Some(pool) = pool {
+        let (id,) = sqlx::query_as::<_, (i64,)>(
+            "SELECT COALESCE(MAX(id), 0) FROM storage.paper_positions"
+        )
+        .fetch_one(pool)
+        .await?;
+
+        return Ok(id);
+    }
+
+    Ok(0)
+}
+
+/// Открытые позиции всех пользователей, продолжают переоцениваться после перезапуска
+pub async fn get_open_positions(
+    pool: &Option<sqlx::PgPool>
+) -> Result<Vec<PaperPosition>, sqlx::Error> {
+    if let Some(pool) = pool {
+        let positions = sqlx::query_as::<_, PaperPosition>(&format!(
+            "SELECT {POSITION_COLUMNS} FROM storage.paper_positions WHERE status = 'open' ORDER BY id"
+        ))
+        .fetch_all(pool)
+        .await?;
+
+        return Ok(positions);
+    }
+
+    Ok(Vec::new())
+}
+
+/// Последние позиции пользователя, новые первыми
+pub async fn get_user_positions(
+    pool: &Option<sqlx::PgPool>,
+    user_id: &str,
+    limit: i64,
+) -> Result<Vec<PaperPosition>, sqlx::Error> {
+    if let Some(pool) = pool {
+        let positions = sqlx::query_as::<_, PaperPosition>(&format!(
+            "SELECT {POSITION_COLUMNS} FROM storage.paper_positions WHERE user_id = $1 ORDER BY opened_at DESC LIMIT $2"
+        ))
+        .bind(user_id)
+        .bind(limit)
+        .fetch_all(pool)
+        .await?;
+
+        return Ok(positions);
+    }
+
+    Ok(Vec::new())
+}
+
+/// Позиция с исполнениями ног одной транзакцией
+pub async fn add_position(
+    pool: &Option<sqlx::PgPool>,
+    position: &PaperPosition,
+    fills: &[PaperFill],
+) -> Result<(), sqlx::Error> {
+    if let Some(pool) = pool {
+        let mut tx = pool.begin().await?;
+
+        sqlx::query(&format!(
+            "INSERT INTO storage.paper_positions ({POSITION_COLUMNS}) \
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, $17, $18, $19, $20, $21)"
+        ))
+        .bind(position.id)
+        .bind(&position.user_id)
+        .bind(&position.symbol)
+        .bind(position.long_exchange)
+        .bind(position.short_exchange)
+        .bind(position.status.as_str())
+        .bind(position.notional)
+        .bind(position.qty)
+        .bind(position.long_entry)
+        .bind(position.short_entry)
+        .bind(position.entry_spread_pct)
+        .bind(position.take_profit_pct)
+        .bind(position.stop_loss_pct)
+        .bind(position.fees)
+        .bind(position.opened_at)
+        .bind(position.closed_at)
+        .bind(position.long_exit)
+        .bind(position.short_exit)
+        .bind(position.exit_spread_pct)
+        .bind(position.realized_pnl)
+        .bind(position.close_reason.map(|reason| reason.as_str()))
+        .execute(&mut tx)
+        .await?;
+
+        add_fills(&mut tx, fills).await?;
+        tx.commit().await?;
+    }
+
+    Ok(())
+}
+
+/// Закрытие позиции с исполнениями выхода одной транзакцией
+pub async fn close_position(
+    pool: &Option<sqlx::PgPool>,
+    position: &PaperPosition,
+    fills: &[PaperFill],
+) -> Result<(), sqlx::Error> {
+    if let Some(pool) = pool {
+        let mut tx = pool.begin().await?;
+
+        sqlx::query(
+            r#"
+            UPDATE storage.paper_positions
+            SET status = $2, fees = $3, closed_at = $4, long_exit = $5, short_exit = $6,
+                exit_spread_pct = $7, realized_pnl = $8, close_reason = $9
+            WHERE id = $1
+            "#
+        )
+        .bind(position.id)
+        .bind(PaperStatus::Closed.as_str())
+        .bind(position.fees)
+        .bind(position.closed_at)
+        .bind(position.long_exit)
+        .bind(position.short_exit)
+        .bind(position.exit_spread_pct)
+        .bind(position.realized_pnl)
+        .bind(position.close_reason.map(|reason| reason.as_str()))
+        .execute(&mut tx)
+        .await?;
+
+        add_fills(&mut tx, fills).await?;
+        tx.commit().await?;
+    }
+
+    Ok(())
+}
+
+async fn add_fills(
+    tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    fills: &[PaperFill],
+) -> Result<(), sqlx::Error> {
+    for fill in fills {
+        sqlx::query(
+            r#"
+            INSERT INTO storage.paper_fills (position_id, exchange, side, qty, price, best_price, fee, timestamp)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+            "#
+        )
+        .bind(fill.position_id)
+        .bind(fill.exchange)
+        .bind(fill.side.as_str())
+        .bind(fill.qty)
+        .bind(fill.price)
+        .bind(fill.best_price)
+        .bind(fill.fee)
+        .bind(fill.timestamp)
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    Ok(())
+}
@@ -1,10 +1,24 @@
+use sqlx::postgres::PgPoolOptions;
 use tracing::{error, info};
 
-pub async fn create_pool() -> Result<sqlx::PgPool, sqlx::Error> {
+use crate::config::DatabaseConfig;
+
+pub async fn create_pool(
+    config: &DatabaseConfig
+) -> Result<sqlx::PgPool, sqlx::Error> {
     dotenv::dotenv().ok();
 
-    let database_url = std::env::var("DATABASE_URL").expect("DATABASE_URL must be set");
-    match sqlx::PgPool::connect(&database_url).await {
+    let database_url = match &config.url {
+        Some(url) => url.clone(),
+        None => std::env::var("DATABASE_URL").expect("DATABASE_URL must be set"),
+    };
+
+    let pool = PgPoolOptions::new()
+        .max_connections(config.max_connections)
+        .connect(&database_url)
+        .await;
+
+    match pool {
         Ok(pool) => {
             info!("Connected to Postgres!");
            Ok(pool)
@@ -0,0 +1,90 @@
+use crate::models::risk::{KillScope, KillSource, KillSwitch, RiskBreach};
+
+pub async fn add_breach(
+    pool: &Option<sqlx::PgPool>,
+    breach: &RiskBreach,
+) -> Result<(), sqlx::Error> {
+    if let Some(pool) = pool {
+        sqlx::query(
+            r#"
+            INSERT INTO storage.risk_breaches (check_name, exchange, symbol, client_order_id, value, limit_value, message, killed, timestamp)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+            "#
+        )
+        .bind(breach.check.as_str())
+        .bind(breach.exchange_id)
+        .bind(&breach.symbol)
+        .bind(&breach.client_order_id)
+        .bind(breach.value)
+        .bind(breach.limit)
+        .bind(&breach.message)
+        .bind(breach.killed.map(|scope| scope.key()))
+        .bind(breach.time)
+        .execute(pool)
+        .await?;
+    }
+
+    Ok(())
+}
+
+/// Kill switch, включённые до перезапуска. Строки с неизвестной областью пропускаются
+pub async fn get_kill_switches(
+    pool: &Option<sqlx::PgPool>
+) -> Result<Vec<KillSwitch>, sqlx::Error> {
+    if let Some(pool) = pool {
+        let rows = sqlx::query_as::<_, (String, String, String, i64)>(
+            "SELECT scope, reason, source, activated_at FROM storage.risk_kill_switches ORDER BY activated_at"
+        )
+        .fetch_all(pool)
+        .await?;
+
+        return Ok(rows
+            .into_iter()
+            .filter_map(|(scope, reason, source, activated_at)| Some(KillSwitch {
+                scope: KillScope::parse(&scope)?,
+                reason,
+                source: KillSource::parse(&source).unwrap_or(KillSource::Admin),
+                activated_at,
+            }))
+            .collect());
+    }
+
+    Ok(Vec::new())
+}
+
+pub async fn set_kill_switch(
+    pool: &Option<sqlx::PgPool>,
+    switch: &KillSwitch,
+) -> Result<(), sqlx::Error> {
+    if let Some(pool) = pool {
+        sqlx::query(
+            r#"
+            INSERT INTO storage.risk_kill_switches (scope, reason, source, activated_at)
+            VALUES ($1, $2, $3, $4)
+            ON CONFLICT (scope) DO UPDATE SET reason = EXCLUDED.reason, source = EXCLUDED.source, activated_at = EXCLUDED.activated_at
+            "#
+        )
+        .bind(switch.scope.key())
+        .bind(&switch.reason)
+        .bind(switch.source.as_str())
+        .bind(switch.activated_at)
+        .execute(pool)
+        .await?;
+    }
+
+    Ok(())
+}
+
+pub async fn remove_kill_switch(
+    pool: &Option<sqlx::PgPool>,
+    scope: KillScope,
+) -> Result<(), sqlx::Error> {
+    if let Some(pool) = pool {
+        sqlx::query("DELETE FROM storage.risk_kill_switches WHERE scope = $1")
+            .bind(scope.key())
+            .execute(pool)
+            .await?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,128 @@
+use std::time::Duration;
+use serde_json::json;
+
+use crate::{config::{self, Config}, models::exchange::ExchangeType, services::metrics::Metric, tests::{Pipeline, lock_config}};
+
+fn assert_close(
+    value: &serde_json::Value,
+    expected: f64
+) {
+    let value = value.as_f64().unwrap();
+    assert!((value - expected).abs() < 1e-9, "{value} != {expected}");
+}
+
+/// Объёмы приходят с обновлением стакана, линия - в начале минуты, история - новому подписчику.
+/// Время tokio на паузе, поэтому минута `db_writer` проходит сразу, как только конвейер простаивает
+#[tokio::test(start_paused = true)]
+async fn chart_receives_volumes_update_line_and_history() {
+    let _config = lock_config(Config::default()).await;
+    let pipeline = Pipeline::start();
+    pipeline.register(ExchangeType::Bybit, "btcusdt").await;
+    pipeline.register(ExchangeType::Gate, "btcusdt").await;
+
+    let subscribe = json!({
+        "action": "subscribe",
+        "channel": "chart",
+        "longExchange": "bybit",
+        "shortExchange": "gate.io",
+        "ticker": "btc"
+    });
+
+    // Зарегистрирован `DataAggregator`, здесь только читается
+    let spreads = Metric::counter("data_aggregator_spreads_total", "", &[]);
+    let spreads_before = spreads.get();
+
+    let mut client = pipeline.connect().await;
+    client.send(subscribe.clone()).await;
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    pipeline.book(ExchangeType::Bybit, "btcusdt", "100", "100.1", 1000.0);
+    pipeline.book(ExchangeType::Gate, "btcusdt", "100.5", "100.6", 2000.0);
+
+    let volume = client.expect("chart", "Volume24h").await;
+    assert_eq!(volume["result"]["symbol"], "btcusdt");
+    assert_close(&volume["result"]["data"]["volume24h"]["long"]["value"], 1000.0);
+    assert_close(&volume["result"]["data"]["volume24h"]["short"]["value"], 2000.0);
+
+    // Покупка на bybit по 100.1 и продажа на gate.io по 100.5, обратно - покупка по 100.6 и продажа по 100
+    let long_spread = (100.5 - 100.1) / ((100.5 + 100.1) / 2.0) * 100.0;
+    let short_spread = (100.0 - 100.6) / ((100.0 + 100.6) / 2.0) * 100.0;
+
+    let update = client.expect("chart", "UpdateLine").await;
+    let line = &update["result"]["data"]["update_line"];
+    assert_close(&line["long"]["value"], long_spread);
+    assert_close(&line["short"]["value"], short_spread);
+    assert_eq!(line["long"]["time"].as_i64().unwrap() % 60, 0);
+    assert!(spreads.get() > spreads_before);
+
+    // История пары теперь в кеше и приходит сразу после подписки
+    let mut late_client = pipeline.connect().await;
+    late_client.send(subscribe).await;
+
+    let history = late_client.expect("chart", "LinesHistory").await;
+    let lines = &history["result"]["data"]["lines_history"];
+    assert_eq!(lines["long"].as_array().unwrap().len(), 1);
+    assert_close(&lines["long"][0]["value"], long_spread);
+    assert_close(&lines["short"][0]["value"], short_spread);
+}
+
+/// Стакан без обновлений дольше `pipeline.book_stale_secs` не даёт спреда
+#[tokio::test(start_paused = true)]
+async fn stale_book_is_left_out_of_spreads() {
+    let mut config = Config::default();
+    config.pipeline.book_stale_secs = 1;
+    // Стаканы приходят с разницей больше секунды, спред не должен отсекаться как `skewed`
+    config.pipeline.spread_max_skew_ms = 10_000;
+    let _config = lock_config(config).await;
+
+    let pipeline = Pipeline::start();
+    pipeline.register(ExchangeType::Bybit, "btcusdt").await;
+    pipeline.register(ExchangeType::Gate, "btcusdt").await;
+
+    let mut client = pipeline.connect().await;
+    client.send(json!({
+        "action": "subscribe",
+        "channel": "chart",
+        "longExchange": "bybit",
+        "shortExchange": "gate.io",
+        "ticker": "btc"
+    })).await;
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    pipeline.book(ExchangeType::Gate, "btcusdt", "100.5", "100.6", 2000.0);
+    tokio::time::sleep(Duration::from_millis(1100)).await;
+    pipeline.book(ExchangeType::Bybit, "btcusdt", "100", "100.1", 1000.0);
+
+    let frame = tokio::time::timeout(Duration::from_secs(90), client.expect("chart", "UpdateLine")).await;
+    assert!(frame.is_err(), "спред посчитан по устаревшему стакану gate.io");
+}
+
+/// Спред с разъехавшимся временем ног не попадает в историю и не копится до следующей минуты
+#[tokio::test(start_paused = true)]
+async fn skewed_spread_is_dropped_at_minute() {
+    let _config = lock_config(Config::default()).await;
+    let pipeline = Pipeline::start();
+    pipeline.register(ExchangeType::Bybit, "btcusdt").await;
+    pipeline.register(ExchangeType::Gate, "btcusdt").await;
+
+    let mut client = pipeline.connect().await;
+    client.send(json!({
+        "action": "subscribe",
+        "channel": "chart",
+        "longExchange": "bybit",
+        "shortExchange": "gate.io",
+        "ticker": "btc"
+    })).await;
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    pipeline.book(ExchangeType::Gate, "btcusdt", "100.5", "100.6", 2000.0);
+    // Время получения ног расходится больше `pipeline.spread_max_skew_ms`
+    tokio::time::sleep(Duration::from_millis(config::get().pipeline.spread_max_skew_ms as u64 + 100)).await;
+    pipeline.book(ExchangeType::Bybit, "btcusdt", "100", "100.1", 1000.0);
+
+    let frame = tokio::time::timeout(Duration::from_secs(90), client.expect("chart", "UpdateLine")).await;
+    assert!(frame.is_err(), "спред с разъехавшимся временем ног записан в историю");
+
+    let pending = Metric::gauge("data_aggregator_pending_lines", "", &[]);
+    assert_eq!(pending.get(), 0);
+}
@@ -0,0 +1,69 @@
+use std::collections::HashMap;
+use serde_json::json;
+
+use crate::{config::Config, models::exchange::ExchangeType, services::exchange::mock_exchange::{MockExchange, MockStep}, tests::{Pipeline, lock_config}};
+
+/// Уровни стороны стакана клиента: цена и накопленный объём
+fn levels(
+    side: &serde_json::Value
+) -> Vec<(f64, f64)> {
+    side.as_array().unwrap().iter().map(|level| (level["price"].as_f64().unwrap(), level["volume"].as_f64().unwrap())).collect()
+}
+
+/// `ExchangeSetup` bybit и gate.io подключаются к `MockExchange`, подписываются по спросу клиента,
+/// а клиент получает стаканы обеих бирж и объёмы из тикеров
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn mock_exchanges_reach_client_websocket() {
+    let bybit_symbols = vec!["BTCUSDT".to_string()];
+    let gate_symbols = vec!["BTC_USDT".to_string()];
+    let bybit = MockExchange::start(ExchangeType::Bybit, &bybit_symbols, MockStep::default_script(&bybit_symbols)).await.unwrap();
+    let gate_script = vec![
+        MockStep::WaitFor { contains: "BTC_USDT".into() },
+        MockStep::Snapshot { symbol: "BTC_USDT".into(), asks: vec![[100.3, 1.0], [100.4, 2.0]], bids: vec![[100.0, 1.0], [99.7, 2.0]] },
+        MockStep::Ticker { symbol: "BTC_USDT".into(), last_price: 100.0, volume: 500_000.0 },
+    ];
+    let gate = MockExchange::start(ExchangeType::Gate, &gate_symbols, gate_script).await.unwrap();
+
+    let config = Config {
+        exchanges: HashMap::from([
+            (ExchangeType::Bybit, bybit.exchange_config()),
+            (ExchangeType::Gate, gate.exchange_config()),
+        ]),
+        ..Config::default()
+    };
+    let _config = lock_config(config).await;
+
+    let pipeline = Pipeline::start();
+    pipeline.start_exchanges();
+
+    let mut client = pipeline.connect().await;
+    for channel in ["order_book", "chart"] {
+        client.send(json!({
+            "action": "subscribe",
+            "channel": channel,
+            "longExchange": "bybit",
+            "shortExchange": "gate.io",
+            "ticker": "btc"
+        })).await;
+    }
+
+    let book = client.expect("order_book", "OrderBook").await;
+    assert_eq!(book["result"]["symbol"], "btcusdt");
+    let data = &book["result"]["data"]["order_book"];
+    // long - bybit по сценарию по умолчанию, short - gate.io
+    assert_eq!(levels(&data["long"]["asks"]), vec![(100.5, 8.0), (100.2, 3.0), (100.1, 1.0)]);
+    assert_eq!(levels(&data["long"]["bids"]), vec![(99.9, 1.0), (99.8, 3.0), (99.5, 8.0)]);
+    assert_eq!(levels(&data["short"]["asks"]), vec![(100.4, 3.0), (100.3, 1.0)]);
+    assert_eq!(levels(&data["short"]["bids"]), vec![(100.0, 1.0), (99.7, 3.0)]);
+    assert_eq!(data["long"]["last_price"], 100.0);
+    assert_eq!(data["skewed"], false);
+
+    let volume = client.expect("chart", "Volume24h").await;
+    assert_eq!(volume["result"]["data"]["volume24h"]["long"]["value"], 1_000_000.0);
+    assert_eq!(volume["result"]["data"]["volume24h"]["short"]["value"], 500_000.0);
+
+    assert!(bybit.script_done());
+    assert!(gate.script_done());
+    assert!(bybit.received().iter().any(|msg| msg.contains("BTCUSDT")));
+    assert!(gate.received().iter().any(|msg| msg.contains("BTC_USDT")));
+}
@@ -0,0 +1,179 @@
+use std::{sync::Arc, time::Duration};
+
+use crate::{models::{aggregator::{ExecutableSpread, KeyMarketType}, exchange::ExchangeType, execution::{Decision, Execution, ExecutionStatus}, order::{OrderRequest, OrderSide, OrderStatus, OrderType, TimeInForce, new_client_order_id}, risk::KillScope}, services::{clock, exchange::{mock_trading::MockTradingOptions, trading_adapter::TradingError}}, tests::{Pipeline, TradingPipeline, mock_trading_options}};
+
+/// Лимитные цены плана: покупка на bybit, продажа на gate.io
+const BUY_LIMIT: f64 = 100.1;
+const SELL_LIMIT: f64 = 99.9;
+
+fn spread() -> ExecutableSpread {
+    ExecutableSpread {
+        key: KeyMarketType::new(ExchangeType::Bybit, ExchangeType::Gate, Arc::new("btcusdt".to_string())),
+        qty: 1.0,
+        buy_price: BUY_LIMIT,
+        sell_price: SELL_LIMIT,
+        buy_limit: BUY_LIMIT,
+        sell_limit: SELL_LIMIT,
+        spread_pct: 0.5,
+        skewed: false,
+        inventory: None,
+        time: clock::now_ms(),
+    }
+}
+
+/// Первое исполнение после завершения
+async fn finished(
+    pipeline: &Pipeline
+) -> Execution {
+    tokio::time::timeout(Duration::from_secs(30), async {
+        loop {
+            let executions = pipeline.executor.executions().await;
+            if let Some(execution) = executions.into_iter().find(|execution| execution.status != ExecutionStatus::Running) {
+                return execution;
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+    }).await.expect("исполнение не завершилось")
+}
+
+fn assert_close(
+    value: f64,
+    expected: f64
+) {
+    assert!((value - expected).abs() < 1e-9, "{value} != {expected}");
+}
+
+/// Bybit берёт комиссию покупки в BTC: в денежный поток она идёт по цене исполнения, а не как сумма в USDT
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn hedged_execution_converts_base_fee_to_quote() {
+    let exchanges = TradingPipeline::start(mock_trading_options(1.0, None), mock_trading_options(1.0, None), |_| {}).await;
+    exchanges.pipeline.spread(spread());
+
+    let execution = finished(&exchanges.pipeline).await;
+    assert_eq!(execution.status, ExecutionStatus::Hedged, "{:#?}", execution.journal);
+    assert_close(execution.long_qty, 1.0);
+    assert_close(execution.short_qty, 1.0);
+
+    // Покупка 1 BTC по 100.1 с комиссией 0.001 BTC. Ответ Gate на создание уже финальный,
+    // поэтому исполнение из приватного потока с комиссией 0.0999 USDT может прийти после итога ноги
+    let bybit_fee = 0.001 * BUY_LIMIT;
+    let gate_fee = 0.001 * SELL_LIMIT;
+    assert!(
+        (execution.fees - bybit_fee).abs() < 1e-9 || (execution.fees - bybit_fee - gate_fee).abs() < 1e-9,
+        "комиссии {}",
+        execution.fees
+    );
+    assert_close(execution.cash_flow, SELL_LIMIT - BUY_LIMIT - execution.fees);
+}
+
+/// Нога, которую не удалось догнать, откатывается рыночным ордером `reduce_only`
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn unfilled_leg_is_unwound_reduce_only() {
+    let exchanges = TradingPipeline::start(mock_trading_options(1.0, None), mock_trading_options(0.0, None), |_| {}).await;
+    exchanges.pipeline.spread(spread());
+
+    let execution = finished(&exchanges.pipeline).await;
+    assert_eq!(execution.status, ExecutionStatus::Unwound, "{:#?}", execution.journal);
+    assert_close(execution.imbalance(), 0.0);
+    assert_eq!(execution.journal.iter().filter(|entry| entry.decision == Decision::Chase).count(), 2);
+
+    let unwind = execution.journal.iter().find(|entry| entry.decision == Decision::Unwind).unwrap();
+    let client_order_id = unwind.client_order_id.clone().unwrap();
+    let order = exchanges.pipeline.orders.order(client_order_id).await.unwrap();
+    assert!(order.request.reduce_only);
+    assert_eq!(order.request.exchange_id, ExchangeType::Bybit);
+    assert_eq!(order.request.side, OrderSide::Sell);
+    assert_eq!(order.status, OrderStatus::Filled);
+}
+
+/// Отмена ноги после `leg_timeout_ms` не прошла: исполнение, о котором не сообщил поток, берётся сверкой с биржей
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn fill_after_failed_cancel_is_synced() {
+    let bybit = MockTradingOptions { reject_cancels: true, ..mock_trading_options(1.0, None) };
+    let exchanges = TradingPipeline::start(bybit, mock_trading_options(1.0, None), |config| {
+        // Без приватного потока ордер bybit остаётся `New`, пока его не сверят через REST
+        if let Some(bybit) = config.exchanges.get_mut(&ExchangeType::Bybit) {
+            bybit.trading.ws_url = Some("ws://127.0.0.1:1".into());
+        }
+        config.execution.leg_timeout_ms = 300;
+    }).await;
+    exchanges.pipeline.spread(spread());
+
+    let execution = finished(&exchanges.pipeline).await;
+    assert_eq!(execution.status, ExecutionStatus::Hedged, "{:#?}", execution.journal);
+    assert_close(execution.long_qty, 1.0);
+    assert!(execution.journal.iter().all(|entry| entry.decision != Decision::Chase));
+    assert_eq!(exchanges.bybit.trading().unwrap().orders(), 1);
+}
+
+/// Под общим kill switch ордер отклоняется, а `reduce_only` доходит до биржи
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn reduce_only_order_passes_kill_switch() {
+    let exchanges = TradingPipeline::start(mock_trading_options(1.0, None), mock_trading_options(1.0, None), |_| {}).await;
+    let pipeline = &exchanges.pipeline;
+    pipeline.risk.set_kill_switch(KillScope::Global, true, "тест".into()).await;
+
+    let request = |reduce_only| OrderRequest {
+        exchange_id: ExchangeType::Bybit,
+        client_order_id: new_client_order_id(),
+        symbol: "btcusdt".into(),
+        side: OrderSide::Sell,
+        order_type: OrderType::Limit,
+        qty: 1.0,
+        price: Some(SELL_LIMIT),
+        time_in_force: TimeInForce::Ioc,
+        reduce_only,
+    };
+
+    let result = pipeline.orders.place(request(false)).await;
+    assert!(matches!(result, Err(TradingError::Risk(_))), "{result:?}");
+
+    let state = pipeline.orders.place(request(true)).await.unwrap();
+    assert!(state.order_id.is_some());
+    assert_eq!(exchanges.bybit.trading().unwrap().orders(), 1);
+}
+
+/// Остановка во время ноги: нога отменяется без ожидания таймаута, догона нет, исполненная нога
+/// откатывается `reduce_only` через ещё работающий `OrderGateway`, и позиция остаётся нулевой
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn shutdown_during_leg_unwinds_to_flat() {
+    // Рыночная покупка на Gate задаётся суммой в USDT по оценке плана, с той же ценой исполнения она берёт ровно 1 BTC
+    let gate = MockTradingOptions { fill_price: SELL_LIMIT, ..mock_trading_options(1.0, None) };
+    let exchanges = TradingPipeline::start(mock_trading_options(0.0, None), gate, |config| {
+        // Без приватного потока нога bybit ждёт итога до таймаута
+        if let Some(bybit) = config.exchanges.get_mut(&ExchangeType::Bybit) {
+            bybit.trading.ws_url = Some("ws://127.0.0.1:1".into());
+        }
+        config.execution.leg_timeout_ms = 60_000;
+    }).await;
+    let pipeline = &exchanges.pipeline;
+    pipeline.spread(spread());
+
+    tokio::time::timeout(Duration::from_secs(10), async {
+        while exchanges.bybit.trading().unwrap().orders() + exchanges.gate.trading().unwrap().orders() < 2 {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+    }).await.expect("ноги не дошли до бирж");
+
+    pipeline.supervisor.shutdown();
+    assert!(pipeline.supervisor.wait(Duration::from_secs(10)).await, "исполнитель не остановился");
+
+    let orders = pipeline.orders.orders().await;
+    let unwind = orders.iter().find(|order| order.request.reduce_only).expect("нет отката");
+    assert_eq!(unwind.request.exchange_id, ExchangeType::Gate);
+    assert_eq!(unwind.request.side, OrderSide::Buy);
+    assert_eq!(unwind.status, OrderStatus::Filled);
+    assert_eq!(orders.len(), 3, "без догона: две ноги и откат");
+
+    for exchange_id in [ExchangeType::Bybit, ExchangeType::Gate] {
+        let position: f64 = orders
+            .iter()
+            .filter(|order| order.request.exchange_id == exchange_id)
+            .map(|order| match order.request.side {
+                OrderSide::Buy => order.filled_qty,
+                OrderSide::Sell => -order.filled_qty,
+            })
+            .sum();
+        assert_close(position, 0.0);
+    }
+}
@@ -0,0 +1,88 @@
+use std::{collections::BTreeMap, sync::Arc, time::Duration};
+use tokio::sync::broadcast;
+
+use crate::{models::{aggregator::ExecutableSpreads, exchange::ExchangeType, inventory::InventoryFit, order::{OrderRequest, OrderSide, OrderStatus, OrderType, TimeInForce, new_client_order_id}}, services::clock, tests::{Pipeline, TradingPipeline, mock_trading_options}};
+
+/// Свободный остаток, как его видит `InventoryTracker`
+async fn free(
+    pipeline: &Pipeline,
+    exchange_id: ExchangeType,
+    asset: &str,
+    expected: impl Fn(f64) -> bool
+) -> f64 {
+    tokio::time::timeout(Duration::from_secs(5), async {
+        loop {
+            let free = pipeline.inventory.snapshot().free(exchange_id, asset, clock::now_ms());
+            if let Some(free) = free.filter(|free| expected(*free)) {
+                return free;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+    }).await.unwrap_or_else(|_| panic!("остаток {asset} на {exchange_id} не дошёл до ожидаемого"))
+}
+
+/// Отметка спреда bybit -> gate.io по свежим стаканам: покупка по 100.1, продажа по 100.5
+async fn fit(
+    pipeline: &Pipeline,
+    spreads: &mut broadcast::Receiver<Arc<ExecutableSpreads>>
+) -> InventoryFit {
+    pipeline.book(ExchangeType::Bybit, "btcusdt", "100", "100.1", 1000.0);
+    pipeline.book(ExchangeType::Gate, "btcusdt", "100.5", "100.6", 2000.0);
+
+    tokio::time::timeout(Duration::from_secs(5), async {
+        loop {
+            let spreads = spreads.recv().await.unwrap();
+            let spread = spreads.spreads.iter().find(|spread| {
+                spread.key.long_exchange == ExchangeType::Bybit && spread.key.short_exchange == ExchangeType::Gate
+            });
+            // Стакан gate.io из второго обновления ещё мог не дойти
+            if let Some(spread) = spread.filter(|spread| spread.buy_limit == 100.1 && spread.sell_limit == 100.5) {
+                return spread.inventory.expect("остатки обеих бирж известны");
+            }
+        }
+    }).await.expect("спред bybit -> gate.io не пришёл")
+}
+
+/// Остатки приходят опросом, исполнение меняет их через приватный поток раньше следующего опроса,
+/// и спред перестаёт быть исполнимым
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn stream_balances_update_spread_fit() {
+    let bybit = mock_trading_options(1.0, Some(BTreeMap::from([("USDT".to_string(), 1000.0)])));
+    let gate = mock_trading_options(1.0, Some(BTreeMap::from([("BTC".to_string(), 2.0)])));
+    let exchanges = TradingPipeline::start(bybit, gate, |config| {
+        // Спреды только отмечаются, исполнитель их не берёт
+        config.execution.pairs[0].threshold_pct = 100.0;
+    }).await;
+    let pipeline = &exchanges.pipeline;
+
+    // Опрос при старте
+    free(pipeline, ExchangeType::Bybit, "USDT", |free| free == 1000.0).await;
+    free(pipeline, ExchangeType::Gate, "BTC", |free| free == 2.0).await;
+
+    let mut spreads = pipeline.spreads();
+    let before = fit(pipeline, &mut spreads).await;
+    assert!(before.executable);
+    assert_eq!(before.max_qty, 2.0);
+
+    let state = pipeline.orders.place(OrderRequest {
+        exchange_id: ExchangeType::Gate,
+        client_order_id: new_client_order_id(),
+        symbol: "btcusdt".into(),
+        side: OrderSide::Sell,
+        order_type: OrderType::Limit,
+        qty: 1.5,
+        price: Some(99.9),
+        time_in_force: TimeInForce::Ioc,
+        reduce_only: false,
+    }).await.unwrap();
+    assert_eq!(state.status, OrderStatus::Filled);
+
+    // Следующий опрос через 15 с, за 5 с остатки может обновить только поток
+    free(pipeline, ExchangeType::Gate, "BTC", |free| (free - 0.5).abs() < 1e-9).await;
+    free(pipeline, ExchangeType::Gate, "USDT", |free| (free - 1.5 * 99.9 * 0.999).abs() < 1e-9).await;
+    assert_eq!(exchanges.gate.trading().unwrap().orders(), 1);
+
+    let after = fit(pipeline, &mut spreads).await;
+    assert!(!after.executable);
+    assert!((after.max_qty - 0.5).abs() < 1e-9, "{}", after.max_qty);
+}
@@ -0,0 +1,370 @@
+//! Прогоны конвейера в процессе: акторы собираются как в `main`, без базы,
+//! клиент подключается к `handle_connection` через `tokio::io::duplex`
+use std::{collections::{BTreeMap, HashMap}, str::FromStr, sync::Arc, time::Duration};
+use futures_util::{SinkExt, StreamExt};
+use rust_decimal::Decimal;
+use serde_json::Value;
+use tokio::{io::DuplexStream, sync::{Mutex, MutexGuard, broadcast, mpsc}};
+use tokio_tungstenite::{WebSocketStream, tungstenite::Message};
+
+use crate::{config::{self, Config, ExchangeConfig, ExecutionPair, TradingConfig}, models::{aggregator::{ExecutableSpread, ExecutableSpreads}, exchange::ExchangeType, exchange_aggregator::BookData, orderbook::{EventTime, Snapshot}}, services::{cache_aggregator::{CacheAggregator, CacheAggregatorCmd}, clock, data_access_layer::{DataAccessLayer, DataAccessLayerCmd}, data_aggregator::{DataAggregator, DataAggregatorCmd}, data_mapping::DataMapping, exchange::{exchange_aggregator::BookUpdatesQueue, exchange_channel_store::{ExchangeChannelStore, ExchangeChannelStoreCmd}, exchange_control::ExchangeControl, exchange_health::{HealthHandle, HealthMonitor}, mock_exchange::{MockExchange, MockStep}, mock_trading::MockTradingOptions}, inventory_tracker::{InventoryHandle, InventoryTracker}, manager_transmitter::ManagerTransmitter, order_gateway::{OrderGateway, OrderGatewayHandle}, paper_trading::{PaperHandle, PaperTrading}, risk_manager::{RiskHandle, RiskManager}, supervisor::Supervisor, arbitrage_executor::{ArbitrageExecutor, ExecutorHandle}}, transport::{client_aggregator::{ClientAggregator, ClientAggregatorCmd}, ws}};
+
+mod chart;
+mod exchanges;
+mod executor;
+mod inventory;
+mod risk;
+
+/// Сколько клиент ждёт кадр. С `start_paused` это время tokio, а не настоящее
+const FRAME_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// Конфиг процесса общий, поэтому тесты конвейера идут по одному
+static CONFIG_LOCK: Mutex<()> = Mutex::const_new(());
+
+/// Ставит `config` на время теста, пока жив guard
+pub async fn lock_config(
+    config: Config
+) -> MutexGuard<'static, ()> {
+    let guard = CONFIG_LOCK.lock().await;
+    config::set(config);
+    guard
+}
+
+/// Ключ API mock биржи в тестах
+pub const MOCK_API_KEY: &str = "test-key";
+pub const MOCK_API_SECRET: &str = "test-secret";
+
+/// Торговля mock биржи с ключом `MOCK_API_KEY`. `fill_ratio` - доля IOC и рыночных ордеров, исполняемая сразу,
+/// рыночные исполняются по 100
+pub fn mock_trading_options(
+    fill_ratio: f64,
+    balances: Option<BTreeMap<String, f64>>
+) -> MockTradingOptions {
+    MockTradingOptions {
+        api_key: MOCK_API_KEY.into(),
+        api_secret: MOCK_API_SECRET.into(),
+        fill_ratio,
+        fill_price: 100.0,
+        balances,
+        reject_cancels: false,
+    }
+}
+
+/// Запущенная mock биржа с торговлей и конфиг биржи, указывающий на неё. `script` - рыночные данные
+pub async fn mock_trading(
+    exchange_id: ExchangeType,
+    symbols: &[&str],
+    script: Vec<MockStep>,
+    options: MockTradingOptions
+) -> (MockExchange, ExchangeConfig) {
+    let symbols: Vec<String> = symbols.iter().map(|symbol| symbol.to_string()).collect();
+    let mut mock = MockExchange::start(exchange_id, &symbols, script).await.unwrap();
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    mock.enable_trading(options, listener).await.unwrap();
+
+    let config = ExchangeConfig {
+        trading: TradingConfig {
+            enabled: true,
+            api_key: Some(MOCK_API_KEY.into()),
+            api_secret: Some(MOCK_API_SECRET.into()),
+            rest_url: Some(mock.rest_url()),
+            ws_url: mock.trading().map(|trading| trading.ws_url().to_string()),
+            ..Default::default()
+        },
+        ..mock.exchange_config()
+    };
+    (mock, config)
+}
+
+/// <b>TradingPipeline</b> конвейер с bybit и gate.io на mock биржах: рыночные данные по сценарию
+/// по умолчанию и торговля. Исполнение пары bybit -> gate.io `btcusdt` включено с порогом 0.1%
+pub struct TradingPipeline {
+    pub pipeline: Pipeline,
+    pub bybit: MockExchange,
+    pub gate: MockExchange,
+    _config: MutexGuard<'static, ()>,
+}
+
+impl TradingPipeline {
+    /// `tune` меняет конфиг до старта. Возвращается, когда обе биржи прислали стаканы
+    pub async fn start(
+        bybit: MockTradingOptions,
+        gate: MockTradingOptions,
+        tune: impl FnOnce(&mut Config)
+    ) -> Self {
+        let bybit_symbols = ["BTCUSDT".to_string()];
+        let gate_symbols = ["BTC_USDT".to_string()];
+        let (bybit, bybit_config) = mock_trading(ExchangeType::Bybit, &["BTCUSDT"], MockStep::default_script(&bybit_symbols), bybit).await;
+        let (gate, gate_config) = mock_trading(ExchangeType::Gate, &["BTC_USDT"], MockStep::default_script(&gate_symbols), gate).await;
+
+        let mut config = Config {
+            exchanges: HashMap::from([(ExchangeType::Bybit, bybit_config), (ExchangeType::Gate, gate_config)]),
+            ..Config::default()
+        };
+        config.execution.enabled = true;
+        config.execution.pairs = vec![ExecutionPair {
+            symbol: "btcusdt".into(),
+            long_exchange: ExchangeType::Bybit,
+            short_exchange: ExchangeType::Gate,
+            threshold_pct: 0.1,
+        }];
+        // Сценарий присылает стакан один раз
+        config.risk.max_book_age_ms = 600_000;
+        tune(&mut config);
+        let guard = lock_config(config).await;
+
+        // `OrderGateway` берёт биржи из конфига при создании
+        let pipeline = Pipeline::start();
+        pipeline.start_exchanges();
+
+        // Стаканы пары запрашивает `ArbitrageExecutor`, сценарий отдаёт их после подписки
+        tokio::time::timeout(Duration::from_secs(20), async {
+            while !(bybit.script_done() && gate.script_done()) {
+                tokio::time::sleep(Duration::from_millis(50)).await;
+            }
+        }).await.expect("mock биржи не получили подписку на стаканы");
+        tokio::time::sleep(Duration::from_millis(500)).await;
+
+        Self { pipeline, bybit, gate, _config: guard }
+    }
+}
+
+/// <b>Pipeline</b> акторы рыночных данных, клиентов и ордеров из `main`. Останавливается при drop
+pub struct Pipeline {
+    pub supervisor: Supervisor,
+    /// `RiskManager` и `OrderGateway`, как в `main` останавливаются после остальных
+    pub trading: Supervisor,
+    pub register_symbol_tx: mpsc::Sender<DataAggregatorCmd>,
+    pub book_updates: Arc<BookUpdatesQueue>,
+    pub health: HealthHandle,
+    pub orders: OrderGatewayHandle,
+    pub risk: RiskHandle,
+    pub executor: ExecutorHandle,
+    pub inventory: InventoryHandle,
+    exchange_channel_store_tx: mpsc::Sender<ExchangeChannelStoreCmd>,
+    client_aggregator_tx: mpsc::Sender<ClientAggregatorCmd>,
+    paper: PaperHandle,
+    executable_spreads: broadcast::Sender<Arc<ExecutableSpreads>>,
+}
+
+impl Pipeline {
+    pub fn start() -> Self {
+        let supervisor = Supervisor::new();
+
+        let (manager_transmitter_tx, manager_transmitter_rx) = mpsc::channel(config::get().pipeline.manager_transmitter_queue);
+        let data_mapping = DataMapping::new(manager_transmitter_tx);
+        let data_mapping_tx = data_mapping.data_mapping_tx.clone();
+        let books_queue = data_mapping.books_queue.clone();
+        supervisor.spawn_actor("DataMapping", data_mapping);
+
+        let (cache_aggregator_tx, cache_aggregator_rx) = mpsc::channel::<Arc<CacheAggregatorCmd>>(64);
+        supervisor.spawn_actor("CacheAggregator", CacheAggregator::new(cache_aggregator_rx, data_mapping_tx.clone(), None));
+
+        let (client_aggregator_chart_tx, client_aggregator_chart_rx) = mpsc::channel::<Arc<ClientAggregatorCmd>>(64);
+        let (client_aggregator_tx, client_aggregator_rx) = mpsc::channel::<ClientAggregatorCmd>(64);
+        let (data_access_layer_tx, data_access_layer_rx) = mpsc::channel::<DataAccessLayerCmd>(64);
+        supervisor.spawn_actor("ClientAggregator", ClientAggregator::new(
+            client_aggregator_rx,
+            client_aggregator_chart_rx,
+            cache_aggregator_tx.clone(),
+            data_access_layer_tx.clone(),
+        ));
+
+        let exchange_channel_store = ExchangeChannelStore::new();
+        let exchange_channel_store_tx = exchange_channel_store.sender_channel.clone();
+        supervisor.spawn_actor("ExchangeChannelStore", exchange_channel_store);
+
+        let trading = Supervisor::new();
+        let (risk_manager, risk) = RiskManager::new(exchange_channel_store_tx.clone(), None);
+        trading.spawn_actor("RiskManager", risk_manager);
+
+        let (order_gateway, orders) = OrderGateway::new(risk.clone());
+        trading.spawn_actor("OrderGateway", order_gateway);
+
+        let (inventory_tracker, inventory) = InventoryTracker::new(orders.clone());
+        supervisor.spawn_actor("InventoryTracker", inventory_tracker);
+
+        let data_aggregator = DataAggregator::new(
+            books_queue,
+            cache_aggregator_tx.clone(),
+            inventory.clone(),
+            None,
+        );
+        let register_symbol_tx = data_aggregator.register_symbol_tx.clone();
+        let book_updates = data_aggregator.book_updates.clone();
+        let executable_spreads = data_aggregator.executable_spreads.clone();
+
+        supervisor.spawn_actor("ManagerTransmitter", ManagerTransmitter::new(
+            manager_transmitter_rx,
+            client_aggregator_chart_tx,
+            cache_aggregator_tx.clone(),
+        ));
+
+        supervisor.spawn_actor("DataAccessLayer", DataAccessLayer::new(
+            data_access_layer_rx,
+            cache_aggregator_tx,
+            data_mapping_tx,
+            exchange_channel_store_tx.clone(),
+            book_updates.clone()
+        ));
+
+        let (paper_trading, paper) = PaperTrading::new(data_access_layer_tx.clone(), exchange_channel_store_tx.clone(), None);
+        supervisor.spawn_actor("PaperTrading", paper_trading);
+
+        let (arbitrage_executor, executor) = ArbitrageExecutor::new(
+            executable_spreads.clone(),
+            orders.clone(),
+            risk.clone(),
+            data_access_layer_tx,
+            None
+        );
+        supervisor.spawn_actor("ArbitrageExecutor", arbitrage_executor);
+
+        supervisor.spawn_actor("DataAggregator", data_aggregator);
+
+        let (health_monitor, health) = HealthMonitor::new();
+        supervisor.spawn_actor("HealthMonitor", health_monitor);
+
+        Self {
+            supervisor,
+            trading,
+            register_symbol_tx,
+            book_updates,
+            health,
+            orders,
+            risk,
+            executor,
+            inventory,
+            exchange_channel_store_tx,
+            client_aggregator_tx,
+            paper,
+            executable_spreads,
+        }
+    }
+
+    /// Сессии бирж из `config.exchanges`, как в `main` без воспроизведения
+    pub fn start_exchanges(&self) {
+        let (exchange_control, _) = ExchangeControl::new(
+            "config.test.toml",
+            self.register_symbol_tx.clone(),
+            self.exchange_channel_store_tx.clone(),
+            self.health.clone(),
+            self.supervisor.clone()
+        );
+        self.supervisor.spawn_actor("ExchangeControl", exchange_control);
+    }
+
+    /// Символ биржи, как его регистрирует `ExchangeStore` при подписке
+    pub async fn register(
+        &self,
+        exchange_id: ExchangeType,
+        symbol: &str
+    ) {
+        self.register_symbol_tx.send(DataAggregatorCmd::MarketRegister {
+            symbol: Arc::new(symbol.to_string()),
+            exchange_id
+        }).await.unwrap();
+    }
+
+    /// Стакан из одного уровня с каждой стороны
+    pub fn book(
+        &self,
+        exchange_id: ExchangeType,
+        symbol: &str,
+        bid: &str,
+        ask: &str,
+        volume24h: f64
+    ) {
+        let symbol = Arc::new(symbol.to_string());
+        let data = BookData {
+            snapshot: Some(Snapshot {
+                a: BTreeMap::from([(Decimal::from_str(ask).unwrap(), 1.0)]),
+                b: BTreeMap::from([(Decimal::from_str(bid).unwrap(), 1.0)]),
+                last_update_id: None,
+            }),
+            last_price: None,
+            volume24h: Some(volume24h),
+            symbol: symbol.clone(),
+            updated_at: Some(clock::instant()),
+            time: Some(EventTime::received(None)),
+        };
+        self.book_updates.push((exchange_id, symbol), Arc::new(data));
+    }
+
+    /// Исполнимые спреды `DataAggregator`. Он считает их, только пока есть получатель
+    pub fn spreads(&self) -> broadcast::Receiver<Arc<ExecutableSpreads>> {
+        self.executable_spreads.subscribe()
+    }
+
+    /// Исполнимый спред, как его рассылает `DataAggregator`
+    pub fn spread(
+        &self,
+        spread: ExecutableSpread
+    ) {
+        self.executable_spreads.send(Arc::new(ExecutableSpreads {
+            symbol: spread.key.symbol.clone(),
+            time: spread.time,
+            spreads: vec![Arc::new(spread)],
+        })).unwrap();
+    }
+
+    /// Клиент WebSocket, обслуживаемый тем же `handle_connection`, что и в `connect_async`
+    pub async fn connect(&self) -> Client {
+        let (client_stream, server_stream) = tokio::io::duplex(1 << 20);
+
+        self.supervisor.track(ws::handle_connection(
+            server_stream,
+            "127.0.0.1:0".parse().unwrap(),
+            self.client_aggregator_tx.clone(),
+            self.health.subscribe(),
+            self.paper.clone(),
+            self.executable_spreads.clone(),
+            self.supervisor.clone(),
+        ));
+
+        let (ws, _) = tokio_tungstenite::client_async("ws://127.0.0.1/", client_stream).await.unwrap();
+        Client { ws }
+    }
+}
+
+impl Drop for Pipeline {
+    fn drop(&mut self) {
+        self.supervisor.shutdown();
+        self.trading.shutdown();
+    }
+}
+
+pub struct Client {
+    ws: WebSocketStream<DuplexStream>,
+}
+
+impl Client {
+    pub async fn send(
+        &mut self,
+        msg: Value
+    ) {
+        self.ws.send(Message::Text(msg.to_string())).await.unwrap();
+    }
+
+    /// Следующий кадр канала `channel` с `unique_id`, остальные кадры пропускаются
+    pub async fn expect(
+        &mut self,
+        channel: &str,
+        unique_id: &str
+    ) -> Value {
+        let wait = async {
+            while let Some(msg) = self.ws.next().await {
+                let Ok(Message::Text(text)) = msg else { continue };
+                let frame: Value = serde_json::from_str(&text).unwrap();
+                if frame["channel"] == channel && frame["result"]["unique_id"] == unique_id {
+                    return frame;
+                }
+            }
+            panic!("соединение закрыто до кадра {channel}/{unique_id}");
+        };
+
+        tokio::time::timeout(FRAME_TIMEOUT, wait)
+            .await
+            .unwrap_or_else(|_| panic!("нет кадра {channel}/{unique_id}"))
+    }
+}
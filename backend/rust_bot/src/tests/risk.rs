@@ -0,0 +1,111 @@
+use std::{collections::HashMap, time::Duration};
+
+use crate::{config, models::{exchange::ExchangeType, order::{OrderRequest, OrderSide, OrderState, OrderStatus, OrderType, TimeInForce, new_client_order_id}, risk::RiskCheck}, services::exchange::trading_adapter::TradingError, tests::{Pipeline, TradingPipeline, mock_trading_options}};
+
+/// Лимитная покупка 1 BTC по 99.9, которую mock биржа оставляет в стакане
+async fn buy(
+    pipeline: &Pipeline,
+    exchange_id: ExchangeType
+) -> Result<OrderState, TradingError> {
+    pipeline.orders.place(OrderRequest {
+        exchange_id,
+        client_order_id: new_client_order_id(),
+        symbol: "btcusdt".into(),
+        side: OrderSide::Buy,
+        order_type: OrderType::Limit,
+        qty: 1.0,
+        price: Some(99.9),
+        time_in_force: TimeInForce::Gtc,
+        reduce_only: false,
+    }).await
+}
+
+async fn last_breach(
+    pipeline: &Pipeline
+) -> RiskCheck {
+    pipeline.risk.status().await.unwrap().breaches[0].check
+}
+
+/// Одновременные ордера проверяются по очереди: второй видит сумму первого.
+/// Отменённый ордер освобождает лимит
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn concurrent_orders_share_symbol_limit() {
+    let exchanges = TradingPipeline::start(mock_trading_options(0.0, None), mock_trading_options(0.0, None), |config| {
+        config.risk.symbol_max_notional = HashMap::from([("btcusdt".to_string(), 150.0)]);
+    }).await;
+    let pipeline = &exchanges.pipeline;
+
+    let (bybit, gate) = tokio::join!(buy(pipeline, ExchangeType::Bybit), buy(pipeline, ExchangeType::Gate));
+    let (open, rejected) = match (bybit, gate) {
+        (Ok(open), Err(rejected)) | (Err(rejected), Ok(open)) => (open, rejected),
+        other => panic!("должен пройти ровно один ордер: {other:?}"),
+    };
+    assert_eq!(open.status, OrderStatus::New);
+    assert!(matches!(rejected, TradingError::Risk(_)), "{rejected:?}");
+    assert_eq!(last_breach(pipeline).await, RiskCheck::SymbolNotional);
+    assert_eq!(exchanges.bybit.trading().unwrap().orders() + exchanges.gate.trading().unwrap().orders(), 1);
+
+    let cancelled = pipeline.orders.cancel(open.request.client_order_id.clone()).await.unwrap();
+    assert_eq!(cancelled.status, OrderStatus::Cancelled);
+
+    // Лимит символа не выключает биржу
+    let state = buy(pipeline, open.request.exchange_id).await.unwrap();
+    assert_eq!(state.status, OrderStatus::New);
+}
+
+/// Лимит биржи считает только её ордера
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn exchange_limit_counts_open_orders_of_exchange() {
+    let exchanges = TradingPipeline::start(mock_trading_options(0.0, None), mock_trading_options(0.0, None), |config| {
+        config.risk.exchange_max_notional = HashMap::from([(ExchangeType::Bybit, 150.0)]);
+    }).await;
+    let pipeline = &exchanges.pipeline;
+
+    buy(pipeline, ExchangeType::Bybit).await.unwrap();
+    buy(pipeline, ExchangeType::Gate).await.unwrap();
+
+    let result = buy(pipeline, ExchangeType::Bybit).await;
+    assert!(matches!(result, Err(TradingError::Risk(_))), "{result:?}");
+    assert_eq!(last_breach(pipeline).await, RiskCheck::ExchangeNotional);
+    assert_eq!(exchanges.bybit.trading().unwrap().orders(), 1);
+    assert_eq!(exchanges.gate.trading().unwrap().orders(), 1);
+}
+
+/// Откат лишнего объёма проходит при исчерпанном лимите символа и устаревшем стакане:
+/// стакан нужен ему только для оценки цены рыночного ордера
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn reduce_only_passes_symbol_limit_and_stale_book() {
+    let exchanges = TradingPipeline::start(mock_trading_options(0.0, None), mock_trading_options(0.0, None), |config| {
+        config.risk.symbol_max_notional = HashMap::from([("btcusdt".to_string(), 150.0)]);
+    }).await;
+    let pipeline = &exchanges.pipeline;
+
+    buy(pipeline, ExchangeType::Bybit).await.unwrap();
+    let result = buy(pipeline, ExchangeType::Gate).await;
+    assert!(matches!(result, Err(TradingError::Risk(_))), "{result:?}");
+    assert_eq!(last_breach(pipeline).await, RiskCheck::SymbolNotional);
+
+    // Сценарий больше не присылает стаканы, с лимитом в 1 мс оба устарели
+    let mut tuned = (*config::get()).clone();
+    tuned.risk.max_book_age_ms = 1;
+    config::set(tuned);
+    tokio::time::sleep(Duration::from_millis(10)).await;
+
+    let result = buy(pipeline, ExchangeType::Gate).await;
+    assert!(matches!(result, Err(TradingError::Risk(_))), "{result:?}");
+    assert_eq!(last_breach(pipeline).await, RiskCheck::StaleBook);
+
+    let unwind = pipeline.orders.place(OrderRequest {
+        exchange_id: ExchangeType::Gate,
+        client_order_id: new_client_order_id(),
+        symbol: "btcusdt".into(),
+        side: OrderSide::Sell,
+        order_type: OrderType::Market,
+        qty: 1.0,
+        price: None,
+        time_in_force: TimeInForce::Ioc,
+        reduce_only: true,
+    }).await;
+    assert!(unwind.is_ok(), "{unwind:?}");
+    assert_eq!(exchanges.gate.trading().unwrap().orders(), 1);
+}
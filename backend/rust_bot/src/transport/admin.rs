@@ -0,0 +1,451 @@
+use std::{net::{IpAddr, SocketAddr}, sync::Arc, time::Duration};
+use anyhow::{Context, anyhow, bail};
+use futures_util::{SinkExt, StreamExt};
+use serde::Deserialize;
+use serde_json::{Value, json};
+use tokio::{net::{TcpListener, TcpStream}, sync::{mpsc, oneshot}};
+use tokio_tungstenite::{accept_hdr_async, tungstenite::{Message, handshake::server::{Callback, ErrorResponse, Request, Response}, http::StatusCode}};
+use tracing::info;
+use tracing_subscriber::{EnvFilter, Registry, reload};
+
+use crate::{config, models::{exchange::ExchangeType, order::{ClientOrderId, OrderRequest, OrderSide, OrderType, TimeInForce, new_client_order_id}, risk::KillScope, websocket::{ClientId, Symbol, normalize_symbol}}, services::{exchange::{exchange_channel_store::{ExchangeChannelStoreCmd, ExchangeHandle}, exchange_control::ExchangeControlHandle, exchange_health::HealthHandle}, arbitrage_executor::ExecutorHandle, inventory_tracker::InventoryHandle, order_gateway::OrderGatewayHandle, risk_manager::RiskHandle}, transport::client_aggregator::ClientAggregatorCmd};
+
+const ADMIN_NAME: &str = "AdminWebsocket";
+
+/// Фильтр логов, который можно заменить без перезапуска
+pub type LogFilterHandle = reload::Handle<EnvFilter, Registry>;
+
+/// Команда администратора: `{"id": 1, "cmd": "dump_book", "exchange": "bybit", "symbol": "btcusdt"}`.
+/// Символы в общем формате (`btcusdt`)
+#[derive(Deserialize, Debug)]
+#[serde(tag="cmd", rename_all="snake_case")]
+pub enum AdminCmd {
+    /// Биржи, соединения и количество символов
+    Exchanges,
+    Clients,
+    /// Сбрасывает стакан и переподписывается на него, чтобы биржа прислала новый снапшот
+    ResyncBook {
+        exchange: ExchangeType,
+        symbol: Symbol
+    },
+    /// Стакан на всю глубину
+    DumpBook {
+        exchange: ExchangeType,
+        symbol: Symbol
+    },
+    DisconnectClient {
+        client_id: String
+    },
+    /// Отключает клиента и запрещает подключения с его IP до `unban_client`
+    BanClient {
+        client_id: String
+    },
+    UnbanClient {
+        ip: IpAddr
+    },
+    /// Фильтр в формате `EnvFilter`, например `debug,sqlx::query=off`. Без фильтра возвращает текущий
+    LogLevel {
+        filter: Option<String>
+    },
+    ReloadConfig,
+    /// Ордер через `OrderGateway`. Без `client_order_id` создаётся новый, с ним повтор не создаёт второй ордер
+    PlaceOrder {
+        exchange: ExchangeType,
+        symbol: Symbol,
+        side: OrderSide,
+        #[serde(default="default_order_type")]
+        order_type: OrderType,
+        qty: f64,
+        price: Option<f64>,
+        #[serde(default)]
+        time_in_force: TimeInForce,
+        client_order_id: Option<ClientOrderId>,
+    },
+    CancelOrder {
+        client_order_id: ClientOrderId
+    },
+    /// Ордера за последний час и незавершённые, или один ордер по `client_order_id`
+    Orders {
+        client_order_id: Option<ClientOrderId>
+    },
+    /// Исполнения арбитража с журналами решений, новые первыми
+    Executions,
+    /// Остатки по валютам на всех биржах с включённой торговлей
+    Inventory,
+    /// Kill switch, суточный результат и последние нарушения лимитов
+    Risk,
+    /// Включает или выключает торговлю. Без `exchange` действует на все биржи
+    KillSwitch {
+        exchange: Option<ExchangeType>,
+        active: bool,
+        reason: Option<String>
+    },
+}
+
+fn default_order_type() -> OrderType {
+    OrderType::Limit
+}
+
+#[derive(Deserialize, Debug)]
+struct AdminRequest {
+    /// Возвращается в ответе как есть
+    id: Option<Value>,
+    #[serde(flatten)]
+    cmd: AdminCmd,
+}
+
+/// Торговые сервисы для команд ордеров, исполнений, остатков и риска
+#[derive(Clone)]
+pub struct TradingHandles {
+    pub orders: OrderGatewayHandle,
+    pub executions: ExecutorHandle,
+    pub inventory: InventoryHandle,
+    pub risk: RiskHandle,
+}
+
+/// <b>AdminApi</b> WebSocket API для операторов. Подключение требует заголовок
+/// `Authorization: Bearer <token>`, каждая команда пишется в лог
+#[derive(Clone)]
+pub struct AdminApi {
+    client_aggregator_tx: mpsc::Sender<ClientAggregatorCmd>,
+    exchange_channel_store_tx: mpsc::Sender<ExchangeChannelStoreCmd>,
+    exchange_control: ExchangeControlHandle,
+    health: HealthHandle,
+    trading: TradingHandles,
+    log_filter: LogFilterHandle,
+}
+
+impl AdminApi {
+    pub fn new(
+        client_aggregator_tx: mpsc::Sender<ClientAggregatorCmd>,
+        exchange_channel_store_tx: mpsc::Sender<ExchangeChannelStoreCmd>,
+        exchange_control: ExchangeControlHandle,
+        health: HealthHandle,
+        trading: TradingHandles,
+        log_filter: LogFilterHandle
+    ) -> Self {
+        Self {
+            client_aggregator_tx,
+            exchange_channel_store_tx,
+            exchange_control,
+            health,
+            trading,
+            log_filter,
+        }
+    }
+
+    pub async fn run(
+        self
+    ) {
+        let config = config::get();
+        if config.admin.token().is_none() {
+            tracing::warn!("{} -> токен не задан, API выключен", ADMIN_NAME);
+            return;
+        }
+
+        let listener = match TcpListener::bind(&config.admin.bind).await {
+            Ok(listener) => listener,
+            Err(e) => {
+                tracing::error!("{} -> не удалось открыть {}: {}", ADMIN_NAME, config.admin.bind, e);
+                return;
+            }
+        };
+
+        info!("{} -> is running", ADMIN_NAME);
+
+        while let Ok((stream, addr)) = listener.accept().await {
+            tokio::spawn(self.clone().handle_connection(stream, addr));
+        }
+    }
+
+    async fn handle_connection(
+        self,
+        stream: TcpStream,
+        addr: SocketAddr
+    ) {
+        let ws_stream = match accept_hdr_async(stream, Authorize).await {
+            Ok(ws_stream) => ws_stream,
+            Err(e) => {
+                tracing::warn!("{} -> {} отклонён: {}", ADMIN_NAME, addr, e);
+                return;
+            }
+        };
+
+        info!("{} -> {} подключился", ADMIN_NAME, addr);
+        let (mut ws_sender, mut ws_receiver) = ws_stream.split();
+
+        while let Some(Ok(msg)) = ws_receiver.next().await {
+            let Message::Text(text) = msg else { continue };
+
+            let (id, result) = match serde_json::from_str::<AdminRequest>(&text) {
+                Ok(request) => {
+                    info!(admin = %addr, cmd = ?request.cmd, "Admin command");
+                    (request.id, self.handle_command(request.cmd).await)
+                },
+                Err(e) => (None, Err(anyhow!("неверная команда: {e}")))
+            };
+
+            let response = match result {
+                Ok(result) => json!({ "id": id, "ok": true, "result": result }),
+                Err(e) => json!({ "id": id, "ok": false, "error": format!("{e:#}") })
+            };
+
+            if ws_sender.send(Message::Text(response.to_string())).await.is_err() {
+                break;
+            }
+        }
+
+        info!("{} -> {} отключился", ADMIN_NAME, addr);
+    }
+
+    async fn handle_command(
+        &self,
+        cmd: AdminCmd
+    ) -> anyhow::Result<Value> {
+        match cmd {
+            AdminCmd::Exchanges => {
+                let sessions = self.exchange_control.status().await;
+                let report = self.health.subscribe().borrow().clone();
+
+                let exchanges: Vec<Value> = sessions
+                    .into_iter()
+                    .map(|session| {
+                        let health = report.exchanges.get(&session.exchange_id);
+                        let connections: Vec<Value> = session.connections
+                            .iter()
+                            .map(|connection| json!({
+                                "index": connection.index,
+                                "symbols": connection.symbols,
+                                "closed": connection.closed,
+                                "status": health.and_then(|health| health.connections.get(&connection.index)),
+                            }))
+                            .collect();
+
+                        json!({
+                            "exchange": session.exchange_id,
+                            "status": health.map(|health| health.status),
+                            "message_rate": health.map(|health| health.message_rate),
+                            "reconnects": health.map(|health| health.reconnects),
+                            "listed": session.listed,
+                            "symbols": session.symbols,
+                            "connections": connections,
+                        })
+                    })
+                    .collect();
+
+                Ok(json!(exchanges))
+            },
+            AdminCmd::Clients => {
+                let (reply, mut rx) = mpsc::channel(1);
+                self.client_aggregator_tx.send(ClientAggregatorCmd::Clients { reply }).await.ok();
+
+                let clients = rx.recv().await.context("ClientAggregator не ответил")?;
+                Ok(json!(clients))
+            },
+            AdminCmd::ResyncBook {
+                exchange,
+                symbol
+            } => {
+                let handle = self.exchange(exchange).await?;
+                let symbol = Arc::new(normalize_symbol(&symbol));
+
+                handle.store.reset_book(symbol.clone()).await;
+                let resubscribed = handle.subscriptions.resync(symbol).await;
+
+                Ok(json!({ "resubscribed": resubscribed }))
+            },
+            AdminCmd::DumpBook {
+                exchange,
+                symbol
+            } => {
+                let handle = self.exchange(exchange).await?;
+                let symbol = normalize_symbol(&symbol);
+                let Some(data) = handle.store.book(Arc::new(symbol.clone())).await else {
+                    bail!("{exchange}: символ {symbol} не зарегистрирован");
+                };
+
+                let (asks, bids, last_update_id) = match &data.snapshot {
+                    Some(snapshot) => (
+                        snapshot.a.iter().collect::<Vec<_>>(),
+                        snapshot.b.iter().rev().collect::<Vec<_>>(),
+                        snapshot.last_update_id
+                    ),
+                    None => (Vec::new(), Vec::new(), None)
+                };
+
+                Ok(json!({
+                    "exchange": exchange,
+                    "symbol": symbol,
+                    "last_price": data.last_price,
+                    "volume24h": data.volume24h,
+                    "time": data.time,
+                    "last_update_id": last_update_id,
+                    "asks": asks,
+                    "bids": bids,
+                }))
+            },
+            AdminCmd::DisconnectClient {
+                client_id
+            } => {
+                self.disconnect(client_id, false).await
+            },
+            AdminCmd::BanClient {
+                client_id
+            } => {
+                self.disconnect(client_id, true).await
+            },
+            AdminCmd::UnbanClient {
+                ip
+            } => {
+                let (reply, mut rx) = mpsc::channel(1);
+                self.client_aggregator_tx.send(ClientAggregatorCmd::Unban { ip, reply }).await.ok();
+
+                let unbanned = rx.recv().await.context("ClientAggregator не ответил")?;
+                Ok(json!({ "unbanned": unbanned }))
+            },
+            AdminCmd::LogLevel {
+                filter
+            } => {
+                if let Some(filter) = filter {
+                    let filter = EnvFilter::try_new(&filter)
+                        .with_context(|| format!("неверный фильтр `{filter}`"))?;
+                    self.log_filter.reload(filter)?;
+                }
+
+                let current = self.log_filter.with_current(|filter| filter.to_string())?;
+                Ok(json!({ "filter": current }))
+            },
+            AdminCmd::ReloadConfig => {
+                self.exchange_control.reload().await?;
+                Ok(json!({ "reloaded": true }))
+            },
+            AdminCmd::PlaceOrder {
+                exchange,
+                symbol,
+                side,
+                order_type,
+                qty,
+                price,
+                time_in_force,
+                client_order_id
+            } => {
+                let order = self.trading.orders.place(OrderRequest {
+                    exchange_id: exchange,
+                    client_order_id: client_order_id.unwrap_or_else(new_client_order_id),
+                    symbol: normalize_symbol(&symbol),
+                    side,
+                    order_type,
+                    qty,
+                    price,
+                    time_in_force,
+                    reduce_only: false,
+                }).await?;
+
+                Ok(json!(order))
+            },
+            AdminCmd::CancelOrder {
+                client_order_id
+            } => {
+                Ok(json!(self.trading.orders.cancel(client_order_id).await?))
+            },
+            AdminCmd::Orders {
+                client_order_id
+            } => {
+                match client_order_id {
+                    Some(client_order_id) => {
+                        let order = self.trading.orders.order(client_order_id.clone()).await;
+                        Ok(json!(order.with_context(|| format!("ордер {client_order_id} не найден"))?))
+                    },
+                    None => Ok(json!(self.trading.orders.orders().await)),
+                }
+            },
+            AdminCmd::Executions => {
+                Ok(json!(self.trading.executions.executions().await))
+            },
+            AdminCmd::Inventory => {
+                Ok(json!(self.trading.inventory.snapshot().assets()))
+            },
+            AdminCmd::Risk => {
+                Ok(json!(self.trading.risk.status().await.context("RiskManager не ответил")?))
+            },
+            AdminCmd::KillSwitch {
+                exchange,
+                active,
+                reason
+            } => {
+                let scope = exchange.map(KillScope::Exchange).unwrap_or(KillScope::Global);
+                let reason = reason.unwrap_or_else(|| "из админки".into());
+                Ok(json!(self.trading.risk.set_kill_switch(scope, active, reason).await))
+            },
+        }
+    }
+
+    async fn disconnect(
+        &self,
+        client_id: String,
+        ban: bool
+    ) -> anyhow::Result<Value> {
+        let client_id = ClientId::parse_str(&client_id)
+            .with_context(|| format!("client_id `{client_id}` не является UUID"))?;
+
+        let (reply, mut rx) = mpsc::channel(1);
+        self.client_aggregator_tx.send(ClientAggregatorCmd::Disconnect { client_id, ban, reply }).await.ok();
+
+        if !rx.recv().await.unwrap_or(false) {
+            bail!("клиент {client_id} не подключён");
+        }
+
+        Ok(json!({ "disconnected": client_id.to_string(), "banned": ban }))
+    }
+
+    async fn exchange(
+        &self,
+        exchange_id: ExchangeType
+    ) -> anyhow::Result<ExchangeHandle> {
+        let (reply, rx) = oneshot::channel();
+        self.exchange_channel_store_tx.send_timeout(
+            ExchangeChannelStoreCmd::GetExchangesChannel { reply },
+            Duration::from_millis(100)
+        ).await.ok();
+
+        let exchanges = rx.await.context("ExchangeChannelStore не ответил")?;
+        let handle = exchanges.borrow().get(&exchange_id).cloned();
+
+        handle.with_context(|| format!("биржа {exchange_id} не запущена"))
+    }
+}
+
+/// Проверка `Authorization: Bearer <token>` при подключении. Токен читается из текущего конфига,
+/// поэтому его можно сменить перезагрузкой
+struct Authorize;
+
+impl Callback for Authorize {
+    fn on_request(
+        self,
+        request: &Request,
+        response: Response
+    ) -> Result<Response, ErrorResponse> {
+        let expected = config::get().admin.token().map(|token| format!("Bearer {token}"));
+        let provided = request
+            .headers()
+            .get("authorization")
+            .and_then(|value| value.to_str().ok());
+
+        if let (Some(expected), Some(provided)) = (expected, provided)
+            && constant_time_eq(expected.as_bytes(), provided.as_bytes()) {
+            return Ok(response);
+        }
+
+        let mut error = ErrorResponse::new(Some("unauthorized".into()));
+        *error.status_mut() = StatusCode::UNAUTHORIZED;
+        Err(error)
+    }
+}
+
+/// Сравнение токена без раннего выхода по первому несовпавшему байту
+fn constant_time_eq(
+    a: &[u8],
+    b: &[u8]
+) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
@@ -1,6 +1,10 @@
-use std::{collections::{HashMap, HashSet}, sync::Arc, time::Duration};
+use std::{collections::{HashMap, HashSet}, net::{IpAddr, SocketAddr}, sync::Arc, time::Duration};
+use async_trait::async_trait;
+use chrono::Utc;
+use serde::Serialize;
 use tokio::sync::{mpsc};
-use crate::{models::{aggregator::{ClientAggregatorUse}, websocket::{ChannelSubscription, ChannelType, ClientId, WsClientMessage}}, services::cache_aggregator::CacheAggregatorCmd};
+use tokio_util::sync::CancellationToken;
+use crate::{models::{aggregator::{ClientAggregatorUse}, exchange::ExchangeType, websocket::{ChannelSubscription, ChannelType, ClientId, Symbol, WsClientMessage}}, services::{cache_aggregator::CacheAggregatorCmd, data_access_layer::DataAccessLayerCmd, metrics::Metric, supervisor::Actor}};
 
 #[derive(Debug)]
 pub enum ClientMpcsChannel {
@@ -12,20 +16,62 @@ pub enum ClientMpcsChannel {
 pub enum ClientAggregatorCmd {
     Register {
         client_id: ClientId,
+        addr: SocketAddr,
         tx: mpsc::Sender<Arc<WsClientMessage>>,
         lines_tx: mpsc::Sender<Arc<WsClientMessage>>,
+        /// Отключает клиента
+        cancel: CancellationToken,
     },
     Use(ClientAggregatorUse),
+    /// Подключённые клиенты
+    Clients {
+        reply: mpsc::Sender<Vec<ClientInfo>>
+    },
+    /// Отключает клиента. `ban` запрещает новые подключения с его IP. `false` - клиента нет
+    Disconnect {
+        client_id: ClientId,
+        ban: bool,
+        reply: mpsc::Sender<bool>
+    },
+    Unban {
+        ip: IpAddr,
+        reply: mpsc::Sender<bool>
+    },
+}
+
+/// Клиент для API администратора
+#[derive(Debug, Clone, Serialize)]
+pub struct ClientInfo {
+    pub client_id: String,
+    pub addr: SocketAddr,
+    /// Unix время подключения в мс
+    pub connected_at: i64,
+    pub subscriptions: usize,
+}
+
+struct ClientConnection {
+    addr: SocketAddr,
+    connected_at: i64,
+    cancel: CancellationToken,
 }
 
 pub struct ClientAggregator {
     client_cmd_rx: mpsc::Receiver<ClientAggregatorCmd>,
     cmd_rx: mpsc::Receiver<Arc<ClientAggregatorCmd>>,
     cache_aggregator_cmd: mpsc::Sender<Arc<CacheAggregatorCmd>>,
+    data_access_layer_tx: mpsc::Sender<DataAccessLayerCmd>,
 
     clients: HashMap<ClientId, HashMap<ChannelType, ClientMpcsChannel>>,
     subscriptions: HashMap<ClientId, HashSet<ChannelSubscription>>,
     sub_index: HashMap<ChannelSubscription, HashSet<ClientId>>,
+    connections: HashMap<ClientId, ClientConnection>,
+    /// IP, с которых подключения сразу закрываются
+    banned: HashSet<IpAddr>,
+
+    clients_gauge: Arc<Metric>,
+    subscriptions_gauge: Arc<Metric>,
+    /// Сообщения, не отправленные клиенту за `send_timeout`
+    dropped_sends: Arc<Metric>,
 }
 
 impl ClientAggregator {
@@ -33,24 +79,40 @@ impl ClientAggregator {
         client_cmd_rx: mpsc::Receiver<ClientAggregatorCmd>,
         cmd_rx: mpsc::Receiver<Arc<ClientAggregatorCmd>>,
         cache_aggregator_cmd: mpsc::Sender<Arc<CacheAggregatorCmd>>,
+        data_access_layer_tx: mpsc::Sender<DataAccessLayerCmd>,
     ) -> Self {
         Self {
             client_cmd_rx,
             cmd_rx,
             cache_aggregator_cmd,
+            data_access_layer_tx,
 
             clients: HashMap::new(),
             subscriptions: HashMap::new(),
             sub_index: HashMap::new(),
+            connections: HashMap::new(),
+            banned: HashSet::new(),
+
+            clients_gauge: Metric::gauge("ws_clients", "Подключённые клиенты", &[]),
+            subscriptions_gauge: Metric::gauge("ws_subscriptions", "Подписки клиентов на каналы", &[]),
+            dropped_sends: Metric::counter("ws_dropped_sends_total", "Сообщения, не отправленные клиенту из-за переполненной очереди", &[]),
         }
     }
-    
-    pub async fn run(mut self) {
+}
+
+#[async_trait]
+impl Actor for ClientAggregator {
+    async fn run(
+        &mut self,
+        shutdown: CancellationToken
+    ) {
         loop {
             tokio::select! {
                 biased;
+                _ = shutdown.cancelled() => return,
                 Some(client_cmd) = self.client_cmd_rx.recv() => {
                     self.handle_cmd(Arc::new(client_cmd)).await;
+                    self.update_gauges();
                 }
 
                 Some(client_cmd) = self.cmd_rx.recv() => {
@@ -59,7 +121,9 @@ impl ClientAggregator {
             }
         }
     }
+}
 
+impl ClientAggregator {
     async fn handle_cmd(
         &mut self, 
         cmd: Arc<ClientAggregatorCmd>
@@ -67,9 +131,23 @@ impl ClientAggregator {
         match cmd.as_ref() {
             ClientAggregatorCmd::Register { 
                 client_id, 
+                addr,
                 tx ,
                 lines_tx,
+                cancel,
             } => {
+                if self.banned.contains(&addr.ip()) {
+                    tracing::warn!("{} -> IP {} заблокирован", client_id, addr.ip());
+                    cancel.cancel();
+                    return;
+                }
+
+                self.connections.insert(*client_id, ClientConnection { 
+                    addr: *addr, 
+                    connected_at: Utc::now().timestamp_millis(), 
+                    cancel: cancel.clone() 
+                });
+
                 let entry = self.clients
                     .entry(*client_id)
                     .or_insert_with(HashMap::new);
@@ -90,9 +168,18 @@ impl ClientAggregator {
                             .or_insert_with(HashSet::new)
                             .insert(client_channel_sub.clone());
 
-                        self.sub_index.entry(client_channel_sub.clone())
-                            .or_insert_with(HashSet::new)
-                            .insert(*client_id);
+                        let clients = self.sub_index.entry(client_channel_sub.clone())
+                            .or_insert_with(HashSet::new);
+
+                        // Первый клиент канала запрашивает стаканы у бирж
+                        if clients.is_empty() {
+                            for (exchange_id, symbol) in Self::books_of(client_channel_sub) {
+                                self.data_access_layer_tx.send(
+                                    DataAccessLayerCmd::AcquireBook { exchange_id, symbol }
+                                ).await.ok();
+                            }
+                        }
+                        clients.insert(*client_id);
 
                         // Инизиализируем данные линий
                         let cache_aggregator_tx = self.cache_aggregator_cmd.clone();
@@ -125,16 +212,20 @@ impl ClientAggregator {
                                     if let Some(ch) = channels.get(&msg.channel) {
                                         match ch {
                                             ClientMpcsChannel::OrderBook(channel_tx) => {
-                                                channel_tx.send_timeout(
+                                                if channel_tx.send_timeout(
                                                     Arc::new(msg.clone()), 
                                                     Duration::from_millis(10)
-                                                ).await.ok();
+                                                ).await.is_err() {
+                                                    self.dropped_sends.inc();
+                                                }
                                             },
                                             ClientMpcsChannel::Lines(channel_tx) => {
-                                                channel_tx.send_timeout(
+                                                if channel_tx.send_timeout(
                                                     Arc::new(msg.clone()), 
                                                     Duration::from_millis(10)
-                                                ).await.ok();
+                                                ).await.is_err() {
+                                                    self.dropped_sends.inc();
+                                                }
                                             },
                                         }
                                     }
@@ -145,11 +236,21 @@ impl ClientAggregator {
                     ClientAggregatorUse::UnRegister(
                         client_id
                     ) => {
+                        self.connections.remove(client_id);
                         self.clients.remove(&client_id);
                         if let Some(subs) = self.subscriptions.remove(&client_id) {
                             for sub in subs {
                                 if let Some(clients) = self.sub_index.get_mut(&sub) {
                                     clients.remove(&*client_id);
+
+                                    // Стаканы без клиентов больше не публикуются
+                                    if clients.is_empty() {
+                                        for (exchange_id, symbol) in Self::books_of(&sub) {
+                                            self.data_access_layer_tx.send(
+                                                DataAccessLayerCmd::ReleaseBook { exchange_id, symbol }
+                                            ).await.ok();
+                                        }
+                                    }
                                 }
                             }
                         }
@@ -158,7 +259,71 @@ impl ClientAggregator {
                         self.sub_index.retain(|_, clients| !clients.is_empty());
                     }
                 }
-            }
+            },
+            ClientAggregatorCmd::Clients { 
+                reply 
+            } => {
+                let clients = self.connections
+                    .iter()
+                    .map(|(client_id, connection)| ClientInfo {
+                        client_id: client_id.to_string(),
+                        addr: connection.addr,
+                        connected_at: connection.connected_at,
+                        subscriptions: self.subscriptions.get(client_id).map_or(0, |subs| subs.len()),
+                    })
+                    .collect();
+
+                reply.send(clients).await.ok();
+            },
+            ClientAggregatorCmd::Disconnect { 
+                client_id, 
+                ban, 
+                reply 
+            } => {
+                // Клиент отпишется сам через `UnRegister`, когда закроет соединение
+                let found = match self.connections.get(client_id) {
+                    Some(connection) => {
+                        if *ban {
+                            self.banned.insert(connection.addr.ip());
+                        }
+                        connection.cancel.cancel();
+                        true
+                    },
+                    None => false
+                };
+
+                reply.send(found).await.ok();
+            },
+            ClientAggregatorCmd::Unban { 
+                ip, 
+                reply 
+            } => {
+                reply.send(self.banned.remove(ip)).await.ok();
+            },
+        }
+    }
+
+    /// Команды клиентов меняют количество подключений и подписок, публикации - нет
+    fn update_gauges(
+        &self
+    ) {
+        self.clients_gauge.set(self.connections.len() as i64);
+        self.subscriptions_gauge.set(self.subscriptions.values().map(|subs| subs.len()).sum::<usize>() as i64);
+    }
+
+    /// Стаканы бирж, которые нужны каналу
+    fn books_of(
+        sub: &ChannelSubscription
+    ) -> Vec<(ExchangeType, Arc<Symbol>)> {
+        match sub {
+            ChannelSubscription::OrderBook { 
+                long_market_type, 
+                short_market_type: _ 
+            } => vec![
+                (long_market_type.long_exchange, long_market_type.symbol.clone()),
+                (long_market_type.short_exchange, long_market_type.symbol.clone()),
+            ],
+            ChannelSubscription::Chart { .. } => Vec::new(),
         }
     }
 }
\ No newline at end of file
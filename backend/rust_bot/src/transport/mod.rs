@@ -1,2 +1,3 @@
 pub mod ws;
-pub mod client_aggregator;
\ No newline at end of file
+pub mod client_aggregator;
+pub mod admin;
\ No newline at end of file
@@ -1,55 +1,83 @@
-use std::{collections::{HashMap}, sync::Arc, time::Duration};
+use std::{collections::{HashMap}, net::SocketAddr, sync::Arc, time::Duration};
 use futures_util::{StreamExt, SinkExt};
-use tokio::{net::TcpListener, sync::{mpsc}};
-use tokio_tungstenite::{accept_async, tungstenite::{Message, protocol::CloseFrame}};
+use itertools::Itertools;
+use tokio::{io::{AsyncRead, AsyncWrite}, net::TcpListener, sync::{broadcast, mpsc, watch}};
+use tokio_tungstenite::{accept_async, tungstenite::{Message, protocol::{CloseFrame, frame::coding::CloseCode}}};
 use tracing::info;
 use uuid::Uuid;
 
-use crate::{models::{aggregator::{ClientAggregatorUse, KeyMarketType}, websocket::{ChannelSubscription, ChannelType, ClientCmd, ClientData, Subscription, WsClientMessage}}, transport::client_aggregator::ClientAggregatorCmd};
+use crate::{config, models::{aggregator::{ClientAggregatorUse, ExecutableSpreads, KeyMarketType}, paper::{PaperEvent, PaperMessage}, websocket::{ChannelSubscription, ChannelType, ClientCmd, ClientData, Subscription, Symbol, WsClientMessage}}, services::{exchange::exchange_health::{HealthHandle, HealthReport}, paper_trading::PaperHandle, supervisor::Supervisor}, transport::client_aggregator::ClientAggregatorCmd};
 
 const PING_DELAY: u64 = 20; // в секундах
 const WEBSOCKET_NAME: &'static str = "ArbitrationWebsocket";
 
+/// Соединения клиентов учитываются в `supervisor` и при остановке закрываются с `CloseCode::Away`
 pub async fn connect_async(
     sender: mpsc::Sender<ClientAggregatorCmd>,
+    health: HealthHandle,
+    paper: PaperHandle,
+    executable_spreads: broadcast::Sender<Arc<ExecutableSpreads>>,
+    supervisor: Supervisor,
 ) {
-    let addr = "127.0.0.1:9000";
+    let addr = config::get().server.bind.clone();
     let listener = TcpListener::bind(addr).await.unwrap();
     
     info!("{} -> is running", WEBSOCKET_NAME);
 
-    while let Ok((stream, _)) = listener.accept().await {
-        tokio::spawn(handle_connection(
+    while let Ok((stream, addr)) = listener.accept().await {
+        supervisor.track(handle_connection(
             stream, 
+            addr,
             sender.clone(),
+            health.subscribe(),
+            paper.clone(),
+            executable_spreads.clone(),
+            supervisor.clone(),
         ));
     }
 }
 
-async fn handle_connection(
-    stream: tokio::net::TcpStream, 
+/// Соединение клиента поверх любого потока: TCP из `connect_async` или `tokio::io::duplex` в тестах
+pub(crate) async fn handle_connection<S: AsyncRead + AsyncWrite + Unpin + Send + 'static>(
+    stream: S, 
+    addr: SocketAddr,
     sender: mpsc::Sender<ClientAggregatorCmd>,
+    mut health_rx: watch::Receiver<Arc<HealthReport>>,
+    paper: PaperHandle,
+    executable_spreads: broadcast::Sender<Arc<ExecutableSpreads>>,
+    supervisor: Supervisor,
 ) {
 
-    let ws_stream: tokio_tungstenite::WebSocketStream<tokio::net::TcpStream> = accept_async(stream).await.unwrap();
+    let ws_stream: tokio_tungstenite::WebSocketStream<S> = accept_async(stream).await.unwrap();
     let (mut ws_sender, mut ws_receiver) = ws_stream.split();
 
     let new_id = Uuid::new_v4();
-    let (orderbook_tx, mut orderbook_rx) = mpsc::channel::<Arc<WsClientMessage>>(100);
-    let (lines_tx, mut lines_rx) = mpsc::channel::<Arc<WsClientMessage>>(100);
-    let cancel_token = tokio_util::sync::CancellationToken::new();
+    let client_queue = config::get().server.client_queue;
+    let (orderbook_tx, mut orderbook_rx) = mpsc::channel::<Arc<WsClientMessage>>(client_queue);
+    let (lines_tx, mut lines_rx) = mpsc::channel::<Arc<WsClientMessage>>(client_queue);
+    // Отменяется и при отключении клиента, и при остановке приложения
+    let shutdown = supervisor.token();
+    let cancel_token = shutdown.child_token();
 
-    let (error_tx, mut error_rx) = mpsc::channel(100);
+    let (error_tx, mut error_rx) = mpsc::channel(client_queue);
+    // Символ, статусы которого нужны клиенту
+    let (health_sub_tx, mut health_sub_rx) = mpsc::channel::<Arc<Symbol>>(4);
+    // Позиции и исполнения бумажной торговли
+    let (paper_tx, mut paper_rx) = mpsc::channel::<Arc<PaperEvent>>(client_queue);
+    // Символ, исполнимые спреды которого нужны клиенту
+    let (opportunities_sub_tx, mut opportunities_sub_rx) = mpsc::channel::<Arc<Symbol>>(4);
 
     sender.send(
         ClientAggregatorCmd::Register { 
             client_id: new_id, 
+            addr,
             tx: orderbook_tx.clone(),
-            lines_tx
+            lines_tx,
+            cancel: cancel_token.clone()
         }
     ).await.ok();
 
-    tokio::spawn({
+    supervisor.track({
         let sender = sender.clone(); 
         let cancel_token = cancel_token.clone();
 
@@ -67,10 +95,51 @@ async fn handle_connection(
 
         let mut interval = tokio::time::interval(Duration::from_millis(100));
         let mut ping_interval = tokio::time::interval(Duration::from_secs(PING_DELAY));
+        let mut health_symbol: Option<Arc<Symbol>> = None;
+        // Спреды считаются, только пока на них кто-то подписан, поэтому подписка создаётся по запросу клиента
+        let mut opportunities: Option<(Arc<Symbol>, broadcast::Receiver<Arc<ExecutableSpreads>>)> = None;
+        // Последние спреды символа, отправляются не чаще `interval`
+        let mut pending_opportunities: Option<Arc<ExecutableSpreads>> = None;
 
         async move {
             loop {
                 tokio::select! {
+                    Some(symbol) = health_sub_rx.recv() => {
+                        health_symbol = Some(symbol);
+                        // Текущие статусы отправляются сразу
+                        health_rx.mark_changed();
+                    },
+                    Ok(_) = health_rx.changed(), if health_symbol.is_some() => {
+                        let Some(symbol) = &health_symbol else { continue };
+                        let report = health_rx.borrow_and_update().for_symbol(symbol);
+                        let msg = serde_json::json!({
+                            "channel": ChannelType::Health,
+                            "result": report
+                        });
+
+                        if ws_sender.send(Message::Text(msg.to_string())).await.is_err() {
+                            cancel_token.cancel();
+                        }
+                    },
+                    Some(symbol) = opportunities_sub_rx.recv() => {
+                        opportunities = Some((symbol, executable_spreads.subscribe()));
+                        pending_opportunities = None;
+                    },
+                    Ok(batch) = recv_opportunities(&mut opportunities) => {
+                        if opportunities.as_ref().is_some_and(|(symbol, _)| *symbol == batch.symbol) {
+                            pending_opportunities = Some(batch);
+                        }
+                    },
+                    Some(event) = paper_rx.recv() => {
+                        let msg = serde_json::json!({
+                            "channel": ChannelType::Paper,
+                            "result": event
+                        });
+
+                        if ws_sender.send(Message::Text(msg.to_string())).await.is_err() {
+                            cancel_token.cancel();
+                        }
+                    },
                     Some(error_msg) = error_rx.recv() => {
                         info!("Принудительно отключили клиента");
                         ws_sender.send(error_msg).await.ok();
@@ -89,11 +158,29 @@ async fn handle_connection(
                         }
                     },
                     _ = cancel_token.cancelled() => {
+                        if shutdown.is_cancelled() {
+                            ws_sender.send(Message::Close(Some(CloseFrame {
+                                code: CloseCode::Away,
+                                reason: "server shutdown".into()
+                            }))).await.ok();
+                        }
+                        ws_sender.close().await.ok();
                         sender.send(ClientAggregatorCmd::Use(ClientAggregatorUse::UnRegister(new_id))).await.ok();
                         info!("{} -> отключился", new_id);
                         break;
                     },
                     _ = interval.tick() => {
+                        if let Some(batch) = pending_opportunities.take() {
+                            let msg = serde_json::json!({
+                                "channel": ChannelType::Opportunities,
+                                "result": batch
+                            });
+
+                            if ws_sender.send(Message::Text(msg.to_string())).await.is_err() {
+                                cancel_token.cancel();
+                            }
+                        }
+
                         for data in books.values() {
                             for result in data.result.values() {
                                 let msg = serde_json::to_string(result).unwrap();
@@ -104,9 +191,14 @@ async fn handle_connection(
                             }
                         }
                         
-                        for data in chart_data.values() {
-                            for result in data.result.values() {
-                                let msg = serde_json::to_string(result).unwrap();
+                        // Данные графика отправляются один раз: сначала история, потом обновления
+                        for data in chart_data.values_mut() {
+                            let results = data.result
+                                .drain()
+                                .sorted_by(|(a, _), (b, _)| a.cmp(b));
+
+                            for (_, result) in results {
+                                let msg = serde_json::to_string(&result).unwrap();
 
                                 if ws_sender.send(Message::Text(msg.to_string())).await.is_err() {
                                     cancel_token.cancel();
@@ -124,21 +216,42 @@ async fn handle_connection(
         }
     });
 
-    // Обрабатываем входящие сообщения от клиента
-    while let Some(msg) = ws_receiver.next().await {
+    // Обрабатываем входящие сообщения от клиента, пока его не отключили
+    while let Some(msg) = tokio::select! {
+        msg = ws_receiver.next() => msg,
+        _ = cancel_token.cancelled() => None
+    } {
         if let Ok(msg) = msg {
             match msg {
                 Message::Text(msg) => {
+                    if let Ok(paper_msg) = serde_json::from_str::<PaperMessage>(&msg)
+                        && paper_msg.channel == ChannelType::Paper {
+                        paper.request(new_id, paper_msg.user_id, paper_msg.request, &paper_tx).await;
+                        continue;
+                    }
+
                     if let Ok(subscription) = serde_json::from_str::<Subscription>(&msg) {       
-                        
+                        if subscription.channel == ChannelType::Health {
+                            let ticker = Arc::new(format!("{}usdt", subscription.ticker.to_lowercase()));
+                            health_sub_tx.send(ticker).await.ok();
+                            continue;
+                        }
+
+                        if subscription.channel == ChannelType::Opportunities {
+                            let ticker = Arc::new(format!("{}usdt", subscription.ticker.to_lowercase()));
+                            opportunities_sub_tx.send(ticker).await.ok();
+                            continue;
+                        }
+
                         // Возращаем ошибку
                         if subscription.long_exchange == subscription.short_exchange {
                             error_tx.send(Message::Close(
                                 Some(CloseFrame {
-                                    code: tokio_tungstenite::tungstenite::protocol::frame::coding::CloseCode::Policy,
+                                    code: CloseCode::Policy,
                                     reason: "invalid_subscirptions".into()
                                 })
                             )).await.ok();
+                            paper.unsubscribe(new_id).await;
                             return ;
                         }
 
@@ -211,4 +324,17 @@ async fn handle_connection(
             }
         }
     }
-}
\ No newline at end of file
+
+    paper.unsubscribe(new_id).await;
+    cancel_token.cancel();
+}
+
+/// Ждёт спреды, пока клиент подписан на `opportunities`, иначе никогда не завершается
+async fn recv_opportunities(
+    opportunities: &mut Option<(Arc<Symbol>, broadcast::Receiver<Arc<ExecutableSpreads>>)>
+) -> Result<Arc<ExecutableSpreads>, broadcast::error::RecvError> {
+    match opportunities {
+        Some((_, rx)) => rx.recv().await,
+        None => std::future::pending().await,
+    }
+}